@@ -0,0 +1,54 @@
+/* SPDX-License-Identifier: GPL-2.0 */
+/*
+ * References to objects owned by Rust code.
+ *
+ * Rust code hands out a struct rust_ref to let C code keep one of its
+ * objects alive, for example as the private data of a C subsystem. The
+ * object is only freed, by the Rust side, once every reference to it has
+ * been released, whether it is held by C or by Rust code.
+ *
+ * The Rust side is in rust/kernel/sync/c_ref.rs.
+ */
+#ifndef _LINUX_RUST_REF_H
+#define _LINUX_RUST_REF_H
+
+struct rust_ref_ops;
+
+/**
+ * struct rust_ref - A counted reference to an object owned by Rust code.
+ * @ptr: The object, or NULL for a null reference. It must only be
+ *       interpreted by the Rust code that created the reference.
+ * @ops: Private to the Rust side.
+ *
+ * A reference is passed around by value. Every copy made with
+ * rust_ref_get() must be released with rust_ref_put(); copying the
+ * struct itself does not take a new reference.
+ */
+struct rust_ref {
+	const void *ptr;
+	const struct rust_ref_ops *ops;
+};
+
+/**
+ * rust_ref_get() - Take a new reference to the object of @ref.
+ * @ref: A reference held by the caller.
+ *
+ * Return: The new reference, or a null reference if @ref is null.
+ */
+struct rust_ref rust_ref_get(const struct rust_ref *ref);
+
+/**
+ * rust_ref_put() - Release a reference.
+ * @ref: The reference, which must not be used afterwards.
+ *
+ * Frees the object if this was the last reference to it. Does nothing
+ * if @ref is null.
+ */
+void rust_ref_put(struct rust_ref ref);
+
+static inline bool rust_ref_is_null(const struct rust_ref *ref)
+{
+	return !ref->ptr;
+}
+
+#endif /* _LINUX_RUST_REF_H */
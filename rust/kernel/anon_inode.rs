@@ -0,0 +1,209 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Anonymous inodes.
+//!
+//! Files that are not reachable through any path, but are created by another operation and
+//! returned as a file descriptor, such as the sessions of a device or the event files of a
+//! subsystem. Their operations are provided by a [`file::Operations`] implementation, whose
+//! [`file::Operations::open`] is never called: the data of each file is given when it is created.
+//!
+//! C header: [`include/linux/anon_inodes.h`](../../../../include/linux/anon_inodes.h)
+
+use crate::{
+    bindings,
+    error::from_kernel_err_ptr,
+    file::{self, File, FileDescriptorReservation},
+    str::CStr,
+    types::ForeignOwnable,
+    ARef, Result, ThisModule,
+};
+use core::{marker::PhantomData, ptr::NonNull};
+
+/// The open adapter of anonymous files, which are never opened through the VFS.
+struct NoOpen;
+
+impl<D: Sync> file::OpenAdapter<D> for NoOpen {
+    unsafe fn convert(_inode: *mut bindings::inode, _file: *mut bindings::file) -> *const D {
+        core::ptr::null()
+    }
+}
+
+/// The file operations of the anonymous files of a [`Factory`], with the module that owns them.
+///
+/// The files point to it for as long as they are open, which may be after the factory and the
+/// state of the module are gone, so it must be static. It is usually defined by
+/// [`anon_inode_factory!`], in the module that implements `T`.
+///
+/// [`anon_inode_factory!`]: crate::anon_inode_factory
+pub struct FactoryVtable<T: file::Operations> {
+    fops: bindings::file_operations,
+    _p: PhantomData<T>,
+}
+
+// SAFETY: The file operations are never modified after they are created.
+unsafe impl<T: file::Operations> Sync for FactoryVtable<T> {}
+
+impl<T: file::Operations> FactoryVtable<T> {
+    /// Creates the file operations of the anonymous files handled by `T`, which belong to
+    /// `module`.
+    ///
+    /// `module` must be the module that implements `T`, so that open files keep it loaded.
+    pub const fn new(module: &'static ThisModule) -> Self {
+        // SAFETY: `NoOpen` is only used for anonymous files, whose `open` callback is never called.
+        let mut fops = *unsafe { file::OperationsVtable::<NoOpen, T>::build() };
+        fops.owner = module.0;
+        Self {
+            fops,
+            _p: PhantomData,
+        }
+    }
+}
+
+/// Creates a [`Factory`] of anonymous files handled by `$type`, which belong to the calling
+/// module.
+///
+/// It defines the static [`FactoryVtable`] of the files, so `$type` cannot be a generic parameter.
+#[macro_export]
+macro_rules! anon_inode_factory {
+    ($type:ty) => {{
+        static VTABLE: $crate::anon_inode::FactoryVtable<$type> =
+            $crate::anon_inode::FactoryVtable::new(&crate::THIS_MODULE);
+        $crate::anon_inode::Factory::new(&VTABLE)
+    }};
+}
+
+/// Creates anonymous files whose operations are handled by `T`.
+///
+/// The files hold a reference to the module that owns their [`FactoryVtable`], so it cannot be
+/// unloaded while they are open. Their operations are static, so the files do not depend on the
+/// factory, which may be dropped before they are released.
+///
+/// # Examples
+///
+/// A device that hands out a new session for each `ioctl`:
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::{
+///     anon_inode::Factory,
+///     c_str,
+///     file::{self, flags, File, IoctlCommand},
+///     io_buffer::IoBufferWriter,
+///     sync::{Arc, ArcBorrow},
+/// };
+///
+/// const NEW_SESSION: u32 = 0x5201;
+///
+/// struct Session {
+///     id: u32,
+/// }
+///
+/// #[vtable]
+/// impl file::Operations for Session {
+///     type Data = Box<Session>;
+///
+///     fn open(_data: &(), _file: &File) -> Result<Box<Session>> {
+///         // Sessions are only created by the factory, which is never opened.
+///         Err(EINVAL)
+///     }
+///
+///     fn read(
+///         session: &Session,
+///         _file: &File,
+///         _data: &mut impl IoBufferWriter,
+///         _offset: &mut u64,
+///     ) -> Result<usize> {
+///         pr_info!("Read from session {}\n", session.id);
+///         Ok(0)
+///     }
+/// }
+///
+/// struct Control;
+///
+/// #[vtable]
+/// impl file::Operations for Control {
+///     type OpenData = Arc<Factory<Session>>;
+///     type Data = Arc<Factory<Session>>;
+///
+///     fn open(factory: &Arc<Factory<Session>>, _file: &File) -> Result<Self::Data> {
+///         Ok(factory.clone())
+///     }
+///
+///     fn ioctl(
+///         factory: ArcBorrow<'_, Factory<Session>>,
+///         _file: &File,
+///         cmd: &mut IoctlCommand,
+///     ) -> Result<i32> {
+///         match cmd.raw() {
+///             (NEW_SESSION, id) => {
+///                 let session = Box::try_new(Session { id: id as u32 })?;
+///                 let flags = flags::O_RDWR | flags::O_CLOEXEC;
+///                 let fd = factory.create_fd(c_str!("[example-session]"), session, flags)?;
+///                 Ok(fd as i32)
+///             }
+///             _ => Err(ENOTTY),
+///         }
+///     }
+/// }
+///
+/// fn new_factory() -> Result<Arc<Factory<Session>>> {
+///     Ok(Arc::try_new(kernel::anon_inode_factory!(Session))?)
+/// }
+/// ```
+pub struct Factory<T: file::Operations> {
+    vtable: &'static FactoryVtable<T>,
+}
+
+impl<T: file::Operations> Factory<T> {
+    /// Creates a new factory of files with the given operations.
+    ///
+    /// Users are encouraged to use the [`anon_inode_factory!`] macro instead, which defines the
+    /// operations in the calling module.
+    ///
+    /// [`anon_inode_factory!`]: crate::anon_inode_factory
+    pub const fn new(vtable: &'static FactoryVtable<T>) -> Self {
+        Self { vtable }
+    }
+
+    /// Creates a new anonymous file called `name`, whose operations get `data`.
+    ///
+    /// `name` is shown as the target of the link in `/proc/<pid>/fd`, and is usually in brackets,
+    /// such as `[kvm-vcpu]`. `flags` may contain an access mode, such as [`file::flags::O_RDWR`],
+    /// and [`file::flags::O_NONBLOCK`]. [`file::Operations::release`] is called with `data` once
+    /// the last reference to the file is dropped.
+    pub fn create_file(&self, name: &CStr, data: T::Data, flags: u32) -> Result<ARef<File>> {
+        let ptr = data.into_foreign();
+        // SAFETY: `name` is a valid C string, and the file operations are static. The callbacks
+        // expect `private_data` to be the value returned by `into_foreign`.
+        let file = from_kernel_err_ptr(unsafe {
+            bindings::anon_inode_getfile(
+                name.as_char_ptr(),
+                &self.vtable.fops,
+                ptr as *mut core::ffi::c_void,
+                flags as _,
+            )
+        });
+        match file {
+            // SAFETY: `anon_inode_getfile` returns a new file with a reference that we own.
+            Ok(file) => Ok(unsafe { ARef::from_raw(NonNull::new_unchecked(file.cast())) }),
+            Err(e) => {
+                // SAFETY: The file was not created, so `ptr` is still owned here.
+                drop(unsafe { T::Data::from_foreign(ptr) });
+                Err(e)
+            }
+        }
+    }
+
+    /// Creates a new anonymous file like [`Factory::create_file`], and installs it in a new file
+    /// descriptor of the current process.
+    ///
+    /// The file descriptor is closed on `exec` if `flags` contains [`file::flags::O_CLOEXEC`].
+    /// Returns the file descriptor, for example to be returned by an `ioctl`.
+    pub fn create_fd(&self, name: &CStr, data: T::Data, flags: u32) -> Result<u32> {
+        let reservation = FileDescriptorReservation::new(flags & file::flags::O_CLOEXEC)?;
+        let file = self.create_file(name, data, flags)?;
+        let fd = reservation.reserved_fd();
+        reservation.commit(file);
+        Ok(fd)
+    }
+}
@@ -0,0 +1,399 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Bitmaps and flags.
+//!
+//! [`Bitmap`] is a fixed-size set of bits with the layout of a C bitmap, an array of
+//! `unsigned long`, whose bits can be set and cleared atomically. It is typically used to allocate
+//! small integers, such as minor numbers, without a lock.
+//!
+//! [`bitflags!`] declares a type for a set of flags, such as the fields of a device register, so
+//! that they are not mixed up with plain integers.
+//!
+//! C header: [`include/linux/bitmap.h`](../../../../include/linux/bitmap.h)
+
+use crate::Result;
+use alloc::{boxed::Box, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+const BITS_PER_LONG: usize = usize::BITS as usize;
+
+/// A fixed-size bitmap whose bits can be modified concurrently.
+///
+/// Modifying a single bit is atomic, like with the `set_bit` family of C functions, but the bitmap
+/// as a whole is not updated atomically: a search may miss a bit that is modified concurrently.
+///
+/// # Panics
+///
+/// Like indexing a slice, the functions that take the index of a bit panic if it is out of
+/// bounds.
+///
+/// # Examples
+///
+/// Allocating minor numbers, and releasing them when the devices go away:
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::bitmap::Bitmap;
+///
+/// struct Minors(Bitmap);
+///
+/// impl Minors {
+///     fn alloc(&self) -> Result<usize> {
+///         self.0.acquire_first_zero().ok_or(EBUSY)
+///     }
+///
+///     fn free(&self, minor: usize) {
+///         self.0.clear(minor);
+///     }
+/// }
+///
+/// let minors = Minors(Bitmap::try_new(4)?);
+/// assert_eq!(minors.alloc()?, 0);
+/// assert_eq!(minors.alloc()?, 1);
+/// minors.free(0);
+/// assert_eq!(minors.alloc()?, 0);
+/// assert_eq!(minors.0.iter().collect::<Vec<_>>(), [0, 1]);
+/// # Ok::<(), Error>(())
+/// ```
+///
+/// # Invariants
+///
+/// `words` has `BITS_TO_LONGS(nbits)` words, and the bits beyond `nbits` in the last one are zero.
+pub struct Bitmap {
+    words: Box<[AtomicUsize]>,
+    nbits: usize,
+}
+
+impl Bitmap {
+    /// Creates a bitmap of `nbits` bits, all clear.
+    pub fn try_new(nbits: usize) -> Result<Self> {
+        let len = (nbits + BITS_PER_LONG - 1) / BITS_PER_LONG;
+        let mut words = Vec::try_with_capacity(len)?;
+        for _ in 0..len {
+            words.try_push(AtomicUsize::new(0))?;
+        }
+        // INVARIANT: There are as many words as needed, and they are all zero.
+        Ok(Self {
+            words: words.try_into_boxed_slice()?,
+            nbits,
+        })
+    }
+
+    /// Returns the number of bits in the bitmap.
+    pub fn len(&self) -> usize {
+        self.nbits
+    }
+
+    /// Returns `true` if the bitmap has no bits.
+    pub fn is_empty(&self) -> bool {
+        self.nbits == 0
+    }
+
+    /// Returns the word that holds bit `index`, and the mask of the bit in it.
+    fn word(&self, index: usize) -> (&AtomicUsize, usize) {
+        assert!(index < self.nbits, "bit index out of bounds");
+        (
+            &self.words[index / BITS_PER_LONG],
+            1 << (index % BITS_PER_LONG),
+        )
+    }
+
+    /// Returns `true` if bit `index` is set.
+    pub fn test(&self, index: usize) -> bool {
+        let (word, mask) = self.word(index);
+        word.load(Ordering::Relaxed) & mask != 0
+    }
+
+    /// Sets bit `index`.
+    ///
+    /// Like `set_bit`, this does not order other memory accesses.
+    pub fn set(&self, index: usize) {
+        let (word, mask) = self.word(index);
+        word.fetch_or(mask, Ordering::Relaxed);
+    }
+
+    /// Clears bit `index`.
+    ///
+    /// Like `clear_bit_unlock`, memory accesses before it are ordered before the bit is cleared, so
+    /// that the bit can be used to release a resource.
+    pub fn clear(&self, index: usize) {
+        let (word, mask) = self.word(index);
+        word.fetch_and(!mask, Ordering::Release);
+    }
+
+    /// Sets bit `index` and returns whether it was already set.
+    ///
+    /// Like `test_and_set_bit`, this is fully ordered.
+    pub fn test_and_set(&self, index: usize) -> bool {
+        let (word, mask) = self.word(index);
+        word.fetch_or(mask, Ordering::SeqCst) & mask != 0
+    }
+
+    /// Clears bit `index` and returns whether it was set.
+    ///
+    /// Like `test_and_clear_bit`, this is fully ordered.
+    pub fn test_and_clear(&self, index: usize) -> bool {
+        let (word, mask) = self.word(index);
+        word.fetch_and(!mask, Ordering::SeqCst) & mask != 0
+    }
+
+    /// Returns the index of the first set bit at or after `start`, if any.
+    pub fn find_next_set(&self, start: usize) -> Option<usize> {
+        self.find_next(start, false)
+    }
+
+    /// Returns the index of the first clear bit at or after `start`, if any.
+    pub fn find_next_zero(&self, start: usize) -> Option<usize> {
+        self.find_next(start, true)
+    }
+
+    /// Returns the index of the first set bit, if any.
+    pub fn find_first_set(&self) -> Option<usize> {
+        self.find_next_set(0)
+    }
+
+    /// Returns the index of the first clear bit, if any.
+    pub fn find_first_zero(&self) -> Option<usize> {
+        self.find_next_zero(0)
+    }
+
+    fn find_next(&self, start: usize, zero: bool) -> Option<usize> {
+        if start >= self.nbits {
+            return None;
+        }
+        let mut index = start / BITS_PER_LONG;
+        // Ignore the bits before `start` in the first word.
+        let mut skip = !0usize << (start % BITS_PER_LONG);
+        while index < self.words.len() {
+            let mut word = self.words[index].load(Ordering::Relaxed);
+            if zero {
+                word = !word;
+            }
+            word &= skip;
+            if word != 0 {
+                let bit = index * BITS_PER_LONG + word.trailing_zeros() as usize;
+                // When looking for a clear bit, the unused bits of the last word look clear.
+                return (bit < self.nbits).then_some(bit);
+            }
+            skip = !0;
+            index += 1;
+        }
+        None
+    }
+
+    /// Finds a clear bit and sets it, returning its index, or [`None`] if all bits are set.
+    ///
+    /// Concurrent callers get different bits.
+    pub fn acquire_first_zero(&self) -> Option<usize> {
+        let mut start = 0;
+        loop {
+            let index = self.find_next_zero(start)?;
+            if !self.test_and_set(index) {
+                return Some(index);
+            }
+            // Someone else took it in the meantime.
+            start = index + 1;
+        }
+    }
+
+    /// Returns an iterator over the indices of the set bits, in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut next = 0;
+        core::iter::from_fn(move || {
+            let index = self.find_next_set(next)?;
+            next = index + 1;
+            Some(index)
+        })
+    }
+
+    /// Returns a pointer to the bitmap, for C functions that take an `unsigned long *` bitmap of
+    /// [`Bitmap::len`] bits.
+    ///
+    /// C code may modify the bitmap concurrently with Rust code only with atomic bit operations.
+    pub fn as_ptr(&self) -> *mut core::ffi::c_ulong {
+        self.words.as_ptr() as _
+    }
+}
+
+/// Declares a type for a set of flags.
+///
+/// The type wraps an unsigned integer in which each flag is a bit, or a group of bits. It has
+/// associated constants for the flags, is combined with the usual bitwise operators, and can
+/// only be created from a raw value with [`from_bits`] or [`from_bits_truncate`], so that
+/// undefined bits do not go unnoticed.
+///
+/// [`from_bits`]: #method.from_bits
+/// [`from_bits_truncate`]: #method.from_bits_truncate
+///
+/// # Examples
+///
+/// ```
+/// use kernel::bitflags;
+///
+/// bitflags! {
+///     /// The status register of the device.
+///     pub struct Status: u32 {
+///         /// The device is ready to accept commands.
+///         const READY = 1 << 0;
+///         /// The last command failed.
+///         const ERROR = 1 << 1;
+///         /// An interrupt is pending.
+///         const IRQ = 1 << 4;
+///     }
+/// }
+///
+/// let status = Status::from_bits_truncate(0x8000_0011);
+/// assert_eq!(status, Status::READY | Status::IRQ);
+/// assert!(status.contains(Status::READY));
+/// assert!(!status.intersects(Status::ERROR));
+/// assert_eq!(Status::from_bits(0x8000_0011), None);
+/// assert_eq!((status - Status::IRQ).bits(), 0x1);
+/// ```
+#[macro_export]
+macro_rules! bitflags {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident: $ty:ty {
+            $(
+                $(#[$fmeta:meta])*
+                const $flag:ident = $value:expr;
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, PartialEq, Eq, Hash)]
+        #[repr(transparent)]
+        $vis struct $name($ty);
+
+        #[allow(dead_code)]
+        impl $name {
+            $(
+                $(#[$fmeta])*
+                pub const $flag: Self = Self($value);
+            )*
+
+            /// Returns a value with no flags set.
+            pub const fn empty() -> Self {
+                Self(0)
+            }
+
+            /// Returns a value with all the defined flags set.
+            pub const fn all() -> Self {
+                Self(0 $(| $value)*)
+            }
+
+            /// Returns the raw value of the flags.
+            pub const fn bits(&self) -> $ty {
+                self.0
+            }
+
+            /// Creates flags from a raw value, or returns [`None`] if it has undefined bits set.
+            pub const fn from_bits(bits: $ty) -> Option<Self> {
+                if bits & !Self::all().0 == 0 {
+                    Some(Self(bits))
+                } else {
+                    None
+                }
+            }
+
+            /// Creates flags from a raw value, ignoring the undefined bits.
+            pub const fn from_bits_truncate(bits: $ty) -> Self {
+                Self(bits & Self::all().0)
+            }
+
+            /// Returns `true` if no flags are set.
+            pub const fn is_empty(&self) -> bool {
+                self.0 == 0
+            }
+
+            /// Returns `true` if all the flags in `other` are set.
+            pub const fn contains(&self, other: Self) -> bool {
+                self.0 & other.0 == other.0
+            }
+
+            /// Returns `true` if any of the flags in `other` is set.
+            pub const fn intersects(&self, other: Self) -> bool {
+                self.0 & other.0 != 0
+            }
+
+            /// Sets the flags in `other`.
+            pub fn insert(&mut self, other: Self) {
+                self.0 |= other.0;
+            }
+
+            /// Clears the flags in `other`.
+            pub fn remove(&mut self, other: Self) {
+                self.0 &= !other.0;
+            }
+
+            /// Sets or clears the flags in `other`, depending on `value`.
+            pub fn set(&mut self, other: Self, value: bool) {
+                if value {
+                    self.insert(other);
+                } else {
+                    self.remove(other);
+                }
+            }
+        }
+
+        impl core::ops::BitOr for $name {
+            type Output = Self;
+            fn bitor(self, rhs: Self) -> Self {
+                Self(self.0 | rhs.0)
+            }
+        }
+
+        impl core::ops::BitOrAssign for $name {
+            fn bitor_assign(&mut self, rhs: Self) {
+                self.0 |= rhs.0;
+            }
+        }
+
+        impl core::ops::BitAnd for $name {
+            type Output = Self;
+            fn bitand(self, rhs: Self) -> Self {
+                Self(self.0 & rhs.0)
+            }
+        }
+
+        impl core::ops::BitAndAssign for $name {
+            fn bitand_assign(&mut self, rhs: Self) {
+                self.0 &= rhs.0;
+            }
+        }
+
+        impl core::ops::Sub for $name {
+            type Output = Self;
+            fn sub(self, rhs: Self) -> Self {
+                Self(self.0 & !rhs.0)
+            }
+        }
+
+        impl core::ops::Not for $name {
+            type Output = Self;
+            fn not(self) -> Self {
+                Self::from_bits_truncate(!self.0)
+            }
+        }
+
+        impl core::fmt::Debug for $name {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                let mut first = true;
+                write!(f, "{}(", stringify!($name))?;
+                $(
+                    if Self::$flag.0 != 0 && self.contains(Self::$flag) {
+                        if !first {
+                            f.write_str(" | ")?;
+                        }
+                        first = false;
+                        f.write_str(stringify!($flag))?;
+                    }
+                )*
+                if first {
+                    write!(f, "{:#x}", self.0)?;
+                }
+                f.write_str(")")
+            }
+        }
+    };
+}
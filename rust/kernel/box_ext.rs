@@ -0,0 +1,65 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Allocating boxes on a given NUMA node.
+
+use crate::{bindings, error::code::*, types::GfpFlags, NumaNode, Result};
+use alloc::boxed::Box;
+use core::mem;
+
+/// Methods of [`Box`] that allocate on a given NUMA node.
+///
+/// # Examples
+///
+/// Keeping the state of a device on the node of the device:
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::device::RawDevice;
+///
+/// struct Queue {
+///     head: u32,
+///     tail: u32,
+///     ring: [u64; 256],
+/// }
+///
+/// fn new_queue(dev: &dyn RawDevice) -> Result<Box<Queue>> {
+///     let queue = Queue {
+///         head: 0,
+///         tail: 0,
+///         ring: [0; 256],
+///     };
+///     Box::try_new_on_node(queue, dev.numa_node())
+/// }
+/// ```
+pub trait BoxExt<T>: Sized {
+    /// Allocates memory on `node` if possible, and moves `value` into it.
+    ///
+    /// The memory comes from another node when `node` runs out of memory.
+    fn try_new_on_node(value: T, node: NumaNode) -> Result<Self>;
+}
+
+impl<T> BoxExt<T> for Box<T> {
+    fn try_new_on_node(value: T, node: NumaNode) -> Result<Self> {
+        if mem::size_of::<T>() == 0 {
+            // Zero-sized values are not allocated.
+            return Ok(Box::try_new(value)?);
+        }
+
+        // SAFETY: This only allocates memory. We check that it succeeds in the next statement.
+        let ptr = unsafe {
+            bindings::__kmalloc_node(mem::size_of::<T>(), GfpFlags::KERNEL.as_raw(), node.id())
+        }
+        .cast::<T>();
+        if ptr.is_null() {
+            return Err(ENOMEM);
+        }
+
+        // SAFETY: `ptr` was just allocated with room for a `T`, with the alignment that the kernel
+        // allocator provides.
+        unsafe { ptr.write(value) };
+
+        // SAFETY: `ptr` was allocated by `kmalloc`, which the kernel allocator frees with `kfree`
+        // like its own allocations, and it holds an initialised `T`.
+        Ok(unsafe { Box::from_raw(ptr) })
+    }
+}
@@ -82,3 +82,31 @@ macro_rules! build_assert {
         }
     }};
 }
+
+/// Evaluates to `0usize`, or fails the build if a boolean expression is `true`.
+///
+/// Like the C `BUILD_BUG_ON_ZERO`, it can be used where an expression is needed, for example to
+/// check the parameters of a size computed in a `const`. As with [`build_assert!`], the condition
+/// is checked at compile time in const context and when the code is optimised otherwise, so it
+/// also works with generic parameters.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::build_bug_on_zero;
+/// const fn ring_size<T>(count: usize) -> usize {
+///     core::mem::size_of::<T>() * count + build_bug_on_zero!(!count.is_power_of_two())
+/// }
+///
+/// const RING: usize = ring_size::<u32>(16);
+/// // const BAD_RING: usize = ring_size::<u32>(12); // Compile-time error
+/// ```
+#[macro_export]
+macro_rules! build_bug_on_zero {
+    ($cond:expr $(,)?) => {{
+        if $cond {
+            $crate::build_error(concat!("BUILD_BUG_ON_ZERO failed: ", stringify!($cond)));
+        }
+        0usize
+    }};
+}
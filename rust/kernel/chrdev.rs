@@ -82,6 +82,11 @@ struct RegistrationInner<const N: usize> {
     _pin: PhantomPinned,
 }
 
+/// The file operations of the devices of a [`Registration<N>`] implemented by `T`.
+///
+/// They are defined in the module that implements `T` with [`crate::file_vtable!`].
+pub type Vtable<T, const N: usize> = file::Vtable<Registration<N>, T>;
+
 /// Character device registration.
 ///
 /// May contain up to a fixed number (`N`) of devices. Must be pinned.
@@ -133,7 +138,10 @@ impl<const N: usize> Registration<{ N }> {
     /// Registers a character device.
     ///
     /// You may call this once per device type, up to `N` times.
-    pub fn register<T: file::Operations<OpenData = ()>>(self: Pin<&mut Self>) -> Result {
+    pub fn register<T: file::Operations<OpenData = ()>>(
+        self: Pin<&mut Self>,
+        vtable: &'static Vtable<T, N>,
+    ) -> Result {
         // SAFETY: We must ensure that we never move out of `this`.
         let this = unsafe { self.get_unchecked_mut() };
         if this.inner.is_none() {
@@ -165,10 +173,7 @@ impl<const N: usize> Registration<{ N }> {
             return Err(EINVAL);
         }
 
-        // SAFETY: The adapter doesn't retrieve any state yet, so it's compatible with any
-        // registration.
-        let fops = unsafe { file::OperationsVtable::<Self, T>::build() };
-        let mut cdev = Cdev::alloc(fops, this.this_module)?;
+        let mut cdev = Cdev::alloc(vtable.fops(), this.this_module)?;
         cdev.add(inner.dev + inner.used as bindings::dev_t, 1)?;
         inner.cdevs[inner.used].replace(cdev);
         inner.used += 1;
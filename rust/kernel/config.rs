@@ -0,0 +1,25 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Kernel configuration.
+//!
+//! Constants with the values of the Kconfig options of the kernel being built, generated from its
+//! configuration file by `scripts/generate_rust_config.sh`.
+//!
+//! Unlike `#[cfg(CONFIG_...)]`, they can be used in ordinary expressions, so both branches are
+//! always type-checked. Boolean and tristate options are `true` when the option is built in;
+//! modular options also have a `_MODULE` constant, as in C. Options whose dependencies are not met
+//! are not defined.
+//!
+//! # Examples
+//!
+//! ```
+//! use kernel::config;
+//!
+//! fn ticks_to_ms(ticks: u64) -> u64 {
+//!     ticks * 1000 / config::CONFIG_HZ as u64
+//! }
+//!
+//! assert_eq!(ticks_to_ms(config::CONFIG_HZ as u64), 1000);
+//! ```
+
+include!(concat!(env!("OBJTREE"), "/rust/kernel/config_generated.rs"));
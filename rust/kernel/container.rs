@@ -0,0 +1,154 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Conversions between structs and their embedded fields.
+//!
+//! Intrusive data structures, such as the C lists, red-black trees and `kref`, only ever see a
+//! field embedded in a larger struct, and hand back pointers to that field. [`Containable`]
+//! records, once per struct and checked by the compiler, where such a field is, so that drivers
+//! convert between the two without computing offsets themselves. It is implemented with
+//! [`impl_containable!`].
+//!
+//! [`impl_containable!`]: crate::impl_containable!
+
+/// A struct that embeds a field of type `F`, which C code refers to.
+///
+/// A struct may embed several fields of the same type, for example to be on two lists at once, in
+/// which case each of them is given a different `ID`.
+///
+/// # Examples
+///
+/// A struct that is reference counted with a `kref`:
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::{bindings, container::Containable, impl_containable};
+///
+/// struct Buffer {
+///     refcount: bindings::kref,
+///     len: usize,
+/// }
+///
+/// impl_containable!(Containable<bindings::kref> for Buffer { refcount });
+///
+/// unsafe extern "C" fn release(kref: *mut bindings::kref) {
+///     // SAFETY: The `kref` that drops to zero is the one embedded in a `Buffer` allocated by
+///     // `Box::try_new`.
+///     drop(unsafe { Box::from_raw(Buffer::from_field(kref)) });
+/// }
+/// ```
+///
+/// A struct that is on two lists, identified by their `ID`:
+///
+/// ```
+/// use kernel::{bindings, container::Containable, impl_containable};
+///
+/// const ALL: u64 = 0;
+/// const PENDING: u64 = 1;
+///
+/// struct Request {
+///     all: bindings::list_head,
+///     pending: bindings::list_head,
+/// }
+///
+/// impl_containable!(Containable<bindings::list_head, ALL> for Request { all });
+/// impl_containable!(Containable<bindings::list_head, PENDING> for Request { pending });
+///
+/// fn is_first_pending(request: &Request, head: &bindings::list_head) -> bool {
+///     let field = <Request as Containable<_, PENDING>>::field(request);
+///     core::ptr::eq(head.next, field)
+/// }
+/// ```
+///
+/// # Safety
+///
+/// Implementers must ensure that [`Containable::FIELD_OFFSET`] is the offset, in bytes, of a field
+/// of type `F` in `Self`. [`impl_containable!`] checks both the offset and the type.
+///
+/// [`impl_containable!`]: crate::impl_containable!
+pub unsafe trait Containable<F, const ID: u64 = 0>: Sized {
+    /// The offset of the field in `Self`.
+    const FIELD_OFFSET: usize;
+
+    /// Returns the field of `self`.
+    fn field(&self) -> &F {
+        // SAFETY: `self` is a valid reference, so the field is too.
+        unsafe { &*Self::raw_get_field(self as *const Self as *mut Self) }
+    }
+
+    /// Returns a pointer to the field of the struct `ptr` points to.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to an allocation that is large enough for a `Self`.
+    unsafe fn raw_get_field(ptr: *mut Self) -> *mut F {
+        // SAFETY: By the safety requirements of the trait, the field is within `Self`, which is
+        // within the allocation by the safety requirements of this function.
+        unsafe { ptr.cast::<u8>().add(Self::FIELD_OFFSET).cast() }
+    }
+
+    /// Returns a pointer to the struct that embeds the field `ptr` points to.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to the field with the same `ID` of a `Self`, as opposed to a standalone
+    /// `F` or the field of another type.
+    unsafe fn from_field(ptr: *mut F) -> *mut Self {
+        // SAFETY: By the safety requirements, `ptr` is `FIELD_OFFSET` bytes into a `Self`.
+        unsafe { ptr.cast::<u8>().sub(Self::FIELD_OFFSET).cast() }
+    }
+
+    /// Returns the struct that embeds `field`.
+    ///
+    /// # Safety
+    ///
+    /// `field` must be the field with the same `ID` of a `Self`, as opposed to a standalone `F`
+    /// or the field of another type.
+    unsafe fn container_of(field: &F) -> &Self {
+        // SAFETY: By the safety requirements, `field` is embedded in a `Self`, which is valid for
+        // at least as long as the field is borrowed.
+        unsafe { &*Self::from_field(field as *const F as *mut F) }
+    }
+}
+
+/// Implements [`Containable`] for a struct and one of its fields.
+///
+/// The `ID`, if any, follows the type of the field. Type parameters of the struct are listed after
+/// `impl`, without bounds. The macro fails to build if the field does not exist or does not have
+/// the given type.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{bindings, impl_containable};
+///
+/// struct Node<K, V> {
+///     links: bindings::rb_node,
+///     key: K,
+///     value: V,
+/// }
+///
+/// impl_containable!(impl<K, V> Containable<bindings::rb_node> for Node<K, V> { links });
+/// ```
+#[macro_export]
+macro_rules! impl_containable {
+    (
+        $(impl<$($generic:ident),* $(,)?>)?
+        Containable<$field_type:ty $(, $id:tt)?> for $type:ty { $field:ident }
+    ) => {
+        // SAFETY: `FIELD_OFFSET` is computed by `offset_of`, from a field whose type is checked to
+        // be `$field_type`.
+        unsafe impl$(<$($generic),*>)? $crate::container::Containable<$field_type $(, $id)?>
+            for $type
+        {
+            const FIELD_OFFSET: usize = {
+                let tmp = core::mem::MaybeUninit::<$type>::uninit();
+                // SAFETY: The pointer is valid and aligned, just not initialised; `addr_of`
+                // ensures that we don't actually read from it (which would be UB) nor create an
+                // intermediate reference. The annotation checks the type of the field.
+                let _field: *const $field_type =
+                    unsafe { core::ptr::addr_of!((*tmp.as_ptr()).$field) };
+                $crate::offset_of!($type, $field) as usize
+            };
+        }
+    };
+}
@@ -0,0 +1,201 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Running code on given CPUs.
+//!
+//! Per-CPU data, such as the counters of [`crate::stats::StatSet`] or per-CPU caches, is sometimes
+//! best handled on the CPU it belongs to, for example to flush it without atomics. The functions
+//! in this module run a closure on a given CPU, or on all of them, and wait for it to complete,
+//! so the closure may borrow from the caller.
+//!
+//! [`call_on_cpu`] and [`on_each_cpu`] interrupt the CPUs, and run the closure in hard interrupt
+//! context: it must be short and must not sleep. [`run_on_cpu`] runs the closure in a kernel
+//! thread bound to the CPU, where it may sleep.
+//!
+//! C header: [`include/linux/smp.h`](../../../../include/linux/smp.h)
+
+use crate::{bindings, error::code::*, Error, Result};
+use core::ffi::{c_long, c_void};
+
+/// Returns the number of possible CPU ids.
+///
+/// CPU ids are smaller than this, but some of them may not be online, or not even present.
+#[cfg(CONFIG_SMP)]
+pub fn nr_cpu_ids() -> u32 {
+    // SAFETY: `nr_cpu_ids` is set up during early boot and never changes afterwards.
+    unsafe { bindings::nr_cpu_ids }
+}
+
+/// Returns the number of possible CPU ids.
+///
+/// CPU ids are smaller than this, but some of them may not be online, or not even present.
+#[cfg(not(CONFIG_SMP))]
+pub fn nr_cpu_ids() -> u32 {
+    1
+}
+
+/// Calls `f` on `cpu`, in hard interrupt context, and waits for it to return.
+///
+/// `f` runs with interrupts disabled, so it must not sleep and should be short. It runs on the
+/// current CPU, with interrupts disabled as well, if that is `cpu`.
+///
+/// This must not be called with interrupts disabled, or from interrupt context, since the CPUs
+/// could deadlock waiting for each other. Returns `ENXIO` if `cpu` is not online, and `EINVAL` if
+/// it is not a valid CPU id.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use core::sync::atomic::{AtomicBool, Ordering};
+/// use kernel::cpu;
+///
+/// /// Checks that `cpu` responds to interrupts.
+/// fn ping(cpu: u32) -> Result<bool> {
+///     let called = AtomicBool::new(false);
+///     cpu::call_on_cpu(cpu, || called.store(true, Ordering::Relaxed))?;
+///     Ok(called.load(Ordering::Relaxed))
+/// }
+/// ```
+pub fn call_on_cpu<F: FnOnce() + Send>(cpu: u32, f: F) -> Result {
+    if cpu >= nr_cpu_ids() {
+        return Err(EINVAL);
+    }
+
+    unsafe extern "C" fn call<F: FnOnce()>(info: *mut c_void) {
+        // SAFETY: `info` points to the `Option<F>` of `call_on_cpu`, which waits for this to
+        // return, and nobody else accesses it meanwhile.
+        let f = unsafe { &mut *info.cast::<Option<F>>() };
+        if let Some(f) = f.take() {
+            f();
+        }
+    }
+
+    let mut f = Some(f);
+    // SAFETY: `call::<F>` matches the type of the pointer, and the call waits for the callback to
+    // complete, so `f` outlives it.
+    let ret = unsafe {
+        bindings::smp_call_function_single(
+            cpu as _,
+            Some(call::<F>),
+            (&mut f as *mut Option<F>).cast(),
+            1,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::from_kernel_errno(ret));
+    }
+    Ok(())
+}
+
+/// Calls `f` on every online CPU, in hard interrupt context, and waits for all the calls to
+/// return.
+///
+/// The calls run concurrently, so `f` must be [`Sync`]. They run with interrupts disabled, so
+/// `f` must not sleep and should be short.
+///
+/// This must not be called with interrupts disabled, or from interrupt context, since the CPUs
+/// could deadlock waiting for each other.
+///
+/// # Examples
+///
+/// ```
+/// use core::sync::atomic::{AtomicU32, Ordering};
+/// use kernel::cpu;
+///
+/// fn count_online() -> u32 {
+///     let count = AtomicU32::new(0);
+///     cpu::on_each_cpu(&|| {
+///         count.fetch_add(1, Ordering::Relaxed);
+///     });
+///     count.load(Ordering::Relaxed)
+/// }
+/// ```
+pub fn on_each_cpu<F: Fn() + Sync>(f: &F) {
+    unsafe extern "C" fn call<F: Fn()>(info: *mut c_void) {
+        // SAFETY: `info` points to the closure of `on_each_cpu`, which waits for all the calls to
+        // return.
+        let f = unsafe { &*info.cast::<F>() };
+        f();
+    }
+
+    // SAFETY: `call::<F>` matches the type of the pointer, and the call waits for the callbacks to
+    // complete, so `f` outlives them. `__cpu_online_mask` is always valid.
+    unsafe {
+        bindings::on_each_cpu_cond_mask(
+            None,
+            Some(call::<F>),
+            f as *const F as *mut c_void,
+            true,
+            core::ptr::addr_of!(bindings::__cpu_online_mask),
+        )
+    };
+}
+
+/// Runs `f` on `cpu`, in a kernel thread bound to it, and returns its result.
+///
+/// Unlike [`call_on_cpu`], `f` runs in process context, so it may sleep. The CPU is kept online
+/// until `f` returns. This sleeps, so it must be called from process context. Returns `ENODEV` if
+/// `cpu` is not online, and `EINVAL` if it is not a valid CPU id.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::cpu;
+///
+/// /// Drains the cache of every CPU, which may sleep.
+/// fn drain_all(drain: impl Fn() -> Result + Sync) -> Result {
+///     for id in 0..cpu::nr_cpu_ids() {
+///         match cpu::run_on_cpu(id, &drain) {
+///             Ok(result) => result?,
+///             // CPUs that are not online have nothing cached.
+///             Err(e) if e == ENODEV => {}
+///             Err(e) => return Err(e),
+///         }
+///     }
+///     Ok(())
+/// }
+/// ```
+pub fn run_on_cpu<R: Send, F: FnOnce() -> R + Send>(cpu: u32, f: F) -> Result<R> {
+    crate::debug_assert_sleepable!();
+    if cpu >= nr_cpu_ids() {
+        return Err(EINVAL);
+    }
+
+    struct Call<R, F> {
+        f: Option<F>,
+        result: Option<R>,
+    }
+
+    unsafe extern "C" fn call<R, F: FnOnce() -> R>(arg: *mut c_void) -> c_long {
+        // SAFETY: `arg` points to the `Call` of `run_on_cpu`, which waits for this to return, and
+        // nobody else accesses it meanwhile.
+        let call = unsafe { &mut *arg.cast::<Call<R, F>>() };
+        if let Some(f) = call.f.take() {
+            call.result = Some(f());
+        }
+        0
+    }
+
+    let mut call_data = Call {
+        f: Some(f),
+        result: None,
+    };
+    let arg = (&mut call_data as *mut Call<R, F>).cast();
+
+    // SAFETY: `call::<R, F>` matches the type of the pointer, and the call waits for the callback
+    // to complete, so `call_data` outlives it.
+    #[cfg(CONFIG_SMP)]
+    let ret = unsafe { bindings::work_on_cpu_safe(cpu as _, Some(call::<R, F>), arg) };
+
+    // Without SMP, the only CPU is the current one, which is always online.
+    // SAFETY: `arg` points to `call_data`, which is not accessed by anyone else meanwhile.
+    #[cfg(not(CONFIG_SMP))]
+    let ret = unsafe { call::<R, F>(arg) };
+
+    match call_data.result {
+        Some(result) => Ok(result),
+        None if ret < 0 => Err(Error::from_kernel_errno(ret as _)),
+        None => Err(EINVAL),
+    }
+}
@@ -0,0 +1,1201 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Debug filesystem (debugfs).
+//!
+//! Allows Rust code to create directories and files in debugfs, where file accesses are handled
+//! by a [`file::Operations`] implementation or, through [`crate::seq_file`], by a sequence of
+//! records.
+//!
+//! Entries created in a directory hold a reference to it, so a directory is only removed once all
+//! the Rust handles to it and to its entries have been dropped.
+//!
+//! C header: [`include/linux/debugfs.h`](../../../../include/linux/debugfs.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/filesystems/debugfs.html>
+
+use crate::{
+    bindings,
+    error::{code::*, from_kernel_err_ptr},
+    file, fs,
+    io_buffer::{IoBufferReader, IoBufferWriter},
+    str::{canonicalize_input, CStr, CString, Formatter},
+    sync::{Arc, Mutex},
+    user_ptr::UserString,
+    Mode, Result,
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    fmt::{self, Write},
+    marker::PhantomData,
+    ops::Deref,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use macros::vtable;
+
+/// Read-only for everyone.
+pub const MODE_444: Mode = Mode::perm(0o444);
+
+/// Read-only for the owner.
+pub const MODE_400: Mode = Mode::perm(0o400);
+
+/// Writable by the owner and readable by everyone.
+pub const MODE_644: Mode = Mode::perm(0o644);
+
+/// Readable and writable by the owner only.
+pub const MODE_600: Mode = Mode::perm(0o600);
+
+/// Write-only for the owner.
+pub const MODE_200: Mode = Mode::perm(0o200);
+
+/// Returns whether `dentry` has been removed from debugfs.
+///
+/// # Safety
+///
+/// `dentry` must be valid, for example because the caller holds a reference to it.
+unsafe fn is_unlinked(dentry: *mut bindings::dentry) -> bool {
+    // SAFETY: `dentry` is valid by the safety requirements.
+    unsafe { bindings::d_unhashed(dentry) || (*dentry).d_inode.is_null() }
+}
+
+crate::init_static_sync! {
+    /// Held while checking whether an entry is still in debugfs and claiming a reference to it for
+    /// removal.
+    ///
+    /// It is released before the entry is removed: `debugfs_remove` waits for the handlers of the
+    /// files that it removes to return, and a handler may itself remove another entry. The removal
+    /// may then race with that of an ancestor, which debugfs handles by checking again whether each
+    /// entry is still there under the lock of its parent inode.
+    static REMOVAL: Mutex<()> = ();
+}
+
+/// Returns the entry called `name` in `parent`, or at the root of debugfs if `parent` is null, with
+/// a reference that the caller owns.
+///
+/// # Safety
+///
+/// `parent` must be null or a valid directory.
+unsafe fn lookup(name: &CStr, parent: *mut bindings::dentry) -> Option<*mut bindings::dentry> {
+    // SAFETY: `name` is a valid C string and `parent` is null or valid by the safety requirements.
+    let dentry = unsafe { bindings::debugfs_lookup(name.as_char_ptr(), parent) };
+    if dentry.is_null() {
+        None
+    } else {
+        Some(dentry)
+    }
+}
+
+/// Returns `EEXIST` if there already is an entry called `name` in `parent`.
+///
+/// debugfs also rejects duplicates, but only after logging an error, which is noise for modules
+/// that handle collisions themselves.
+///
+/// # Safety
+///
+/// `parent` must be null or a valid directory.
+unsafe fn check_absent(name: &CStr, parent: *mut bindings::dentry) -> Result {
+    // SAFETY: `parent` is null or valid by the safety requirements.
+    match unsafe { lookup(name, parent) } {
+        Some(dentry) => {
+            // SAFETY: `lookup` returned a reference that we own.
+            unsafe { bindings::dput(dentry) };
+            Err(EEXIST)
+        }
+        None => Ok(()),
+    }
+}
+
+/// A directory in debugfs.
+///
+/// The directory is removed, together with everything in it, when the last reference to it is
+/// dropped. Since entries created in it hold such a reference, this only happens once they have
+/// all been dropped, unless the directory is removed earlier by its own parent with
+/// [`DebugFsDirectory::remove_child`].
+///
+/// A directory obtained with [`DebugFsDirectory::create_or_open`] that already existed is not
+/// removed by this object, since it belongs to whoever created it.
+///
+/// # Invariants
+///
+/// The directory holds a reference to `dentry`, so it remains valid even after it is removed
+/// from debugfs. `dentry` is a directory.
+pub struct DebugFsDirectory {
+    dentry: *mut bindings::dentry,
+    parent: Option<Arc<DebugFsDirectory>>,
+
+    /// Whether the directory was created by this object, and is removed when it is dropped.
+    owned: bool,
+}
+
+impl DebugFsDirectory {
+    /// Creates a new directory called `name`, in `parent` or at the root of debugfs.
+    ///
+    /// Returns `EEXIST` if there already is an entry called `name`, for example because another
+    /// instance of the module created it. Modules with several instances can then pick another
+    /// name, or share the directory with [`DebugFsDirectory::create_or_open`].
+    pub fn create(name: &CStr, parent: Option<Arc<DebugFsDirectory>>) -> Result<Arc<Self>> {
+        let parent_dentry = match &parent {
+            Some(p) => p.live_dentry()?,
+            None => core::ptr::null_mut(),
+        };
+        // SAFETY: `parent_dentry` is either null or a valid directory.
+        unsafe { check_absent(name, parent_dentry)? };
+        // SAFETY: `name` is a valid C string and `parent_dentry` is either null or a valid
+        // directory.
+        let dentry = from_kernel_err_ptr(unsafe {
+            bindings::debugfs_create_dir(name.as_char_ptr(), parent_dentry)
+        })?;
+        // SAFETY: `dentry` is valid.
+        unsafe { bindings::dget(dentry) };
+
+        // INVARIANT: We took a reference to the directory that was just created.
+        let dir = Self {
+            dentry,
+            parent,
+            owned: true,
+        };
+        // On failure, `dir` is dropped, which removes the directory again.
+        Ok(Arc::try_new(dir)?)
+    }
+
+    /// Returns the directory called `name`, in `parent` or at the root of debugfs, creating it if
+    /// it does not exist.
+    ///
+    /// A directory that already existed is left in debugfs when the returned object is dropped,
+    /// and entries created in it are removed when they are dropped, as usual. If it is removed by
+    /// its creator in the meantime, creating entries in it fails with `ENOENT`. Returns `ENOTDIR`
+    /// if `name` exists but is not a directory.
+    ///
+    /// # Examples
+    ///
+    /// Instances of a driver that share a directory, each with its own subdirectory:
+    ///
+    /// ```
+    /// # use kernel::prelude::*;
+    /// use kernel::{c_str, debugfs::DebugFsDirectory, str::CString, sync::Arc};
+    ///
+    /// fn instance_dir(id: u32) -> Result<Arc<DebugFsDirectory>> {
+    ///     let root = DebugFsDirectory::create_or_open(c_str!("rust_example"), None)?;
+    ///     let name = CString::try_from_fmt(fmt!("dev{}", id))?;
+    ///     DebugFsDirectory::create(&name, Some(root))
+    /// }
+    /// ```
+    pub fn create_or_open(name: &CStr, parent: Option<Arc<DebugFsDirectory>>) -> Result<Arc<Self>> {
+        let parent_dentry = match &parent {
+            Some(p) => p.live_dentry()?,
+            None => core::ptr::null_mut(),
+        };
+        // SAFETY: `parent_dentry` is either null or a valid directory.
+        let dentry = match unsafe { lookup(name, parent_dentry) } {
+            Some(dentry) => dentry,
+            None => match Self::create(name, parent.clone()) {
+                // Another instance may have created it since we looked.
+                Err(e) if e == EEXIST => {
+                    // SAFETY: `parent_dentry` is either null or a valid directory.
+                    unsafe { lookup(name, parent_dentry) }.ok_or(ENOENT)?
+                }
+                result => return result,
+            },
+        };
+
+        // SAFETY: `lookup` only returns positive dentries, with a reference that we own, which
+        // keeps the inode alive.
+        let inode = unsafe { fs::INode::from_ptr((*dentry).d_inode) };
+        if !inode.is_dir() {
+            // SAFETY: We own the reference returned by `lookup`.
+            unsafe { bindings::dput(dentry) };
+            return Err(ENOTDIR);
+        }
+
+        // INVARIANT: We own the reference returned by `lookup`, and `dentry` is a directory.
+        let dir = Self {
+            dentry,
+            parent,
+            owned: false,
+        };
+        Ok(Arc::try_new(dir)?)
+    }
+
+    /// Returns a handle to the directory, for use with C APIs that take its dentry.
+    pub fn entry(&self) -> DebugFsEntry<'_> {
+        // SAFETY: By the type invariants, we hold a reference to `self.dentry`.
+        unsafe { DebugFsEntry::new(self.dentry) }
+    }
+
+    /// Returns the dentry of the directory, or `ENOENT` if it has been removed.
+    fn live_dentry(&self) -> Result<*mut bindings::dentry> {
+        // SAFETY: By the type invariants, `self.dentry` is valid.
+        if unsafe { is_unlinked(self.dentry) } {
+            return Err(ENOENT);
+        }
+        Ok(self.dentry)
+    }
+
+    /// Removes the entry called `name` from the directory, recursively.
+    ///
+    /// The [`DebugFsFile`] or [`DebugFsDirectory`] that corresponds to the entry remains valid,
+    /// but it no longer has any effect on debugfs. Returns `ENOENT` if there is no such entry.
+    ///
+    /// The handlers of the files being removed may call this to remove other entries, but not the
+    /// entry they belong to or one of its ancestors: removing a file waits for its handlers to
+    /// return, so that would deadlock.
+    pub fn remove_child(&self, name: &CStr) -> Result {
+        let child = {
+            let _guard = REMOVAL.lock();
+            let dentry = self.live_dentry()?;
+            // SAFETY: `name` is a valid C string and `dentry` is a valid directory.
+            let child = unsafe { bindings::debugfs_lookup(name.as_char_ptr(), dentry) };
+            if child.is_null() {
+                return Err(ENOENT);
+            }
+            child
+        };
+
+        // SAFETY: `debugfs_lookup` returned a valid dentry with a reference that we own, which
+        // keeps it valid even if it is removed concurrently.
+        unsafe {
+            bindings::debugfs_remove(child);
+            bindings::dput(child);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for DebugFsDirectory {
+    fn drop(&mut self) {
+        if self.owned {
+            // SAFETY: By the type invariants, we hold a reference to `self.dentry`. If the
+            // directory is still in debugfs, so is its parent, which we hold a reference to as
+            // well.
+            unsafe { remove_entry(self.dentry) };
+        } else {
+            // SAFETY: By the type invariants, we hold a reference to `self.dentry`.
+            unsafe { bindings::dput(self.dentry) };
+        }
+    }
+}
+
+crate::deprecated_alias! {
+    /// The former name of [`DebugFsDirectory`].
+    pub type DebugFsDirEntry = DebugFsDirectory;
+    since = "6.3", note = "use `kernel::debugfs::DebugFsDirectory` instead",
+}
+
+// SAFETY: The directory is only used through functions that may be called from any thread.
+unsafe impl Send for DebugFsDirectory {}
+
+// SAFETY: `remove_child` may be called concurrently from any thread.
+unsafe impl Sync for DebugFsDirectory {}
+
+/// The debugfs directory of a module, with a subdirectory for each of its devices.
+///
+/// Drivers that handle several devices use it to get the same layout, `<module>/<id>/`, without
+/// building the names themselves. It is usually created with [`debugfs_device_directories!`],
+/// which names the directory after the module.
+///
+/// The directory is removed once this object and the directories of all the devices have been
+/// dropped.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::{
+///     debugfs::{DebugFsDirectory, DeviceDirectories},
+///     sync::Arc,
+/// };
+///
+/// struct Device {
+///     dir: Arc<DebugFsDirectory>,
+/// }
+///
+/// fn probe(dirs: &DeviceDirectories, index: u32) -> Result<Device> {
+///     // Creates `/sys/kernel/debug/<module>/<index>`.
+///     let dir = dirs.create_device(index)?;
+///     Ok(Device { dir })
+/// }
+///
+/// fn init() -> Result<DeviceDirectories> {
+///     kernel::debugfs_device_directories!()
+/// }
+/// ```
+pub struct DeviceDirectories {
+    root: Arc<DebugFsDirectory>,
+}
+
+impl DeviceDirectories {
+    /// Creates a directory called `name` at the root of debugfs, to hold the directories of the
+    /// devices.
+    ///
+    /// Returns `EEXIST` if there already is an entry called `name`.
+    pub fn create(name: &CStr) -> Result<Self> {
+        Ok(Self {
+            root: DebugFsDirectory::create(name, None)?,
+        })
+    }
+
+    /// Returns the directory of the module, for entries that are not specific to a device.
+    pub fn root(&self) -> &Arc<DebugFsDirectory> {
+        &self.root
+    }
+
+    /// Creates the directory of the device `id`, named after it.
+    ///
+    /// `id` is usually the index of the device, or a name that is unique among the devices of the
+    /// module. Returns `EEXIST` if another device already has the same `id`.
+    pub fn create_device(&self, id: impl fmt::Display) -> Result<Arc<DebugFsDirectory>> {
+        let name = CString::try_from_fmt(crate::fmt!("{}", id))?;
+        DebugFsDirectory::create(&name, Some(self.root.clone()))
+    }
+}
+
+/// Creates the [`DeviceDirectories`] of the calling module, named after it.
+///
+/// This is the same as [`DeviceDirectories::create`] with the name of the module.
+#[macro_export]
+macro_rules! debugfs_device_directories {
+    () => {
+        $crate::debugfs::DeviceDirectories::create(
+            // SAFETY: `__LOG_PREFIX` is the name of the module, null-terminated by the `module!`
+            // proc macro, or a fixed null-terminated value defined in a kernel crate.
+            unsafe { $crate::str::CStr::from_bytes_with_nul_unchecked(crate::__LOG_PREFIX) },
+        )
+    };
+}
+
+/// Provides the file operations of a debugfs file.
+///
+/// It is implemented for all [`file::Operations`] implementations, and by other modules that
+/// build debugfs files on top of their own operations, such as [`crate::seq_file`].
+///
+/// # Safety
+///
+/// The `open` callback of the operations returned by [`FileVtable::fops`] must only interpret
+/// `i_private` of the inode as a pointer to [`FileVtable::OpenData`].
+pub unsafe trait FileVtable {
+    /// The type of the data passed to the file operations when the file is opened.
+    type OpenData: Sync;
+
+    /// The static file operations of the file, with the module that owns them.
+    ///
+    /// They are defined in the module that implements the file, usually with
+    /// [`crate::file_vtable!`], so that open files keep it loaded.
+    type Vtable: Sync + 'static;
+
+    /// Returns the file operations in `vtable`.
+    fn fops(vtable: &'static Self::Vtable) -> &'static bindings::file_operations;
+}
+
+/// The file operations of a debugfs file implemented by `T`.
+pub type Vtable<T> = file::Vtable<InodeAdapter, T>;
+
+/// Retrieves the open data of debugfs files from their inode.
+pub struct InodeAdapter;
+
+impl<D: Sync> file::OpenAdapter<D> for InodeAdapter {
+    unsafe fn convert(inode: *mut bindings::inode, _file: *mut bindings::file) -> *const D {
+        // SAFETY: The caller must guarantee that `inode` is valid. debugfs stores the data passed
+        // to `debugfs_create_file` in `i_private`, which is never null for files created by
+        // `DebugFsFile::create`, and the open callback checks for null anyway.
+        unsafe { (*inode).i_private as *const D }
+    }
+}
+
+// SAFETY: `InodeAdapter` interprets `i_private` as a pointer to `T::OpenData`.
+unsafe impl<T: file::Operations> FileVtable for T {
+    type OpenData = T::OpenData;
+    type Vtable = Vtable<T>;
+
+    fn fops(vtable: &'static Vtable<T>) -> &'static bindings::file_operations {
+        vtable.fops()
+    }
+}
+
+/// Creates a new file called `name` with permissions `mode`, in `parent` or at the root of
+/// debugfs.
+///
+/// This is the same as [`DebugFsFile::create`].
+pub fn debugfs_create<T: FileVtable>(
+    name: &CStr,
+    parent: Option<Arc<DebugFsDirectory>>,
+    mode: Mode,
+    vtable: &'static T::Vtable,
+    data: T::OpenData,
+) -> Result<DebugFsFile<T>> {
+    DebugFsFile::create(name, parent, mode, vtable, data)
+}
+
+/// A `u64` counter inside an object shared through an [`Arc`], as exported by
+/// [`debugfs_create_atomic_u64`].
+pub struct AtomicU64Field<T> {
+    owner: Arc<T>,
+    field: fn(&T) -> &AtomicU64,
+}
+
+impl<T> AtomicU64Field<T> {
+    fn get(&self) -> &AtomicU64 {
+        (self.field)(&self.owner)
+    }
+}
+
+/// The file operations of a file created by [`debugfs_create_atomic_u64`].
+pub struct AtomicU64File<T>(PhantomData<T>);
+
+#[vtable]
+impl<T: Send + Sync + 'static> file::Operations for AtomicU64File<T> {
+    type OpenData = AtomicU64Field<T>;
+    type Data = Box<AtomicU64Field<T>>;
+
+    fn open(field: &AtomicU64Field<T>, _file: &file::File) -> Result<Self::Data> {
+        Ok(Box::try_new(AtomicU64Field {
+            owner: field.owner.clone(),
+            field: field.field,
+        })?)
+    }
+
+    fn read(
+        field: &AtomicU64Field<T>,
+        _file: &file::File,
+        data: &mut impl IoBufferWriter,
+        offset: &mut u64,
+    ) -> Result<usize> {
+        // Large enough for the 20 digits of `u64::MAX` and a newline.
+        let mut buf = [0u8; 24];
+        // SAFETY: `buf` is valid for writes for the lifetime of the formatter.
+        let mut f = unsafe { Formatter::from_buffer(buf.as_mut_ptr(), buf.len()) };
+        writeln!(f, "{}", field.get().load(Ordering::Relaxed)).map_err(|_| EINVAL)?;
+        let text = &buf[..f.bytes_written()];
+
+        let start = core::cmp::min(usize::try_from(*offset).unwrap_or(usize::MAX), text.len());
+        let len = core::cmp::min(data.len(), text.len() - start);
+        data.write_slice(&text[start..][..len])?;
+        *offset += len as u64;
+        Ok(len)
+    }
+
+    fn write(
+        field: &AtomicU64Field<T>,
+        _file: &file::File,
+        data: &mut impl IoBufferReader,
+        _offset: &mut u64,
+    ) -> Result<usize> {
+        let len = data.len();
+        let text = UserString::<24>::read_from(data)?;
+        let value = core::str::from_utf8(canonicalize_input(text.as_bytes()))
+            .ok()
+            .and_then(|text| text.parse::<u64>().ok())
+            .ok_or(EINVAL)?;
+        field.get().store(value, Ordering::Relaxed);
+        Ok(len)
+    }
+}
+
+/// Creates a file called `name` with permissions `mode`, in `parent` or at the root of debugfs,
+/// that shows the value of a counter of `owner`.
+///
+/// `field` returns the counter from `owner`. Reading the file only loads the counter, so it is
+/// suitable for counters that are updated in hot paths. If `mode` allows it, writing a number to
+/// the file sets the counter, for example to reset it to zero. The file keeps a reference to
+/// `owner`, as do the files that are open, so the counter remains valid while it can be accessed.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use core::sync::atomic::{AtomicU64, Ordering};
+/// use kernel::{
+///     c_str,
+///     debugfs::{self, AtomicU64File, DebugFsDirectory, DebugFsFile, MODE_644},
+///     sync::Arc,
+/// };
+///
+/// struct SharedState {
+///     interrupts: AtomicU64,
+/// }
+///
+/// fn handle_interrupt(state: &SharedState) {
+///     state.interrupts.fetch_add(1, Ordering::Relaxed);
+/// }
+///
+/// fn export(
+///     state: &Arc<SharedState>,
+///     dir: Arc<DebugFsDirectory>,
+/// ) -> Result<DebugFsFile<AtomicU64File<SharedState>>> {
+///     debugfs::debugfs_create_atomic_u64(
+///         c_str!("interrupts"),
+///         Some(dir),
+///         MODE_644,
+///         kernel::file_vtable!(debugfs::Vtable<AtomicU64File<SharedState>>),
+///         state.clone(),
+///         |s| &s.interrupts,
+///     )
+/// }
+/// ```
+pub fn debugfs_create_atomic_u64<T: Send + Sync + 'static>(
+    name: &CStr,
+    parent: Option<Arc<DebugFsDirectory>>,
+    mode: Mode,
+    vtable: &'static Vtable<AtomicU64File<T>>,
+    owner: Arc<T>,
+    field: fn(&T) -> &AtomicU64,
+) -> Result<DebugFsFile<AtomicU64File<T>>> {
+    DebugFsFile::create(name, parent, mode, vtable, AtomicU64Field { owner, field })
+}
+
+/// A field of a state struct exported by [`debugfs_export!`].
+pub struct ExportedField<T> {
+    /// The name of the file that shows the field.
+    pub name: &'static CStr,
+
+    /// Writes the value of the field, without a trailing newline.
+    pub show: fn(&T, &mut fmt::Formatter<'_>) -> fmt::Result,
+}
+
+/// A state struct whose fields can be exported to debugfs with [`debugfs_export`].
+///
+/// It is usually implemented with the [`debugfs_export!`] macro.
+pub trait DebugFsExport: Send + Sync + Sized + 'static {
+    /// The fields that are exported, one file each.
+    const FIELDS: &'static [ExportedField<Self>];
+
+    /// Returns the file operations of the files, which belong to the module that defines `Self`.
+    fn vtable() -> &'static Vtable<ExportedFieldFile<Self>>;
+}
+
+/// Shows a field of a state struct through [`fmt::Display`].
+struct ShowField<'a, T>(&'a T, fn(&T, &mut fmt::Formatter<'_>) -> fmt::Result);
+
+impl<T> fmt::Display for ShowField<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (self.1)(self.0, f)
+    }
+}
+
+/// A field of a state struct shared through an [`Arc`], as exported by [`debugfs_export`].
+pub struct ExportedFieldData<T> {
+    owner: Arc<T>,
+    show: fn(&T, &mut fmt::Formatter<'_>) -> fmt::Result,
+}
+
+/// The file operations of the files created by [`debugfs_export`].
+pub struct ExportedFieldFile<T>(PhantomData<T>);
+
+#[vtable]
+impl<T: DebugFsExport> file::Operations for ExportedFieldFile<T> {
+    type OpenData = ExportedFieldData<T>;
+    type Data = Box<CString>;
+
+    fn open(field: &ExportedFieldData<T>, _file: &file::File) -> Result<Self::Data> {
+        // The value is captured when the file is opened, so that reads in several chunks see
+        // consistent contents.
+        let text = ShowField(&*field.owner, field.show);
+        let text = CString::try_from_fmt(crate::fmt!("{text}\n"))?;
+        Ok(Box::try_new(text)?)
+    }
+
+    fn read(
+        text: &CString,
+        _file: &file::File,
+        data: &mut impl IoBufferWriter,
+        offset: &mut u64,
+    ) -> Result<usize> {
+        let text = text.as_bytes();
+        let start = core::cmp::min(usize::try_from(*offset).unwrap_or(usize::MAX), text.len());
+        let len = core::cmp::min(data.len(), text.len() - start);
+        data.write_slice(&text[start..][..len])?;
+        *offset += len as u64;
+        Ok(len)
+    }
+}
+
+/// The debugfs directory of a state struct, as created by [`debugfs_export`].
+///
+/// The files are removed when it is dropped, and the directory once nothing else refers to it.
+pub struct DebugFsExported<T: DebugFsExport> {
+    dir: Arc<DebugFsDirectory>,
+    _files: Vec<DebugFsFile<ExportedFieldFile<T>>>,
+}
+
+impl<T: DebugFsExport> DebugFsExported<T> {
+    /// Returns the directory that holds the files, for example to add other entries to it.
+    pub fn dir(&self) -> &Arc<DebugFsDirectory> {
+        &self.dir
+    }
+}
+
+/// Creates a directory called `name`, in `parent` or at the root of debugfs, with a read-only file
+/// for each field of `owner` listed in [`DebugFsExport::FIELDS`].
+///
+/// Each file shows the value of its field when it is opened, followed by a newline. The files keep
+/// a reference to `owner`, as do the files that are open, so the fields remain valid while they can
+/// be accessed.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use core::sync::atomic::{AtomicU32, Ordering};
+/// use kernel::{
+///     debugfs::{self, DebugFsDirectory, DebugFsExported},
+///     str::CStr,
+///     sync::{smutex::Mutex, Arc},
+/// };
+///
+/// struct State {
+///     name: &'static CStr,
+///     resets: AtomicU32,
+///     mode: Mutex<u8>,
+/// }
+///
+/// // Creates the files `name`, `resets` and `mode`.
+/// kernel::debugfs_export!(State {
+///     name,
+///     resets => |s| s.resets.load(Ordering::Relaxed),
+///     mode => |s| *s.mode.lock(),
+/// });
+///
+/// fn export(
+///     state: &Arc<State>,
+///     dir: Arc<DebugFsDirectory>,
+/// ) -> Result<DebugFsExported<State>> {
+///     debugfs::debugfs_export(state.name, Some(dir), state.clone())
+/// }
+/// ```
+pub fn debugfs_export<T: DebugFsExport>(
+    name: &CStr,
+    parent: Option<Arc<DebugFsDirectory>>,
+    owner: Arc<T>,
+) -> Result<DebugFsExported<T>> {
+    let dir = DebugFsDirectory::create(name, parent)?;
+    let mut files = Vec::try_with_capacity(T::FIELDS.len())?;
+    for field in T::FIELDS {
+        let data = ExportedFieldData {
+            owner: owner.clone(),
+            show: field.show,
+        };
+        files.try_push(DebugFsFile::create(
+            field.name,
+            Some(dir.clone()),
+            MODE_444,
+            T::vtable(),
+            data,
+        )?)?;
+    }
+    Ok(DebugFsExported { dir, _files: files })
+}
+
+/// Implements [`DebugFsExport`] for a state struct, with a file for each of the listed fields.
+///
+/// Each file is named after its field. By default, it shows the field through [`fmt::Display`].
+/// A field can instead be followed by `=>` and a closure that takes the struct and returns a
+/// value to show, for fields that are atomics or behind a lock.
+///
+/// See [`debugfs_export`] for an example.
+#[macro_export]
+macro_rules! debugfs_export {
+    ($type:ty { $($field:ident $(=> $show:expr)?),* $(,)? }) => {
+        impl $crate::debugfs::DebugFsExport for $type {
+            const FIELDS: &'static [$crate::debugfs::ExportedField<Self>] = &[
+                $(
+                    $crate::debugfs::ExportedField {
+                        name: $crate::c_str!(::core::stringify!($field)),
+                        show: |state, f| {
+                            $crate::__debugfs_export_show!($type, state, f, $field $(, $show)?)
+                        },
+                    },
+                )*
+            ];
+
+            fn vtable(
+            ) -> &'static $crate::debugfs::Vtable<$crate::debugfs::ExportedFieldFile<Self>> {
+                $crate::file_vtable!(
+                    $crate::debugfs::Vtable<$crate::debugfs::ExportedFieldFile<$type>>
+                )
+            }
+        }
+    };
+}
+
+/// Shows a field exported by [`debugfs_export!`].
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __debugfs_export_show {
+    ($type:ty, $state:ident, $f:ident, $field:ident) => {
+        ::core::fmt::Display::fmt(&$state.$field, $f)
+    };
+    ($type:ty, $state:ident, $f:ident, $field:ident, $show:expr) => {{
+        let show: fn(&$type) -> _ = $show;
+        ::core::fmt::Display::fmt(&show($state), $f)
+    }};
+}
+
+/// A handle to an entry in debugfs, borrowed from the object that owns it.
+///
+/// It allows C APIs that take the dentry of a debugfs entry, such as `relay_open`, to be used
+/// with entries created from Rust, while the Rust object keeps sole control over the lifetime of
+/// the entry.
+///
+/// # Invariants
+///
+/// A reference to `dentry` is held for the lifetime `'a`.
+#[derive(Clone, Copy)]
+pub struct DebugFsEntry<'a> {
+    dentry: *mut bindings::dentry,
+    _p: PhantomData<&'a ()>,
+}
+
+impl DebugFsEntry<'_> {
+    /// Creates a new handle to `dentry`.
+    ///
+    /// # Safety
+    ///
+    /// The caller must hold a reference to `dentry` for the lifetime of the handle.
+    unsafe fn new(dentry: *mut bindings::dentry) -> Self {
+        // INVARIANT: The safety requirements guarantee the invariants.
+        Self {
+            dentry,
+            _p: PhantomData,
+        }
+    }
+
+    /// Returns whether the entry has been removed from debugfs, for example by
+    /// [`DebugFsDirectory::remove_child`] on one of its ancestors.
+    pub fn is_removed(&self) -> bool {
+        // SAFETY: By the type invariants, we hold a reference to `self.dentry`.
+        unsafe { is_unlinked(self.dentry) }
+    }
+
+    /// Returns the dentry of the entry.
+    ///
+    /// The dentry remains valid for the lifetime of the handle, but it may be removed from
+    /// debugfs in the meantime. It must not be removed, nor its reference count decremented,
+    /// through the returned pointer.
+    pub fn as_ptr(&self) -> *mut bindings::dentry {
+        self.dentry
+    }
+
+    /// Returns the path of the entry, relative to the root of debugfs.
+    ///
+    /// The path starts with a `/`, and debugfs is usually mounted at `/sys/kernel/debug`. It is
+    /// meant for diagnostics, for example to log where an entry was created, since it may be stale
+    /// as soon as it is returned if the entry or one of its ancestors is removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kernel::prelude::*;
+    /// use kernel::{
+    ///     c_str,
+    ///     debugfs::{self, DebugFsDirectory, DebugFsFile, MODE_444},
+    ///     file,
+    ///     sync::Arc,
+    /// };
+    ///
+    /// fn create_status<T: file::Operations<OpenData = ()>>(
+    ///     dir: Arc<DebugFsDirectory>,
+    ///     vtable: &'static debugfs::Vtable<T>,
+    /// ) -> Result<DebugFsFile<T>> {
+    ///     let file = debugfs::debugfs_create(c_str!("status"), Some(dir), MODE_444, vtable, ())?;
+    ///     pr_info!("Status in {}\n", file.entry().path()?);
+    ///     Ok(file)
+    /// }
+    /// ```
+    pub fn path(&self) -> Result<DebugFsPath> {
+        let mut buf = Vec::try_with_capacity(bindings::PATH_MAX as usize)?;
+        let base = buf.as_mut_ptr();
+        // SAFETY: By the type invariants, we hold a reference to `self.dentry`, and `buf` is valid
+        // for writes of `PATH_MAX` bytes.
+        let path = from_kernel_err_ptr(unsafe {
+            bindings::dentry_path_raw(self.dentry, base.cast(), bindings::PATH_MAX as _)
+        })?;
+
+        // The path is written at the end of the buffer, with its `NUL` terminator in the last byte.
+        let start = path as usize - base as usize;
+        let len = bindings::PATH_MAX as usize - start;
+        // SAFETY: The `len` bytes at `start` were initialised by `dentry_path_raw`, and both
+        // ranges are within the capacity of `buf`.
+        unsafe {
+            core::ptr::copy(base.add(start), base, len);
+            buf.set_len(len);
+        }
+
+        // INVARIANT: `dentry_path_raw` returns a `NUL`-terminated string, which does not contain
+        // other `NUL` bytes since it is made of names.
+        Ok(DebugFsPath { buf })
+    }
+}
+
+/// The path of a debugfs entry, as returned by [`DebugFsEntry::path`].
+///
+/// # Invariants
+///
+/// `buf` is `NUL`-terminated and contains no other `NUL` bytes.
+pub struct DebugFsPath {
+    buf: Vec<u8>,
+}
+
+impl Deref for DebugFsPath {
+    type Target = CStr;
+
+    fn deref(&self) -> &CStr {
+        // SAFETY: By the type invariants, `buf` is a `NUL`-terminated string without other `NUL`
+        // bytes.
+        unsafe { CStr::from_bytes_with_nul_unchecked(&self.buf) }
+    }
+}
+
+impl fmt::Display for DebugFsPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// Removes `dentry` from debugfs unless it already has been, and drops the reference to it.
+///
+/// It must not be called from a handler of `dentry` or of one of its descendants, since removing a
+/// file waits for its handlers to return.
+///
+/// # Safety
+///
+/// The caller must own a reference to `dentry`.
+unsafe fn remove_entry(dentry: *mut bindings::dentry) {
+    // The entry, or one of its ancestors, may be removed concurrently by another entry or by
+    // `remove_child`, so only the check is done under `REMOVAL`. The reference owned by the caller
+    // keeps `dentry` valid until the removal is done.
+    let linked = {
+        let _guard = REMOVAL.lock();
+        // SAFETY: The caller owns a reference to `dentry`.
+        !unsafe { is_unlinked(dentry) }
+    };
+    if linked {
+        // SAFETY: The caller owns a reference to `dentry`.
+        unsafe { bindings::debugfs_remove(dentry) };
+    }
+    // SAFETY: The caller owns a reference to `dentry`.
+    unsafe { bindings::dput(dentry) };
+}
+
+/// A file in debugfs whose accesses are handled by `T`.
+///
+/// The file is removed from debugfs when it is dropped, unless it has already been removed by
+/// [`DebugFsDirectory::remove_child`]. It holds a reference to its parent directory, so the
+/// directory cannot be removed while the file exists.
+///
+/// Removing the file waits for its handlers to return, so it must not be dropped, or removed with
+/// [`DebugFsDirectory::remove_child`], by one of its own handlers.
+///
+/// # Invariants
+///
+/// The file holds a reference to `dentry`, so it remains valid even after it is removed from
+/// debugfs. The inode of `dentry` points to `open_data` while the file is in debugfs.
+pub struct DebugFsFile<T: FileVtable> {
+    dentry: *mut bindings::dentry,
+    parent: Option<Arc<DebugFsDirectory>>,
+    open_data: Box<T::OpenData>,
+    _p: PhantomData<T>,
+}
+
+impl<T: FileVtable> DebugFsFile<T> {
+    /// Creates a new file called `name` with permissions `mode`, in `parent` or at the root of
+    /// debugfs.
+    ///
+    /// `mode` is usually one of the `MODE_*` constants, such as [`MODE_444`], or else built with
+    /// [`Mode::perm`]. `vtable` holds the file operations, defined by the module that implements
+    /// `T`. `data` is passed to the file operations every time the file is opened.
+    ///
+    /// Returns `EEXIST` if there already is an entry called `name`.
+    pub fn create(
+        name: &CStr,
+        parent: Option<Arc<DebugFsDirectory>>,
+        mode: Mode,
+        vtable: &'static T::Vtable,
+        data: T::OpenData,
+    ) -> Result<Self> {
+        Self::create_with_size(name, parent, mode, 0, vtable, data)
+    }
+
+    /// Creates a new file like [`DebugFsFile::create`], and reports its size as `size`.
+    ///
+    /// This is meant for entries whose contents have a fixed size, so that tools that `stat` the
+    /// file before reading it behave sensibly. The size can be updated with
+    /// [`DebugFsFile::set_size`].
+    pub fn create_with_size(
+        name: &CStr,
+        parent: Option<Arc<DebugFsDirectory>>,
+        mode: Mode,
+        size: u64,
+        vtable: &'static T::Vtable,
+        data: T::OpenData,
+    ) -> Result<Self> {
+        let open_data = Box::try_new(data)?;
+        let parent_dentry = match &parent {
+            Some(p) => p.live_dentry()?,
+            None => core::ptr::null_mut(),
+        };
+        // SAFETY: `parent_dentry` is either null or a valid directory.
+        unsafe { check_absent(name, parent_dentry)? };
+
+        // SAFETY: `name` is a valid C string, `parent_dentry` is either null or a valid
+        // directory, and `open_data` remains valid until the file is removed in `drop`. The
+        // file operations are only called by the VFS while the file exists.
+        let dentry = from_kernel_err_ptr(unsafe {
+            bindings::debugfs_create_file(
+                name.as_char_ptr(),
+                mode.as_int(),
+                parent_dentry,
+                &*open_data as *const T::OpenData as *mut _,
+                T::fops(vtable),
+            )
+        })?;
+
+        // INVARIANT: We take a reference to the file that was just created with `open_data` as
+        // its private data.
+        // SAFETY: `dentry` is valid.
+        unsafe { bindings::dget(dentry) };
+        let file = Self {
+            dentry,
+            parent,
+            open_data,
+            _p: PhantomData,
+        };
+        file.set_size(size);
+        Ok(file)
+    }
+
+    /// Sets the size reported for the file, for example by `stat(2)`.
+    pub fn set_size(&self, size: u64) {
+        // SAFETY: By the type invariants, we hold a reference to `self.dentry`, so it is valid.
+        let inode = unsafe { (*self.dentry).d_inode };
+        if !inode.is_null() {
+            // SAFETY: `inode` is valid while the dentry holds it.
+            unsafe { bindings::i_size_write(inode, size as _) };
+        }
+    }
+
+    /// Returns the data passed to the file operations when the file is opened.
+    pub fn open_data(&self) -> &T::OpenData {
+        &self.open_data
+    }
+
+    /// Returns the directory the file was created in, if any.
+    pub fn parent(&self) -> Option<&Arc<DebugFsDirectory>> {
+        self.parent.as_ref()
+    }
+
+    /// Returns a handle to the file, for use with C APIs that take its dentry.
+    pub fn entry(&self) -> DebugFsEntry<'_> {
+        // SAFETY: By the type invariants, we hold a reference to `self.dentry`.
+        unsafe { DebugFsEntry::new(self.dentry) }
+    }
+}
+
+impl<T: FileVtable> Drop for DebugFsFile<T> {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, we hold a reference to `self.dentry`. Once the file is
+        // removed, no file operations can use `open_data` anymore.
+        unsafe { remove_entry(self.dentry) };
+    }
+}
+
+// SAFETY: The file is only used through functions that may be called from any thread.
+unsafe impl<T: FileVtable> Send for DebugFsFile<T> where T::OpenData: Send {}
+
+// SAFETY: Shared references only give access to the open data, which is `Sync`.
+unsafe impl<T: FileVtable> Sync for DebugFsFile<T> {}
+
+/// A symbolic link in debugfs.
+///
+/// The link is removed from debugfs when it is dropped, unless it has already been removed by
+/// [`DebugFsDirectory::remove_child`]. It holds a reference to its parent directory.
+///
+/// # Invariants
+///
+/// The link holds a reference to `dentry`, so it remains valid even after it is removed from
+/// debugfs.
+pub struct DebugFsSymlink {
+    dentry: *mut bindings::dentry,
+
+    /// Keeps the parent directory alive while the link is in debugfs.
+    _parent: Option<Arc<DebugFsDirectory>>,
+}
+
+impl DebugFsSymlink {
+    /// Creates a symbolic link called `name`, in `parent` or at the root of debugfs, that points
+    /// to `target`.
+    ///
+    /// `target` is a path, which is relative to `parent` unless it starts with `/`. Returns
+    /// `EEXIST` if there already is an entry called `name`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kernel::prelude::*;
+    /// use kernel::c_str;
+    /// use kernel::debugfs::{DebugFsDirectory, DebugFsSymlink};
+    /// use kernel::sync::Arc;
+    ///
+    /// fn create() -> Result<(Arc<DebugFsDirectory>, DebugFsSymlink)> {
+    ///     let dir = DebugFsDirectory::create(c_str!("rust_dev0"), None)?;
+    ///     // `/sys/kernel/debug/rust_current` points to `/sys/kernel/debug/rust_dev0`.
+    ///     let link = DebugFsSymlink::create(c_str!("rust_current"), None, c_str!("rust_dev0"))?;
+    ///     Ok((dir, link))
+    /// }
+    /// ```
+    pub fn create(
+        name: &CStr,
+        parent: Option<Arc<DebugFsDirectory>>,
+        target: &CStr,
+    ) -> Result<Self> {
+        let parent_dentry = match &parent {
+            Some(p) => p.live_dentry()?,
+            None => core::ptr::null_mut(),
+        };
+        // SAFETY: `parent_dentry` is either null or a valid directory.
+        unsafe { check_absent(name, parent_dentry)? };
+
+        // SAFETY: `name` and `target` are valid C strings, and `parent_dentry` is either null or
+        // a valid directory.
+        let dentry = from_kernel_err_ptr(unsafe {
+            bindings::debugfs_create_symlink(
+                name.as_char_ptr(),
+                parent_dentry,
+                target.as_char_ptr(),
+            )
+        })?;
+        // SAFETY: `dentry` is valid.
+        unsafe { bindings::dget(dentry) };
+
+        // INVARIANT: We took a reference to the link that was just created.
+        Ok(Self {
+            dentry,
+            _parent: parent,
+        })
+    }
+
+    /// Returns a handle to the link, for use with C APIs that take its dentry.
+    pub fn entry(&self) -> DebugFsEntry<'_> {
+        // SAFETY: By the type invariants, we hold a reference to `self.dentry`.
+        unsafe { DebugFsEntry::new(self.dentry) }
+    }
+}
+
+impl Drop for DebugFsSymlink {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, we hold a reference to `self.dentry`.
+        unsafe { remove_entry(self.dentry) };
+    }
+}
+
+// SAFETY: The link is only used through functions that may be called from any thread.
+unsafe impl Send for DebugFsSymlink {}
+
+// SAFETY: `DebugFsSymlink` only gives access to handles, which can be used from any thread.
+unsafe impl Sync for DebugFsSymlink {}
+
+/// Creates the contents of an automounted debugfs directory; see [`DebugFsAutomount`].
+pub trait Automount {
+    /// The type of the data passed to [`Automount::automount`].
+    type Data: Sync + 'static;
+
+    /// Mounts the contents of the directory.
+    ///
+    /// It is called when the directory is first entered after being created or after its
+    /// previous mount expired, with `mountpoint` referring to the directory.
+    fn automount(data: &Self::Data, mountpoint: DebugFsEntry<'_>) -> Result<fs::VfsMount>;
+}
+
+/// A directory in debugfs whose contents are only mounted when it is entered.
+///
+/// This allows drivers to defer building large trees, such as dumps of internal state, until they
+/// are actually looked at. The contents are provided by [`Automount::automount`], typically as an
+/// instance of a file system implemented with [`crate::fs`], mounted with
+/// [`fs::VfsMount::submount`].
+///
+/// The directory is removed when this object is dropped, unless it has already been removed by
+/// [`DebugFsDirectory::remove_child`].
+///
+/// # Invariants
+///
+/// The directory holds a reference to `dentry`, so it remains valid even after it is removed from
+/// debugfs.
+pub struct DebugFsAutomount<T: Automount> {
+    dentry: *mut bindings::dentry,
+
+    /// Keeps the parent directory alive while the directory is in debugfs.
+    _parent: Option<Arc<DebugFsDirectory>>,
+    _p: PhantomData<T>,
+}
+
+impl<T: Automount> DebugFsAutomount<T> {
+    /// Creates an automounted directory called `name`, in `parent` or at the root of debugfs.
+    ///
+    /// `data` is passed to [`Automount::automount`]. It must be static because a path lookup that
+    /// started before the directory was removed can still trigger the automount afterwards.
+    /// Returns `EEXIST` if there already is an entry called `name`.
+    pub fn create(
+        name: &CStr,
+        parent: Option<Arc<DebugFsDirectory>>,
+        data: &'static T::Data,
+    ) -> Result<Self> {
+        let parent_dentry = match &parent {
+            Some(p) => p.live_dentry()?,
+            None => core::ptr::null_mut(),
+        };
+        // SAFETY: `parent_dentry` is either null or a valid directory.
+        unsafe { check_absent(name, parent_dentry)? };
+
+        // SAFETY: `name` is a valid C string, `parent_dentry` is either null or a valid
+        // directory, and `data` is static.
+        let dentry = from_kernel_err_ptr(unsafe {
+            bindings::debugfs_create_automount(
+                name.as_char_ptr(),
+                parent_dentry,
+                Some(Self::automount_callback),
+                data as *const T::Data as *mut _,
+            )
+        })?;
+        // SAFETY: `dentry` is valid.
+        unsafe { bindings::dget(dentry) };
+
+        // INVARIANT: We took a reference to the directory that was just created.
+        Ok(Self {
+            dentry,
+            _parent: parent,
+            _p: PhantomData,
+        })
+    }
+
+    /// Returns a handle to the directory, for use with C APIs that take its dentry.
+    pub fn entry(&self) -> DebugFsEntry<'_> {
+        // SAFETY: By the type invariants, we hold a reference to `self.dentry`.
+        unsafe { DebugFsEntry::new(self.dentry) }
+    }
+
+    unsafe extern "C" fn automount_callback(
+        dentry: *mut bindings::dentry,
+        data: *mut core::ffi::c_void,
+    ) -> *mut bindings::vfsmount {
+        // SAFETY: `data` is the static reference passed to `debugfs_create_automount` in
+        // `create`.
+        let data = unsafe { &*(data as *const T::Data) };
+        // SAFETY: The VFS holds a reference to `dentry` while the automount is in progress.
+        let mountpoint = unsafe { DebugFsEntry::new(dentry) };
+        match T::automount(data, mountpoint) {
+            Ok(mount) => mount.into_raw(),
+            // SAFETY: `ERR_PTR` only encodes the error number in a pointer.
+            Err(e) => unsafe { bindings::ERR_PTR(e.to_kernel_errno() as _) as _ },
+        }
+    }
+}
+
+impl<T: Automount> Drop for DebugFsAutomount<T> {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, we hold a reference to `self.dentry`.
+        unsafe { remove_entry(self.dentry) };
+    }
+}
+
+// SAFETY: The directory is only used through functions that may be called from any thread.
+unsafe impl<T: Automount> Send for DebugFsAutomount<T> {}
+
+// SAFETY: `DebugFsAutomount` only gives access to handles, which can be used from any thread.
+unsafe impl<T: Automount> Sync for DebugFsAutomount<T> {}
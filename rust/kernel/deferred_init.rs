@@ -0,0 +1,310 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Deferred initialisation.
+//!
+//! Some modules take a long time to become usable, for example because they load firmware that
+//! a slow device then needs to boot. Doing that in [`crate::Module::init`] delays the boot, or
+//! `insmod`, by as much.
+//!
+//! A [`DeferredInit`] instead runs the slow part of the initialisation from a work queue, while
+//! `init` registers the devices right away. Their `open` callbacks wait for the initialisation to
+//! complete (or fail right away for non-blocking opens), and its progress can be exposed to user
+//! space with a [`ReadinessFile`] in debugfs.
+
+use crate::{
+    error::code::*,
+    file::{self, flags, File},
+    fmt,
+    init::InPlaceInit,
+    io_buffer::IoBufferWriter,
+    new_condvar, new_mutex, pin_init,
+    str::CString,
+    sync::{smutex, Arc, ArcBorrow, CondVar, Mutex, OnceCell, UniqueArc},
+    workqueue::{Queue, Work, WorkAdapter},
+    Error, Result,
+};
+use alloc::boxed::Box;
+use core::pin::Pin;
+use macros::vtable;
+
+#[cfg(CONFIG_DEBUG_FS)]
+use crate::{
+    debugfs::{self, DebugFsDirectory, DebugFsFile},
+    str::CStr,
+    types::Mode,
+};
+
+/// The state of a [`DeferredInit`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    /// The initialisation has not completed yet.
+    Pending,
+
+    /// The initialisation succeeded.
+    Ready,
+
+    /// The initialisation failed with the given error, or returned `ECANCELED` if it was
+    /// cancelled before it started.
+    Failed(Error),
+}
+
+/// A value that is initialised asynchronously, from a work queue.
+///
+/// # Examples
+///
+/// A device that only becomes usable once its firmware is loaded:
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::{
+///     c_str,
+///     debugfs::{self, DebugFsFile, MODE_444},
+///     deferred_init::{DeferredInit, ReadinessFile},
+///     file::{self, File},
+///     io_buffer::IoBufferWriter,
+///     miscdev,
+///     sync::{Arc, ArcBorrow},
+///     workqueue,
+/// };
+///
+/// struct Firmware {
+///     version: u32,
+/// }
+///
+/// fn load_firmware() -> Result<Firmware> {
+///     // Uploads the firmware and waits for the device to boot it, which takes seconds.
+///     Ok(Firmware { version: 3 })
+/// }
+///
+/// struct Device;
+///
+/// #[vtable]
+/// impl file::Operations for Device {
+///     type OpenData = Arc<DeferredInit<Firmware>>;
+///     type Data = Arc<DeferredInit<Firmware>>;
+///
+///     fn open(fw: &Arc<DeferredInit<Firmware>>, file: &File) -> Result<Self::Data> {
+///         // Blocks until the firmware is loaded, unless the file is opened with `O_NONBLOCK`.
+///         fw.wait_open(file)?;
+///         Ok(fw.clone())
+///     }
+///
+///     fn read(
+///         fw: ArcBorrow<'_, DeferredInit<Firmware>>,
+///         _file: &File,
+///         _data: &mut impl IoBufferWriter,
+///         _offset: &mut u64,
+///     ) -> Result<usize> {
+///         // Files can only be opened once the firmware is loaded.
+///         let _version = fw.get().ok_or(EIO)?.version;
+///         Ok(0)
+///     }
+/// }
+///
+/// struct Module {
+///     fw: Arc<DeferredInit<Firmware>>,
+///     _reg: Pin<Box<miscdev::Registration<Device>>>,
+///     _ready: DebugFsFile<ReadinessFile<Firmware>>,
+/// }
+///
+/// impl Module {
+///     fn new() -> Result<Self> {
+///         let fw = DeferredInit::try_spawn(workqueue::system_long(), load_firmware)?;
+///         let vtable = kernel::file_vtable!(debugfs::Vtable<ReadinessFile<Firmware>>);
+///         let ready =
+///             DeferredInit::debugfs_create(&fw, c_str!("example_ready"), None, MODE_444, vtable)?;
+///         let vtable = kernel::file_vtable!(miscdev::Vtable<Device>);
+///         let reg = miscdev::Registration::new_pinned(vtable, fmt!("example"), fw.clone())?;
+///         Ok(Self {
+///             fw,
+///             _reg: reg,
+///             _ready: ready,
+///         })
+///     }
+/// }
+///
+/// impl Drop for Module {
+///     fn drop(&mut self) {
+///         // The work item runs code of the module, so it must not outlive it.
+///         self.fw.cancel();
+///     }
+/// }
+/// ```
+///
+/// # Invariants
+///
+/// `value` is only set while `state` is [`State::Pending`], which then becomes [`State::Ready`].
+/// `init` is `None` once `state` is not [`State::Pending`].
+pub struct DeferredInit<T> {
+    work: Work,
+    init: smutex::Mutex<Option<Box<dyn FnOnce() -> Result<T> + Send>>>,
+    state: Mutex<State>,
+    completed: CondVar,
+    value: OnceCell<T>,
+}
+
+impl<T: Send + Sync + 'static> DeferredInit<T> {
+    /// Runs `init` from `queue`, and returns the object that receives its result.
+    ///
+    /// Slow initialisations, which may sleep for long, should use [`crate::workqueue::system_long`]
+    /// or a queue of their own. The owner must call [`DeferredInit::cancel`] before the module is
+    /// unloaded.
+    pub fn try_spawn(
+        queue: &Queue,
+        init: impl FnOnce() -> Result<T> + Send + 'static,
+    ) -> Result<Arc<Self>> {
+        let init: Box<dyn FnOnce() -> Result<T> + Send> = Box::try_new(init)?;
+        let this = UniqueArc::try_pin_init(pin_init!(Self {
+            // SAFETY: `work` is initialised below.
+            work: unsafe { Work::new() },
+            init: smutex::Mutex::new(Some(init)),
+            state <- new_mutex!(State::Pending, "DeferredInit::state"),
+            completed <- new_condvar!("DeferredInit::completed"),
+            value: OnceCell::new(),
+        }))?;
+        // SAFETY: The object is not moved out of its allocation, which is converted to an `Arc`.
+        let this = unsafe { Pin::into_inner_unchecked(this) };
+        crate::init_work_item!(&this);
+        let this: Arc<Self> = this.into();
+        queue.enqueue(this.clone());
+        Ok(this)
+    }
+
+    /// Returns the state of the initialisation.
+    pub fn state(&self) -> State {
+        *self.state.lock()
+    }
+
+    /// Returns the value, or [`None`] if the initialisation has not succeeded (yet).
+    pub fn get(&self) -> Option<&T> {
+        self.value.get()
+    }
+
+    /// Waits for the initialisation to complete, and returns the value or the error it failed
+    /// with.
+    ///
+    /// Returns `ERESTARTSYS` if the wait is interrupted by a signal.
+    pub fn wait(&self) -> Result<&T> {
+        let mut state = self.state.lock();
+        loop {
+            match *state {
+                State::Pending => {
+                    if self.completed.wait(&mut state) {
+                        return Err(ERESTARTSYS);
+                    }
+                }
+                State::Ready => return self.value.get().ok_or(EINVAL),
+                State::Failed(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Waits for the initialisation to complete on behalf of the `open` callback of `file`.
+    ///
+    /// This is the same as [`DeferredInit::wait`], except that it returns `EAGAIN` instead of
+    /// waiting if the file is opened with `O_NONBLOCK`.
+    pub fn wait_open(&self, file: &File) -> Result<&T> {
+        if file.flags() & flags::O_NONBLOCK != 0 {
+            return match self.state() {
+                State::Pending => Err(EAGAIN),
+                State::Ready => self.value.get().ok_or(EINVAL),
+                State::Failed(e) => Err(e),
+            };
+        }
+        self.wait()
+    }
+
+    /// Cancels the initialisation if it has not started yet, otherwise waits for it to complete.
+    ///
+    /// Once this returns, the work item is not running and will not run again. If it had not
+    /// started, the state becomes [`State::Failed`] with `ECANCELED`, and waiters are woken up.
+    /// This may sleep, so it must be called from process context.
+    pub fn cancel(&self) {
+        crate::debug_assert_sleepable!();
+        self.work.cancel();
+        // The work item did not run if it was cancelled, so the initialiser is still here.
+        let init = self.init.lock().take();
+        if init.is_some() {
+            self.complete(Err(ECANCELED));
+        }
+    }
+
+    /// Records the result of the initialisation and wakes up the waiters.
+    fn complete(&self, result: Result<T>) {
+        let mut state = self.state.lock();
+        *state = match result {
+            Ok(value) => {
+                // The value can only be set here, while the state is pending, so it is empty.
+                let _ = self.value.set(value);
+                State::Ready
+            }
+            Err(e) => State::Failed(e),
+        };
+        drop(state);
+        self.completed.notify_all();
+    }
+
+    /// Creates a file called `name` with permissions `mode`, in `parent` or at the root of
+    /// debugfs, that shows the state of `this`.
+    ///
+    /// Reading the file returns `pending`, `ready` or `failed: <error>`, followed by a newline,
+    /// so that scripts can poll it to know when the device is usable. `vtable` holds the file
+    /// operations, defined by the module that implements `T`.
+    #[cfg(CONFIG_DEBUG_FS)]
+    pub fn debugfs_create(
+        this: &Arc<Self>,
+        name: &CStr,
+        parent: Option<Arc<DebugFsDirectory>>,
+        mode: Mode,
+        vtable: &'static debugfs::Vtable<ReadinessFile<T>>,
+    ) -> Result<DebugFsFile<ReadinessFile<T>>> {
+        debugfs::debugfs_create(name, parent, mode, vtable, this.clone())
+    }
+}
+
+// SAFETY: `DeferredInit::work` is of type `Work`.
+unsafe impl<T: Send + Sync + 'static> WorkAdapter for DeferredInit<T> {
+    type Target = Self;
+    const FIELD_OFFSET: isize = crate::offset_of!(Self, work);
+
+    fn run(this: Arc<Self>) {
+        // The initialiser is gone if the initialisation was cancelled in the meantime.
+        let init = this.init.lock().take();
+        if let Some(init) = init {
+            this.complete(init());
+        }
+    }
+}
+
+/// The file operations of a file created by [`DeferredInit::debugfs_create`].
+pub struct ReadinessFile<T>(core::marker::PhantomData<T>);
+
+#[vtable]
+impl<T: Send + Sync + 'static> file::Operations for ReadinessFile<T> {
+    type OpenData = Arc<DeferredInit<T>>;
+    type Data = Arc<DeferredInit<T>>;
+
+    fn open(init: &Arc<DeferredInit<T>>, _file: &File) -> Result<Self::Data> {
+        Ok(init.clone())
+    }
+
+    fn read(
+        init: ArcBorrow<'_, DeferredInit<T>>,
+        _file: &File,
+        data: &mut impl IoBufferWriter,
+        offset: &mut u64,
+    ) -> Result<usize> {
+        let line = match init.state() {
+            State::Pending => CString::try_from_fmt(fmt!("pending\n"))?,
+            State::Ready => CString::try_from_fmt(fmt!("ready\n"))?,
+            State::Failed(e) => CString::try_from_fmt(fmt!("failed: {:?}\n", e))?,
+        };
+        let line = line.as_bytes();
+
+        let start = core::cmp::min(usize::try_from(*offset).unwrap_or(usize::MAX), line.len());
+        let len = core::cmp::min(data.len(), line.len() - start);
+        data.write_slice(&line[start..][..len])?;
+        *offset += len as u64;
+        Ok(len)
+    }
+}
@@ -0,0 +1,221 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Logging that does not wait for the console.
+//!
+//! `printk` can take a long time when the console is slow, which hot paths, such as interrupt
+//! handlers or the submission path of a fast device, cannot afford. A [`DeferredLogger`] formats
+//! records into a buffer without taking locks, and a kernel thread prints them later.
+
+use crate::{
+    bindings, event_log::EventLog, prelude::*, stats::StatSet, str::CStr, sync::Arc, task::Task,
+    ARef,
+};
+use core::{
+    fmt::{self, Display, Write},
+    time::Duration,
+};
+
+/// The maximum length of a record, longer ones are truncated.
+pub const RECORD_LEN: usize = 127;
+
+/// The index of the counter of the records logged, in [`DeferredLogger::stats`].
+pub const LOGGED: usize = 0;
+
+/// The index of the counter of the records printed, in [`DeferredLogger::stats`].
+pub const PRINTED: usize = 1;
+
+/// The index of the counter of the records dropped before they could be printed, in
+/// [`DeferredLogger::stats`].
+pub const DROPPED: usize = 2;
+
+/// A formatted record, waiting to be printed.
+#[derive(Clone, Copy)]
+struct Record {
+    len: u8,
+    buf: [u8; RECORD_LEN],
+}
+
+impl Record {
+    fn format(args: fmt::Arguments<'_>) -> Self {
+        let mut record = Self {
+            len: 0,
+            buf: [0; RECORD_LEN],
+        };
+        // Writing to a record never fails, it truncates the text instead.
+        let _ = record.write_fmt(args);
+        record
+    }
+}
+
+impl Write for Record {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let start = self.len as usize;
+        let len = s.len().min(RECORD_LEN - start);
+        self.buf[start..start + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len as u8;
+        Ok(())
+    }
+}
+
+impl Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = &self.buf[..self.len as usize];
+        // Truncation may have split the last character, which is left out.
+        let text = match core::str::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(e) => core::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or(""),
+        };
+        f.write_str(text)
+    }
+}
+
+/// The part of a [`DeferredLogger`] shared with its thread.
+struct Shared<const N: usize> {
+    name: &'static CStr,
+    log: EventLog<Record, N>,
+    stats: Arc<StatSet<3>>,
+}
+
+impl<const N: usize> Shared<N> {
+    /// Returns whether records were logged since `next`.
+    fn pending(&self, next: u64) -> bool {
+        self.log.recorded() != next
+    }
+
+    /// Prints the records logged since `next`, which is updated to the position of the first
+    /// record that was not printed yet.
+    ///
+    /// Records that were overwritten before they could be printed are counted as dropped. So are
+    /// the rare records that were still being written while newer ones were printed.
+    fn drain(&self, next: &mut u64) {
+        if !self.pending(*next) {
+            return;
+        }
+        let entries = match self.log.snapshot() {
+            Ok(entries) => entries,
+            // The records are printed on the next pass, unless they are overwritten by then.
+            Err(_) => return,
+        };
+
+        let (mut printed, mut dropped) = (0, 0);
+        for entry in entries.iter().filter(|entry| entry.pos >= *next) {
+            dropped += entry.pos - *next;
+            pr_info!("{}: {}\n", self.name, entry.event);
+            printed += 1;
+            *next = entry.pos + 1;
+        }
+        self.stats.add(PRINTED, printed);
+        self.stats.add(DROPPED, dropped);
+    }
+}
+
+/// A logger whose records are printed by a kernel thread, so that logging never waits for the
+/// console.
+///
+/// [`DeferredLogger::log`] formats a record into a buffer of the last `N` records without taking
+/// locks, so it may be called from any context, including interrupt handlers. The thread wakes up
+/// periodically and prints the new records with `pr_info`, prefixed with the name of the logger.
+/// When records are logged faster than they are printed, the oldest ones are overwritten and
+/// counted as dropped.
+///
+/// The number of records logged, printed and dropped is kept in [`DeferredLogger::stats`], which
+/// can be exported to debugfs with [`crate::stats::StatSetDir`].
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use core::time::Duration;
+/// use kernel::{c_str, deferred_log::DeferredLogger};
+///
+/// struct Queue {
+///     logger: DeferredLogger<256>,
+/// }
+///
+/// impl Queue {
+///     fn try_new() -> Result<Self> {
+///         Ok(Self {
+///             logger: DeferredLogger::try_new(c_str!("queue"), Duration::from_millis(100))?,
+///         })
+///     }
+///
+///     // Called in interrupt context.
+///     fn complete(&self, tag: u32, status: i32) {
+///         if status != 0 {
+///             self.logger.log(fmt!("request {tag} failed: {status}"));
+///         }
+///     }
+/// }
+/// ```
+pub struct DeferredLogger<const N: usize> {
+    shared: Arc<Shared<N>>,
+    task: ARef<Task>,
+}
+
+impl<const N: usize> DeferredLogger<N> {
+    /// Creates a new logger called `name`, whose thread prints the records every `interval`.
+    ///
+    /// The thread is called `name` as well.
+    pub fn try_new(name: &'static CStr, interval: Duration) -> Result<Self> {
+        let shared = Arc::try_new(Shared {
+            name,
+            log: EventLog::new(),
+            stats: Arc::try_new(StatSet::new(["logged", "printed", "dropped"])?)?,
+        })?;
+
+        let ms = interval.as_millis().try_into().unwrap_or(u32::MAX);
+        // `msecs_to_jiffies` is inline, so this calls the out-of-line conversion it falls back to
+        // for values that are not constant.
+        // SAFETY: This function has no safety requirements.
+        let timeout = unsafe { bindings::__msecs_to_jiffies(ms) } as _;
+        let thread = shared.clone();
+        let task = Task::spawn(fmt!("{name}"), move || {
+            let mut next = 0;
+            loop {
+                // The state is set before checking for a stop request, so that a `kthread_stop`
+                // that lands after the check wakes the thread up instead of being lost.
+                // SAFETY: This function has no safety requirements.
+                unsafe { bindings::set_current_state(bindings::TASK_INTERRUPTIBLE as _) };
+                // The stop request is checked before draining, so that the records logged before
+                // the logger is dropped are printed.
+                // SAFETY: This is called from the thread created by `Task::spawn`.
+                let stop = unsafe { bindings::kthread_should_stop() };
+                if !stop && !thread.pending(next) {
+                    // SAFETY: This function has no safety requirements.
+                    unsafe { bindings::schedule_timeout(timeout) };
+                }
+                // SAFETY: This function has no safety requirements.
+                unsafe { bindings::__set_current_state(bindings::TASK_RUNNING as _) };
+
+                thread.drain(&mut next);
+                if stop {
+                    break;
+                }
+            }
+        })?;
+
+        Ok(Self { shared, task })
+    }
+
+    /// Logs a record with the text of `args`, truncated to [`RECORD_LEN`] bytes.
+    ///
+    /// It never blocks and may be called from any context.
+    pub fn log(&self, args: fmt::Arguments<'_>) {
+        self.shared.log.record(Record::format(args));
+        self.shared.stats.inc(LOGGED);
+    }
+
+    /// Returns the counters of the logger, indexed by [`LOGGED`], [`PRINTED`] and [`DROPPED`].
+    pub fn stats(&self) -> &Arc<StatSet<3>> {
+        &self.shared.stats
+    }
+}
+
+impl<const N: usize> Drop for DeferredLogger<N> {
+    fn drop(&mut self) {
+        // SAFETY: The task was created by `Task::spawn` and only exits once it is asked to stop,
+        // and `self.task` holds a reference to it. This waits for the remaining records to be
+        // printed.
+        unsafe { bindings::kthread_stop(self.task.0.get()) };
+    }
+}
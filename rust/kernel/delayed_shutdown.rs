@@ -0,0 +1,178 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Draining of in-flight operations before shutdown.
+//!
+//! Unregistering a device does not wait for the callbacks that are already running on its open
+//! files: `misc_deregister`, for example, only stops new files from being opened. A module that
+//! tears down the state used by those callbacks in [`Drop`] may therefore race with a `read` or an
+//! `ioctl` that is still executing.
+//!
+//! A [`Quiesce`] counts the callbacks that are in flight. Once [`Quiesce::drain`] returns, all of
+//! them have completed and new ones are refused, so the state they use can be torn down even if
+//! files are still open. [`file::Operations`] implementations opt in by returning the quiesce from
+//! [`file::Operations::quiesce`], which makes all the callbacks on open files, apart from
+//! `release`, run inside it.
+//!
+//! Sequence files do not need it: removing a debugfs or `/proc` entry already waits for the
+//! operations in progress on it.
+//!
+//! [`file::Operations`]: crate::file::Operations
+//! [`file::Operations::quiesce`]: crate::file::Operations::quiesce
+
+use crate::{
+    init::PinInit,
+    pin_init,
+    str::CStr,
+    sync::{LockClassKey, Srcu, SrcuReadGuard},
+};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Tracks the operations in flight on an object so that they can be drained before it is torn
+/// down.
+///
+/// Operations run inside a guard returned by [`Quiesce::enter`], which may sleep. Once
+/// [`Quiesce::drain`] is called, [`Quiesce::enter`] fails, and `drain` waits for the guards that
+/// were already handed out to be dropped.
+///
+/// Instances must be initialised in place, for example with the [`new_quiesce`] macro.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::{
+///     delayed_shutdown::Quiesce,
+///     file::{self, File},
+///     init::InPlaceInit,
+///     io_buffer::IoBufferWriter,
+///     miscdev, new_quiesce, pin_init,
+///     sync::{Arc, ArcBorrow, UniqueArc},
+/// };
+///
+/// struct Device {
+///     quiesce: Quiesce,
+/// }
+///
+/// #[vtable]
+/// impl file::Operations for Device {
+///     type OpenData = Arc<Device>;
+///     type Data = Arc<Device>;
+///
+///     fn open(dev: &Arc<Device>, _file: &File) -> Result<Arc<Device>> {
+///         Ok(dev.clone())
+///     }
+///
+///     fn quiesce<'a>(dev: &'a ArcBorrow<'_, Device>) -> Option<&'a Quiesce> {
+///         Some(&dev.quiesce)
+///     }
+///
+///     fn read(
+///         _dev: ArcBorrow<'_, Device>,
+///         _file: &File,
+///         _data: &mut impl IoBufferWriter,
+///         _offset: &mut u64,
+///     ) -> Result<usize> {
+///         // Only runs while the device is not being shut down.
+///         Ok(0)
+///     }
+/// }
+///
+/// struct Module {
+///     dev: Arc<Device>,
+///     _reg: Pin<Box<miscdev::Registration<Device>>>,
+/// }
+///
+/// impl Module {
+///     fn new() -> Result<Self> {
+///         let dev: Arc<Device> = UniqueArc::try_pin_init(pin_init!(Device {
+///             quiesce <- new_quiesce!("Device::quiesce"),
+///         }))?
+///         .into();
+///         let vtable = kernel::file_vtable!(miscdev::Vtable<Device>);
+///         let reg = miscdev::Registration::new_pinned(vtable, fmt!("example"), dev.clone())?;
+///         Ok(Self { dev, _reg: reg })
+///     }
+/// }
+///
+/// impl Drop for Module {
+///     fn drop(&mut self) {
+///         // No callback is running on the open files once this returns, and none will start.
+///         self.dev.quiesce.drain();
+///     }
+/// }
+/// ```
+///
+/// [`new_quiesce`]: crate::new_quiesce
+pub struct Quiesce {
+    srcu: Srcu,
+    draining: AtomicBool,
+}
+
+impl Quiesce {
+    /// Creates an initialiser of a new quiesce.
+    ///
+    /// Users are encouraged to use the [`new_quiesce`] macro instead, which creates a lock class
+    /// for each call site.
+    ///
+    /// [`new_quiesce`]: crate::new_quiesce
+    pub fn new(name: &'static CStr, key: &'static LockClassKey) -> impl PinInit<Self> {
+        pin_init!(Self {
+            srcu <- Srcu::new(name, key),
+            draining: AtomicBool::new(false),
+        })
+    }
+
+    /// Enters an operation, or returns [`None`] if the object is being drained.
+    ///
+    /// The operation ends when the returned guard is dropped. It may sleep until then, but it
+    /// delays [`Quiesce::drain`].
+    pub fn enter(&self) -> Option<QuiesceGuard<'_>> {
+        let guard = self.srcu.read_lock();
+        // Either `drain` sees this operation when it synchronises, or this sees the flag, since
+        // `synchronize` orders the store of the flag before the read-side sections that follow.
+        if self.draining.load(Ordering::Acquire) {
+            return None;
+        }
+        Some(QuiesceGuard { _srcu: guard })
+    }
+
+    /// Returns `true` once [`Quiesce::drain`] has been called.
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Acquire)
+    }
+
+    /// Refuses new operations and waits for the ones in flight to complete.
+    ///
+    /// This may sleep, so it must be called from process context, and not from within an
+    /// operation of the same object, otherwise it deadlocks. Calling it again returns once the
+    /// operations that were entered before the first call have completed.
+    pub fn drain(&self) {
+        crate::debug_assert_sleepable!();
+        self.draining.store(true, Ordering::Release);
+        self.srcu.synchronize();
+    }
+}
+
+/// An operation in flight on an object tracked by a [`Quiesce`].
+///
+/// The operation ends when the guard is dropped.
+#[must_use = "the operation ends when the guard is dropped"]
+pub struct QuiesceGuard<'a> {
+    _srcu: SrcuReadGuard<'a>,
+}
+
+/// Creates an initialiser of a [`Quiesce`].
+///
+/// The optional name is used for lockdep; it defaults to the location of the call.
+///
+/// [`Quiesce`]: crate::delayed_shutdown::Quiesce
+#[macro_export]
+macro_rules! new_quiesce {
+    ($(,)?) => {
+        $crate::new_quiesce!(::core::concat!(::core::file!(), ":", ::core::line!()))
+    };
+    ($name:expr $(,)?) => {{
+        static CLASS: $crate::sync::LockClassKey = $crate::sync::LockClassKey::new();
+        $crate::delayed_shutdown::Quiesce::new($crate::c_str!($name), &CLASS)
+    }};
+}
@@ -0,0 +1,78 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Transition paths for renamed parts of the crate.
+//!
+//! Modules built out of tree are written against a given version of this crate, and break when a
+//! type or a trait they use is renamed. Renamed items therefore keep their former name, declared
+//! with [`deprecated_alias!`], for two releases after the one that renamed them, so that their
+//! users get a warning that tells them what to use instead before the build fails.
+//!
+//! Deprecated names must not be used within the kernel tree, and are removed once the two
+//! releases have passed.
+//!
+//! [`deprecated_alias!`]: crate::deprecated_alias!
+
+/// Declares the former name of a renamed type or trait, which warns when it is used.
+///
+/// The alias is declared like a type alias, or like a trait alias for traits, followed by the
+/// kernel release that renamed the item and the note shown in the warning. Both are added to the
+/// documentation of the alias.
+///
+/// Trait aliases are not supported by the language, so the former name of a trait is a trait
+/// implemented by all the types that implement the new one. It can be used in bounds, but types
+/// must implement the new trait: code that still implements the former name gets a conflicting
+/// implementation error (`E0119`) with the blanket implementation instead of a deprecation warning,
+/// since the former trait has no items of its own to implement. Renaming the trait in such `impl`
+/// blocks is the only change these users need.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::deprecated_alias;
+///
+/// /// Operations of a device.
+/// pub trait DeviceOperations {}
+///
+/// /// A device with some state.
+/// pub struct Device<T>(T);
+///
+/// deprecated_alias! {
+///     /// The former name of [`DeviceOperations`].
+///     pub trait Operations = DeviceOperations;
+///     since = "6.3", note = "use `DeviceOperations` instead",
+/// }
+///
+/// deprecated_alias! {
+///     /// The former name of [`Device`].
+///     pub type DeviceState<T = ()> = Device<T>;
+///     since = "6.3", note = "use `Device` instead",
+/// }
+/// ```
+#[macro_export]
+macro_rules! deprecated_alias {
+    (
+        $(#[$meta:meta])*
+        $vis:vis type $old:ident $(<$($param:ident $(= $default:ty)?),* $(,)?>)? = $new:ty;
+        since = $since:literal, note = $note:literal $(,)?
+    ) => {
+        $(#[$meta])*
+        #[doc = ""]
+        #[doc = concat!("Deprecated since ", $since, ": ", $note, ".")]
+        #[deprecated(since = $since, note = $note)]
+        $vis type $old $(<$($param $(= $default)?),*>)? = $new;
+    };
+    (
+        $(#[$meta:meta])*
+        $vis:vis trait $old:ident = $new:path;
+        since = $since:literal, note = $note:literal $(,)?
+    ) => {
+        $(#[$meta])*
+        #[doc = ""]
+        #[doc = concat!("Deprecated since ", $since, ": ", $note, ".")]
+        #[deprecated(since = $since, note = $note)]
+        $vis trait $old: $new {}
+
+        #[allow(deprecated)]
+        impl<T: $new + ?Sized> $old for T {}
+    };
+}
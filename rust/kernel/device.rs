@@ -12,7 +12,7 @@ use crate::{
     revocable::{Revocable, RevocableGuard},
     str::CStr,
     sync::{LockClassKey, NeedsLockClass, RevocableMutex, RevocableMutexGuard, UniqueArc},
-    Result,
+    NumaNode, Result,
 };
 use core::{
     fmt,
@@ -52,6 +52,20 @@ pub unsafe trait RawDevice {
         unsafe { CStr::from_char_ptr(name) }
     }
 
+    /// Returns the NUMA node of the device, or [`NumaNode::ANY`] if it is not attached to one.
+    fn numa_node(&self) -> NumaNode {
+        #[cfg(CONFIG_NUMA)]
+        {
+            // SAFETY: `raw_device` is valid because `self` keeps it alive. The node of a device is
+            // set before it is added, and does not change afterwards.
+            let id = unsafe { (*self.raw_device()).numa_node };
+            NumaNode::new(id).unwrap_or(NumaNode::ANY)
+        }
+
+        #[cfg(not(CONFIG_NUMA))]
+        NumaNode::ANY
+    }
+
     /// Lookups a clock producer consumed by this device.
     ///
     /// Returns a managed reference to the clock producer.
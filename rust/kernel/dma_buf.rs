@@ -0,0 +1,474 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Sharing of buffers between devices.
+//!
+//! A driver that allocates a buffer, such as a camera or a display controller, exports it as a
+//! dma-buf, whose file descriptor is passed by user space to other drivers, the importers. Each
+//! importer attaches its device to the buffer, and asks the exporter for a scatter-gather table of
+//! the buffer mapped for that device.
+//!
+//! Synchronisation between devices is not exposed yet: the fences of a buffer are those of its
+//! implicit reservation object, which is managed by the C side.
+//!
+//! C header: [`include/linux/dma-buf.h`](../../../../include/linux/dma-buf.h)
+
+use crate::{
+    bindings,
+    device::RawDevice,
+    error::{code::*, from_kernel_err_ptr, from_kernel_result, to_result},
+    pages::Pages,
+    str::CStr,
+    types::Opaque,
+    Error, Result, ThisModule, PAGE_SIZE,
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{marker::PhantomData, ptr::NonNull};
+use macros::vtable;
+
+/// The direction of the transfers done by a device on a mapping.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataDirection {
+    /// The device both reads from and writes to the buffer.
+    Bidirectional,
+    /// The device reads from the buffer.
+    ToDevice,
+    /// The device writes to the buffer.
+    FromDevice,
+}
+
+impl DataDirection {
+    fn from_raw(dir: bindings::dma_data_direction) -> Result<Self> {
+        match dir {
+            bindings::dma_data_direction_DMA_BIDIRECTIONAL => Ok(Self::Bidirectional),
+            bindings::dma_data_direction_DMA_TO_DEVICE => Ok(Self::ToDevice),
+            bindings::dma_data_direction_DMA_FROM_DEVICE => Ok(Self::FromDevice),
+            _ => Err(EINVAL),
+        }
+    }
+
+    fn as_raw(self) -> bindings::dma_data_direction {
+        match self {
+            Self::Bidirectional => bindings::dma_data_direction_DMA_BIDIRECTIONAL,
+            Self::ToDevice => bindings::dma_data_direction_DMA_TO_DEVICE,
+            Self::FromDevice => bindings::dma_data_direction_DMA_FROM_DEVICE,
+        }
+    }
+}
+
+/// The attachment of an importing device to a buffer.
+///
+/// Wraps the kernel's `struct dma_buf_attachment`.
+#[repr(transparent)]
+pub struct Attachment(Opaque<bindings::dma_buf_attachment>);
+
+impl Attachment {
+    /// Creates a reference to an [`Attachment`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `ptr` is valid and remains valid for the lifetime of the
+    /// returned [`Attachment`] instance.
+    unsafe fn from_ptr<'a>(ptr: *const bindings::dma_buf_attachment) -> &'a Attachment {
+        // SAFETY: The safety requirements guarantee the validity of the dereference, while the
+        // `Attachment` type being transparent makes the cast ok.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns whether the importer can access the buffer directly over the bus, without going
+    /// through system memory.
+    pub fn peer2peer(&self) -> bool {
+        // SAFETY: The attachment is valid by the type invariants of `&Attachment`.
+        unsafe { (*self.0.get()).peer2peer }
+    }
+}
+
+// SAFETY: The importing device is attached until `detach` is called, which cannot happen while
+// the exporter holds a reference to the attachment.
+unsafe impl RawDevice for Attachment {
+    fn raw_device(&self) -> *mut bindings::device {
+        // SAFETY: The attachment is valid by the type invariants of `&Attachment`.
+        unsafe { (*self.0.get()).dev }
+    }
+}
+
+/// The part of an [`SgTable`] whose address is given to the C side.
+///
+/// `table` is the first field so that a pointer to it is also a pointer to the whole struct.
+#[repr(C)]
+struct SgTableInner {
+    table: bindings::sg_table,
+    mapping: Option<(*mut bindings::device, DataDirection)>,
+}
+
+/// A scatter-gather table describing the pages of a buffer, which borrows them for `'a`.
+///
+/// It is returned by [`Operations::map`], after being mapped for the device of the attachment
+/// with [`SgTable::dma_map`].
+///
+/// # Invariants
+///
+/// `inner.table` was allocated with `sg_alloc_table_from_pages`. If `inner.mapping` is set, the
+/// table is mapped for the given device and direction.
+pub struct SgTable<'a> {
+    inner: Box<SgTableInner>,
+    _p: PhantomData<&'a ()>,
+}
+
+impl<'a> SgTable<'a> {
+    /// Creates a table that covers all of `pages`, in order.
+    pub fn from_pages<const ORDER: u32>(pages: &'a [Pages<ORDER>]) -> Result<Self> {
+        let per_set = 1usize << ORDER;
+        let count = pages.len().checked_mul(per_set).ok_or(EINVAL)?;
+        let size = count.checked_mul(PAGE_SIZE).ok_or(EINVAL)?;
+        let mut raw = Vec::try_with_capacity(count)?;
+        for set in pages {
+            for i in 0..per_set {
+                // SAFETY: By the type invariants of `Pages`, `set.pages` points to `per_set`
+                // contiguous pages.
+                raw.try_push(unsafe { set.pages.add(i) })?;
+            }
+        }
+
+        let mut inner = Box::try_new(SgTableInner {
+            table: bindings::sg_table::default(),
+            mapping: None,
+        })?;
+        // SAFETY: `inner.table` is a valid, empty table, and `raw` contains `count` valid pages.
+        to_result(unsafe {
+            bindings::sg_alloc_table_from_pages(
+                &mut inner.table,
+                raw.as_mut_ptr(),
+                count as _,
+                0,
+                size as _,
+                bindings::GFP_KERNEL,
+            )
+        })?;
+        // INVARIANT: The table was just allocated, and is not mapped.
+        Ok(Self {
+            inner,
+            _p: PhantomData,
+        })
+    }
+
+    /// Maps the table for the device of `attachment`, so that it can be used by the importer.
+    ///
+    /// Returns `EBUSY` if the table is already mapped.
+    pub fn dma_map(&mut self, attachment: &Attachment, dir: DataDirection) -> Result {
+        if self.inner.mapping.is_some() {
+            return Err(EBUSY);
+        }
+        let dev = attachment.raw_device();
+        // SAFETY: The table is valid by the type invariants, and `dev` is valid while attached.
+        to_result(unsafe {
+            bindings::dma_map_sgtable(dev, &mut self.inner.table, dir.as_raw(), 0)
+        })?;
+        // INVARIANT: The table was just mapped for `dev` and `dir`.
+        self.inner.mapping = Some((dev, dir));
+        Ok(())
+    }
+
+    /// Gives the ownership of the table to the C side.
+    fn into_raw(self) -> *mut bindings::sg_table {
+        Box::into_raw(self.inner).cast()
+    }
+
+    /// Takes back the ownership of a table given to the C side.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by [`SgTable::into_raw`], and the pages it borrows must still
+    /// be valid for `'a`.
+    unsafe fn from_raw(ptr: *mut bindings::sg_table) -> Self {
+        Self {
+            // SAFETY: `table` is the first field of `SgTableInner`, so `ptr` is the pointer
+            // returned by `Box::into_raw`.
+            inner: unsafe { Box::from_raw(ptr.cast()) },
+            _p: PhantomData,
+        }
+    }
+}
+
+impl Drop for SgTable<'_> {
+    fn drop(&mut self) {
+        if let Some((dev, dir)) = self.inner.mapping {
+            // SAFETY: By the type invariants, the table is mapped for `dev` and `dir`.
+            unsafe { bindings::dma_unmap_sgtable(dev, &mut self.inner.table, dir.as_raw(), 0) };
+        }
+        // SAFETY: By the type invariants, the table was allocated with `sg_alloc_table_from_pages`.
+        unsafe { bindings::sg_free_table(&mut self.inner.table) };
+    }
+}
+
+/// Corresponds to the kernel's `struct dma_buf_ops`.
+///
+/// It is implemented by the data of an exported buffer, which is dropped once the last reference
+/// to the buffer is released.
+#[vtable]
+pub trait Operations: Send + Sync + Sized {
+    /// Called when a device is attached to the buffer, so that it can be rejected if it cannot
+    /// access the memory of the buffer.
+    ///
+    /// Corresponds to the `attach` function pointer in `struct dma_buf_ops`.
+    fn attach(&self, _attachment: &Attachment) -> Result {
+        Ok(())
+    }
+
+    /// Called when a device is detached from the buffer.
+    ///
+    /// Corresponds to the `detach` function pointer in `struct dma_buf_ops`.
+    fn detach(&self, _attachment: &Attachment) {}
+
+    /// Returns a table of the buffer, mapped for the device of `attachment` in direction `dir`.
+    ///
+    /// Corresponds to the `map_dma_buf` function pointer in `struct dma_buf_ops`.
+    fn map<'a>(&'a self, attachment: &Attachment, dir: DataDirection) -> Result<SgTable<'a>>;
+
+    /// Called when the importer no longer uses `table`, which is unmapped when it is dropped.
+    ///
+    /// Corresponds to the `unmap_dma_buf` function pointer in `struct dma_buf_ops`.
+    fn unmap(&self, _attachment: &Attachment, table: SgTable<'_>, _dir: DataDirection) {
+        drop(table);
+    }
+}
+
+unsafe extern "C" fn attach_callback<T: Operations>(
+    dmabuf: *mut bindings::dma_buf,
+    attach: *mut bindings::dma_buf_attachment,
+) -> core::ffi::c_int {
+    from_kernel_result! {
+        // SAFETY: `priv_` was set by `Exporter::export` to a `Box<T>` that is only freed in
+        // `release_callback`, and the attachment is valid for the duration of the call.
+        let (data, attachment) = unsafe {
+            (
+                &*((*dmabuf).priv_ as *const T),
+                Attachment::from_ptr(attach),
+            )
+        };
+        data.attach(attachment)?;
+        Ok(0)
+    }
+}
+
+unsafe extern "C" fn detach_callback<T: Operations>(
+    dmabuf: *mut bindings::dma_buf,
+    attach: *mut bindings::dma_buf_attachment,
+) {
+    // SAFETY: As in `attach_callback`.
+    let (data, attachment) = unsafe {
+        (
+            &*((*dmabuf).priv_ as *const T),
+            Attachment::from_ptr(attach),
+        )
+    };
+    data.detach(attachment);
+}
+
+unsafe extern "C" fn map_callback<T: Operations>(
+    attach: *mut bindings::dma_buf_attachment,
+    dir: bindings::dma_data_direction,
+) -> *mut bindings::sg_table {
+    // SAFETY: The attachment is valid for the duration of the call, and its buffer is alive.
+    let (data, attachment) = unsafe {
+        (
+            &*((*(*attach).dmabuf).priv_ as *const T),
+            Attachment::from_ptr(attach),
+        )
+    };
+    match DataDirection::from_raw(dir).and_then(|dir| data.map(attachment, dir)) {
+        Ok(table) => table.into_raw(),
+        // SAFETY: `ERR_PTR` only encodes the error number in a pointer.
+        Err(e) => unsafe { bindings::ERR_PTR(e.to_kernel_errno() as _) as _ },
+    }
+}
+
+unsafe extern "C" fn unmap_callback<T: Operations>(
+    attach: *mut bindings::dma_buf_attachment,
+    table: *mut bindings::sg_table,
+    dir: bindings::dma_data_direction,
+) {
+    // SAFETY: As in `map_callback`.
+    let (data, attachment) = unsafe {
+        (
+            &*((*(*attach).dmabuf).priv_ as *const T),
+            Attachment::from_ptr(attach),
+        )
+    };
+    // SAFETY: `table` was returned by `map_callback`, and borrows from `data`, which is alive.
+    let table = unsafe { SgTable::from_raw(table) };
+    // `map_callback` only succeeds for valid directions.
+    let dir = DataDirection::from_raw(dir).unwrap_or(DataDirection::Bidirectional);
+    data.unmap(attachment, table, dir);
+}
+
+unsafe extern "C" fn release_callback<T: Operations>(dmabuf: *mut bindings::dma_buf) {
+    // SAFETY: `priv_` was set by `Exporter::export` from `Box::into_raw`, and this is the last
+    // use of the buffer.
+    drop(unsafe { Box::from_raw((*dmabuf).priv_ as *mut T) });
+}
+
+/// The operations of the buffers handled by `T`.
+struct OperationsVtable<T>(PhantomData<T>);
+
+impl<T: Operations> OperationsVtable<T> {
+    const VTABLE: bindings::dma_buf_ops = bindings::dma_buf_ops {
+        cache_sgt_mapping: false,
+        attach: if T::HAS_ATTACH {
+            Some(attach_callback::<T>)
+        } else {
+            None
+        },
+        detach: if T::HAS_DETACH {
+            Some(detach_callback::<T>)
+        } else {
+            None
+        },
+        pin: None,
+        unpin: None,
+        map_dma_buf: Some(map_callback::<T>),
+        unmap_dma_buf: Some(unmap_callback::<T>),
+        release: Some(release_callback::<T>),
+        begin_cpu_access: None,
+        end_cpu_access: None,
+        mmap: None,
+        vmap: None,
+        vunmap: None,
+    };
+
+    /// Returns the operations, which are static since buffers may outlive their exporter.
+    const fn build() -> &'static bindings::dma_buf_ops {
+        &Self::VTABLE
+    }
+}
+
+/// Exports buffers whose operations are handled by `T`.
+///
+/// The buffers hold a reference to the module given to [`Exporter::new`], so it cannot be
+/// unloaded while they are alive. Their operations are static, so the buffers do not depend on
+/// the exporter, which may be dropped before they are released.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::{
+///     c_str,
+///     dma_buf::{Attachment, DataDirection, Exporter, Operations, SgTable},
+///     file::flags,
+///     pages::Pages,
+/// };
+///
+/// struct Frame {
+///     pages: Vec<Pages<0>>,
+/// }
+///
+/// #[vtable]
+/// impl Operations for Frame {
+///     fn map<'a>(&'a self, attachment: &Attachment, dir: DataDirection) -> Result<SgTable<'a>> {
+///         let mut table = SgTable::from_pages(&self.pages)?;
+///         table.dma_map(attachment, dir)?;
+///         Ok(table)
+///     }
+/// }
+///
+/// fn export_frame(exporter: &Exporter<Frame>, pages: Vec<Pages<0>>) -> Result<u32> {
+///     let size = pages.len() * kernel::PAGE_SIZE;
+///     let buf = exporter.export(Box::try_new(Frame { pages })?, size, flags::O_RDWR)?;
+///     buf.into_fd(flags::O_CLOEXEC)
+/// }
+///
+/// fn new_exporter(module: &'static ThisModule) -> Exporter<Frame> {
+///     Exporter::new(c_str!("example"), module)
+/// }
+/// ```
+pub struct Exporter<T: Operations> {
+    name: &'static CStr,
+    module: &'static ThisModule,
+    _p: PhantomData<T>,
+}
+
+impl<T: Operations> Exporter<T> {
+    /// Creates a new exporter of buffers that belong to `module`.
+    ///
+    /// `name` identifies the exporter in `/sys/kernel/debug/dma_buf/bufinfo`. `module` must be
+    /// the module that implements `T`, so that buffers keep it loaded.
+    pub const fn new(name: &'static CStr, module: &'static ThisModule) -> Self {
+        Self {
+            name,
+            module,
+            _p: PhantomData,
+        }
+    }
+
+    /// Exports a new buffer of `size` bytes, whose operations get `data`.
+    ///
+    /// `flags` are the flags of the file of the buffer, and usually contain an access mode, such
+    /// as [`crate::file::flags::O_RDWR`].
+    pub fn export(&self, data: Box<T>, size: usize, flags: u32) -> Result<DmaBuf> {
+        let ptr = Box::into_raw(data);
+        let info = bindings::dma_buf_export_info {
+            exp_name: self.name.as_char_ptr(),
+            owner: self.module.0,
+            ops: OperationsVtable::<T>::build(),
+            size: size as _,
+            flags: flags as _,
+            priv_: ptr.cast(),
+            ..Default::default()
+        };
+        // SAFETY: `info` is valid, and the operations are static. The buffer holds a reference to
+        // `owner`, which keeps the callbacks loaded. The callbacks expect `priv_` to be a `Box<T>`.
+        match from_kernel_err_ptr(unsafe { bindings::dma_buf_export(&info) }) {
+            // INVARIANT: `dma_buf_export` returns a new buffer with a reference that we own.
+            // SAFETY: `from_kernel_err_ptr` only returns valid pointers, which are not null.
+            Ok(buf) => Ok(DmaBuf {
+                ptr: unsafe { NonNull::new_unchecked(buf) },
+            }),
+            Err(e) => {
+                // SAFETY: The buffer was not created, so `ptr` is still owned here.
+                drop(unsafe { Box::from_raw(ptr) });
+                Err(e)
+            }
+        }
+    }
+}
+
+/// A reference to an exported buffer.
+///
+/// # Invariants
+///
+/// `ptr` is a valid buffer, and we hold a reference to it.
+pub struct DmaBuf {
+    ptr: NonNull<bindings::dma_buf>,
+}
+
+// SAFETY: The buffer is reference counted, and its reference can be released from any thread.
+unsafe impl Send for DmaBuf {}
+
+// SAFETY: `DmaBuf` has no methods that take `&self`.
+unsafe impl Sync for DmaBuf {}
+
+impl DmaBuf {
+    /// Installs the buffer in a new file descriptor of the current process, which takes over the
+    /// reference to it.
+    ///
+    /// The file descriptor is closed on `exec` if `flags` contains
+    /// [`crate::file::flags::O_CLOEXEC`]. Returns the file descriptor, for example to be returned
+    /// by an `ioctl`.
+    pub fn into_fd(self, flags: u32) -> Result<u32> {
+        // SAFETY: By the type invariants, `ptr` is a valid buffer.
+        let fd = unsafe { bindings::dma_buf_fd(self.ptr.as_ptr(), flags as _) };
+        if fd < 0 {
+            return Err(Error::from_kernel_errno(fd));
+        }
+        // The file descriptor now owns our reference.
+        core::mem::forget(self);
+        Ok(fd as _)
+    }
+}
+
+impl Drop for DmaBuf {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, we hold a reference to the buffer.
+        unsafe { bindings::dma_buf_put(self.ptr.as_ptr()) };
+    }
+}
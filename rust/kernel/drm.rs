@@ -0,0 +1,517 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! DRM display devices.
+//!
+//! A minimal binding for simple display controllers: a single fixed mode, a single primary plane
+//! in `XRGB8888` format, and a virtual connector. Userspace renders into dumb buffers; on every
+//! update the damaged area is copied into a `vmalloc`-backed shadow framebuffer that is handed to
+//! the driver's [`Driver::dirty`] callback. Framebuffer (fbdev) emulation is set up as well.
+//!
+//! C headers: [`include/drm/drm_drv.h`](../../../../include/drm/drm_drv.h) and
+//! [`include/drm/drm_simple_kms_helper.h`](../../../../include/drm/drm_simple_kms_helper.h)
+
+use crate::{
+    bindings, c_str, device,
+    error::{code::*, from_kernel_err_ptr},
+    str::CStr,
+    to_result,
+    types::ForeignOwnable,
+    Result, ThisModule,
+};
+use alloc::boxed::Box;
+use core::{ffi::c_void, marker::PhantomData, mem::size_of};
+use macros::vtable;
+
+/// The number of bytes per pixel of the only supported format, `XRGB8888`.
+pub const BYTES_PER_PIXEL: usize = 4;
+
+const FORMATS: [u32; 1] = [bindings::DRM_FORMAT_XRGB8888];
+
+/// The fixed display mode of a device.
+#[derive(Clone, Copy)]
+pub struct Mode {
+    /// The width in pixels.
+    pub width: u16,
+
+    /// The height in pixels.
+    pub height: u16,
+
+    /// The refresh rate in Hz.
+    pub refresh: u16,
+}
+
+/// A rectangle of pixels, where `x2` and `y2` are exclusive.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rect {
+    /// The leftmost column.
+    pub x1: u32,
+
+    /// The topmost row.
+    pub y1: u32,
+
+    /// The column past the rightmost one.
+    pub x2: u32,
+
+    /// The row past the bottom one.
+    pub y2: u32,
+}
+
+impl Rect {
+    /// Returns the width of the rectangle.
+    pub fn width(&self) -> u32 {
+        self.x2 - self.x1
+    }
+
+    /// Returns the height of the rectangle.
+    pub fn height(&self) -> u32 {
+        self.y2 - self.y1
+    }
+}
+
+/// The shadow framebuffer of a device, with the size of its mode and in `XRGB8888` format.
+pub struct Framebuffer<'a> {
+    data: &'a [u8],
+    mode: Mode,
+}
+
+impl<'a> Framebuffer<'a> {
+    /// Returns the contents of the framebuffer.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns the number of bytes between the start of two consecutive rows.
+    pub fn pitch(&self) -> usize {
+        self.mode.width as usize * BYTES_PER_PIXEL
+    }
+
+    /// Returns the pixels of row `y` between columns `x1` (inclusive) and `x2` (exclusive).
+    pub fn row(&self, y: u32, x1: u32, x2: u32) -> &'a [u8] {
+        let start = y as usize * self.pitch();
+        &self.data[start + x1 as usize * BYTES_PER_PIXEL..start + x2 as usize * BYTES_PER_PIXEL]
+    }
+}
+
+/// Corresponds to the callbacks of the kernel's `struct drm_simple_display_pipe_funcs`.
+#[vtable]
+pub trait Driver {
+    /// The type of the context data stored with the device and made available to callbacks.
+    type Data: ForeignOwnable + Send + Sync = ();
+
+    /// The name of the driver.
+    const NAME: &'static CStr;
+
+    /// A description of the driver.
+    const DESC: &'static CStr;
+
+    /// The date of the driver, in `YYYYMMDD` format.
+    const DATE: &'static CStr;
+
+    /// Called when the display is turned on.
+    fn enable(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>) {}
+
+    /// Called when the display is turned off.
+    fn disable(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>) {}
+
+    /// Called after `rect` of the framebuffer has been updated.
+    ///
+    /// The driver is expected to transfer the damaged area to the hardware.
+    fn dirty(data: <Self::Data as ForeignOwnable>::Borrowed<'_>, fb: &Framebuffer<'_>, rect: Rect);
+}
+
+/// The vtables of a device.
+///
+/// They are allocated for each device and freed when the device is released.
+struct Vtables {
+    driver: bindings::drm_driver,
+    fops: bindings::file_operations,
+    pipe_funcs: bindings::drm_simple_display_pipe_funcs,
+    connector_funcs: bindings::drm_connector_funcs,
+    connector_helper_funcs: bindings::drm_connector_helper_funcs,
+    mode_config_funcs: bindings::drm_mode_config_funcs,
+}
+
+/// The per-device state, allocated and freed by the DRM core.
+///
+/// The `drm` field must be first so that pointers to it can be freed by the DRM core.
+#[repr(C)]
+struct DeviceData {
+    drm: bindings::drm_device,
+    pipe: bindings::drm_simple_display_pipe,
+    connector: bindings::drm_connector,
+    mode: Mode,
+    shadow: *mut u8,
+    data: *const c_void,
+    vtables: *mut Vtables,
+}
+
+struct Callbacks<T>(PhantomData<T>);
+
+impl<T: Driver> Callbacks<T> {
+    fn vtables(module: &'static ThisModule) -> Vtables {
+        Vtables {
+            driver: bindings::drm_driver {
+                driver_features: bindings::drm_driver_feature_DRIVER_GEM
+                    | bindings::drm_driver_feature_DRIVER_MODESET
+                    | bindings::drm_driver_feature_DRIVER_ATOMIC,
+                name: T::NAME.as_char_ptr() as _,
+                desc: T::DESC.as_char_ptr() as _,
+                date: T::DATE.as_char_ptr() as _,
+                major: 1,
+                dumb_create: Some(bindings::drm_gem_shmem_dumb_create),
+                gem_prime_import_sg_table: Some(bindings::drm_gem_shmem_prime_import_sg_table),
+                prime_handle_to_fd: Some(bindings::drm_gem_prime_handle_to_fd),
+                prime_fd_to_handle: Some(bindings::drm_gem_prime_fd_to_handle),
+                gem_prime_mmap: Some(bindings::drm_gem_prime_mmap),
+                ..Default::default()
+            },
+            fops: bindings::file_operations {
+                owner: module.0,
+                open: Some(bindings::drm_open),
+                release: Some(bindings::drm_release),
+                unlocked_ioctl: Some(bindings::drm_ioctl),
+                compat_ioctl: Some(bindings::drm_compat_ioctl),
+                poll: Some(bindings::drm_poll),
+                read: Some(bindings::drm_read),
+                llseek: Some(bindings::noop_llseek),
+                mmap: Some(bindings::drm_gem_mmap),
+                ..Default::default()
+            },
+            pipe_funcs: bindings::drm_simple_display_pipe_funcs {
+                enable: Some(Self::enable_callback),
+                disable: Some(Self::disable_callback),
+                update: Some(Self::update_callback),
+                ..Default::default()
+            },
+            connector_funcs: bindings::drm_connector_funcs {
+                reset: Some(bindings::drm_atomic_helper_connector_reset),
+                fill_modes: Some(bindings::drm_helper_probe_single_connector_modes),
+                destroy: Some(bindings::drm_connector_cleanup),
+                atomic_duplicate_state: Some(bindings::drm_atomic_helper_connector_duplicate_state),
+                atomic_destroy_state: Some(bindings::drm_atomic_helper_connector_destroy_state),
+                ..Default::default()
+            },
+            connector_helper_funcs: bindings::drm_connector_helper_funcs {
+                get_modes: Some(Self::get_modes_callback),
+                ..Default::default()
+            },
+            mode_config_funcs: bindings::drm_mode_config_funcs {
+                fb_create: Some(bindings::drm_gem_fb_create_with_dirty),
+                atomic_check: Some(bindings::drm_atomic_helper_check),
+                atomic_commit: Some(bindings::drm_atomic_helper_commit),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Returns the device data that contains `pipe`.
+    ///
+    /// # Safety
+    ///
+    /// `pipe` must be the `pipe` field of a valid [`DeviceData`].
+    unsafe fn device_data<'a>(pipe: *mut bindings::drm_simple_display_pipe) -> &'a DeviceData {
+        // SAFETY: By the safety requirements, `pipe` is embedded in a valid `DeviceData`.
+        unsafe { &*crate::container_of!(pipe, DeviceData, pipe) }
+    }
+
+    unsafe extern "C" fn enable_callback(
+        pipe: *mut bindings::drm_simple_display_pipe,
+        _crtc_state: *mut bindings::drm_crtc_state,
+        _plane_state: *mut bindings::drm_plane_state,
+    ) {
+        // SAFETY: The DRM core only calls this with the pipe initialised by `Registration::new`,
+        // whose `data` remains valid until the device is released.
+        let data = unsafe { T::Data::borrow(Self::device_data(pipe).data) };
+        T::enable(data);
+    }
+
+    unsafe extern "C" fn disable_callback(pipe: *mut bindings::drm_simple_display_pipe) {
+        // SAFETY: The DRM core only calls this with the pipe initialised by `Registration::new`,
+        // whose `data` remains valid until the device is released.
+        let data = unsafe { T::Data::borrow(Self::device_data(pipe).data) };
+        T::disable(data);
+    }
+
+    unsafe extern "C" fn update_callback(
+        pipe: *mut bindings::drm_simple_display_pipe,
+        old_state: *mut bindings::drm_plane_state,
+    ) {
+        // SAFETY: The DRM core only calls this with the pipe initialised by `Registration::new`.
+        let dd = unsafe { Self::device_data(pipe) };
+        let drm = &dd.drm as *const _ as *mut bindings::drm_device;
+
+        // SAFETY: The plane state is valid while the update runs.
+        let state = unsafe { (*pipe).plane.state };
+        // SAFETY: `state` is valid.
+        let fb = unsafe { (*state).fb };
+        if fb.is_null() {
+            return;
+        }
+
+        let mut clip = bindings::drm_rect::default();
+        // SAFETY: Both states are valid and `clip` is a valid destination.
+        if !unsafe { bindings::drm_atomic_helper_damage_merged(old_state, state, &mut clip) } {
+            return;
+        }
+
+        let mut idx = 0;
+        // SAFETY: `drm` is valid. A `false` return means that the device was unplugged.
+        if !unsafe { bindings::drm_dev_enter(drm, &mut idx) } {
+            return;
+        }
+
+        let rect = Rect {
+            x1: clip.x1.max(0) as u32,
+            y1: clip.y1.max(0) as u32,
+            x2: (clip.x2.max(0) as u32).min(dd.mode.width.into()),
+            y2: (clip.y2.max(0) as u32).min(dd.mode.height.into()),
+        };
+
+        // SAFETY: `fb` is valid, and the shadow buffer was allocated for the size of the mode,
+        // which `rect` is clamped to.
+        if rect.x1 < rect.x2 && rect.y1 < rect.y2 && unsafe { Self::copy(dd, fb, &rect) }.is_ok() {
+            // SAFETY: The shadow buffer holds `width * height` pixels and is only written by this
+            // callback, which the DRM core does not run concurrently for the same plane.
+            let data = unsafe {
+                core::slice::from_raw_parts(
+                    dd.shadow,
+                    dd.mode.width as usize * dd.mode.height as usize * BYTES_PER_PIXEL,
+                )
+            };
+            let shadow = Framebuffer {
+                data,
+                mode: dd.mode,
+            };
+            // SAFETY: `data` remains valid until the device is released.
+            T::dirty(unsafe { T::Data::borrow(dd.data) }, &shadow, rect);
+        }
+
+        // SAFETY: `drm_dev_enter` succeeded above.
+        unsafe { bindings::drm_dev_exit(idx) };
+    }
+
+    /// Copies `rect` of `fb` into the shadow buffer.
+    ///
+    /// # Safety
+    ///
+    /// `fb` must be valid, and `rect` must be non-empty and lie within both `fb` and the mode.
+    unsafe fn copy(dd: &DeviceData, fb: *mut bindings::drm_framebuffer, rect: &Rect) -> Result {
+        let mut map = [bindings::iosys_map::default(); bindings::DRM_FORMAT_MAX_PLANES as usize];
+        let mut data = [bindings::iosys_map::default(); bindings::DRM_FORMAT_MAX_PLANES as usize];
+
+        // SAFETY: `fb` is valid and both arrays have room for all planes.
+        to_result(unsafe { bindings::drm_gem_fb_vmap(fb, map.as_mut_ptr(), data.as_mut_ptr()) })?;
+
+        // SAFETY: `fb` is valid and mapped.
+        let ret = to_result(unsafe {
+            bindings::drm_gem_fb_begin_cpu_access(fb, bindings::dma_data_direction_DMA_FROM_DEVICE)
+        });
+        if ret.is_ok() {
+            // SAFETY: `fb` is valid.
+            let src_pitch = unsafe { (*fb).pitches[0] } as usize;
+            let dst_pitch = dd.mode.width as usize * BYTES_PER_PIXEL;
+            let offset = rect.x1 as usize * BYTES_PER_PIXEL;
+            let len = rect.width() as usize * BYTES_PER_PIXEL;
+            // SAFETY: Dumb buffers are backed by system memory, so the mapping is a kernel virtual
+            // address.
+            let src = unsafe { data[0].__bindgen_anon_1.vaddr } as *const u8;
+            for y in rect.y1 as usize..rect.y2 as usize {
+                // SAFETY: By the safety requirements, the row lies within both buffers.
+                unsafe {
+                    core::ptr::copy_nonoverlapping(
+                        src.add(y * src_pitch + offset),
+                        dd.shadow.add(y * dst_pitch + offset),
+                        len,
+                    )
+                };
+            }
+
+            // SAFETY: `begin_cpu_access` succeeded above.
+            unsafe {
+                bindings::drm_gem_fb_end_cpu_access(
+                    fb,
+                    bindings::dma_data_direction_DMA_FROM_DEVICE,
+                )
+            };
+        }
+
+        // SAFETY: `fb` was mapped above.
+        unsafe { bindings::drm_gem_fb_vunmap(fb, map.as_mut_ptr()) };
+        ret
+    }
+
+    unsafe extern "C" fn get_modes_callback(
+        connector: *mut bindings::drm_connector,
+    ) -> core::ffi::c_int {
+        // SAFETY: The DRM core only calls this with the connector initialised by
+        // `Registration::new`, which is embedded in a `DeviceData`.
+        let dd = unsafe { &*crate::container_of!(connector, DeviceData, connector) };
+        let drm = &dd.drm as *const _ as *mut bindings::drm_device;
+
+        // SAFETY: `drm` is valid.
+        let mode = unsafe {
+            bindings::drm_cvt_mode(
+                drm,
+                dd.mode.width.into(),
+                dd.mode.height.into(),
+                dd.mode.refresh.into(),
+                false,
+                false,
+                false,
+            )
+        };
+        if mode.is_null() {
+            return 0;
+        }
+
+        // SAFETY: `mode` was just allocated and `connector` is valid.
+        unsafe {
+            (*mode).type_ |= bindings::DRM_MODE_TYPE_PREFERRED;
+            bindings::drm_mode_probed_add(connector, mode);
+        }
+        1
+    }
+
+    unsafe extern "C" fn release_callback(_drm: *mut bindings::drm_device, ptr: *mut c_void) {
+        let dd = ptr as *mut DeviceData;
+        // SAFETY: This action is only registered by `Registration::new`, with fields initialised
+        // as described there. The DRM core no longer uses the vtables when managed actions run.
+        unsafe {
+            bindings::vfree((*dd).shadow as _);
+            if !(*dd).data.is_null() {
+                T::Data::from_foreign((*dd).data);
+            }
+            drop(Box::from_raw((*dd).vtables));
+        }
+    }
+}
+
+/// A registered DRM display device.
+///
+/// The device is allocated as a managed resource of its parent, so its memory is only freed once
+/// the parent is unbound and all userspace references are gone; dropping the registration
+/// unplugs it so that the driver is no longer called.
+///
+/// # Invariants
+///
+/// `drm` is the `drm` field of a valid and registered [`DeviceData`].
+pub struct Registration<T: Driver> {
+    drm: *mut bindings::drm_device,
+    _p: PhantomData<T>,
+}
+
+impl<T: Driver> Registration<T> {
+    /// Creates and registers a new DRM display device with a fixed `mode`.
+    pub fn new(
+        parent: &dyn device::RawDevice,
+        mode: Mode,
+        data: T::Data,
+        module: &'static ThisModule,
+    ) -> Result<Self> {
+        if mode.width == 0 || mode.height == 0 || mode.refresh == 0 {
+            return Err(EINVAL);
+        }
+
+        let mut vtables = Box::try_new(Callbacks::<T>::vtables(module))?;
+        // The vtables are heap-allocated, so this pointer remains valid when the box is moved.
+        vtables.driver.fops = &vtables.fops;
+
+        // SAFETY: `parent` is a valid device and `vtables.driver` is fully initialised. The DRM
+        // core allocates a zeroed `DeviceData` and initialises its `drm` field.
+        let drm = from_kernel_err_ptr(unsafe {
+            bindings::__devm_drm_dev_alloc(
+                parent.raw_device(),
+                &vtables.driver,
+                size_of::<DeviceData>(),
+                crate::offset_of!(DeviceData, drm) as _,
+            )
+        })? as *mut bindings::drm_device;
+        let dd = drm as *mut DeviceData;
+
+        let size = mode.width as usize * mode.height as usize * BYTES_PER_PIXEL;
+        // SAFETY: `dd` was just allocated and nothing else accesses it yet. Ownership of the
+        // vtables and of `data` is transferred to the release action registered below.
+        unsafe {
+            (*dd).mode = mode;
+            (*dd).vtables = Box::into_raw(vtables);
+            (*dd).data = data.into_foreign();
+            (*dd).shadow = bindings::vzalloc(size as _) as _;
+        }
+
+        // SAFETY: `drm` is valid and the release callback frees what was stored above. On
+        // failure, the callback is called immediately.
+        to_result(unsafe {
+            bindings::__drmm_add_action_or_reset(
+                drm,
+                Some(Callbacks::<T>::release_callback),
+                dd as _,
+                c_str!("rust_drm_release").as_char_ptr(),
+            )
+        })?;
+
+        // SAFETY: `dd` is valid. On error, the partially initialised device is cleaned up when
+        // the parent is unbound.
+        unsafe {
+            if (*dd).shadow.is_null() {
+                return Err(ENOMEM);
+            }
+            let vtables = &*(*dd).vtables;
+
+            to_result(bindings::drmm_mode_config_init(drm))?;
+            let config = &mut (*drm).mode_config;
+            config.min_width = mode.width.into();
+            config.max_width = mode.width.into();
+            config.min_height = mode.height.into();
+            config.max_height = mode.height.into();
+            config.preferred_depth = 24;
+            config.funcs = &vtables.mode_config_funcs;
+
+            (*dd).connector.helper_private = &vtables.connector_helper_funcs as *const _ as _;
+            to_result(bindings::drm_connector_init(
+                drm,
+                &mut (*dd).connector,
+                &vtables.connector_funcs,
+                bindings::DRM_MODE_CONNECTOR_VIRTUAL as _,
+            ))?;
+
+            to_result(bindings::drm_simple_display_pipe_init(
+                drm,
+                &mut (*dd).pipe,
+                &vtables.pipe_funcs,
+                FORMATS.as_ptr(),
+                FORMATS.len() as _,
+                core::ptr::null(),
+                &mut (*dd).connector,
+            ))?;
+            bindings::drm_plane_enable_fb_damage_clips(&mut (*dd).pipe.plane);
+            bindings::drm_mode_config_reset(drm);
+
+            to_result(bindings::drm_dev_register(drm, 0))?;
+            bindings::drm_fbdev_generic_setup(drm, 32);
+        }
+
+        // INVARIANT: The device was initialised and registered above.
+        Ok(Self {
+            drm,
+            _p: PhantomData,
+        })
+    }
+}
+
+// SAFETY: `Registration` does not expose any of its state across threads.
+unsafe impl<T: Driver> Sync for Registration<T> {}
+
+// SAFETY: `Registration` is not restricted to a single thread, and its `T::Data` is also `Send`
+// so it may be moved to different threads.
+unsafe impl<T: Driver> Send for Registration<T> {}
+
+impl<T: Driver> Drop for Registration<T> {
+    /// Unplugs the device and turns the display off.
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, `drm` is valid and registered.
+        unsafe {
+            bindings::drm_dev_unplug(self.drm);
+            bindings::drm_atomic_helper_shutdown(self.drm);
+        }
+    }
+}
@@ -204,6 +204,33 @@ impl Error {
         Error(errno)
     }
 
+    /// Creates an [`Error`] from a negative `errno`, such as one returned by a C function.
+    ///
+    /// This is the checked way for drivers to turn a raw error code into an [`Error`], which is
+    /// always in range. It is a bug to pass an out-of-range `errno`: it is reported and `EINVAL`
+    /// is returned instead. Use [`Error::try_from_errno`] if the value may be out of range.
+    pub fn from_errno(errno: core::ffi::c_int) -> Error {
+        Self::from_kernel_errno(errno)
+    }
+
+    /// Creates an [`Error`] from a negative `errno`, or returns [`None`] if it is out of range.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kernel::prelude::*;
+    /// assert_eq!(Error::try_from_errno(-22), Some(EINVAL));
+    /// assert_eq!(Error::try_from_errno(0), None);
+    /// assert_eq!(Error::try_from_errno(22), None);
+    /// ```
+    pub fn try_from_errno(errno: core::ffi::c_int) -> Option<Error> {
+        if errno < -(bindings::MAX_ERRNO as i32) || errno >= 0 {
+            return None;
+        }
+        // INVARIANT: The check above ensures the type invariant will hold.
+        Some(Error(errno))
+    }
+
     /// Creates an [`Error`] from a kernel error code.
     ///
     /// # Safety
@@ -220,6 +247,14 @@ impl Error {
         self.0
     }
 
+    /// Returns the kernel error code, as a negative `errno` suitable for returning to C.
+    ///
+    /// This is the same as [`Error::to_kernel_errno`], under the name used by the rest of the
+    /// conversion helpers in this module.
+    pub fn to_errno(self) -> core::ffi::c_int {
+        self.0
+    }
+
     /// Returns a string representing the error, if one exists.
     #[cfg(not(testlib))]
     pub fn name(&self) -> Option<&'static CStr> {
@@ -299,6 +334,86 @@ impl From<core::convert::Infallible> for Error {
     }
 }
 
+/// An [`Error`] together with the stage of a multi-step operation that failed.
+///
+/// It makes failures of operations such as [`crate::Module::init`] diagnosable: the stage and the
+/// error are logged by [`ErrorContext::log`], where the caller decides to report the failure.
+/// Converting it into an [`Error`], which is what the `?` operator does in functions that return
+/// [`Result`], drops the stage without logging it. Use [`Context::context`] to attach a stage to a
+/// result.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::error::{code::*, Context, ErrorContext};
+///
+/// fn allocate() -> Result {
+///     Err(ENOMEM)
+/// }
+///
+/// fn init() -> Result {
+///     // Logs "allocating buffers failed: ENOMEM".
+///     allocate()
+///         .context("allocating buffers")
+///         .map_err(ErrorContext::log)?;
+///     Ok(())
+/// }
+///
+/// assert_eq!(init(), Err(ENOMEM));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ErrorContext {
+    error: Error,
+    stage: &'static str,
+}
+
+impl ErrorContext {
+    /// Creates a new error context for `error`, which happened at `stage`.
+    pub fn new(error: Error, stage: &'static str) -> Self {
+        Self { error, stage }
+    }
+
+    /// Returns the error.
+    pub fn error(&self) -> Error {
+        self.error
+    }
+
+    /// Returns the stage at which the error happened.
+    pub fn stage(&self) -> &'static str {
+        self.stage
+    }
+
+    /// Logs the stage and the error, and returns the error.
+    pub fn log(self) -> Error {
+        crate::pr_err!("{}\n", self);
+        self.error
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failed: {:?}", self.stage, self.error)
+    }
+}
+
+impl From<ErrorContext> for Error {
+    fn from(e: ErrorContext) -> Error {
+        e.error
+    }
+}
+
+/// Extension trait to attach an [`ErrorContext`] to results.
+pub trait Context<T> {
+    /// Attaches `stage` to the error, if any.
+    fn context(self, stage: &'static str) -> core::result::Result<T, ErrorContext>;
+}
+
+impl<T, E: Into<Error>> Context<T> for core::result::Result<T, E> {
+    fn context(self, stage: &'static str) -> core::result::Result<T, ErrorContext> {
+        self.map_err(|e| ErrorContext::new(e.into(), stage))
+    }
+}
+
 /// A [`Result`] with an [`Error`] error type.
 ///
 /// To be used as the return type for functions that may fail.
@@ -321,6 +436,14 @@ impl From<core::convert::Infallible> for Error {
 /// just an [`Error`].
 pub type Result<T = ()> = core::result::Result<T, Error>;
 
+crate::deprecated_alias! {
+    /// The former name of [`Result`].
+    ///
+    /// Out-of-tree code written against older versions of this crate may still refer to it.
+    pub type KernelResult<T = ()> = Result<T>;
+    since = "6.3", note = "use `kernel::error::Result` instead",
+}
+
 // # Invariant: `-bindings::MAX_ERRNO` fits in an `i16`.
 crate::static_assert!(bindings::MAX_ERRNO <= -(i16::MIN as i32) as u32);
 
@@ -395,8 +518,6 @@ pub(crate) use from_kernel_result;
 ///     }
 /// }
 /// ```
-// TODO: Remove `dead_code` marker once an in-kernel client is available.
-#[allow(dead_code)]
 pub(crate) fn from_kernel_err_ptr<T>(ptr: *mut T) -> Result<*mut T> {
     // CAST: Casting a pointer to `*const core::ffi::c_void` is always valid.
     let const_ptr: *const core::ffi::c_void = ptr.cast();
@@ -428,3 +549,42 @@ pub fn to_result(err: core::ffi::c_int) -> Result {
         Ok(())
     }
 }
+
+/// Transforms a kernel "error pointer" to a normal pointer.
+///
+/// This is the public counterpart of the helper used within this crate, for drivers that call C
+/// functions returning error pointers directly. A null pointer is not an error pointer, so it is
+/// returned as is.
+///
+/// # Examples
+///
+/// ```ignore
+/// # use kernel::prelude::*;
+/// # use kernel::{bindings, error::from_errno_ptr};
+/// fn get_clock(dev: *mut bindings::device) -> Result<*mut bindings::clk> {
+///     // SAFETY: FFI call.
+///     from_errno_ptr(unsafe { bindings::clk_get(dev, core::ptr::null()) })
+/// }
+/// ```
+pub fn from_errno_ptr<T>(ptr: *mut T) -> Result<*mut T> {
+    from_kernel_err_ptr(ptr)
+}
+
+/// Converts an [`Option`] as returned by a lookup to a [`Result`], using `err` when it is `None`.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::error::opt_to_result;
+///
+/// fn first_even(values: &[u32]) -> Result<u32> {
+///     opt_to_result(values.iter().copied().find(|v| v % 2 == 0), ENOENT)
+/// }
+///
+/// assert_eq!(first_even(&[1, 2, 3]), Ok(2));
+/// assert_eq!(first_even(&[1, 3]), Err(ENOENT));
+/// ```
+pub fn opt_to_result<T>(opt: Option<T>, err: Error) -> Result<T> {
+    opt.ok_or(err)
+}
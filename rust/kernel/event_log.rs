@@ -0,0 +1,261 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Logs of recent events.
+//!
+//! An [`EventLog`] keeps the last few events recorded by a driver, for example the commands it
+//! sent to a device and their results, so that they can be inspected when something goes wrong.
+//! Events can be recorded from any context without taking locks, and the log can be shown in
+//! debugfs with [`debugfs_create_log`].
+
+use crate::prelude::*;
+use core::{
+    cell::UnsafeCell,
+    fmt::{self, Display},
+    mem::MaybeUninit,
+    sync::atomic::{fence, AtomicU64, Ordering},
+};
+
+#[cfg(CONFIG_DEBUG_FS)]
+use crate::{
+    debugfs::{DebugFsDirectory, DebugFsFile, MODE_400},
+    seq_file::{self, SeqFileAdapter, SeqOperations},
+    sync::{Arc, ArcBorrow},
+};
+
+/// An entry of an [`EventLog`].
+struct Slot<T> {
+    /// `2 * pos + 1` while the event at position `pos` is being written, `2 * pos + 2` once it is
+    /// complete, and zero if the slot was never used.
+    seq: AtomicU64,
+    event: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> Slot<T> {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: Self = Self {
+        seq: AtomicU64::new(0),
+        event: UnsafeCell::new(MaybeUninit::uninit()),
+    };
+}
+
+/// A log that keeps the last `N` events recorded in it.
+///
+/// [`EventLog::record`] never blocks and may be called from any context, including interrupt
+/// handlers. When the log is full, recording an event overwrites the oldest one. If a slot is
+/// still being written to when its turn comes again, because an older writer was interrupted for
+/// long enough for the log to wrap around, the newer event is dropped instead.
+///
+/// Readers copy events out while they may be overwritten and discard the copies that turn out to
+/// be inconsistent, which is why events must be [`Copy`]. They should be small, such as an enum
+/// with a few numbers in each variant.
+///
+/// # Examples
+///
+/// ```
+/// use core::fmt;
+/// use kernel::event_log::EventLog;
+///
+/// #[derive(Clone, Copy)]
+/// enum Event {
+///     Reset,
+///     Command { opcode: u8, status: i32 },
+/// }
+///
+/// impl fmt::Display for Event {
+///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         match self {
+///             Event::Reset => write!(f, "reset"),
+///             Event::Command { opcode, status } => write!(f, "command {opcode:#x}: {status}"),
+///         }
+///     }
+/// }
+///
+/// static LOG: EventLog<Event, 64> = EventLog::new();
+///
+/// LOG.record(Event::Reset);
+/// LOG.record(Event::Command { opcode: 0x12, status: 0 });
+/// assert_eq!(LOG.recorded(), 2);
+/// ```
+///
+/// # Invariants
+///
+/// The event of a slot is initialised once its sequence number is even and non-zero.
+pub struct EventLog<T: Copy + Display, const N: usize> {
+    slots: [Slot<T>; N],
+    /// The position of the next event to be recorded.
+    next: AtomicU64,
+    dropped: AtomicU64,
+}
+
+// SAFETY: Events are copied in by `record` and out by readers, possibly on different threads.
+unsafe impl<T: Copy + Display + Send, const N: usize> Sync for EventLog<T, N> {}
+
+// SAFETY: The log only owns events of type `T`, which are `Send`.
+unsafe impl<T: Copy + Display + Send, const N: usize> Send for EventLog<T, N> {}
+
+impl<T: Copy + Display, const N: usize> EventLog<T, N> {
+    /// Creates a new empty log.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `N` is zero. In const context, that is a build error.
+    pub const fn new() -> Self {
+        assert!(N > 0);
+        // INVARIANT: All the sequence numbers are zero.
+        Self {
+            slots: [Slot::EMPTY; N],
+            next: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Records `event`, overwriting the oldest one if the log is full.
+    pub fn record(&self, event: T) {
+        let pos = self.next.fetch_add(1, Ordering::Relaxed);
+        let slot = &self.slots[(pos % N as u64) as usize];
+        let writing = pos * 2 + 1;
+
+        let mut seq = slot.seq.load(Ordering::Relaxed);
+        loop {
+            // Another writer is still busy with this slot, or a newer one already claimed it.
+            if seq % 2 == 1 || seq > writing {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            match slot
+                .seq
+                .compare_exchange_weak(seq, writing, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(current) => seq = current,
+            }
+        }
+
+        // Orders the update of the sequence number before the write of the event, so that readers
+        // that see the new event also see that the slot is being written.
+        fence(Ordering::Release);
+        // SAFETY: The slot was claimed above, so no other writer accesses it until it is released
+        // below. Readers may copy it concurrently but discard the copy in that case.
+        unsafe { (*slot.event.get()).write(event) };
+        // INVARIANT: The event was just initialised.
+        slot.seq.store(writing + 1, Ordering::Release);
+    }
+
+    /// Returns the number of events recorded since the log was created, including the ones that
+    /// have since been overwritten or dropped.
+    pub fn recorded(&self) -> u64 {
+        self.next.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of events that were dropped because their slot was still busy.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Returns a copy of the event at position `pos`, if it is still in the log.
+    fn get(&self, pos: u64) -> Option<T> {
+        let slot = &self.slots[(pos % N as u64) as usize];
+        let complete = pos * 2 + 2;
+
+        if slot.seq.load(Ordering::Acquire) != complete {
+            return None;
+        }
+        // SAFETY: The event is initialised by the type invariants, since the sequence number is
+        // even and non-zero. It may be overwritten concurrently, in which case the sequence number
+        // changes and the copy is discarded without being interpreted.
+        let event = unsafe { core::ptr::read_volatile(slot.event.get()) };
+        // Orders the copy of the event before the second check of the sequence number.
+        fence(Ordering::Acquire);
+        if slot.seq.load(Ordering::Relaxed) != complete {
+            return None;
+        }
+        // SAFETY: The slot was not modified while it was being copied, so the copy is a complete
+        // event.
+        Some(unsafe { event.assume_init() })
+    }
+
+    /// Copies the events that are currently in the log, from the oldest to the newest.
+    ///
+    /// Events that are being written or overwritten while the log is copied are skipped.
+    pub fn snapshot(&self) -> Result<Vec<LogEntry<T>>> {
+        let next = self.next.load(Ordering::Acquire);
+        let first = next.saturating_sub(N as u64);
+        let mut entries = Vec::try_with_capacity((next - first) as usize)?;
+        for pos in first..next {
+            if let Some(event) = self.get(pos) {
+                entries.try_push(LogEntry { pos, event })?;
+            }
+        }
+        Ok(entries)
+    }
+}
+
+impl<T: Copy + Display, const N: usize> Default for EventLog<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An event copied out of an [`EventLog`], together with its position in the log.
+///
+/// It is shown as a line starting with its position.
+pub struct LogEntry<T> {
+    /// The number of events that were recorded before this one.
+    pub pos: u64,
+
+    /// The event.
+    pub event: T,
+}
+
+impl<T: Display> Display for LogEntry<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>8}: {}", self.pos, self.event)
+    }
+}
+
+/// The sequence operations of the debugfs file of an [`EventLog`].
+///
+/// The events are copied when the file is opened, so all the reads of an open file show the same
+/// events.
+#[cfg(CONFIG_DEBUG_FS)]
+pub struct EventLogFile<T, const N: usize>(core::marker::PhantomData<T>);
+
+#[cfg(CONFIG_DEBUG_FS)]
+impl<T: Copy + Display + Send + 'static, const N: usize> SeqOperations for EventLogFile<T, N> {
+    type OpenData = Arc<EventLog<T, N>>;
+    type DataWrapper = Arc<EventLog<T, N>>;
+    type OpenState = Vec<LogEntry<T>>;
+    type IteratorWrapper<'a> = core::slice::Iter<'a, LogEntry<T>>;
+    type Item<'a> = &'a LogEntry<T>;
+
+    fn open(log: &Arc<EventLog<T, N>>) -> Result<Arc<EventLog<T, N>>> {
+        Ok(log.clone())
+    }
+
+    fn open_state(log: ArcBorrow<'_, EventLog<T, N>>) -> Result<Vec<LogEntry<T>>> {
+        log.snapshot()
+    }
+
+    fn start<'a>(
+        _log: ArcBorrow<'a, EventLog<T, N>>,
+        entries: &'a mut Vec<LogEntry<T>>,
+    ) -> Option<Self::IteratorWrapper<'a>> {
+        Some(entries.iter())
+    }
+}
+
+/// Creates a file called `name`, in `parent` or at the root of debugfs, that shows the events in
+/// `log`, from the oldest to the newest.
+///
+/// The file is only readable by its owner, since events may contain sensitive information. Its
+/// operations are in `vtable`, defined by the module that creates the file with
+/// [`crate::file_vtable!`]. It is removed when the returned handle is dropped.
+#[cfg(CONFIG_DEBUG_FS)]
+pub fn debugfs_create_log<T: Copy + Display + Send + 'static, const N: usize>(
+    name: &CStr,
+    parent: Option<Arc<DebugFsDirectory>>,
+    vtable: &'static seq_file::Vtable<EventLogFile<T, N>>,
+    log: Arc<EventLog<T, N>>,
+) -> Result<DebugFsFile<SeqFileAdapter<EventLogFile<T, N>>>> {
+    seq_file::debugfs_create_file(name, parent, MODE_400, vtable, log)
+}
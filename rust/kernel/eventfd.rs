@@ -0,0 +1,113 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Event file descriptors.
+//!
+//! An eventfd is a counter that user space can wait on with `read(2)`, `poll(2)` or `epoll`. A
+//! driver that is given one, usually through an `ioctl`, signals it to report that something
+//! completed, without having to implement `poll` on its own files.
+//!
+//! C header: [`include/linux/eventfd.h`](../../../../include/linux/eventfd.h)
+
+use crate::{bindings, error::from_kernel_err_ptr, file::File, Result};
+use core::ptr::NonNull;
+
+/// A reference to the context of an eventfd.
+///
+/// It remains valid, and can be signalled, after user space closes the file descriptor it was
+/// obtained from.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::eventfd::EventFdCtx;
+///
+/// struct Job {
+///     done: EventFdCtx,
+/// }
+///
+/// fn submit(fd: u32) -> Result<Job> {
+///     // The file descriptor is passed by user space, for example as the argument of an `ioctl`.
+///     Ok(Job {
+///         done: EventFdCtx::from_fd(fd)?,
+///     })
+/// }
+///
+/// fn complete(job: Job) {
+///     // User space waiting on the eventfd reads 1.
+///     job.done.signal();
+/// }
+/// ```
+///
+/// # Invariants
+///
+/// `ptr` is a valid eventfd context, and we hold a reference to it.
+pub struct EventFdCtx {
+    ptr: NonNull<bindings::eventfd_ctx>,
+}
+
+// SAFETY: The context is reference counted and can be signalled and released from any thread.
+unsafe impl Send for EventFdCtx {}
+
+// SAFETY: `signal` takes the lock of the context, so it may be called concurrently.
+unsafe impl Sync for EventFdCtx {}
+
+impl EventFdCtx {
+    /// Returns the context of the eventfd open as `fd` in the current process.
+    ///
+    /// Returns `EBADF` if `fd` is not open, and `EINVAL` if it is not an eventfd.
+    pub fn from_fd(fd: u32) -> Result<Self> {
+        // SAFETY: FFI call, `eventfd_ctx_fdget` checks that `fd` is an eventfd.
+        let ptr = from_kernel_err_ptr(unsafe { bindings::eventfd_ctx_fdget(fd as _) })?;
+        // INVARIANT: `eventfd_ctx_fdget` returned a valid context with a reference that we own.
+        Ok(Self {
+            // SAFETY: `from_kernel_err_ptr` only returns valid pointers, which are not null.
+            ptr: unsafe { NonNull::new_unchecked(ptr) },
+        })
+    }
+
+    /// Returns the context of the eventfd `file`.
+    ///
+    /// Returns `EINVAL` if `file` is not an eventfd.
+    pub fn from_file(file: &File) -> Result<Self> {
+        // SAFETY: `file` is valid, and `eventfd_ctx_fileget` checks that it is an eventfd.
+        let ptr = from_kernel_err_ptr(unsafe { bindings::eventfd_ctx_fileget(file.0.get()) })?;
+        // INVARIANT: `eventfd_ctx_fileget` returned a valid context with a reference that we own.
+        Ok(Self {
+            // SAFETY: `from_kernel_err_ptr` only returns valid pointers, which are not null.
+            ptr: unsafe { NonNull::new_unchecked(ptr) },
+        })
+    }
+
+    /// Adds one to the counter of the eventfd, which wakes up the tasks waiting on it.
+    ///
+    /// This never sleeps, so it may be called from atomic context, including interrupt handlers.
+    pub fn signal(&self) {
+        self.signal_n(1);
+    }
+
+    /// Adds `n` to the counter of the eventfd, which wakes up the tasks waiting on it.
+    ///
+    /// The counter saturates rather than overflows, so the amount that was actually added is
+    /// returned. Like [`EventFdCtx::signal`], this never sleeps.
+    pub fn signal_n(&self, n: u64) -> u64 {
+        // SAFETY: By the type invariants, `ptr` is a valid context.
+        unsafe { bindings::eventfd_signal(self.ptr.as_ptr(), n) }
+    }
+}
+
+impl Clone for EventFdCtx {
+    fn clone(&self) -> Self {
+        // SAFETY: By the type invariants, `ptr` is a valid context.
+        unsafe { bindings::eventfd_ctx_get(self.ptr.as_ptr()) };
+        // INVARIANT: We just took a new reference to the context.
+        Self { ptr: self.ptr }
+    }
+}
+
+impl Drop for EventFdCtx {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, we hold a reference to the context.
+        unsafe { bindings::eventfd_ctx_put(self.ptr.as_ptr()) };
+    }
+}
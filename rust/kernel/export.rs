@@ -0,0 +1,140 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Exporting symbols to other modules.
+//!
+//! [`export_symbol!`] is the Rust equivalent of `EXPORT_SYMBOL_GPL` and `EXPORT_SYMBOL_NS_GPL`: it
+//! emits the `__ksymtab` entry that lets modules loaded later, written in Rust or in C, link
+//! against a function or a static of the module. [`module_import_ns!`] is the equivalent of
+//! `MODULE_IMPORT_NS`, for modules that use symbols exported in a namespace.
+//!
+//! Exported symbols have no CRC, since Rust code cannot be built with `CONFIG_MODVERSIONS`.
+//!
+//! C header: [`include/linux/export.h`](../../../../include/linux/export.h)
+
+/// Converts a `.modinfo` entry to the NUL-terminated array that is stored in the section.
+///
+/// `N` must be one more than the length of `entry`, which is checked at compile time when it is
+/// used to initialise a static.
+///
+/// Public but hidden since it should only be used from public macros.
+#[doc(hidden)]
+pub const fn modinfo<const N: usize>(entry: &str) -> [u8; N] {
+    let bytes = entry.as_bytes();
+    assert!(bytes.len() + 1 == N);
+    let mut out = [0u8; N];
+    let mut i = 0;
+    while i < bytes.len() {
+        out[i] = bytes[i];
+        i += 1;
+    }
+    out
+}
+
+/// Exports a function or a static to other modules, for GPL-compatible modules only.
+///
+/// The item must be declared with `#[no_mangle]`, so that other modules find it under its name,
+/// and functions must use the C ABI if C code calls them. The symbol may optionally be exported
+/// in a namespace, in which case the modules that use it must import the namespace with
+/// [`module_import_ns!`].
+///
+/// The other modules refer to the symbol by name: a Rust module declares it in an `extern "C"`
+/// block, and a C module with a prototype in a header.
+///
+/// # Examples
+///
+/// ```ignore
+/// use core::sync::atomic::{AtomicU64, Ordering};
+/// use kernel::export_symbol;
+///
+/// #[no_mangle]
+/// pub static RUST_SAMPLE_EVENTS: AtomicU64 = AtomicU64::new(0);
+/// export_symbol!(RUST_SAMPLE_EVENTS);
+///
+/// /// Records an event on behalf of another module.
+/// #[no_mangle]
+/// pub extern "C" fn rust_sample_record_event() -> u64 {
+///     RUST_SAMPLE_EVENTS.fetch_add(1, Ordering::Relaxed) + 1
+/// }
+/// export_symbol!(rust_sample_record_event, "RUST_SAMPLE");
+/// ```
+#[macro_export]
+macro_rules! export_symbol {
+    ($name:ident $(,)?) => {
+        $crate::export_symbol!($name, "");
+    };
+    ($name:ident, $ns:literal $(,)?) => {
+        core::arch::global_asm!(concat!(
+            ".pushsection \"__ksymtab_strings\",\"aMS\",%progbits,1\n",
+            "__kstrtab_", stringify!($name), ":\n",
+            ".asciz \"", stringify!($name), "\"\n",
+            "__kstrtabns_", stringify!($name), ":\n",
+            ".asciz \"", $ns, "\"\n",
+            ".popsection\n",
+        ));
+        $crate::export_symbol!(@ksymtab $name);
+    };
+    (@ksymtab $name:ident) => {
+        // Same layout as `struct kernel_symbol`, with offsets relative to each field.
+        #[cfg(CONFIG_HAVE_ARCH_PREL32_RELOCATIONS)]
+        core::arch::global_asm!(concat!(
+            ".pushsection \"___ksymtab_gpl+", stringify!($name), "\",\"a\"\n",
+            ".balign 4\n",
+            "__ksymtab_", stringify!($name), ":\n",
+            ".long ", stringify!($name), " - .\n",
+            ".long __kstrtab_", stringify!($name), " - .\n",
+            ".long __kstrtabns_", stringify!($name), " - .\n",
+            ".popsection\n",
+        ));
+
+        // Same layout as `struct kernel_symbol`, with absolute addresses.
+        #[cfg(all(not(CONFIG_HAVE_ARCH_PREL32_RELOCATIONS), CONFIG_64BIT))]
+        core::arch::global_asm!(concat!(
+            ".pushsection \"___ksymtab_gpl+", stringify!($name), "\",\"a\"\n",
+            ".balign 8\n",
+            "__ksymtab_", stringify!($name), ":\n",
+            ".quad ", stringify!($name), "\n",
+            ".quad __kstrtab_", stringify!($name), "\n",
+            ".quad __kstrtabns_", stringify!($name), "\n",
+            ".popsection\n",
+        ));
+
+        #[cfg(all(not(CONFIG_HAVE_ARCH_PREL32_RELOCATIONS), not(CONFIG_64BIT)))]
+        core::arch::global_asm!(concat!(
+            ".pushsection \"___ksymtab_gpl+", stringify!($name), "\",\"a\"\n",
+            ".balign 4\n",
+            "__ksymtab_", stringify!($name), ":\n",
+            ".long ", stringify!($name), "\n",
+            ".long __kstrtab_", stringify!($name), "\n",
+            ".long __kstrtabns_", stringify!($name), "\n",
+            ".popsection\n",
+        ));
+    };
+}
+
+/// Allows the module to use the symbols exported in the namespace `ns`.
+///
+/// This is the Rust equivalent of `MODULE_IMPORT_NS`. Without it, `modpost` warns about the use of
+/// symbols exported in the namespace, and the module fails to load if namespaces are enforced.
+///
+/// # Examples
+///
+/// ```ignore
+/// kernel::module_import_ns!("RUST_SAMPLE");
+///
+/// extern "C" {
+///     fn rust_sample_record_event() -> u64;
+/// }
+/// ```
+#[macro_export]
+macro_rules! module_import_ns {
+    ($ns:literal $(,)?) => {
+        // Built-in code is not checked against namespaces, so only modules need the entry.
+        #[cfg(MODULE)]
+        const _: () = {
+            const ENTRY: &str = concat!("import_ns=", $ns);
+            #[link_section = ".modinfo"]
+            #[used]
+            static IMPORT_NS: [u8; ENTRY.len() + 1] = $crate::export::modinfo(ENTRY);
+        };
+    };
+}
@@ -8,14 +8,16 @@
 use crate::{
     bindings,
     cred::Credential,
+    delayed_shutdown::{Quiesce, QuiesceGuard},
     error::{code::*, from_kernel_result, Error, Result},
+    fs::INode,
     io_buffer::{IoBufferReader, IoBufferWriter},
     iov_iter::IovIter,
     mm,
     sync::CondVar,
     types::ForeignOwnable,
     user_ptr::{UserSlicePtr, UserSlicePtrReader, UserSlicePtrWriter},
-    ARef, AlwaysRefCounted,
+    ARef, AlwaysRefCounted, ThisModule,
 };
 use core::convert::{TryFrom, TryInto};
 use core::{cell::UnsafeCell, marker, mem, ptr};
@@ -104,6 +106,30 @@ pub mod flags {
     pub const O_RDWR: u32 = bindings::O_RDWR;
 }
 
+/// Modes of a [`File`], which describe how it may be accessed.
+///
+/// They are derived by the VFS from the flags passed to `open(2)` and the capabilities of the
+/// file, and correspond to the `FMODE_*` constants of C.
+pub mod mode {
+    /// File is open for reading.
+    pub const FMODE_READ: u32 = bindings::FMODE_READ;
+
+    /// File is open for writing.
+    pub const FMODE_WRITE: u32 = bindings::FMODE_WRITE;
+
+    /// File is seekable.
+    pub const FMODE_LSEEK: u32 = bindings::FMODE_LSEEK;
+
+    /// File can be accessed using `pread(2)`.
+    pub const FMODE_PREAD: u32 = bindings::FMODE_PREAD;
+
+    /// File can be accessed using `pwrite(2)`.
+    pub const FMODE_PWRITE: u32 = bindings::FMODE_PWRITE;
+
+    /// File is opened for execution with `sys_execve` or `sys_uselib`.
+    pub const FMODE_EXEC: u32 = bindings::FMODE_EXEC;
+}
+
 /// Wraps the kernel's `struct file`.
 ///
 /// # Invariants
@@ -146,6 +172,27 @@ impl File {
         unsafe { core::ptr::addr_of!((*self.0.get()).f_pos).read() as _ }
     }
 
+    /// Returns the inode of the file.
+    ///
+    /// # Examples
+    ///
+    /// A read callback that reports the end of the file once the position reaches its size:
+    ///
+    /// ```
+    /// use kernel::file::File;
+    ///
+    /// fn at_end(file: &File) -> bool {
+    ///     file.pos() >= file.inode().size()
+    /// }
+    /// ```
+    pub fn inode(&self) -> &INode {
+        // SAFETY: The file is valid because the shared reference guarantees a nonzero refcount.
+        let ptr = unsafe { core::ptr::addr_of!((*self.0.get()).f_inode).read() };
+        // SAFETY: The file holds a reference to its inode, which never changes, so it remains
+        // valid for the lifetime of `self`.
+        unsafe { INode::from_ptr(ptr) }
+    }
+
     /// Returns the credentials of the task that originally opened the file.
     pub fn cred(&self) -> &Credential {
         // SAFETY: The file is valid because the shared reference guarantees a nonzero refcount.
@@ -163,6 +210,30 @@ impl File {
         // SAFETY: The file is valid because the shared reference guarantees a nonzero refcount.
         unsafe { core::ptr::addr_of!((*self.0.get()).f_flags).read() }
     }
+
+    /// Returns the access mode the file was opened with, that is, one of [`flags::O_RDONLY`],
+    /// [`flags::O_WRONLY`] and [`flags::O_RDWR`].
+    pub fn access_mode(&self) -> u32 {
+        self.flags() & flags::O_ACCMODE
+    }
+
+    /// Returns the mode of the file.
+    ///
+    /// The mode is a combination of the constants in [`mode`].
+    pub fn mode(&self) -> u32 {
+        // SAFETY: The file is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { core::ptr::addr_of!((*self.0.get()).f_mode).read() as _ }
+    }
+
+    /// Returns `true` if the file is open for reading.
+    pub fn is_readable(&self) -> bool {
+        self.mode() & mode::FMODE_READ != 0
+    }
+
+    /// Returns `true` if the file is open for writing.
+    pub fn is_writable(&self) -> bool {
+        self.mode() & mode::FMODE_WRITE != 0
+    }
 }
 
 // SAFETY: The type invariants guarantee that `File` is always ref-counted.
@@ -292,23 +363,29 @@ impl<A: OpenAdapter<T::OpenData>, T: Operations> OperationsVtable<A, T> {
     ///
     /// # Safety
     ///
-    /// The returned value of `A::convert` must be a valid non-null pointer and
+    /// The returned value of `A::convert` must be a valid pointer or null, and
     /// `T:open` must return a valid non-null pointer on an `Ok` result.
     unsafe extern "C" fn open_callback(
         inode: *mut bindings::inode,
         file: *mut bindings::file,
     ) -> core::ffi::c_int {
         from_kernel_result! {
-            // SAFETY: `A::convert` must return a valid non-null pointer that
-            // should point to data in the inode or file that lives longer
-            // than the following use of `T::open`.
+            // SAFETY: `A::convert` must return a valid pointer or null. If it is valid, it
+            // should point to data in the inode or file that lives longer than the following use
+            // of `T::open`.
             let arg = unsafe { A::convert(inode, file) };
+            if arg.is_null() {
+                // This is a bug in the adapter, but it only affects this file, so it is reported
+                // as an error rather than taking the kernel down.
+                debug_assert!(false, "open adapter returned no data");
+                return Err(EINVAL);
+            }
             // SAFETY: The C contract guarantees that `file` is valid. Additionally,
             // `fileref` never outlives this function, so it is guaranteed to be
             // valid.
             let fileref = unsafe { File::from_ptr(file) };
-            // SAFETY: `arg` was previously returned by `A::convert` and must
-            // be a valid non-null pointer.
+            // SAFETY: `arg` was previously returned by `A::convert` and checked to be non-null,
+            // so it is valid.
             let ptr = T::open(unsafe { &*arg }, fileref)?.into_foreign();
             // SAFETY: The C contract guarantees that `private_data` is available
             // for implementers of the file operations (no other C code accesses
@@ -334,15 +411,13 @@ impl<A: OpenAdapter<T::OpenData>, T: Operations> OperationsVtable<A, T> {
             // references to `file` have been released, so we know it can't be called while this
             // function is running.
             let f = unsafe { T::Data::borrow((*file).private_data) };
+            // SAFETY: `f` is borrowed from `file`, and the guard is dropped before returning.
+            let _guard = unsafe { Self::enter_quiesce(&f)? };
             // No `FMODE_UNSIGNED_OFFSET` support, so `offset` must be in [0, 2^63).
             // See <https://github.com/fishinabarrel/linux-kernel-module-rust/pull/113>.
-            let read = T::read(
-                f,
-                unsafe { File::from_ptr(file) },
-                &mut data,
-                unsafe { *offset }.try_into()?,
-            )?;
-            unsafe { (*offset) += bindings::loff_t::try_from(read).unwrap() };
+            let mut pos = unsafe { *offset }.try_into()?;
+            let read = T::read(f, unsafe { File::from_ptr(file) }, &mut data, &mut pos)?;
+            unsafe { *offset = pos.try_into()? };
             Ok(read as _)
         }
     }
@@ -361,13 +436,11 @@ impl<A: OpenAdapter<T::OpenData>, T: Operations> OperationsVtable<A, T> {
             // references to `file` have been released, so we know it can't be called while this
             // function is running.
             let f = unsafe { T::Data::borrow((*file).private_data) };
-            let read = T::read(
-                f,
-                unsafe { File::from_ptr(file) },
-                &mut iter,
-                offset.try_into()?,
-            )?;
-            unsafe { (*iocb).ki_pos += bindings::loff_t::try_from(read).unwrap() };
+            // SAFETY: `f` is borrowed from `file`, and the guard is dropped before returning.
+            let _guard = unsafe { Self::enter_quiesce(&f)? };
+            let mut pos = offset.try_into()?;
+            let read = T::read(f, unsafe { File::from_ptr(file) }, &mut iter, &mut pos)?;
+            unsafe { (*iocb).ki_pos = pos.try_into()? };
             Ok(read as _)
         }
     }
@@ -387,15 +460,13 @@ impl<A: OpenAdapter<T::OpenData>, T: Operations> OperationsVtable<A, T> {
             // references to `file` have been released, so we know it can't be called while this
             // function is running.
             let f = unsafe { T::Data::borrow((*file).private_data) };
+            // SAFETY: `f` is borrowed from `file`, and the guard is dropped before returning.
+            let _guard = unsafe { Self::enter_quiesce(&f)? };
             // No `FMODE_UNSIGNED_OFFSET` support, so `offset` must be in [0, 2^63).
             // See <https://github.com/fishinabarrel/linux-kernel-module-rust/pull/113>.
-            let written = T::write(
-                f,
-                unsafe { File::from_ptr(file) },
-                &mut data,
-                unsafe { *offset }.try_into()?,
-            )?;
-            unsafe { (*offset) += bindings::loff_t::try_from(written).unwrap() };
+            let mut pos = unsafe { *offset }.try_into()?;
+            let written = T::write(f, unsafe { File::from_ptr(file) }, &mut data, &mut pos)?;
+            unsafe { *offset = pos.try_into()? };
             Ok(written as _)
         }
     }
@@ -414,13 +485,11 @@ impl<A: OpenAdapter<T::OpenData>, T: Operations> OperationsVtable<A, T> {
             // references to `file` have been released, so we know it can't be called while this
             // function is running.
             let f = unsafe { T::Data::borrow((*file).private_data) };
-            let written = T::write(
-                f,
-                unsafe { File::from_ptr(file) },
-                &mut iter,
-                offset.try_into()?,
-            )?;
-            unsafe { (*iocb).ki_pos += bindings::loff_t::try_from(written).unwrap() };
+            // SAFETY: `f` is borrowed from `file`, and the guard is dropped before returning.
+            let _guard = unsafe { Self::enter_quiesce(&f)? };
+            let mut pos = offset.try_into()?;
+            let written = T::write(f, unsafe { File::from_ptr(file) }, &mut iter, &mut pos)?;
+            unsafe { (*iocb).ki_pos = pos.try_into()? };
             Ok(written as _)
         }
     }
@@ -429,6 +498,9 @@ impl<A: OpenAdapter<T::OpenData>, T: Operations> OperationsVtable<A, T> {
         _inode: *mut bindings::inode,
         file: *mut bindings::file,
     ) -> core::ffi::c_int {
+        // SAFETY: The VFS only calls `release` once, for files whose `open` callback succeeded,
+        // once all the other callbacks have returned. So `private_data` holds the value that
+        // `open_callback` got from `T::Data::into_foreign`, and nothing else uses it anymore.
         let ptr = mem::replace(unsafe { &mut (*file).private_data }, ptr::null_mut());
         T::release(unsafe { T::Data::from_foreign(ptr as _) }, unsafe {
             File::from_ptr(file)
@@ -454,6 +526,8 @@ impl<A: OpenAdapter<T::OpenData>, T: Operations> OperationsVtable<A, T> {
             // references to `file` have been released, so we know it can't be called while this
             // function is running.
             let f = unsafe { T::Data::borrow((*file).private_data) };
+            // SAFETY: `f` is borrowed from `file`, and the guard is dropped before returning.
+            let _guard = unsafe { Self::enter_quiesce(&f)? };
             let off = T::seek(f, unsafe { File::from_ptr(file) }, off)?;
             Ok(off as bindings::loff_t)
         }
@@ -471,6 +545,8 @@ impl<A: OpenAdapter<T::OpenData>, T: Operations> OperationsVtable<A, T> {
             // references to `file` have been released, so we know it can't be called while this
             // function is running.
             let f = unsafe { T::Data::borrow((*file).private_data) };
+            // SAFETY: `f` is borrowed from `file`, and the guard is dropped before returning.
+            let _guard = unsafe { Self::enter_quiesce(&f)? };
             let mut cmd = IoctlCommand::new(cmd as _, arg as _);
             let ret = T::ioctl(f, unsafe { File::from_ptr(file) }, &mut cmd)?;
             Ok(ret as _)
@@ -489,6 +565,8 @@ impl<A: OpenAdapter<T::OpenData>, T: Operations> OperationsVtable<A, T> {
             // references to `file` have been released, so we know it can't be called while this
             // function is running.
             let f = unsafe { T::Data::borrow((*file).private_data) };
+            // SAFETY: `f` is borrowed from `file`, and the guard is dropped before returning.
+            let _guard = unsafe { Self::enter_quiesce(&f)? };
             let mut cmd = IoctlCommand::new(cmd as _, arg as _);
             let ret = T::compat_ioctl(f, unsafe { File::from_ptr(file) }, &mut cmd)?;
             Ok(ret as _)
@@ -506,6 +584,8 @@ impl<A: OpenAdapter<T::OpenData>, T: Operations> OperationsVtable<A, T> {
             // references to `file` have been released, so we know it can't be called while this
             // function is running.
             let f = unsafe { T::Data::borrow((*file).private_data) };
+            // SAFETY: `f` is borrowed from `file`, and the guard is dropped before returning.
+            let _guard = unsafe { Self::enter_quiesce(&f)? };
 
             // SAFETY: The C API guarantees that `vma` is valid for the duration of this call.
             // `area` only lives within this call, so it is guaranteed to be valid.
@@ -534,8 +614,10 @@ impl<A: OpenAdapter<T::OpenData>, T: Operations> OperationsVtable<A, T> {
             // references to `file` have been released, so we know it can't be called while this
             // function is running.
             let f = unsafe { T::Data::borrow((*file).private_data) };
+            // SAFETY: `f` is borrowed from `file`, and the guard is dropped before returning.
+            let _guard = unsafe { Self::enter_quiesce(&f)? };
             let res = T::fsync(f, unsafe { File::from_ptr(file) }, start, end, datasync)?;
-            Ok(res.try_into().unwrap())
+            Ok(res.try_into()?)
         }
     }
 
@@ -548,6 +630,11 @@ impl<A: OpenAdapter<T::OpenData>, T: Operations> OperationsVtable<A, T> {
         // callback, which the C API guarantees that will be called only when all references to
         // `file` have been released, so we know it can't be called while this function is running.
         let f = unsafe { T::Data::borrow((*file).private_data) };
+        // SAFETY: `f` is borrowed from `file`, and the guard is dropped before returning.
+        let _guard = match unsafe { Self::enter_quiesce(&f) } {
+            Ok(guard) => guard,
+            Err(_) => return bindings::POLLERR,
+        };
         match T::poll(f, unsafe { File::from_ptr(file) }, unsafe {
             &PollTable::from_ptr(wait)
         }) {
@@ -556,6 +643,30 @@ impl<A: OpenAdapter<T::OpenData>, T: Operations> OperationsVtable<A, T> {
         }
     }
 
+    /// Enters the [`Quiesce`] of an open file, if its operations have one.
+    ///
+    /// The guard is not tied to the borrow of `data`, so that `data` can then be passed to the
+    /// operation. Fails with `ENODEV` once the quiesce is being drained.
+    ///
+    /// # Safety
+    ///
+    /// `data` must be borrowed from the `private_data` of an open file, and the guard must be
+    /// dropped before the callback that borrowed it returns.
+    unsafe fn enter_quiesce<'a>(
+        data: &<T::Data as ForeignOwnable>::Borrowed<'a>,
+    ) -> Result<Option<QuiesceGuard<'a>>> {
+        match T::quiesce(data) {
+            Some(quiesce) => {
+                let quiesce: *const Quiesce = quiesce;
+                // SAFETY: The quiesce is part of the data of the file, which is only freed by
+                // `release`. By the safety requirements, that cannot happen until the callback
+                // returns, after the guard is dropped.
+                unsafe { &*quiesce }.enter().map(Some).ok_or(ENODEV)
+            }
+            None => Ok(None),
+        }
+    }
+
     const VTABLE: bindings::file_operations = bindings::file_operations {
         open: Some(Self::open_callback),
         release: Some(Self::release_callback),
@@ -644,6 +755,81 @@ impl<A: OpenAdapter<T::OpenData>, T: Operations> OperationsVtable<A, T> {
     }
 }
 
+/// The file operations of the files handled by `T`, with the module that owns them.
+///
+/// The VFS holds a reference to the module while the files are open, so that it is not unloaded
+/// until the last one is released. The operations must therefore be static, and belong to the
+/// module that implements `T`. They are usually defined by [`file_vtable!`].
+///
+/// `A` is the adapter of the registration that the files are opened through. Registrations name
+/// their operations with a type alias, such as [`crate::miscdev::Vtable`].
+///
+/// [`file_vtable!`]: crate::file_vtable
+pub struct Vtable<A, T> {
+    fops: bindings::file_operations,
+    _p: marker::PhantomData<(A, T)>,
+}
+
+// SAFETY: The file operations are never modified after they are created.
+unsafe impl<A, T> Sync for Vtable<A, T> {}
+
+impl<A: OpenAdapter<T::OpenData>, T: Operations> Vtable<A, T> {
+    /// Creates the file operations of the files handled by `T`, which belong to `module`.
+    ///
+    /// `module` must be the module that implements `T`, so that open files keep it loaded.
+    pub const fn new(module: &'static ThisModule) -> Self {
+        let mut fops = OperationsVtable::<A, T>::VTABLE;
+        fops.owner = module.0;
+        Self {
+            fops,
+            _p: marker::PhantomData,
+        }
+    }
+
+    /// Returns the file operations, for registrations that use the adapter `A`.
+    pub(crate) fn fops(&'static self) -> &'static bindings::file_operations {
+        &self.fops
+    }
+}
+
+/// Defines the static file operations `$vtable` of the calling module, and returns a reference to
+/// them.
+///
+/// `$vtable` is the type of the operations taken by a registration, such as
+/// [`miscdev::Vtable<T>`](crate::miscdev::Vtable), or any other type that has a
+/// `const fn new(&'static ThisModule)`. The operations are defined in a static, so `$vtable`
+/// cannot depend on generic parameters.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::{file::{self, File}, miscdev, sync::Arc};
+///
+/// struct Device;
+///
+/// #[vtable]
+/// impl file::Operations for Device {
+///     type OpenData = Arc<Device>;
+///
+///     fn open(_device: &Arc<Device>, _file: &File) -> Result {
+///         Ok(())
+///     }
+/// }
+///
+/// fn register(device: Arc<Device>) -> Result<Pin<Box<miscdev::Registration<Device>>>> {
+///     let vtable = kernel::file_vtable!(miscdev::Vtable<Device>);
+///     miscdev::Registration::new_pinned(vtable, fmt!("example"), device)
+/// }
+/// ```
+#[macro_export]
+macro_rules! file_vtable {
+    ($vtable:ty) => {{
+        static VTABLE: $vtable = <$vtable>::new(&crate::THIS_MODULE);
+        &VTABLE
+    }};
+}
+
 /// Allows the handling of ioctls defined with the `_IO`, `_IOR`, `_IOW`, and `_IOWR` macros.
 ///
 /// For each macro, there is a handler function that takes the appropriate types as arguments.
@@ -759,8 +945,8 @@ pub trait OpenAdapter<T: Sync> {
     /// # Safety
     ///
     /// This function must be called only when [`struct file_operations::open`] is being called for
-    /// a file that was registered by the implementer. The returned pointer must be valid and
-    /// not-null.
+    /// a file that was registered by the implementer. The returned pointer must either be valid,
+    /// or null if the data cannot be found, in which case opening the file fails with `EINVAL`.
     unsafe fn convert(_inode: *mut bindings::inode, _file: *mut bindings::file) -> *const T;
 }
 
@@ -782,7 +968,31 @@ pub trait Operations {
 
     /// Creates a new instance of this file.
     ///
+    /// `file` gives access to the flags and the mode the file is being opened with, so that
+    /// unsupported ones can be rejected here rather than when the file is used.
+    ///
     /// Corresponds to the `open` function pointer in `struct file_operations`.
+    ///
+    /// # Examples
+    ///
+    /// A read-only file that refuses to be opened for writing or to be truncated:
+    ///
+    /// ```
+    /// # use kernel::prelude::*;
+    /// use kernel::file::{self, flags, File};
+    ///
+    /// struct Status;
+    ///
+    /// #[vtable]
+    /// impl file::Operations for Status {
+    ///     fn open(_context: &(), file: &File) -> Result {
+    ///         if file.is_writable() || file.flags() & flags::O_TRUNC != 0 {
+    ///             return Err(EACCES);
+    ///         }
+    ///         Ok(())
+    ///     }
+    /// }
+    /// ```
     fn open(context: &Self::OpenData, file: &File) -> Result<Self::Data>;
 
     /// Cleans up after the last reference to the file goes away.
@@ -790,29 +1000,53 @@ pub trait Operations {
     /// Note that context data is moved, so it will be freed automatically unless the
     /// implementation moves it elsewhere.
     ///
+    /// It is called exactly once for each successful call to [`Operations::open`], after all the
+    /// other operations on the file have returned. That is also the case when the VFS fails to
+    /// complete the open after [`Operations::open`] succeeded, so cleanup such as decrementing a
+    /// count of open files can be done here unconditionally.
+    ///
     /// Corresponds to the `release` function pointer in `struct file_operations`.
     fn release(_data: Self::Data, _file: &File) {}
 
+    /// Returns the [`Quiesce`] that tracks the operations in flight on the file, if any.
+    ///
+    /// When it returns one, all the other operations on open files, apart from
+    /// [`Operations::release`], run inside [`Quiesce::enter`]. Once the quiesce is drained, they
+    /// fail with `ENODEV` without being called, and `poll` reports `POLLERR`, so the state they
+    /// use can be torn down while files are still open.
+    fn quiesce<'a>(_data: &'a <Self::Data as ForeignOwnable>::Borrowed<'_>) -> Option<&'a Quiesce> {
+        None
+    }
+
     /// Reads data from this file to the caller's buffer.
     ///
+    /// `offset` is the position of the file when the read starts. Implementations are responsible
+    /// for updating it, which for regular files means advancing it by the number of bytes read.
+    /// Files without a meaningful position, such as streams, can leave it unchanged. The new
+    /// position is only stored if the read succeeds.
+    ///
     /// Corresponds to the `read` and `read_iter` function pointers in `struct file_operations`.
     fn read(
         _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
         _file: &File,
         _writer: &mut impl IoBufferWriter,
-        _offset: u64,
+        _offset: &mut u64,
     ) -> Result<usize> {
         Err(EINVAL)
     }
 
     /// Writes data from the caller's buffer to this file.
     ///
+    /// `offset` is the position of the file when the write starts. As with [`Operations::read`],
+    /// implementations are responsible for updating it, and the new position is only stored if
+    /// the write succeeds.
+    ///
     /// Corresponds to the `write` and `write_iter` function pointers in `struct file_operations`.
     fn write(
         _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
         _file: &File,
         _reader: &mut impl IoBufferReader,
-        _offset: u64,
+        _offset: &mut u64,
     ) -> Result<usize> {
         Err(EINVAL)
     }
@@ -886,3 +1120,13 @@ pub trait Operations {
         Ok(bindings::POLLIN | bindings::POLLOUT | bindings::POLLRDNORM | bindings::POLLWRNORM)
     }
 }
+
+crate::deprecated_alias! {
+    /// The former name of [`Operations`].
+    ///
+    /// It can be used in bounds. Implementations of it must be renamed to implement [`Operations`],
+    /// since they conflict with the implementation of this trait for all the types that implement
+    /// [`Operations`].
+    pub trait FileOperations = Operations;
+    since = "6.3", note = "implement `kernel::file::Operations` instead",
+}
@@ -0,0 +1,255 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Tracking of objects owned by foreign code.
+//!
+//! With `CONFIG_RUST_DEBUG_ASSERTIONS`, the [`ForeignOwnable`] implementations record every
+//! object handed to C code with [`ForeignOwnable::into_foreign`], together with its type and the
+//! location of the call, and forget it again in [`ForeignOwnable::from_foreign`]. A pointer that is
+//! borrowed or converted back without being live, for example because its object was already
+//! freed, is reported right away. Objects that are never converted back, that is, leaks, stay in
+//! the table, which can be dumped with [`debugfs_create`].
+//!
+//! The table has a fixed size. Once it overflows, further objects are not tracked, and unknown
+//! pointers are no longer reported since they may be among them.
+//!
+//! [`ForeignOwnable`]: crate::types::ForeignOwnable
+//! [`ForeignOwnable::into_foreign`]: crate::types::ForeignOwnable::into_foreign
+//! [`ForeignOwnable::from_foreign`]: crate::types::ForeignOwnable::from_foreign
+
+use crate::{
+    pr_err, pr_warn,
+    print::{HashedPtr, RestrictedPtr},
+};
+use core::{
+    ffi::c_void,
+    fmt,
+    panic::Location,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+};
+
+#[cfg(CONFIG_DEBUG_FS)]
+use crate::{
+    debugfs::{DebugFsDirectory, DebugFsFile},
+    seq_file::{self, SeqFileAdapter, SeqOperations},
+    str::CStr,
+    sync::Arc,
+    Mode, Result,
+};
+
+/// The maximum number of objects that are tracked at the same time.
+const CAPACITY: usize = 4096;
+
+/// The value of [`Entry::ptr`] for an unused entry.
+const FREE: usize = 0;
+
+/// The value of [`Entry::ptr`] for an entry that is being filled in.
+const BUSY: usize = 1;
+
+/// A live object.
+///
+/// An entry is claimed by changing `ptr` from [`FREE`] to [`BUSY`], and published by storing the
+/// pointer once the other fields are set. It is released by changing `ptr` back to [`FREE`].
+struct Entry {
+    ptr: AtomicUsize,
+    /// The `core::any::type_name` function of the type of the object.
+    type_name: AtomicUsize,
+    location: AtomicPtr<Location<'static>>,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const UNUSED: Entry = Entry {
+    ptr: AtomicUsize::new(FREE),
+    type_name: AtomicUsize::new(0),
+    location: AtomicPtr::new(ptr::null_mut()),
+};
+
+static TABLE: [Entry; CAPACITY] = [UNUSED; CAPACITY];
+
+/// Set once an object could not be tracked because the table was full.
+static OVERFLOWED: AtomicBool = AtomicBool::new(false);
+
+/// Returns the index of the entry to start looking from for `ptr`.
+fn slot(ptr: usize) -> usize {
+    // Objects are at least word-aligned, so the low bits carry no information.
+    (ptr >> 4) % CAPACITY
+}
+
+/// Returns the entry that tracks `ptr`, if any.
+fn find(ptr: usize) -> Option<&'static Entry> {
+    let start = slot(ptr);
+    (0..CAPACITY)
+        .map(|i| &TABLE[(start + i) % CAPACITY])
+        .find(|e| e.ptr.load(Ordering::Acquire) == ptr)
+}
+
+fn report(what: &str, ptr: *const c_void, type_name: &str, location: &Location<'_>) {
+    pr_err!(
+        "{} of {} ({}) at {}, which is not owned by foreign code\n",
+        what,
+        HashedPtr::new(ptr),
+        type_name,
+        location
+    );
+}
+
+/// Records that `ptr`, an object of type `T`, was handed to foreign code at `location`.
+pub(crate) fn track_into<T>(ptr: *const c_void, location: &'static Location<'static>) {
+    // All zero-sized objects have the same dangling address.
+    if core::mem::size_of::<T>() == 0 || ptr.is_null() {
+        return;
+    }
+
+    let start = slot(ptr as usize);
+    for i in 0..CAPACITY {
+        let entry = &TABLE[(start + i) % CAPACITY];
+        if entry
+            .ptr
+            .compare_exchange(FREE, BUSY, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            let type_name: fn() -> &'static str = core::any::type_name::<T>;
+            entry.type_name.store(type_name as usize, Ordering::Relaxed);
+            entry
+                .location
+                .store(location as *const _ as *mut _, Ordering::Relaxed);
+            entry.ptr.store(ptr as usize, Ordering::Release);
+            return;
+        }
+    }
+
+    if !OVERFLOWED.swap(true, Ordering::Relaxed) {
+        pr_warn!("Too many objects owned by foreign code, tracking is now incomplete\n");
+    }
+}
+
+/// Checks that `ptr`, an object of type `T`, is owned by foreign code when it is borrowed at
+/// `location`.
+pub(crate) fn check_live<T>(ptr: *const c_void, location: &'static Location<'static>) {
+    if core::mem::size_of::<T>() == 0 || OVERFLOWED.load(Ordering::Relaxed) {
+        return;
+    }
+    if find(ptr as usize).is_none() {
+        report("Borrow", ptr, core::any::type_name::<T>(), location);
+    }
+}
+
+/// Records that `ptr`, an object of type `T`, was taken back from foreign code at `location`.
+pub(crate) fn track_from<T>(ptr: *const c_void, location: &'static Location<'static>) {
+    if core::mem::size_of::<T>() == 0 {
+        return;
+    }
+
+    // Another entry for the same pointer may be published concurrently only if the object is
+    // freed and reallocated in the meantime, in which case either entry may be released.
+    let released = find(ptr as usize).map_or(false, |entry| {
+        entry
+            .ptr
+            .compare_exchange(ptr as usize, FREE, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+    });
+    if !released && !OVERFLOWED.load(Ordering::Relaxed) {
+        report("Release", ptr, core::any::type_name::<T>(), location);
+    }
+}
+
+/// An object owned by foreign code, as shown by the file created by [`debugfs_create`].
+pub struct LiveObject {
+    ptr: usize,
+    type_name: &'static str,
+    location: &'static Location<'static>,
+}
+
+impl LiveObject {
+    /// Returns the object tracked by `entry`, if it is in use.
+    fn from_entry(entry: &Entry) -> Option<Self> {
+        let ptr = entry.ptr.load(Ordering::Acquire);
+        if ptr == FREE || ptr == BUSY {
+            return None;
+        }
+        let type_name = entry.type_name.load(Ordering::Relaxed);
+        let location = entry.location.load(Ordering::Relaxed);
+        // SAFETY: The fields of a published entry are set before its pointer, with release
+        // ordering, and they are never cleared afterwards, so they hold a `core::any::type_name`
+        // function and a static location. If the entry is reused concurrently, they may be those
+        // of another object.
+        unsafe {
+            Some(Self {
+                ptr,
+                type_name: core::mem::transmute::<usize, fn() -> &'static str>(type_name)(),
+                location: &*location,
+            })
+        }
+    }
+}
+
+impl fmt::Display for LiveObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ptr = RestrictedPtr::new(self.ptr as *const c_void);
+        writeln!(f, "{} {} {}", ptr, self.type_name, self.location)
+    }
+}
+
+/// An iterator over the objects that are currently owned by foreign code.
+///
+/// Objects that are handed over or taken back during the iteration may or may not be included.
+pub struct LiveObjects {
+    next: usize,
+}
+
+impl Iterator for LiveObjects {
+    type Item = LiveObject;
+
+    fn next(&mut self) -> Option<LiveObject> {
+        while self.next < CAPACITY {
+            let entry = &TABLE[self.next];
+            self.next += 1;
+            if let Some(object) = LiveObject::from_entry(entry) {
+                return Some(object);
+            }
+        }
+        None
+    }
+}
+
+/// Returns an iterator over the objects that are currently owned by foreign code.
+pub fn live_objects() -> LiveObjects {
+    LiveObjects { next: 0 }
+}
+
+/// The records of the file created by [`debugfs_create`].
+#[cfg(CONFIG_DEBUG_FS)]
+pub struct LiveObjectsFile;
+
+#[cfg(CONFIG_DEBUG_FS)]
+impl SeqOperations for LiveObjectsFile {
+    type DataWrapper = ();
+    type IteratorWrapper<'a> = LiveObjects;
+    type Item<'a> = LiveObject;
+
+    fn open(_: &()) -> Result {
+        Ok(())
+    }
+
+    fn start<'a>(_: (), _: &'a mut ()) -> Option<LiveObjects> {
+        Some(live_objects())
+    }
+}
+
+/// Creates a file called `name` with permissions `mode`, in `parent` or at the root of debugfs,
+/// that lists the objects currently owned by foreign code.
+///
+/// Each line shows the address of an object, restricted like with `%pK`, its type, and where it
+/// was handed over.
+#[cfg(CONFIG_DEBUG_FS)]
+pub fn debugfs_create(
+    name: &CStr,
+    parent: Option<Arc<DebugFsDirectory>>,
+    mode: Mode,
+) -> Result<DebugFsFile<SeqFileAdapter<LiveObjectsFile>>> {
+    // `LiveObjectsFile` is implemented by this crate alone, so its files do not keep any module
+    // loaded.
+    static VTABLE: seq_file::Vtable<LiveObjectsFile> =
+        seq_file::Vtable::new(&crate::BUILTIN_MODULE);
+    seq_file::debugfs_create_file(name, parent, mode, &VTABLE, ())
+}
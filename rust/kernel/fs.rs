@@ -2,16 +2,23 @@
 
 //! File systems.
 //!
-//! C headers: [`include/linux/fs.h`](../../../../include/linux/fs.h)
+//! C headers: [`include/linux/fs.h`](../../../../include/linux/fs.h) and
+//! [`include/linux/kernel_read_file.h`](../../../../include/linux/kernel_read_file.h)
 
 use crate::{
-    bindings, error::code::*, error::from_kernel_result, str::CStr, to_result,
-    types::ForeignOwnable, AlwaysRefCounted, Error, Result, ScopeGuard, ThisModule,
+    bindings,
+    error::code::*,
+    error::{from_kernel_err_ptr, from_kernel_result},
+    str::CStr,
+    to_result,
+    types::{ForeignOwnable, Mode},
+    AlwaysRefCounted, Error, Result, ScopeGuard, ThisModule,
 };
 use alloc::boxed::Box;
 use core::{
     cell::UnsafeCell,
     marker::{PhantomData, PhantomPinned},
+    ops::Deref,
     pin::Pin,
     ptr,
 };
@@ -541,6 +548,184 @@ impl Drop for Registration {
     }
 }
 
+/// A mounted instance of a file system.
+///
+/// The mount is released when this object is dropped, unless it has been handed over to the VFS,
+/// for example by returning it from an automount callback such as
+/// [`crate::debugfs::Automount::automount`].
+///
+/// # Invariants
+///
+/// `ptr` is a valid mount that we own a reference to.
+pub struct VfsMount {
+    ptr: *mut bindings::vfsmount,
+}
+
+impl VfsMount {
+    /// Mounts a new instance of the file system registered with `fs`, to be attached on
+    /// `mountpoint`.
+    ///
+    /// `name` is the name of the mount source, as shown in `/proc/mounts`. Returns `EINVAL` if
+    /// `fs` is not registered.
+    ///
+    /// # Safety
+    ///
+    /// `mountpoint` must be a valid dentry, and it must remain so for the duration of the call.
+    pub unsafe fn submount(
+        mountpoint: *mut bindings::dentry,
+        fs: &Registration,
+        name: &CStr,
+    ) -> Result<Self> {
+        if !fs.is_registered {
+            return Err(EINVAL);
+        }
+
+        // SAFETY: `mountpoint` is valid by the safety requirements, `fs` is a registered file
+        // system type, and `name` is a valid C string.
+        let ptr = from_kernel_err_ptr(unsafe {
+            bindings::vfs_submount(mountpoint, fs.fs.get(), name.as_char_ptr(), ptr::null_mut())
+        })?;
+        // INVARIANT: `vfs_submount` returned a new mount that we own a reference to.
+        Ok(Self { ptr })
+    }
+
+    /// Gives up ownership of the mount and returns a raw pointer to it.
+    pub(crate) fn into_raw(self) -> *mut bindings::vfsmount {
+        let ptr = self.ptr;
+        core::mem::forget(self);
+        ptr
+    }
+}
+
+impl Drop for VfsMount {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, we own a reference to the mount.
+        unsafe { bindings::mntput(self.ptr) };
+    }
+}
+
+// SAFETY: A reference to a mount can be released from any thread.
+unsafe impl Send for VfsMount {}
+
+/// The purpose of a file read with [`read_file`].
+///
+/// It is passed to the security hooks, which LSMs and IMA use to decide whether the read is
+/// allowed, and to measure or appraise the contents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadFileId {
+    /// Any other file, such as calibration data.
+    Unknown,
+
+    /// Firmware for a device.
+    Firmware,
+
+    /// A policy, for example the rules of a security module.
+    Policy,
+
+    /// An X.509 certificate.
+    X509Certificate,
+}
+
+impl ReadFileId {
+    fn as_raw(self) -> bindings::kernel_read_file_id {
+        match self {
+            Self::Unknown => bindings::kernel_read_file_id_READING_UNKNOWN,
+            Self::Firmware => bindings::kernel_read_file_id_READING_FIRMWARE,
+            Self::Policy => bindings::kernel_read_file_id_READING_POLICY,
+            Self::X509Certificate => bindings::kernel_read_file_id_READING_X509_CERTIFICATE,
+        }
+    }
+}
+
+/// The contents of a file read with [`read_file`].
+///
+/// # Invariants
+///
+/// `ptr` points to a buffer of at least `len` initialised bytes, allocated with `vmalloc`, that we
+/// own.
+pub struct FileContents {
+    ptr: ptr::NonNull<u8>,
+    len: usize,
+}
+
+impl Deref for FileContents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: By the type invariants, `ptr` points to `len` initialised bytes that we own.
+        unsafe { core::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for FileContents {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, the buffer was allocated with `vmalloc` and we own it.
+        unsafe { bindings::vfree(self.ptr.as_ptr().cast()) };
+    }
+}
+
+// SAFETY: The buffer is plain memory owned by `FileContents`, so it can be freed from any thread.
+unsafe impl Send for FileContents {}
+
+// SAFETY: Shared references only allow reading the buffer.
+unsafe impl Sync for FileContents {}
+
+/// Reads the whole file at `path` into memory.
+///
+/// This is meant for modules that load a configuration file at init, such as a policy table or
+/// calibration data. The read goes through `kernel_read_file_from_path`, so the `kernel_read_file`
+/// and `kernel_post_read_file` security hooks are called with `id`, as they are for firmware.
+///
+/// `path` is resolved in the mount namespace of the current task, and relative paths are relative
+/// to its working directory. Returns `EFBIG` if the file is larger than `max_size` bytes, and
+/// `EINVAL` if it is empty. It may sleep, so it must be called from process context.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::{c_str, fs};
+///
+/// fn load_table() -> Result<Vec<u32>> {
+///     let contents = fs::read_file(
+///         c_str!("/lib/firmware/example/table.bin"),
+///         4096,
+///         fs::ReadFileId::Policy,
+///     )?;
+///     let mut table = Vec::new();
+///     for entry in contents.chunks_exact(4) {
+///         table.try_push(u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]))?;
+///     }
+///     Ok(table)
+/// }
+/// ```
+pub fn read_file(path: &CStr, max_size: usize, id: ReadFileId) -> Result<FileContents> {
+    crate::debug_assert_sleepable!();
+    let mut buf = ptr::null_mut();
+    // SAFETY: `path` is a valid C string, and `buf` is a valid pointer to a null pointer, so the
+    // buffer is allocated by the callee. A null `file_size` requires the whole file to be read.
+    let ret = unsafe {
+        bindings::kernel_read_file_from_path(
+            path.as_char_ptr(),
+            0,
+            &mut buf,
+            max_size,
+            ptr::null_mut(),
+            id.as_raw(),
+        )
+    };
+    if ret < 0 {
+        return Err(Error::from_kernel_errno(ret as _));
+    }
+    let ptr = ptr::NonNull::new(buf.cast()).ok_or(ENOMEM)?;
+    // INVARIANT: On success, `buf` points to a `vmalloc` buffer of `ret` bytes that were read
+    // from the file, which is now owned by the caller.
+    Ok(FileContents {
+        ptr,
+        len: ret as usize,
+    })
+}
+
 /// State of [`NewSuperBlock`] that indicates that [`NewSuperBlock::init`] needs to be called
 /// eventually.
 pub struct NeedsInit;
@@ -716,6 +901,60 @@ pub struct SuperBlock<T: Type + ?Sized>(
 #[repr(transparent)]
 pub struct INode(pub(crate) UnsafeCell<bindings::inode>);
 
+impl INode {
+    /// Creates a reference to an [`INode`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `ptr` is valid and remains valid for the lifetime of the
+    /// returned [`INode`] instance.
+    pub(crate) unsafe fn from_ptr<'a>(ptr: *const bindings::inode) -> &'a INode {
+        // SAFETY: The safety requirements guarantee the validity of the dereference, while the
+        // `INode` type being transparent makes the cast ok.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the inode number, which identifies the inode within its file system.
+    pub fn ino(&self) -> u64 {
+        // SAFETY: The inode is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { core::ptr::addr_of!((*self.0.get()).i_ino).read() as _ }
+    }
+
+    /// Returns the mode of the inode, that is, its file type and permissions.
+    pub fn mode(&self) -> Mode {
+        // SAFETY: The inode is valid because the shared reference guarantees a nonzero refcount.
+        Mode::from_int(unsafe { core::ptr::addr_of!((*self.0.get()).i_mode).read() })
+    }
+
+    /// Returns `true` if the inode is a directory.
+    pub fn is_dir(&self) -> bool {
+        u32::from(self.mode().as_int()) & bindings::S_IFMT == bindings::S_IFDIR
+    }
+
+    /// Returns `true` if the inode is a regular file.
+    pub fn is_regular_file(&self) -> bool {
+        u32::from(self.mode().as_int()) & bindings::S_IFMT == bindings::S_IFREG
+    }
+
+    /// Returns the size of the file, in bytes.
+    ///
+    /// It may change concurrently, for example while the file is being written to.
+    pub fn size(&self) -> u64 {
+        // SAFETY: The inode is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { bindings::i_size_read(self.0.get()) as _ }
+    }
+
+    /// Returns the private data of the inode (`struct inode::i_private`).
+    ///
+    /// Its meaning depends on the file system, for example debugfs stores there the data passed
+    /// when the file was created, so it can only be interpreted by the code that created the
+    /// inode.
+    pub fn private_data(&self) -> *mut core::ffi::c_void {
+        // SAFETY: The inode is valid because the shared reference guarantees a nonzero refcount.
+        unsafe { core::ptr::addr_of!((*self.0.get()).i_private).read() }
+    }
+}
+
 // SAFETY: The type invariants guarantee that `INode` is always ref-counted.
 unsafe impl AlwaysRefCounted for INode {
     fn inc_ref(&self) {
@@ -0,0 +1,375 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! In-place initialisation of pinned objects.
+//!
+//! Synchronisation primitives such as [`Mutex`], [`SpinLock`] and [`CondVar`] must be initialised
+//! once they are at their final location in memory. Doing that by hand requires `unsafe` code to
+//! create the primitive uninitialised, to project the pinned container onto the field, and to call
+//! the initialisation macro.
+//!
+//! This module instead describes how to initialise an object as a [`PinInit`] value, which is run
+//! directly on the final memory location, for example by [`InPlaceInit::try_pin_init`]. The
+//! [`pin_init!`] macro combines initialisers of the fields of a struct into an initialiser of the
+//! struct, and [`new_mutex!`], [`new_spinlock!`] and [`new_condvar!`] create initialisers of the
+//! synchronisation primitives.
+//!
+//! # Examples
+//!
+//! ```
+//! use kernel::{init::InPlaceInit, new_condvar, new_mutex, pin_init};
+//! use kernel::sync::{CondVar, Mutex, UniqueArc};
+//!
+//! struct Queue {
+//!     len: Mutex<usize>,
+//!     not_empty: CondVar,
+//!     name: &'static str,
+//! }
+//!
+//! let queue = UniqueArc::try_pin_init(pin_init!(Queue {
+//!     len <- new_mutex!(0, "Queue::len"),
+//!     not_empty <- new_condvar!("Queue::not_empty"),
+//!     name: "example",
+//! }))?;
+//! assert_eq!(*queue.len.lock(), 0);
+//! # Ok::<(), Error>(())
+//! ```
+//!
+//! [`Mutex`]: crate::sync::Mutex
+//! [`SpinLock`]: crate::sync::SpinLock
+//! [`CondVar`]: crate::sync::CondVar
+
+use crate::{error::Error, sync::UniqueArc};
+use alloc::{alloc::AllocError, boxed::Box};
+use core::{marker::PhantomData, mem::MaybeUninit, pin::Pin};
+
+/// An initialiser for a pinned object of type `T`.
+///
+/// It is consumed by running it on the memory location where the object lives from then on.
+///
+/// # Safety
+///
+/// When [`PinInit::__pinned_init`] returns `Ok(())`, the object at `slot` must be fully
+/// initialised. When it returns an error, `slot` must be left uninitialised, that is, everything
+/// that was initialised must have been dropped again.
+pub unsafe trait PinInit<T, E = Error>: Sized {
+    /// Initialises the object at `slot`.
+    ///
+    /// # Safety
+    ///
+    /// `slot` must be valid for writes and properly aligned. If initialisation succeeds, the
+    /// object must not be moved until it is dropped.
+    unsafe fn __pinned_init(self, slot: *mut T) -> Result<(), E>;
+}
+
+/// An initialiser implemented by a closure, see [`pin_init_from_closure`].
+struct InitClosure<F, T, E>(F, PhantomData<fn(*mut T) -> Result<(), E>>);
+
+// SAFETY: The requirements are upheld by the caller of `pin_init_from_closure`.
+unsafe impl<T, E, F> PinInit<T, E> for InitClosure<F, T, E>
+where
+    F: FnOnce(*mut T) -> Result<(), E>,
+{
+    unsafe fn __pinned_init(self, slot: *mut T) -> Result<(), E> {
+        (self.0)(slot)
+    }
+}
+
+/// Creates an initialiser from a closure that initialises the object at the given location.
+///
+/// # Safety
+///
+/// The closure must satisfy the requirements of [`PinInit`]: when it returns `Ok(())` the object
+/// must be fully initialised, and when it returns an error the object must be uninitialised.
+pub unsafe fn pin_init_from_closure<T, E>(
+    f: impl FnOnce(*mut T) -> Result<(), E>,
+) -> impl PinInit<T, E> {
+    InitClosure(f, PhantomData)
+}
+
+/// Drops a field that was initialised by [`pin_init!`] if the initialisation of a later field
+/// fails.
+///
+/// Public but hidden since it should only be used from public macros.
+#[doc(hidden)]
+pub struct DropGuard<T>(*mut T);
+
+impl<T> DropGuard<T> {
+    /// Creates a new guard for `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to an initialised object that is not dropped by anything else unless the
+    /// guard is forgotten.
+    #[doc(hidden)]
+    pub unsafe fn new(ptr: *mut T) -> Self {
+        Self(ptr)
+    }
+}
+
+impl<T> Drop for DropGuard<T> {
+    fn drop(&mut self) {
+        // SAFETY: By the safety requirements of `new`, the object is initialised and owned by the
+        // guard.
+        unsafe { core::ptr::drop_in_place(self.0) };
+    }
+}
+
+/// Smart pointers that can allocate memory and initialise an object in it in place.
+pub trait InPlaceInit<T>: Sized {
+    /// Allocates memory and initialises a pinned object in it with `init`.
+    fn try_pin_init<E: From<AllocError>>(init: impl PinInit<T, E>) -> Result<Pin<Self>, E>;
+}
+
+impl<T> InPlaceInit<T> for Box<T> {
+    fn try_pin_init<E: From<AllocError>>(init: impl PinInit<T, E>) -> Result<Pin<Self>, E> {
+        let mut this = Box::<T>::try_new_uninit()?;
+        // SAFETY: The memory is valid for writes and aligned, and it is freed without dropping
+        // the object if initialisation fails.
+        unsafe { init.__pinned_init(this.as_mut_ptr())? };
+        // SAFETY: The object was initialised above, and `MaybeUninit<T>` has the layout of `T`.
+        let this = unsafe { Box::from_raw(Box::into_raw(this).cast::<T>()) };
+        // SAFETY: The object is not moved out of the box until it is dropped.
+        Ok(unsafe { Pin::new_unchecked(this) })
+    }
+}
+
+impl<T> InPlaceInit<T> for UniqueArc<T> {
+    fn try_pin_init<E: From<AllocError>>(init: impl PinInit<T, E>) -> Result<Pin<Self>, E> {
+        let mut this = UniqueArc::<T>::try_new_uninit().map_err(|_| AllocError)?;
+        // SAFETY: The memory is valid for writes and aligned, and it is freed without dropping
+        // the object if initialisation fails.
+        unsafe { init.__pinned_init(this.as_mut_ptr())? };
+        // SAFETY: The object was initialised above. `UniqueArc<MaybeUninit<T>>` only holds a
+        // pointer to the shared allocation, which has the same layout for `MaybeUninit<T>` and
+        // `T`.
+        let this = unsafe { core::mem::transmute::<UniqueArc<MaybeUninit<T>>, UniqueArc<T>>(this) };
+        // SAFETY: The object is not moved out of the allocation until it is dropped.
+        Ok(unsafe { Pin::new_unchecked(this) })
+    }
+}
+
+/// Creates an initialiser of a struct from values and initialisers of its fields.
+///
+/// Fields given as `field: value` are moved into place, and fields given as `field <- init` are
+/// initialised in place by `init`, which must implement [`PinInit`]. All the fields of the struct
+/// must be given. Expressions may use the `?` operator to fail with an [`Error`], in which case
+/// the fields initialised so far are dropped again.
+///
+/// See the [module-level documentation](self) for an example.
+#[macro_export]
+macro_rules! pin_init {
+    ($t:path { $($fields:tt)* }) => {{
+        let init = move |slot: *mut $t| -> ::core::result::Result<(), $crate::error::Error> {
+            $crate::__pin_init_fields!(slot, $($fields)*);
+            // Ensure that every field of the struct was given, so that the object is fully
+            // initialised. This closure is never called.
+            #[allow(unreachable_code, clippy::diverging_sub_expression)]
+            let _ = || -> $t { $crate::__pin_init_fields!(@check $t, [], $($fields)*) };
+            Ok(())
+        };
+        // SAFETY: `__pin_init_fields!` initialises all fields (checked above) and drops the ones
+        // it initialised if a later one fails.
+        unsafe { $crate::init::pin_init_from_closure(init) }
+    }};
+}
+
+/// Initialises the fields given to [`pin_init!`].
+///
+/// Public but hidden since it should only be used from public macros.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __pin_init_fields {
+    ($slot:ident $(,)?) => {};
+    ($slot:ident, $f:ident <- $e:expr $(, $($rest:tt)*)?) => {
+        let init = $e;
+        // SAFETY: `slot` is valid for writes and the field is not initialised yet.
+        unsafe {
+            $crate::init::PinInit::__pinned_init(init, ::core::ptr::addr_of_mut!((*$slot).$f))?
+        };
+        $crate::__pin_init_fields!(@guard $slot, $f, $($($rest)*)?);
+    };
+    ($slot:ident, $f:ident : $e:expr $(, $($rest:tt)*)?) => {
+        let value = $e;
+        // SAFETY: `slot` is valid for writes and the field is not initialised yet.
+        unsafe { ::core::ptr::addr_of_mut!((*$slot).$f).write(value) };
+        $crate::__pin_init_fields!(@guard $slot, $f, $($($rest)*)?);
+    };
+    (@guard $slot:ident, $f:ident, $($rest:tt)*) => {
+        // SAFETY: The field was initialised above and is only dropped by the guard.
+        let guard = unsafe { $crate::init::DropGuard::new(::core::ptr::addr_of_mut!((*$slot).$f)) };
+        $crate::__pin_init_fields!($slot, $($rest)*);
+        // All the fields were initialised, so the object now owns this one.
+        ::core::mem::forget(guard);
+    };
+    (@check $t:path, [$($done:ident)*] $(,)?) => {
+        $t { $($done: ::core::panic!(),)* }
+    };
+    (@check $t:path, [$($done:ident)*], $f:ident $(<-)? $(:)? $e:expr $(, $($rest:tt)*)?) => {
+        $crate::__pin_init_fields!(@check $t, [$($done)* $f], $($($rest)*)?)
+    };
+}
+
+/// Creates an initialiser of a [`Mutex`] protecting `value`.
+///
+/// The optional name is used for lockdep; it defaults to the location of the call.
+///
+/// [`Mutex`]: crate::sync::Mutex
+#[macro_export]
+macro_rules! new_mutex {
+    ($value:expr $(,)?) => {
+        $crate::new_mutex!(
+            $value,
+            ::core::concat!(::core::file!(), ":", ::core::line!())
+        )
+    };
+    ($value:expr, $name:expr $(,)?) => {{
+        let value = $value;
+        let init = move |slot: *mut $crate::sync::Mutex<_>| {
+            // SAFETY: The mutex is initialised right after being written to its final location,
+            // which `slot` is by the safety requirements of `PinInit`.
+            unsafe {
+                slot.write($crate::sync::Mutex::new(value));
+                $crate::init_with_lockdep!(::core::pin::Pin::new_unchecked(&mut *slot), $name);
+            }
+            Ok(())
+        };
+        // SAFETY: The closure always initialises the mutex.
+        unsafe { $crate::init::pin_init_from_closure::<_, $crate::error::Error>(init) }
+    }};
+}
+
+/// Creates an initialiser of a [`SpinLock`] protecting `value`.
+///
+/// The optional name is used for lockdep; it defaults to the location of the call.
+///
+/// [`SpinLock`]: crate::sync::SpinLock
+#[macro_export]
+macro_rules! new_spinlock {
+    ($value:expr $(,)?) => {
+        $crate::new_spinlock!(
+            $value,
+            ::core::concat!(::core::file!(), ":", ::core::line!())
+        )
+    };
+    ($value:expr, $name:expr $(,)?) => {{
+        let value = $value;
+        let init = move |slot: *mut $crate::sync::SpinLock<_>| {
+            // SAFETY: The spinlock is initialised right after being written to its final
+            // location, which `slot` is by the safety requirements of `PinInit`.
+            unsafe {
+                slot.write($crate::sync::SpinLock::new(value));
+                $crate::init_with_lockdep!(::core::pin::Pin::new_unchecked(&mut *slot), $name);
+            }
+            Ok(())
+        };
+        // SAFETY: The closure always initialises the spinlock.
+        unsafe { $crate::init::pin_init_from_closure::<_, $crate::error::Error>(init) }
+    }};
+}
+
+/// Creates an initialiser of a [`CondVar`].
+///
+/// The optional name is used for lockdep; it defaults to the location of the call.
+///
+/// [`CondVar`]: crate::sync::CondVar
+#[macro_export]
+macro_rules! new_condvar {
+    ($(,)?) => {
+        $crate::new_condvar!(::core::concat!(::core::file!(), ":", ::core::line!()))
+    };
+    ($name:expr $(,)?) => {{
+        let init = move |slot: *mut $crate::sync::CondVar| {
+            // SAFETY: The condition variable is initialised right after being written to its
+            // final location, which `slot` is by the safety requirements of `PinInit`.
+            unsafe {
+                slot.write($crate::sync::CondVar::new());
+                $crate::init_with_lockdep!(::core::pin::Pin::new_unchecked(&mut *slot), $name);
+            }
+            Ok(())
+        };
+        // SAFETY: The closure always initialises the condition variable.
+        unsafe { $crate::init::pin_init_from_closure::<_, $crate::error::Error>(init) }
+    }};
+}
+
+/// Creates an initialiser of an [`Srcu`] domain.
+///
+/// The optional name is used for lockdep; it defaults to the location of the call.
+///
+/// [`Srcu`]: crate::sync::Srcu
+#[macro_export]
+macro_rules! new_srcu {
+    ($(,)?) => {
+        $crate::new_srcu!(::core::concat!(::core::file!(), ":", ::core::line!()))
+    };
+    ($name:expr $(,)?) => {{
+        static CLASS: $crate::sync::LockClassKey = $crate::sync::LockClassKey::new();
+        $crate::sync::Srcu::new($crate::c_str!($name), &CLASS)
+    }};
+}
+
+/// Creates an initialiser of a [`Completion`].
+///
+/// The optional name is used for lockdep; it defaults to the location of the call.
+///
+/// [`Completion`]: crate::sync::Completion
+#[macro_export]
+macro_rules! new_completion {
+    ($(,)?) => {
+        $crate::new_completion!(::core::concat!(::core::file!(), ":", ::core::line!()))
+    };
+    ($name:expr $(,)?) => {{
+        static CLASS: $crate::sync::LockClassKey = $crate::sync::LockClassKey::new();
+        $crate::sync::Completion::new($crate::c_str!($name), &CLASS)
+    }};
+}
+
+/// Creates an initialiser of a [`Timer`] with the given data and [`TimerFlags`].
+///
+/// The optional name is used for lockdep; it defaults to the location of the call.
+///
+/// [`Timer`]: crate::timer::Timer
+/// [`TimerFlags`]: crate::timer::TimerFlags
+#[macro_export]
+macro_rules! new_timer {
+    ($data:expr, $flags:expr $(,)?) => {
+        $crate::new_timer!(
+            $data,
+            $flags,
+            ::core::concat!(::core::file!(), ":", ::core::line!())
+        )
+    };
+    ($data:expr, $flags:expr, $name:expr $(,)?) => {{
+        static CLASS: $crate::sync::LockClassKey = $crate::sync::LockClassKey::new();
+        $crate::timer::Timer::new($data, $flags, $crate::c_str!($name), &CLASS)
+    }};
+}
+
+/// Creates an initialiser of a [`Snapshot`] holding `value`.
+///
+/// The optional name is used for lockdep; it defaults to the location of the call.
+///
+/// [`Snapshot`]: crate::sync::Snapshot
+#[macro_export]
+macro_rules! new_snapshot {
+    ($value:expr $(,)?) => {
+        $crate::new_snapshot!(
+            $value,
+            ::core::concat!(::core::file!(), ":", ::core::line!())
+        )
+    };
+    ($value:expr, $name:expr $(,)?) => {{
+        let value = $value;
+        let init = move |slot: *mut $crate::sync::Snapshot<_>| {
+            // SAFETY: The snapshot is initialised right after being written to its final
+            // location, which `slot` is by the safety requirements of `PinInit`.
+            unsafe {
+                slot.write($crate::sync::Snapshot::new(value));
+                $crate::init_with_lockdep!(::core::pin::Pin::new_unchecked(&mut *slot), $name);
+            }
+            Ok(())
+        };
+        // SAFETY: The closure always initialises the snapshot.
+        unsafe { $crate::init::pin_init_from_closure::<_, $crate::error::Error>(init) }
+    }};
+}
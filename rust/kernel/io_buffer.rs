@@ -2,7 +2,7 @@
 
 //! Buffers used in IO.
 
-use crate::Result;
+use crate::{error::code::*, Result};
 use alloc::vec::Vec;
 use core::mem::{size_of, MaybeUninit};
 
@@ -29,14 +29,48 @@ pub trait IoBufferReader {
     ///
     /// Returns `EFAULT` if the address does not currently point to mapped, readable memory.
     fn read_all(&mut self) -> Result<Vec<u8>> {
-        let mut data = Vec::<u8>::new();
-        data.try_resize(self.len(), 0)?;
+        let len = self.len();
+        let mut data = Vec::<u8>::try_with_capacity(len)?;
+        let mut buf = ReadBuf::new(&mut data.spare_capacity_mut()[..len]);
+        self.read_into(&mut buf, len)?;
 
-        // SAFETY: The output buffer is valid as we just allocated it.
-        unsafe { self.read_raw(data.as_mut_ptr(), data.len())? };
+        // SAFETY: The first `len` bytes of the spare capacity were just initialised.
+        unsafe { data.set_len(len) };
         Ok(data)
     }
 
+    /// Reads `len` bytes from the io buffer and appends them to the filled part of `buf`.
+    ///
+    /// The bytes are copied directly into the unfilled part of `buf`, which does not need to be
+    /// initialised beforehand.
+    ///
+    /// Returns `EFAULT` if `len` is bigger than the remaining size of the io buffer or of `buf`,
+    /// or if the address does not currently point to mapped, readable memory. In that case, the
+    /// filled part of `buf` is left unchanged.
+    fn read_into(&mut self, buf: &mut ReadBuf<'_>, len: usize) -> Result {
+        if len > buf.remaining() {
+            return Err(EFAULT);
+        }
+
+        // SAFETY: The unfilled part of `buf` is valid for writes of at least `len` bytes, and
+        // `MaybeUninit<u8>` has the same layout as `u8`.
+        unsafe { self.read_raw(buf.unfilled().as_mut_ptr().cast(), len)? };
+
+        // SAFETY: `read_raw` initialised the first `len` unfilled bytes.
+        unsafe { buf.advance(len) };
+        Ok(())
+    }
+
+    /// Reads bytes from the io buffer into the uninitialised slice `out`, until it is full.
+    ///
+    /// Returns the initialised slice. Errors are the same as with [`IoBufferReader::read_into`].
+    fn read_uninit<'a>(&mut self, out: &'a mut [MaybeUninit<u8>]) -> Result<&'a mut [u8]> {
+        let len = out.len();
+        let mut buf = ReadBuf::new(out);
+        self.read_into(&mut buf, len)?;
+        Ok(buf.into_filled())
+    }
+
     /// Reads a byte slice from the io buffer.
     ///
     /// Returns `EFAULT` if the byte slice is bigger than the remaining size of the user slice or
@@ -56,8 +90,121 @@ pub trait IoBufferReader {
     }
 }
 
+/// A buffer that is filled incrementally from an [`IoBufferReader`], without being initialised
+/// first.
+///
+/// It wraps a slice of possibly uninitialised bytes and keeps track of how many of them, from the
+/// start, have been filled. Only the filled part can be accessed.
+///
+/// # Examples
+///
+/// Copies the data written by userspace into a freshly allocated buffer, so that every byte is
+/// written only once:
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use core::mem::MaybeUninit;
+/// use kernel::io_buffer::{IoBufferReader, ReadBuf};
+///
+/// const BLOCK_SIZE: usize = 4096;
+///
+/// /// Reads a block, padded with zeroes if userspace wrote less than a whole block.
+/// fn read_block(data: &mut impl IoBufferReader) -> Result<Box<[u8; BLOCK_SIZE]>> {
+///     let mut block = Box::<[u8; BLOCK_SIZE]>::try_new_uninit()?;
+///     let ptr = block.as_mut_ptr().cast::<[MaybeUninit<u8>; BLOCK_SIZE]>();
+///     // SAFETY: `[MaybeUninit<u8>; N]` has the same layout as `[u8; N]` and may be uninitialised.
+///     let mut buf = ReadBuf::new(unsafe { &mut *ptr });
+///
+///     let len = core::cmp::min(data.len(), buf.remaining());
+///     data.read_into(&mut buf, len)?;
+///     buf.fill_zeros();
+///
+///     // SAFETY: The whole block was filled above.
+///     Ok(unsafe { block.assume_init() })
+/// }
+/// ```
+///
+/// # Invariants
+///
+/// The first `filled` bytes of `buf` are initialised, and `filled <= buf.len()`.
+pub struct ReadBuf<'a> {
+    buf: &'a mut [MaybeUninit<u8>],
+    filled: usize,
+}
+
+impl<'a> ReadBuf<'a> {
+    /// Creates a new empty buffer that is filled in `buf`.
+    pub fn new(buf: &'a mut [MaybeUninit<u8>]) -> Self {
+        // INVARIANT: No bytes are filled yet.
+        Self { buf, filled: 0 }
+    }
+
+    /// Returns the total size of the buffer.
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns the number of bytes that can still be filled.
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.filled
+    }
+
+    /// Returns the filled part of the buffer.
+    pub fn filled(&self) -> &[u8] {
+        // SAFETY: The first `filled` bytes are initialised by the type invariants.
+        unsafe { &*(&self.buf[..self.filled] as *const [MaybeUninit<u8>] as *const [u8]) }
+    }
+
+    /// Returns the filled part of the buffer, mutably.
+    pub fn filled_mut(&mut self) -> &mut [u8] {
+        // SAFETY: The first `filled` bytes are initialised by the type invariants.
+        unsafe { &mut *(&mut self.buf[..self.filled] as *mut [MaybeUninit<u8>] as *mut [u8]) }
+    }
+
+    /// Consumes the buffer and returns its filled part, with the lifetime of the underlying slice.
+    pub fn into_filled(self) -> &'a mut [u8] {
+        let filled = self.filled;
+        // SAFETY: The first `filled` bytes are initialised by the type invariants.
+        unsafe { &mut *(&mut self.buf[..filled] as *mut [MaybeUninit<u8>] as *mut [u8]) }
+    }
+
+    /// Fills the rest of the buffer with zeroes.
+    pub fn fill_zeros(&mut self) {
+        for byte in &mut self.buf[self.filled..] {
+            byte.write(0);
+        }
+        // INVARIANT: All the bytes were just initialised.
+        self.filled = self.buf.len();
+    }
+
+    /// Returns the unfilled part of the buffer.
+    fn unfilled(&mut self) -> &mut [MaybeUninit<u8>] {
+        &mut self.buf[self.filled..]
+    }
+
+    /// Marks the next `len` bytes as filled.
+    ///
+    /// # Safety
+    ///
+    /// The first `len` bytes of the unfilled part must have been initialised, and `len` must not
+    /// exceed [`ReadBuf::remaining`].
+    unsafe fn advance(&mut self, len: usize) {
+        // INVARIANT: The caller guarantees that the bytes are initialised and within bounds.
+        self.filled += len;
+    }
+}
+
 /// Represents a buffer to be written to during IO.
 pub trait IoBufferWriter {
+    /// Whether the buffer is an [`IovIter`], that is, whether a `read` file operation that gets it
+    /// was called through `read_iter` rather than `read`.
+    ///
+    /// The two paths are meant to be handled the same way; this is for statistics and tracing that
+    /// tell them apart.
+    ///
+    /// [`IovIter`]: crate::iov_iter::IovIter
+    const IS_IOV_ITER: bool = false;
+
     /// Returns the number of bytes left to be written into the io buffer.
     ///
     /// Note that even writing less than this number of bytes may fail.
@@ -78,6 +225,55 @@ pub trait IoBufferWriter {
     /// 20 bytes, then EFAULT is returned and the writer is advanced by 20 bytes.
     fn clear(&mut self, len: usize) -> Result;
 
+    /// Writes up to `len` zeroes to the io buffer, and returns how many were written.
+    ///
+    /// Unlike [`IoBufferWriter::clear`], it is not an error for `len` to exceed the remaining
+    /// size of the io buffer: only as many zeroes as fit are written. This is meant for read
+    /// handlers that return holes, such as the part of a sparse device between two chunks of
+    /// data, without having to allocate a zeroed buffer to copy from.
+    ///
+    /// Returns `EFAULT` if the address does not currently point to mapped, writable memory, in
+    /// which case the writer is still advanced past the zeroes that could be written.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kernel::prelude::*;
+    /// use kernel::io_buffer::IoBufferWriter;
+    ///
+    /// /// Reads from a device whose `size` bytes are all zero, except for `data` at `start`.
+    /// fn read_sparse(
+    ///     out: &mut impl IoBufferWriter,
+    ///     offset: &mut u64,
+    ///     size: u64,
+    ///     start: u64,
+    ///     data: &[u8],
+    /// ) -> Result<usize> {
+    ///     let mut total = 0;
+    ///     while !out.is_empty() && *offset < size {
+    ///         let pos = *offset;
+    ///         let len = if pos < start {
+    ///             out.write_zeros((start - pos) as usize)?
+    ///         } else if pos < start + data.len() as u64 {
+    ///             let chunk = &data[(pos - start) as usize..];
+    ///             let len = core::cmp::min(chunk.len(), out.len());
+    ///             out.write_slice(&chunk[..len])?;
+    ///             len
+    ///         } else {
+    ///             out.write_zeros((size - pos) as usize)?
+    ///         };
+    ///         *offset += len as u64;
+    ///         total += len;
+    ///     }
+    ///     Ok(total)
+    /// }
+    /// ```
+    fn write_zeros(&mut self, len: usize) -> Result<usize> {
+        let len = core::cmp::min(len, self.len());
+        self.clear(len)?;
+        Ok(len)
+    }
+
     /// Writes a byte slice into the io buffer.
     ///
     /// Returns `EFAULT` if the byte slice is bigger than the remaining size of the io buffer or if
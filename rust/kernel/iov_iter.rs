@@ -38,6 +38,8 @@ impl IovIter {
 }
 
 impl IoBufferWriter for IovIter {
+    const IS_IOV_ITER: bool = true;
+
     fn len(&self) -> usize {
         self.common_len()
     }
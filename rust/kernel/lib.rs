@@ -34,16 +34,19 @@ compile_error!("Missing kernel configuration for conditional compilation");
 #[cfg(not(test))]
 #[cfg(not(testlib))]
 mod allocator;
+pub mod box_ext;
 mod build_assert;
 pub mod error;
+pub mod init;
 pub mod prelude;
 pub mod print;
-mod static_assert;
+pub mod static_assert;
 #[doc(hidden)]
 pub mod std_vendor;
 pub mod str;
 pub mod sync;
 pub mod types;
+pub mod vec_ext;
 
 #[doc(hidden)]
 pub use bindings;
@@ -51,28 +54,75 @@ pub use macros;
 
 #[cfg(CONFIG_ARM_AMBA)]
 pub mod amba;
+pub mod anon_inode;
+pub mod bitmap;
 pub mod chrdev;
 #[cfg(CONFIG_COMMON_CLK)]
 pub mod clk;
+pub mod config;
+pub mod container;
+pub mod cpu;
 pub mod cred;
+#[cfg(CONFIG_DEBUG_FS)]
+pub mod debugfs;
+pub mod deferred_init;
+pub mod deferred_log;
 pub mod delay;
+pub mod delayed_shutdown;
+pub mod deprecated;
 pub mod device;
+#[cfg(CONFIG_DMA_SHARED_BUFFER)]
+pub mod dma_buf;
 pub mod driver;
+#[cfg(CONFIG_DRM)]
+pub mod drm;
+pub mod event_log;
+#[cfg(CONFIG_EVENTFD)]
+pub mod eventfd;
+pub mod export;
 pub mod file;
+#[cfg(CONFIG_RUST_DEBUG_ASSERTIONS)]
+pub mod foreign_tracking;
 pub mod fs;
 pub mod gpio;
 pub mod hwrng;
 pub mod irq;
 pub mod kasync;
+#[cfg(CONFIG_VFIO_MDEV)]
+pub mod mdev;
 pub mod miscdev;
 pub mod mm;
+pub mod module_global;
+#[cfg(CONFIG_MTD)]
+pub mod mtd;
 #[cfg(CONFIG_NET)]
 pub mod net;
 pub mod pages;
+#[cfg(CONFIG_PERF_EVENTS)]
+pub mod perf;
 pub mod power;
+pub mod preempt;
+#[cfg(CONFIG_PROC_FS)]
+pub mod proc_fs;
+pub mod ratelimit;
 pub mod revocable;
+#[cfg(CONFIG_RPMSG)]
+pub mod rpmsg;
 pub mod security;
+pub mod seq_file;
+pub mod shrinker;
+pub mod signal;
+#[cfg(CONFIG_SND_PCM)]
+pub mod sound;
+pub mod stages;
+pub mod stats;
+pub mod sysinfo;
 pub mod task;
+pub mod timer;
+#[cfg(CONFIG_TTY)]
+pub mod tty;
+#[cfg(CONFIG_UIO)]
+pub mod uio;
 pub mod workqueue;
 
 pub mod linked_list;
@@ -106,7 +156,7 @@ pub use build_error::build_error;
 pub use crate::error::{to_result, Error, Result};
 pub use crate::types::{
     bit, bits_iter, ARef, AlwaysRefCounted, Bit, Bool, Either, Either::Left, Either::Right, False,
-    ForeignOwnable, Mode, Opaque, ScopeGuard, True,
+    ForeignOwnable, GfpFlags, Mode, NumaNode, Opaque, ScopeGuard, True,
 };
 
 use core::marker::PhantomData;
@@ -140,6 +190,14 @@ pub struct ThisModule(*mut bindings::module);
 // SAFETY: `THIS_MODULE` may be used from all threads within a module.
 unsafe impl Sync for ThisModule {}
 
+/// The [`ThisModule`] of this crate, which is always built into the kernel.
+///
+/// It owns the file operations of the files that are implemented by this crate alone, rather
+/// than by types of the module that creates them.
+// SAFETY: `THIS_MODULE` is null for code built into the kernel.
+pub(crate) static BUILTIN_MODULE: ThisModule =
+    unsafe { ThisModule::from_ptr(core::ptr::null_mut()) };
+
 impl ThisModule {
     /// Creates a [`ThisModule`] given the `THIS_MODULE` pointer.
     ///
@@ -150,10 +208,57 @@ impl ThisModule {
         ThisModule(ptr)
     }
 
+    /// Returns `true` if the code is built into the kernel rather than in a loadable module.
+    pub fn is_builtin(&self) -> bool {
+        self.0.is_null()
+    }
+
+    /// Returns the name of the module, or `None` for code built into the kernel.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kernel::prelude::*;
+    /// fn log_identity(module: &ThisModule) {
+    ///     match module.name() {
+    ///         Some(name) => pr_info!("Loaded as module {}\n", name),
+    ///         None => pr_info!("Built into the kernel\n"),
+    ///     }
+    /// }
+    /// ```
+    pub fn name(&self) -> Option<&str::CStr> {
+        if self.0.is_null() {
+            return None;
+        }
+
+        // SAFETY: The pointer is the `THIS_MODULE` of the caller, which is valid while its code
+        // runs, and the name of a module is a `NUL`-terminated string that never changes.
+        Some(unsafe { str::CStr::from_char_ptr((*self.0).name.as_ptr()) })
+    }
+
+    /// Returns the number of references currently held on the module, for example by open files
+    /// whose operations it provides.
+    ///
+    /// Returns `None` for code built into the kernel, and when modules cannot be unloaded, in
+    /// which case references are not counted. The value may change as soon as it is returned, so
+    /// it is only meant for diagnostics.
+    pub fn refcount(&self) -> Option<u32> {
+        #[cfg(CONFIG_MODULE_UNLOAD)]
+        if !self.0.is_null() {
+            // SAFETY: The pointer is the `THIS_MODULE` of the caller, which is a valid module.
+            let count = unsafe { bindings::module_refcount(self.0) };
+            return u32::try_from(count).ok();
+        }
+
+        None
+    }
+
     /// Locks the module parameters to access them.
     ///
-    /// Returns a [`KParamGuard`] that will release the lock when dropped.
-    pub fn kernel_param_lock(&self) -> KParamGuard<'_> {
+    /// Returns a [`KParamGuard`] that will release the lock when dropped. This is the lock that
+    /// writes to parameters through `sysfs` take, so it must be held to read parameters that are
+    /// not [`Copy`] or to read several parameters consistently.
+    pub fn param_lock(&self) -> KParamGuard<'_> {
         // SAFETY: `kernel_param_lock` will check if the pointer is null and
         // use the built-in mutex in that case.
         #[cfg(CONFIG_SYSFS)]
@@ -167,11 +272,21 @@ impl ThisModule {
             phantom: PhantomData,
         }
     }
+
+    /// Locks the module parameters to access them.
+    ///
+    /// This is the same as [`ThisModule::param_lock`].
+    pub fn kernel_param_lock(&self) -> KParamGuard<'_> {
+        self.param_lock()
+    }
 }
 
 /// Scoped lock on the kernel parameters of [`ThisModule`].
 ///
 /// Lock will be released when this struct is dropped.
+///
+/// While it is held, parameters cannot be changed through `sysfs`, so values read with
+/// [`module_param::read_locked`] remain consistent until it is dropped.
 pub struct KParamGuard<'a> {
     #[cfg(CONFIG_SYSFS)]
     this_module: &'a ThisModule,
@@ -222,6 +337,9 @@ macro_rules! offset_of {
 
 /// Produces a pointer to an object from a pointer to one of its fields.
 ///
+/// For structs whose field is handed to C code, implementing [`container::Containable`] once
+/// with [`impl_containable!`] checks the type of the field and avoids repeating its name.
+///
 /// # Safety
 ///
 /// Callers must ensure that the pointer to the field is in fact a pointer to the specified field,
@@ -0,0 +1,446 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! VFIO mediated devices.
+//!
+//! Mediated devices (mdevs) are virtual devices created on top of a physical parent device,
+//! usually to share it among virtual machines. The parent driver registers the types of mdevs it
+//! supports; instances are then created and removed by userspace through sysfs.
+//!
+//! C headers: [`include/linux/mdev.h`](../../../../include/linux/mdev.h) and
+//! [`include/uapi/linux/vfio.h`](../../../../include/uapi/linux/vfio.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/driver-api/vfio-mediated-device.html>
+
+use crate::{
+    bindings, device,
+    error::{code::*, from_kernel_result},
+    io_buffer::{IoBufferReader, IoBufferWriter},
+    str::CStr,
+    to_result,
+    types::ForeignOwnable,
+    user_ptr::UserSlicePtr,
+    Result, ThisModule,
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{cell::UnsafeCell, marker::PhantomData, mem::size_of, pin::Pin};
+use macros::vtable;
+
+/// Flags that describe a device region.
+pub mod region_flags {
+    use crate::bindings;
+
+    /// The region supports `read(2)`.
+    pub const READ: u32 = bindings::VFIO_REGION_INFO_FLAG_READ;
+
+    /// The region supports `write(2)`.
+    pub const WRITE: u32 = bindings::VFIO_REGION_INFO_FLAG_WRITE;
+
+    /// The region supports `mmap(2)`.
+    pub const MMAP: u32 = bindings::VFIO_REGION_INFO_FLAG_MMAP;
+}
+
+/// Flags that describe a device interrupt.
+pub mod irq_flags {
+    use crate::bindings;
+
+    /// The interrupt can be signalled through an eventfd.
+    pub const EVENTFD: u32 = bindings::VFIO_IRQ_INFO_EVENTFD;
+
+    /// The interrupt can be masked.
+    pub const MASKABLE: u32 = bindings::VFIO_IRQ_INFO_MASKABLE;
+
+    /// The interrupt is automatically masked after it is signalled.
+    pub const AUTOMASKED: u32 = bindings::VFIO_IRQ_INFO_AUTOMASKED;
+
+    /// Interrupts must not be reconfigured while the device is in use.
+    pub const NORESIZE: u32 = bindings::VFIO_IRQ_INFO_NORESIZE;
+}
+
+/// A VFIO device API, which tells userspace how to drive a mediated device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceApi {
+    /// A PCI device, `vfio-pci`.
+    Pci,
+
+    /// A platform device, `vfio-platform`.
+    Platform,
+
+    /// An AMBA device, `vfio-amba`.
+    Amba,
+
+    /// An s390 channel I/O device, `vfio-ccw`.
+    Ccw,
+
+    /// An s390 adjunct processor device, `vfio-ap`.
+    Ap,
+}
+
+impl DeviceApi {
+    /// Returns the name of the API, as shown in sysfs.
+    pub fn name(self) -> &'static CStr {
+        match self {
+            Self::Pci => crate::c_str!("vfio-pci"),
+            Self::Platform => crate::c_str!("vfio-platform"),
+            Self::Amba => crate::c_str!("vfio-amba"),
+            Self::Ccw => crate::c_str!("vfio-ccw"),
+            Self::Ap => crate::c_str!("vfio-ap"),
+        }
+    }
+
+    /// Returns the flag that reports the API in `VFIO_DEVICE_GET_INFO`.
+    fn flags(self) -> u32 {
+        match self {
+            Self::Pci => bindings::VFIO_DEVICE_FLAGS_PCI,
+            Self::Platform => bindings::VFIO_DEVICE_FLAGS_PLATFORM,
+            Self::Amba => bindings::VFIO_DEVICE_FLAGS_AMBA,
+            Self::Ccw => bindings::VFIO_DEVICE_FLAGS_CCW,
+            Self::Ap => bindings::VFIO_DEVICE_FLAGS_AP,
+        }
+    }
+}
+
+/// Describes a region of a mediated device, as reported by `VFIO_DEVICE_GET_REGION_INFO`.
+#[derive(Clone, Copy, Default)]
+pub struct RegionInfo {
+    /// A combination of the [`region_flags`] constants.
+    pub flags: u32,
+
+    /// The size of the region in bytes.
+    pub size: u64,
+
+    /// The offset of the region within the device file descriptor.
+    pub offset: u64,
+}
+
+/// Describes an interrupt of a mediated device, as reported by `VFIO_DEVICE_GET_IRQ_INFO`.
+#[derive(Clone, Copy, Default)]
+pub struct IrqInfo {
+    /// A combination of the [`irq_flags`] constants.
+    pub flags: u32,
+
+    /// The number of interrupts of this kind.
+    pub count: u32,
+}
+
+/// A type of mediated device supported by a parent device.
+pub struct Type {
+    /// The name of the type in sysfs.
+    pub sysfs_name: &'static CStr,
+
+    /// A human-readable name of the type.
+    pub pretty_name: &'static CStr,
+}
+
+/// Wraps the kernel's `struct mdev_device`.
+///
+/// # Invariants
+///
+/// The pointer `Device::ptr` is non-null and valid.
+pub struct Device {
+    ptr: *mut bindings::mdev_device,
+}
+
+impl Device {
+    /// Creates a new mediated device wrapper.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `ptr` is non-null and valid for the lifetime of the object.
+    unsafe fn from_ptr(ptr: *mut bindings::mdev_device) -> Self {
+        // INVARIANT: The safety requirements guarantee the invariants.
+        Self { ptr }
+    }
+
+    /// Returns the index of the type the device was created with, in the slice passed to
+    /// [`Registration::register`].
+    pub fn type_index(&self) -> usize {
+        // SAFETY: `self.ptr` is valid by the type invariants, and so is its type.
+        unsafe { (*(*self.ptr).type_).type_group_id as _ }
+    }
+}
+
+// SAFETY: The device returned by `raw_device` is the raw mdev device.
+unsafe impl device::RawDevice for Device {
+    fn raw_device(&self) -> *mut bindings::device {
+        // SAFETY: `self.ptr` is valid by the type invariants.
+        unsafe { &mut (*self.ptr).dev }
+    }
+}
+
+/// Corresponds to the kernel's `struct mdev_driver`.
+#[vtable]
+pub trait Driver {
+    /// The type of the per-device data returned by [`Driver::probe`].
+    type Data: ForeignOwnable + Send + Sync = ();
+
+    /// The VFIO device API implemented by the devices.
+    const DEVICE_API: DeviceApi;
+
+    /// The number of regions of each device.
+    const NUM_REGIONS: u32 = 0;
+
+    /// The number of interrupt kinds of each device.
+    const NUM_IRQS: u32 = 0;
+
+    /// Called when userspace creates a new mediated device.
+    fn probe(mdev: &Device) -> Result<Self::Data>;
+
+    /// Called when userspace removes a mediated device.
+    ///
+    /// The data is moved, so it will be freed automatically unless the implementation moves it
+    /// elsewhere.
+    fn remove(_data: Self::Data) {}
+
+    /// Describes the region with the given index.
+    fn region_info(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _index: u32,
+    ) -> Result<RegionInfo> {
+        Err(EINVAL)
+    }
+
+    /// Describes the interrupt with the given index.
+    fn irq_info(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _index: u32,
+    ) -> Result<IrqInfo> {
+        Err(EINVAL)
+    }
+}
+
+/// Returns the offset of the end of `$field`, of type `$field_type`, in `$type`, like
+/// `offsetofend` in C.
+macro_rules! offset_of_end {
+    ($type:ty, $field:ident, $field_type:ty) => {
+        crate::offset_of!($type, $field) as usize + size_of::<$field_type>()
+    };
+}
+
+/// Answers the VFIO ioctls that query device, region and interrupt information.
+///
+/// Drivers call it from their VFIO `ioctl` handler with the raw command and argument. It returns
+/// `ENOTTY` for other commands so that the driver can handle them.
+pub fn handle_info_ioctl<T: Driver>(
+    data: <T::Data as ForeignOwnable>::Borrowed<'_>,
+    cmd: u32,
+    arg: usize,
+) -> Result<i32> {
+    match cmd {
+        bindings::VFIO_DEVICE_GET_INFO => {
+            let mut info = bindings::vfio_device_info::default();
+            let minsz = offset_of_end!(bindings::vfio_device_info, num_irqs, u32);
+            read_info(&mut info, arg, minsz)?;
+            info.flags = T::DEVICE_API.flags();
+            info.num_regions = T::NUM_REGIONS;
+            info.num_irqs = T::NUM_IRQS;
+            write_info(&info, arg, minsz)
+        }
+        bindings::VFIO_DEVICE_GET_REGION_INFO => {
+            let mut info = bindings::vfio_region_info::default();
+            let minsz = offset_of_end!(bindings::vfio_region_info, offset, u64);
+            read_info(&mut info, arg, minsz)?;
+            if info.index >= T::NUM_REGIONS {
+                return Err(EINVAL);
+            }
+            let region = T::region_info(data, info.index)?;
+            info.flags = region.flags;
+            info.size = region.size;
+            info.offset = region.offset;
+            write_info(&info, arg, minsz)
+        }
+        bindings::VFIO_DEVICE_GET_IRQ_INFO => {
+            let mut info = bindings::vfio_irq_info::default();
+            let minsz = offset_of_end!(bindings::vfio_irq_info, count, u32);
+            read_info(&mut info, arg, minsz)?;
+            if info.index >= T::NUM_IRQS {
+                return Err(EINVAL);
+            }
+            let irq = T::irq_info(data, info.index)?;
+            info.flags = irq.flags;
+            info.count = irq.count;
+            write_info(&info, arg, minsz)
+        }
+        _ => Err(ENOTTY),
+    }
+}
+
+/// Reads the first `minsz` bytes of a VFIO info structure whose first field is `argsz`.
+///
+/// As in C drivers, `minsz` is the end of the last field that the ioctl uses, so that callers
+/// built against an older version of the structure, without the fields added since, are accepted.
+fn read_info<S>(info: &mut S, arg: usize, minsz: usize) -> Result {
+    // SAFETY: `info` is a valid, plain C structure of at least `minsz` bytes, and all VFIO info
+    // structures are made of integers, so any bit pattern is valid. The argument is read only
+    // once, so there are no TOCTOU issues.
+    unsafe {
+        UserSlicePtr::new(arg as _, minsz)
+            .reader()
+            .read_raw(info as *mut S as _, minsz)?
+    };
+    // SAFETY: All VFIO info structures start with a `u32` `argsz` field.
+    let argsz = unsafe { *(info as *const S as *const u32) };
+    if (argsz as usize) < minsz {
+        return Err(EINVAL);
+    }
+    Ok(())
+}
+
+/// Writes the first `len` bytes of a VFIO info structure back to userspace.
+fn write_info<S>(info: &S, arg: usize, len: usize) -> Result<i32> {
+    // SAFETY: `info` is a valid, plain C structure of at least `len` bytes without padding.
+    unsafe {
+        UserSlicePtr::new(arg as _, len)
+            .writer()
+            .write_raw(info as *const S as _, len)?
+    };
+    Ok(0)
+}
+
+/// A registration of a parent device of mediated devices.
+pub struct Registration<T: Driver> {
+    driver: UnsafeCell<bindings::mdev_driver>,
+    parent: UnsafeCell<bindings::mdev_parent>,
+    types: Vec<bindings::mdev_type>,
+    type_ptrs: Vec<*mut bindings::mdev_type>,
+    driver_registered: bool,
+    registered: bool,
+    _p: PhantomData<T>,
+}
+
+impl<T: Driver> Registration<T> {
+    /// Creates a new [`Registration`] but does not register it yet.
+    ///
+    /// It is allowed to move.
+    pub fn new() -> Self {
+        Self {
+            driver: UnsafeCell::new(bindings::mdev_driver::default()),
+            parent: UnsafeCell::new(bindings::mdev_parent::default()),
+            types: Vec::new(),
+            type_ptrs: Vec::new(),
+            driver_registered: false,
+            registered: false,
+            _p: PhantomData,
+        }
+    }
+
+    /// Registers `parent` as a parent of mediated devices of the given types.
+    ///
+    /// Returns a pinned heap-allocated representation of the registration.
+    pub fn new_pinned(
+        parent: &dyn device::RawDevice,
+        name: &'static CStr,
+        types: &[Type],
+        module: &'static ThisModule,
+    ) -> Result<Pin<Box<Self>>> {
+        let mut reg = Pin::from(Box::try_new(Self::new())?);
+        reg.as_mut().register(parent, name, types, module)?;
+        Ok(reg)
+    }
+
+    /// Registers the mdev driver `name` and makes `parent` a parent of mediated devices of the
+    /// given types.
+    ///
+    /// It must be pinned because the memory block that represents the registration is
+    /// self-referential.
+    pub fn register(
+        self: Pin<&mut Self>,
+        parent: &dyn device::RawDevice,
+        name: &'static CStr,
+        types: &[Type],
+        module: &'static ThisModule,
+    ) -> Result {
+        // SAFETY: We never move out of `this`.
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.registered || types.is_empty() {
+            return Err(EINVAL);
+        }
+
+        this.types.try_reserve_exact(types.len())?;
+        this.type_ptrs.try_reserve_exact(types.len())?;
+        for (i, t) in types.iter().enumerate() {
+            let mut raw = bindings::mdev_type::default();
+            raw.sysfs_name = t.sysfs_name.as_char_ptr();
+            raw.pretty_name = t.pretty_name.as_char_ptr();
+            raw.type_group_id = i as _;
+            this.types.try_push(raw)?;
+        }
+        // The pointers are only taken once `types` is fully populated, so they remain valid.
+        for t in this.types.iter_mut() {
+            this.type_ptrs.try_push(t)?;
+        }
+
+        let driver = this.driver.get_mut();
+        driver.device_api = T::DEVICE_API.name().as_char_ptr();
+        driver.probe = Some(Self::probe_callback);
+        driver.remove = Some(Self::remove_callback);
+        driver.driver.name = name.as_char_ptr();
+        driver.driver.owner = module.0;
+
+        // SAFETY: `driver` is fully initialised and, since `self` is pinned, will remain at the
+        // same address until it is unregistered in `drop`.
+        to_result(unsafe { bindings::mdev_register_driver(this.driver.get()) })?;
+        this.driver_registered = true;
+
+        // SAFETY: `parent`, `driver` and the types all remain valid until they are unregistered
+        // in `drop`.
+        to_result(unsafe {
+            bindings::mdev_register_parent(
+                this.parent.get(),
+                parent.raw_device(),
+                this.driver.get(),
+                this.type_ptrs.as_mut_ptr(),
+                this.type_ptrs.len() as _,
+            )
+        })?;
+
+        this.registered = true;
+        Ok(())
+    }
+
+    unsafe extern "C" fn probe_callback(mdev: *mut bindings::mdev_device) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The mdev core only calls this callback with valid devices.
+            let dev = unsafe { Device::from_ptr(mdev) };
+            let data = T::probe(&dev)?;
+            // SAFETY: `dev` is valid, and the driver data is only retrieved by `remove_callback`.
+            unsafe { bindings::dev_set_drvdata(&mut (*mdev).dev, data.into_foreign() as _) };
+            Ok(0)
+        }
+    }
+
+    unsafe extern "C" fn remove_callback(mdev: *mut bindings::mdev_device) {
+        // SAFETY: The mdev core only calls `remove` on devices for which `probe` succeeded, so the
+        // driver data was set by `probe_callback` with a value returned by `into_foreign`.
+        let ptr = unsafe { bindings::dev_get_drvdata(&(*mdev).dev) };
+        T::remove(unsafe { T::Data::from_foreign(ptr) });
+    }
+}
+
+impl<T: Driver> Default for Registration<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// SAFETY: `Registration` does not expose any of its state across threads.
+unsafe impl<T: Driver> Sync for Registration<T> {}
+
+// SAFETY: All functions work from any thread.
+unsafe impl<T: Driver> Send for Registration<T> {}
+
+impl<T: Driver> Drop for Registration<T> {
+    /// Removes the registration from the kernel if it has completed successfully before.
+    fn drop(&mut self) {
+        if self.registered {
+            // SAFETY: `registered` being `true` indicates that `mdev_register_parent` succeeded.
+            // This removes all mediated devices, calling `remove_callback` for each of them.
+            unsafe { bindings::mdev_unregister_parent(self.parent.get()) };
+        }
+
+        if self.driver_registered {
+            // SAFETY: `driver_registered` being `true` indicates that `mdev_register_driver`
+            // succeeded.
+            unsafe { bindings::mdev_unregister_driver(self.driver.get()) };
+        }
+    }
+}
@@ -9,7 +9,7 @@
 use crate::bindings;
 use crate::error::{code::*, Error, Result};
 use crate::file;
-use crate::{device, str::CStr, str::CString, ThisModule};
+use crate::{device, str::CStr, str::CString};
 use alloc::boxed::Box;
 use core::marker::PhantomPinned;
 use core::{fmt, mem::MaybeUninit, pin::Pin};
@@ -20,15 +20,16 @@ use core::{fmt, mem::MaybeUninit, pin::Pin};
 ///
 /// ```
 /// # use kernel::{c_str, device::RawDevice, file, miscdev, prelude::*};
-/// fn example(
-///     reg: Pin<&mut miscdev::Registration<impl file::Operations<OpenData = ()>>>,
+/// fn example<T: file::Operations<OpenData = ()>>(
+///     reg: Pin<&mut miscdev::Registration<T>>,
+///     vtable: &'static miscdev::Vtable<T>,
 ///     parent: &dyn RawDevice,
 /// ) -> Result {
 ///     miscdev::Options::new()
 ///         .mode(0o600)
 ///         .minor(10)
 ///         .parent(parent)
-///         .register(reg, fmt!("sample"), ())
+///         .register(reg, vtable, fmt!("sample"), ())
 /// }
 /// ```
 #[derive(Default)]
@@ -73,25 +74,32 @@ impl<'a> Options<'a> {
     pub fn register<T: file::Operations>(
         &self,
         reg: Pin<&mut Registration<T>>,
+        vtable: &'static Vtable<T>,
         name: fmt::Arguments<'_>,
         open_data: T::OpenData,
     ) -> Result {
-        reg.register_with_options(name, open_data, self)
+        reg.register_with_options(vtable, name, open_data, self)
     }
 
     /// Allocates a new registration of a misc device and completes the registration with the
     /// configured options.
     pub fn register_new<T: file::Operations>(
         &self,
+        vtable: &'static Vtable<T>,
         name: fmt::Arguments<'_>,
         open_data: T::OpenData,
     ) -> Result<Pin<Box<Registration<T>>>> {
         let mut r = Pin::from(Box::try_new(Registration::new())?);
-        self.register(r.as_mut(), name, open_data)?;
+        self.register(r.as_mut(), vtable, name, open_data)?;
         Ok(r)
     }
 }
 
+/// The file operations of a miscellaneous device implemented by `T`.
+///
+/// They are defined in the module that implements `T` with [`crate::file_vtable!`].
+pub type Vtable<T> = file::Vtable<Registration<T>, T>;
+
 /// A registration of a miscellaneous device.
 ///
 /// # Invariants
@@ -126,8 +134,12 @@ impl<T: file::Operations> Registration<T> {
     /// Registers a miscellaneous device.
     ///
     /// Returns a pinned heap-allocated representation of the registration.
-    pub fn new_pinned(name: fmt::Arguments<'_>, open_data: T::OpenData) -> Result<Pin<Box<Self>>> {
-        Options::new().register_new(name, open_data)
+    pub fn new_pinned(
+        vtable: &'static Vtable<T>,
+        name: fmt::Arguments<'_>,
+        open_data: T::OpenData,
+    ) -> Result<Pin<Box<Self>>> {
+        Options::new().register_new(vtable, name, open_data)
     }
 
     /// Registers a miscellaneous device with the rest of the kernel.
@@ -136,10 +148,11 @@ impl<T: file::Operations> Registration<T> {
     /// self-referential.
     pub fn register(
         self: Pin<&mut Self>,
+        vtable: &'static Vtable<T>,
         name: fmt::Arguments<'_>,
         open_data: T::OpenData,
     ) -> Result {
-        Options::new().register(self, name, open_data)
+        Options::new().register(self, vtable, name, open_data)
     }
 
     /// Registers a miscellaneous device with the rest of the kernel. Additional optional settings
@@ -149,6 +162,7 @@ impl<T: file::Operations> Registration<T> {
     /// self-referential.
     pub fn register_with_options(
         self: Pin<&mut Self>,
+        vtable: &'static Vtable<T>,
         name: fmt::Arguments<'_>,
         open_data: T::OpenData,
         opts: &Options<'_>,
@@ -162,8 +176,7 @@ impl<T: file::Operations> Registration<T> {
 
         let name = CString::try_from_fmt(name)?;
 
-        // SAFETY: The adapter is compatible with `misc_register`.
-        this.mdev.fops = unsafe { file::OperationsVtable::<Self, T>::build() };
+        this.mdev.fops = vtable.fops();
         this.mdev.name = name.as_char_ptr();
         this.mdev.minor = opts.minor.unwrap_or(bindings::MISC_DYNAMIC_MINOR as i32);
         this.mdev.mode = opts.mode.unwrap_or(0);
@@ -191,6 +204,19 @@ impl<T: file::Operations> Registration<T> {
 
         Ok(())
     }
+
+    /// Returns the minor number of the device, if it is registered.
+    ///
+    /// When no minor was requested in the [`Options`], it is the one allocated dynamically during
+    /// registration, so it can be used to tell apart the devices of a module that registers
+    /// several of them.
+    pub fn minor(&self) -> Option<i32> {
+        if self.registered {
+            Some(self.mdev.minor)
+        } else {
+            None
+        }
+    }
 }
 
 impl<T: file::Operations> Default for Registration<T> {
@@ -238,14 +264,17 @@ impl<T: file::Operations> Drop for Registration<T> {
 }
 
 /// Kernel module that exposes a single miscdev device implemented by `T`.
+///
+/// It is declared with [`module_misc_device`], which defines its file operations.
 pub struct Module<T: file::Operations<OpenData = ()>> {
     _dev: Pin<Box<Registration<T>>>,
 }
 
-impl<T: file::Operations<OpenData = ()>> crate::Module for Module<T> {
-    fn init(name: &'static CStr, _module: &'static ThisModule) -> Result<Self> {
+impl<T: file::Operations<OpenData = ()>> Module<T> {
+    /// Registers the misc device of the module called `name`.
+    pub fn new(vtable: &'static Vtable<T>, name: &'static CStr) -> Result<Self> {
         Ok(Self {
-            _dev: Registration::new_pinned(crate::fmt!("{name}"), ())?,
+            _dev: Registration::new_pinned(vtable, crate::fmt!("{name}"), ())?,
         })
     }
 }
@@ -281,7 +310,18 @@ impl<T: file::Operations<OpenData = ()>> crate::Module for Module<T> {
 #[macro_export]
 macro_rules! module_misc_device {
     (type: $type:ty, $($f:tt)*) => {
-        type ModuleType = kernel::miscdev::Module<$type>;
+        struct ModuleType(kernel::miscdev::Module<$type>);
+
+        impl kernel::Module for ModuleType {
+            fn init(
+                name: &'static kernel::str::CStr,
+                _module: &'static kernel::ThisModule,
+            ) -> kernel::error::Result<Self> {
+                let vtable = kernel::file_vtable!(kernel::miscdev::Vtable<$type>);
+                Ok(Self(kernel::miscdev::Module::new(vtable, name)?))
+            }
+        }
+
         module! {
             type: ModuleType,
             $($f)*
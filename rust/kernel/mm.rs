@@ -63,6 +63,24 @@ pub mod virt {
             unsafe { (*self.vma).vm_end as _ }
         }
 
+        /// Returns the size of the virtual memory area in bytes.
+        pub fn len(&self) -> usize {
+            self.end() - self.start()
+        }
+
+        /// Returns `true` if the virtual memory area is empty.
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+
+        /// Returns the offset, in pages, within the file being mapped.
+        ///
+        /// This is the `offset` argument passed to `mmap(2)`, divided by the page size.
+        pub fn pgoff(&self) -> usize {
+            // SAFETY: `self.vma` is valid by the type invariants.
+            unsafe { (*self.vma).vm_pgoff as _ }
+        }
+
         /// Maps a single page at the given address within the virtual memory area.
         pub fn insert_page(&mut self, address: usize, page: &pages::Pages<0>) -> Result {
             // SAFETY: The page is guaranteed to be order 0 by the type system. The range of
@@ -0,0 +1,156 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Module-global state.
+//!
+//! Some callbacks from C do not receive a context pointer, for example the `set` hook of a module
+//! parameter or some notifier callbacks. A [`ModuleGlobal`] lets them reach the state of the
+//! module, which is installed in a static during module initialisation and removed again when the
+//! module is unloaded.
+
+use crate::{bindings, error::code::*, revocable::RevocableGuard, sync::rcu, Result};
+use alloc::boxed::Box;
+use core::{
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+/// State of a module that is reachable from a static.
+///
+/// The state is installed with [`ModuleGlobal::install`], usually from [`crate::Module::init`],
+/// which returns a registration that should be stored in the module: dropping it, when the module
+/// is unloaded, removes the state and waits for all the users to be done with it before dropping
+/// it. The state is accessed with [`ModuleGlobal::try_access`], which fails if it is not installed.
+///
+/// Accesses are RCU read-side critical sections, so they are cheap and may happen in any context,
+/// but must not sleep.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use core::sync::atomic::{AtomicU64, Ordering};
+/// use kernel::module_global::{ModuleGlobal, ModuleGlobalRegistration};
+///
+/// struct State {
+///     events: AtomicU64,
+/// }
+///
+/// static STATE: ModuleGlobal<State> = ModuleGlobal::new();
+///
+/// // Called from C without any context.
+/// extern "C" fn notify() {
+///     if let Some(state) = STATE.try_access() {
+///         state.events.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// struct MyModule {
+///     _state: ModuleGlobalRegistration<State>,
+/// }
+///
+/// impl kernel::Module for MyModule {
+///     fn init(_name: &'static CStr, _module: &'static ThisModule) -> Result<Self> {
+///         let state = STATE.install(State {
+///             events: AtomicU64::new(0),
+///         })?;
+///         Ok(MyModule { _state: state })
+///     }
+/// }
+/// ```
+///
+/// # Invariants
+///
+/// `ptr` is either null or a pointer obtained from [`Box::into_raw`], which is owned by the
+/// [`ModuleGlobalRegistration`] that installed it.
+pub struct ModuleGlobal<T> {
+    ptr: AtomicPtr<T>,
+}
+
+// SAFETY: The state is shared between all the threads that access it, and may be dropped from any
+// of them.
+unsafe impl<T: Send + Sync> Sync for ModuleGlobal<T> {}
+
+impl<T> ModuleGlobal<T> {
+    /// Creates a new global without any state installed.
+    pub const fn new() -> Self {
+        // INVARIANT: The pointer is null.
+        Self {
+            ptr: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Installs `value` as the state of the global.
+    ///
+    /// The state remains installed until the returned registration is dropped. Returns `EBUSY` if
+    /// a state is already installed.
+    pub fn install(&'static self, value: T) -> Result<ModuleGlobalRegistration<T>> {
+        let new = Box::into_raw(Box::try_new(value)?);
+        if self
+            .ptr
+            .compare_exchange(ptr::null_mut(), new, Ordering::Release, Ordering::Relaxed)
+            .is_err()
+        {
+            // SAFETY: `new` was just obtained from `Box::into_raw` and was not published.
+            drop(unsafe { Box::from_raw(new) });
+            return Err(EBUSY);
+        }
+
+        // INVARIANT: The registration owns the state that was just installed.
+        Ok(ModuleGlobalRegistration { global: self })
+    }
+
+    /// Tries to access the state of the global.
+    ///
+    /// Returns `None` if no state is installed. Otherwise, the state remains valid while the
+    /// returned guard is alive; callers must not sleep until it is dropped.
+    pub fn try_access(&self) -> Option<RevocableGuard<'_, T>> {
+        let guard = rcu::read_lock();
+        let state = self.ptr.load(Ordering::Acquire);
+        if state.is_null() {
+            None
+        } else {
+            // INVARIANT: The guard holds the RCU read-side lock, which keeps the state alive: it is
+            // only dropped after the pointer is cleared and a grace period has elapsed.
+            Some(RevocableGuard::new(state, guard))
+        }
+    }
+
+    /// Calls `f` with the state of the global, if it is installed.
+    ///
+    /// `f` must not sleep.
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.try_access().map(|state| f(&state))
+    }
+}
+
+impl<T> Default for ModuleGlobal<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The installation of the state of a [`ModuleGlobal`].
+///
+/// The state is removed from the global and dropped when the registration is dropped.
+///
+/// # Invariants
+///
+/// The pointer in `global` is owned by this registration.
+pub struct ModuleGlobalRegistration<T: 'static> {
+    global: &'static ModuleGlobal<T>,
+}
+
+impl<T> Drop for ModuleGlobalRegistration<T> {
+    fn drop(&mut self) {
+        let state = self.global.ptr.swap(ptr::null_mut(), Ordering::Relaxed);
+
+        // Waits for the users that accessed the state before it was removed.
+        // SAFETY: Just an FFI call, there are no further requirements.
+        unsafe { bindings::synchronize_rcu() };
+
+        // SAFETY: By the type invariants, the state was obtained from `Box::into_raw` and is owned
+        // by the registration. It can no longer be accessed, since it was removed from the global
+        // and the grace period has elapsed.
+        drop(unsafe { Box::from_raw(state) });
+    }
+}
@@ -4,16 +4,40 @@
 //!
 //! C header: [`include/linux/moduleparam.h`](../../../include/linux/moduleparam.h)
 
-use crate::error::{code::*, from_kernel_result};
-use crate::str::{CStr, Formatter};
-use core::fmt::Write;
+use crate::error::{code::*, from_kernel_result, Error};
+use crate::str::{self, CStr};
+use crate::KParamGuard;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// The maximum number of bytes the [`core::fmt::Display`] implementation of a [`ModuleParam`]
+/// can write without being truncated in `sysfs`.
+///
+/// It is [`crate::PAGE_SIZE`] minus the null terminator.
+pub const MAX_PARAM_DISPLAY: usize = crate::PAGE_SIZE - 1;
+
+/// What the representation of a parameter ends with when it is truncated in `sysfs`.
+const TRUNCATION_MARKER: &str = "...(truncated)\n";
+
+/// Whether a truncation of a parameter in `sysfs` has been reported already.
+static TRUNCATION_REPORTED: AtomicBool = AtomicBool::new(false);
 
 /// Types that can be used for module parameters.
 ///
-/// Note that displaying the type in `sysfs` will fail if
+/// Note that the representation of the type in `sysfs` is truncated if
 /// [`alloc::string::ToString::to_string`] (as implemented through the
-/// [`core::fmt::Display`] trait) writes more than [`PAGE_SIZE`]
-/// bytes (including an additional null terminator).
+/// [`core::fmt::Display`] trait) writes more than [`MAX_PARAM_DISPLAY`]
+/// bytes, which leaves room for an additional null terminator in a
+/// [`PAGE_SIZE`] buffer. Truncated output ends with an explicit marker, and
+/// the first truncation is logged.
+///
+/// # Locking
+///
+/// Parameters that are writable through `sysfs` can change while the module is running. The
+/// kernel sets them with the parameter lock of the module held (see
+/// [`crate::ThisModule::param_lock`]), so readers must hold the same lock for as long as
+/// they use the value, or they may see a value that is being replaced or freed. The `read`
+/// methods generated by [`macros::module`] for such parameters take a [`KParamGuard`] and tie the
+/// lifetime of the returned reference to it, see [`read_locked`].
 ///
 /// [`PAGE_SIZE`]: `crate::PAGE_SIZE`
 pub trait ModuleParam: core::fmt::Display + core::marker::Sized {
@@ -50,6 +74,13 @@ pub trait ModuleParam: core::fmt::Display + core::marker::Sized {
     /// [`kmalloc`]: ../../../include/linux/slab.h
     fn try_from_param_arg(arg: Option<&'static [u8]>) -> Option<Self>;
 
+    /// The error reported when [`ModuleParam::try_from_param_arg`] fails for `arg`.
+    ///
+    /// Defaults to [`EINVAL`].
+    fn param_arg_error(_arg: Option<&[u8]>) -> Error {
+        EINVAL
+    }
+
     /// Get the current value of the parameter for use in the kernel module.
     ///
     /// This function should not be used directly. Instead use the wrapper
@@ -80,7 +111,7 @@ pub trait ModuleParam: core::fmt::Display + core::marker::Sized {
                 let _ = unsafe { core::ptr::replace(old_value, new_value) };
                 0
             }
-            None => EINVAL.to_kernel_errno(),
+            None => Self::param_arg_error(arg).to_kernel_errno(),
         }
     }
 
@@ -97,10 +128,30 @@ pub trait ModuleParam: core::fmt::Display + core::marker::Sized {
         param: *const crate::bindings::kernel_param,
     ) -> core::ffi::c_int {
         from_kernel_result! {
-            // SAFETY: The C contracts guarantees that the buffer is at least `PAGE_SIZE` bytes.
-            let mut f = unsafe { Formatter::from_buffer(buf.cast(), crate::PAGE_SIZE) };
-            unsafe { write!(f, "{}\0", *((*param).__bindgen_anon_1.arg as *mut Self)) }?;
-            Ok(f.bytes_written().try_into()?)
+            // SAFETY: The `arg` field of `param` is an instance of `Self` by the safety
+            // requirements.
+            let value = unsafe { &*((*param).__bindgen_anon_1.arg as *const Self) };
+            // SAFETY: The C contracts guarantees that the buffer is at least `PAGE_SIZE` bytes,
+            // which is more than the length of the marker.
+            let (written, truncated) = unsafe {
+                str::format_truncated(
+                    buf.cast(),
+                    crate::PAGE_SIZE,
+                    TRUNCATION_MARKER,
+                    format_args!("{}", value),
+                )
+            }?;
+            if truncated && !TRUNCATION_REPORTED.swap(true, Ordering::Relaxed) {
+                // SAFETY: The name of a parameter is a valid C string.
+                let name = unsafe { CStr::from_char_ptr((*param).name) };
+                crate::pr_warn!(
+                    "Value of parameter {} truncated to {} bytes\n",
+                    name,
+                    MAX_PARAM_DISPLAY
+                );
+            }
+            // The count includes the null terminator.
+            Ok((written + 1).try_into()?)
         }
     }
 
@@ -116,6 +167,27 @@ pub trait ModuleParam: core::fmt::Display + core::marker::Sized {
     }
 }
 
+/// Returns the value of the parameter stored at `param`.
+///
+/// The returned reference cannot outlive `guard`, so the value cannot be replaced or freed by a
+/// concurrent write through `sysfs` while it is in use. This is meant to be used by the `read`
+/// methods generated by [`macros::module`] for string and array parameters, which span several
+/// words and would otherwise be prone to torn reads.
+///
+/// # Safety
+///
+/// `param` must point to the storage of a parameter of the module whose parameter lock is held by
+/// `guard`.
+pub unsafe fn read_locked<'a, T: ModuleParam>(
+    param: *const T,
+    guard: &'a KParamGuard<'_>,
+) -> &'a T::Value {
+    let _ = guard;
+    // SAFETY: By the safety requirements, `param` is valid, and the parameter lock held by
+    // `guard` prevents it from being modified for the lifetime of the returned reference.
+    unsafe { (*param).value() }
+}
+
 /// Trait for parsing integers.
 ///
 /// Strings beginning with `0x`, `0o`, or `0b` are parsed as hex, octal, or
@@ -498,3 +570,210 @@ make_param_ops!(
     PARAM_OPS_STR,
     StringParam
 );
+
+/// A string parameter stored in a fixed-size buffer of `N` bytes.
+///
+/// It holds at most `N - 1` bytes followed by a null terminator, so a parameter declared with
+/// `max_length: L` in the [`macros::module`] macro is stored as `StrParam<{ L + 1 }>`. Setting a
+/// longer value fails with [`E2BIG`] and leaves the current value unchanged.
+///
+/// Unlike [`StringParam`], it never allocates, so it works the same whether the parameter is set
+/// at boot time or later.
+///
+/// # Invariants
+///
+/// `buf[len]` is zero and `buf[..len]` contains no zeros.
+pub struct StrParam<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> StrParam<N> {
+    /// Create an instance of `StrParam` initialized with `default`.
+    ///
+    /// This function is only meant to be used in the [`macros::module`] macro. It fails to build
+    /// if `default` does not fit in the buffer or contains a null byte.
+    pub const fn create(default: &[u8]) -> Self {
+        if default.len() >= N {
+            panic!("string parameter default value is too long");
+        }
+        let mut buf = [0; N];
+        let mut i = 0;
+        while i < default.len() {
+            if default[i] == 0 {
+                panic!("string parameter default value contains a null byte");
+            }
+            buf[i] = default[i];
+            i += 1;
+        }
+        // INVARIANT: `buf` was zero-initialised and `default` has no zeros.
+        Self {
+            buf,
+            len: default.len(),
+        }
+    }
+
+    fn as_cstr(&self) -> &CStr {
+        // SAFETY: By the type invariants, `buf[..=len]` is null-terminated without interior zeros.
+        unsafe { CStr::from_bytes_with_nul_unchecked(&self.buf[..=self.len]) }
+    }
+}
+
+impl<const N: usize> core::fmt::Display for StrParam<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match core::str::from_utf8(self.as_cstr().as_bytes()) {
+            Ok(utf8) => write!(f, "{}", utf8),
+            Err(_) => write!(f, "{}", self.as_cstr()),
+        }
+    }
+}
+
+impl<const N: usize> ModuleParam for StrParam<N> {
+    type Value = CStr;
+
+    const NOARG_ALLOWED: bool = false;
+
+    fn try_from_param_arg(arg: Option<&'static [u8]>) -> Option<Self> {
+        let arg = arg?;
+        if arg.len() >= N || arg.contains(&0) {
+            return None;
+        }
+        let mut buf = [0; N];
+        buf[..arg.len()].copy_from_slice(arg);
+        // INVARIANT: `buf` was zero-initialised, `arg` has no zeros and is shorter than `N`.
+        Some(Self {
+            buf,
+            len: arg.len(),
+        })
+    }
+
+    fn param_arg_error(arg: Option<&[u8]>) -> Error {
+        match arg {
+            Some(arg) if arg.len() >= N => E2BIG,
+            _ => EINVAL,
+        }
+    }
+
+    fn value(&self) -> &Self::Value {
+        self.as_cstr()
+    }
+}
+
+/// A string parameter of any length, like the C [`charp`] parameter.
+///
+/// Values set after the allocator is available are copied into memory owned by the parameter,
+/// which is released when the value is replaced or the module is unloaded. This type is meant to
+/// be used by the [`macros::module`] macro; the `read` method generated by that macro returns it,
+/// and [`CharpParam::get`] gives access to the string.
+///
+/// [`charp`]: ../../../include/linux/moduleparam.h
+pub enum CharpParam {
+    /// The parameter has no value, like a null `charp`.
+    Null,
+
+    /// A borrowed value.
+    ///
+    /// Either the default value (which is static in the module) or borrowed from the original
+    /// argument buffer used to set the value.
+    Ref(&'static CStr),
+
+    /// A null-terminated value that was allocated when the parameter was set.
+    Owned(alloc::vec::Vec<u8>),
+}
+
+impl CharpParam {
+    /// Create an instance of `CharpParam` initialized with `default`.
+    ///
+    /// This function is only meant to be used in the [`macros::module`] macro.
+    pub const fn create(default: Option<&'static CStr>) -> Self {
+        match default {
+            Some(s) => CharpParam::Ref(s),
+            None => CharpParam::Null,
+        }
+    }
+
+    /// Returns the value of the parameter.
+    ///
+    /// Fails with [`ENOENT`] if the parameter has no value.
+    pub fn get(&self) -> crate::error::Result<&CStr> {
+        match self {
+            CharpParam::Null => Err(ENOENT),
+            CharpParam::Ref(s) => Ok(s),
+            // SAFETY: `Owned` values are created by `try_from_param_arg` from a C string, so
+            // they are null-terminated without interior zeros.
+            CharpParam::Owned(vec) => Ok(unsafe { CStr::from_bytes_with_nul_unchecked(vec) }),
+        }
+    }
+}
+
+impl core::fmt::Display for CharpParam {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.get() {
+            Ok(s) => match core::str::from_utf8(s.as_bytes()) {
+                Ok(utf8) => write!(f, "{}", utf8),
+                Err(_) => write!(f, "{}", s),
+            },
+            Err(_) => f.write_str("(null)"),
+        }
+    }
+}
+
+impl ModuleParam for CharpParam {
+    type Value = CharpParam;
+
+    const NOARG_ALLOWED: bool = false;
+
+    /// Copies `arg` into memory owned by the parameter.
+    ///
+    /// Fails if the allocator is not available yet; [`ModuleParam::set_param`] borrows the
+    /// argument buffer in that case.
+    fn try_from_param_arg(arg: Option<&'static [u8]>) -> Option<Self> {
+        let arg = arg?;
+        // SAFETY: It is always safe to call [`slab_is_available`](../../../include/linux/slab.h).
+        if !unsafe { crate::bindings::slab_is_available() } || arg.contains(&0) {
+            return None;
+        }
+        let mut vec = alloc::vec::Vec::new();
+        vec.try_reserve_exact(arg.len() + 1).ok()?;
+        vec.try_extend_from_slice(arg).ok()?;
+        vec.try_push(0).ok()?;
+        Some(CharpParam::Owned(vec))
+    }
+
+    unsafe extern "C" fn set_param(
+        val: *const core::ffi::c_char,
+        param: *const crate::bindings::kernel_param,
+    ) -> core::ffi::c_int {
+        if val.is_null() {
+            return EINVAL.to_kernel_errno();
+        }
+        // SAFETY: By the safety requirements, `val` is a valid null-terminated string. When the
+        // allocator is not available, the parameter is set at boot time and the argument buffer
+        // remains valid for the lifetime of the kernel.
+        let arg = unsafe { CStr::from_char_ptr(val) };
+        // SAFETY: It is always safe to call [`slab_is_available`](../../../include/linux/slab.h).
+        let new_value = if unsafe { crate::bindings::slab_is_available() } {
+            match Self::try_from_param_arg(Some(arg.as_bytes())) {
+                Some(v) => v,
+                None => return ENOMEM.to_kernel_errno(),
+            }
+        } else {
+            CharpParam::Ref(arg)
+        };
+        // SAFETY: By the safety requirements, the `arg` field of `param` is an instance of `Self`.
+        // The old value is dropped, which releases its memory if it was allocated.
+        unsafe { *((*param).__bindgen_anon_1.arg as *mut Self) = new_value };
+        0
+    }
+
+    fn value(&self) -> &Self::Value {
+        self
+    }
+}
+
+make_param_ops!(
+    /// Rust implementation of [`kernel_param_ops`](../../../include/linux/moduleparam.h)
+    /// for [`CharpParam`].
+    PARAM_OPS_CHARP,
+    CharpParam
+);
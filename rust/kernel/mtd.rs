@@ -0,0 +1,332 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Memory Technology Devices (MTD).
+//!
+//! Allows drivers to register flash-like devices, which are read and written in place and
+//! erased in blocks.
+//!
+//! C header: [`include/linux/mtd/mtd.h`](../../../../include/linux/mtd/mtd.h)
+
+use crate::{
+    bindings,
+    error::{code::*, from_kernel_result},
+    str::CString,
+    to_result,
+    types::ForeignOwnable,
+    Result, ScopeGuard, ThisModule,
+};
+use alloc::boxed::Box;
+use core::{cell::UnsafeCell, fmt, marker::PhantomData, pin::Pin};
+use macros::vtable;
+
+/// The kind of memory of an MTD device.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// RAM, which can be written without being erased first.
+    Ram,
+
+    /// Read-only memory.
+    Rom,
+
+    /// NOR flash.
+    NorFlash,
+
+    /// NAND flash.
+    NandFlash,
+}
+
+impl Kind {
+    fn as_raw(self) -> (u8, u32) {
+        let (kind, flags) = match self {
+            Kind::Ram => (bindings::MTD_RAM, bindings::MTD_CAP_RAM),
+            Kind::Rom => (bindings::MTD_ROM, bindings::MTD_CAP_ROM),
+            Kind::NorFlash => (bindings::MTD_NORFLASH, bindings::MTD_CAP_NORFLASH),
+            Kind::NandFlash => (bindings::MTD_NANDFLASH, bindings::MTD_CAP_NANDFLASH),
+        };
+        (kind as _, flags as _)
+    }
+}
+
+/// The layout of an MTD device.
+#[derive(Clone, Copy)]
+pub struct Geometry {
+    /// The kind of memory.
+    pub kind: Kind,
+
+    /// The total size of the device in bytes.
+    pub size: u64,
+
+    /// The size of an erase block in bytes. The total size must be a multiple of it.
+    pub erase_size: u32,
+
+    /// The minimal writable unit in bytes, for example 1 for RAM or NOR flash and the page size
+    /// for NAND flash.
+    pub write_size: u32,
+}
+
+/// Corresponds to the callbacks of the kernel's `struct mtd_info`.
+///
+/// The MTD core checks that all requests lie within the device before calling them.
+#[vtable]
+pub trait Operations {
+    /// The type of the context data stored in the registration and made available to callbacks.
+    type Data: ForeignOwnable + Send + Sync = ();
+
+    /// Reads into `buf` from the device, starting at byte `offset`.
+    ///
+    /// Returns the number of bytes read.
+    fn read(
+        data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<usize>;
+
+    /// Writes `buf` to the device, starting at byte `offset`.
+    ///
+    /// Returns the number of bytes written.
+    fn write(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _offset: u64,
+        _buf: &[u8],
+    ) -> Result<usize> {
+        Err(EROFS)
+    }
+
+    /// Erases `len` bytes starting at byte `offset`, both multiples of the erase block size.
+    fn erase(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _offset: u64,
+        _len: u64,
+    ) -> Result {
+        Err(EROFS)
+    }
+}
+
+/// A registration of an MTD device.
+///
+/// The device cannot be unregistered while it is in use, for example while `/dev/mtdX` is open.
+/// If the registration is dropped then, the device, its name and its data are leaked instead, so
+/// that it keeps working for its users; they hold a reference to the module given when
+/// registering, so it cannot be unloaded meanwhile.
+///
+/// # Invariants
+///
+/// If `mtd` is `Some`, the device is registered, its `priv_` holds a pointer returned by
+/// [`ForeignOwnable::into_foreign`], and its `name` points to `name`.
+pub struct Registration<T: Operations> {
+    mtd: Option<Box<UnsafeCell<bindings::mtd_info>>>,
+    name: Option<CString>,
+    _p: PhantomData<T>,
+}
+
+impl<T: Operations> Registration<T> {
+    /// Creates a new [`Registration`] but does not register it yet.
+    ///
+    /// It is allowed to move.
+    pub fn new() -> Self {
+        // INVARIANT: `mtd` is `None`.
+        Self {
+            mtd: None,
+            name: None,
+            _p: PhantomData,
+        }
+    }
+
+    /// Registers an MTD device.
+    ///
+    /// Returns a pinned heap-allocated representation of the registration.
+    pub fn new_pinned(
+        name: fmt::Arguments<'_>,
+        geometry: Geometry,
+        data: T::Data,
+        module: &'static ThisModule,
+    ) -> Result<Pin<Box<Self>>> {
+        let mut reg = Pin::from(Box::try_new(Self::new())?);
+        reg.as_mut().register(name, geometry, data, module)?;
+        Ok(reg)
+    }
+
+    /// Registers an MTD device with the rest of the kernel.
+    ///
+    /// The device is writable if `T` implements [`Operations::write`] and `geometry.kind` is not
+    /// [`Kind::Rom`].
+    pub fn register(
+        self: Pin<&mut Self>,
+        name: fmt::Arguments<'_>,
+        geometry: Geometry,
+        data: T::Data,
+        module: &'static ThisModule,
+    ) -> Result {
+        // SAFETY: We never move out of `this`.
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.mtd.is_some() {
+            return Err(EINVAL);
+        }
+
+        if geometry.size == 0
+            || geometry.erase_size == 0
+            || geometry.write_size == 0
+            || geometry.size % u64::from(geometry.erase_size) != 0
+        {
+            return Err(EINVAL);
+        }
+
+        let name = CString::try_from_fmt(name)?;
+        let mut mtd = Box::try_new(UnsafeCell::new(bindings::mtd_info::default()))?;
+        let data_pointer = data.into_foreign();
+
+        // SAFETY: `data_pointer` comes from the call to `into_foreign` above.
+        let guard = ScopeGuard::new(|| unsafe {
+            T::Data::from_foreign(data_pointer);
+        });
+
+        let writable = T::HAS_WRITE && geometry.kind != Kind::Rom;
+        let (kind, mut flags) = geometry.kind.as_raw();
+        if !writable {
+            flags &= !bindings::MTD_WRITEABLE;
+        }
+        let raw = mtd.get_mut();
+        raw.type_ = kind;
+        raw.flags = flags;
+        raw.size = geometry.size;
+        raw.erasesize = geometry.erase_size;
+        raw.writesize = geometry.write_size;
+        raw.writebufsize = geometry.write_size;
+        raw.name = name.as_char_ptr();
+        raw.owner = module.0;
+        raw._read = Some(Self::read_callback);
+        raw._write = if writable {
+            Some(Self::write_callback)
+        } else {
+            None
+        };
+        raw._erase = if T::HAS_ERASE {
+            Some(Self::erase_callback)
+        } else {
+            None
+        };
+        raw.priv_ = data_pointer as _;
+
+        // SAFETY: `mtd` is fully initialised and is boxed, so it remains at the same address until
+        // it is unregistered in `drop`, or forever if it is leaked there.
+        to_result(unsafe {
+            bindings::mtd_device_parse_register(
+                mtd.get(),
+                core::ptr::null(),
+                core::ptr::null_mut(),
+                core::ptr::null(),
+                0,
+            )
+        })?;
+
+        // INVARIANT: `priv_` and `name` were set above and registration succeeded.
+        this.mtd = Some(mtd);
+        this.name = Some(name);
+        guard.dismiss();
+        Ok(())
+    }
+
+    /// Returns the context data of `mtd`.
+    ///
+    /// # Safety
+    ///
+    /// `mtd` must be the device of a registration of this type, and the registration must
+    /// outlive the returned borrow.
+    unsafe fn data<'a>(mtd: *mut bindings::mtd_info) -> <T::Data as ForeignOwnable>::Borrowed<'a> {
+        // SAFETY: By the safety requirements, `priv_` holds a value returned by `into_foreign`,
+        // and `from_foreign` is only called once the device is unregistered.
+        unsafe { T::Data::borrow((*mtd).priv_) }
+    }
+
+    unsafe extern "C" fn read_callback(
+        mtd: *mut bindings::mtd_info,
+        from: bindings::loff_t,
+        len: usize,
+        retlen: *mut usize,
+        buf: *mut u8,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The MTD core only calls this with our device, a buffer valid for `len`
+            // bytes and a valid `retlen`.
+            let (data, buf) =
+                unsafe { (Self::data(mtd), core::slice::from_raw_parts_mut(buf, len)) };
+            let read = T::read(data, from as _, buf)?;
+            // SAFETY: `retlen` is valid.
+            unsafe { *retlen = read };
+            Ok(0)
+        }
+    }
+
+    unsafe extern "C" fn write_callback(
+        mtd: *mut bindings::mtd_info,
+        to: bindings::loff_t,
+        len: usize,
+        retlen: *mut usize,
+        buf: *const u8,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The MTD core only calls this with our device, a buffer valid for `len`
+            // bytes and a valid `retlen`.
+            let (data, buf) = unsafe { (Self::data(mtd), core::slice::from_raw_parts(buf, len)) };
+            let written = T::write(data, to as _, buf)?;
+            // SAFETY: `retlen` is valid.
+            unsafe { *retlen = written };
+            Ok(0)
+        }
+    }
+
+    unsafe extern "C" fn erase_callback(
+        mtd: *mut bindings::mtd_info,
+        instr: *mut bindings::erase_info,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The MTD core only calls this with our device and a valid request.
+            let (data, addr, len) = unsafe { (Self::data(mtd), (*instr).addr, (*instr).len) };
+            if let Err(e) = T::erase(data, addr, len) {
+                // SAFETY: `instr` is valid.
+                unsafe { (*instr).fail_addr = bindings::MTD_FAIL_ADDR_UNKNOWN as _ };
+                return Err(e);
+            }
+            Ok(0)
+        }
+    }
+}
+
+impl<T: Operations> Default for Registration<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// SAFETY: `Registration` does not expose any of its state across threads.
+unsafe impl<T: Operations> Sync for Registration<T> {}
+
+// SAFETY: `Registration` is not restricted to a single thread, and its `T::Data` is also `Send`
+// so it may be moved to different threads.
+#[allow(clippy::non_send_fields_in_send_ty)]
+unsafe impl<T: Operations> Send for Registration<T> {}
+
+impl<T: Operations> Drop for Registration<T> {
+    /// Removes the registration from the kernel if it has completed successfully before.
+    fn drop(&mut self) {
+        if let Some(mut mtd) = self.mtd.take() {
+            // SAFETY: By the type invariants, the device is registered.
+            let ret = unsafe { bindings::mtd_device_unregister(mtd.get()) };
+            if ret != 0 {
+                // The device is still in use, and its users keep calling the callbacks. They hold
+                // a reference to the module, so the callbacks remain valid, but the device, its
+                // name and its data must stay alive as well.
+                crate::pr_warn!("MTD device still in use, leaking it ({})\n", ret);
+                Box::leak(mtd);
+                if let Some(name) = self.name.take() {
+                    core::mem::forget(name);
+                }
+                return;
+            }
+
+            // SAFETY: By the type invariants, `priv_` came from `into_foreign`, and no callbacks
+            // can run anymore now that the device is unregistered.
+            unsafe { T::Data::from_foreign(mtd.get_mut().priv_) };
+        }
+    }
+}
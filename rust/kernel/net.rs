@@ -188,6 +188,34 @@ pub enum SocketAddr {
     V6(SocketAddrV6),
 }
 
+impl SocketAddr {
+    /// Returns the protocol family of the address, a pointer to it and its length, as expected by
+    /// the socket functions.
+    fn as_raw(&self) -> (u32, *mut bindings::sockaddr, usize) {
+        match self {
+            SocketAddr::V4(addr) => (
+                bindings::PF_INET,
+                addr as *const _ as _,
+                core::mem::size_of::<bindings::sockaddr_in>(),
+            ),
+            SocketAddr::V6(addr) => (
+                bindings::PF_INET6,
+                addr as *const _ as _,
+                core::mem::size_of::<bindings::sockaddr_in6>(),
+            ),
+        }
+    }
+}
+
+// `SocketAddr::as_raw` passes the addresses as a `sockaddr`, which the C side reads the family
+// from.
+crate::static_assert!(
+    core::mem::size_of::<bindings::sockaddr_in>() >= core::mem::size_of::<bindings::sockaddr>()
+);
+crate::static_assert!(
+    core::mem::size_of::<bindings::sockaddr_in6>() >= core::mem::size_of::<bindings::sockaddr>()
+);
+
 /// An IPv4 socket address.
 ///
 /// This is equivalent to C's `sockaddr_in`.
@@ -246,18 +274,7 @@ impl TcpListener {
     /// It is configured to listen on the given socket address for the given namespace.
     pub fn try_new(ns: &Namespace, addr: &SocketAddr) -> Result<Self> {
         let mut socket = core::ptr::null_mut();
-        let (pf, addr, addrlen) = match addr {
-            SocketAddr::V4(addr) => (
-                bindings::PF_INET,
-                addr as *const _ as _,
-                core::mem::size_of::<bindings::sockaddr_in>(),
-            ),
-            SocketAddr::V6(addr) => (
-                bindings::PF_INET6,
-                addr as *const _ as _,
-                core::mem::size_of::<bindings::sockaddr_in6>(),
-            ),
-        };
+        let (pf, addr, addrlen) = addr.as_raw();
 
         // SAFETY: The namespace is valid and the output socket pointer is valid for write.
         to_result(unsafe {
@@ -390,3 +407,85 @@ impl Drop for TcpStream {
         unsafe { bindings::sock_release(self.sock) };
     }
 }
+
+/// A UDP socket that sends datagrams to a single peer.
+///
+/// # Invariants
+///
+/// The socket pointer is always non-null and valid.
+pub struct UdpSocket {
+    pub(crate) sock: *mut bindings::socket,
+}
+
+// SAFETY: `UdpSocket` is just a wrapper for a kernel socket, which can be used from any thread.
+unsafe impl Send for UdpSocket {}
+
+// SAFETY: `UdpSocket` is just a wrapper for a kernel socket, which can be used from any thread.
+unsafe impl Sync for UdpSocket {}
+
+impl UdpSocket {
+    /// Creates a new UDP socket in the given namespace, connected to the given socket address.
+    ///
+    /// Connecting a UDP socket does not send anything, it only sets the destination of
+    /// [`UdpSocket::send`], so this does not fail if nothing listens at `addr`.
+    pub fn try_connect(ns: &Namespace, addr: &SocketAddr) -> Result<Self> {
+        let mut socket = core::ptr::null_mut();
+        let (pf, addr, addrlen) = addr.as_raw();
+
+        // SAFETY: The namespace is valid and the output socket pointer is valid for write.
+        to_result(unsafe {
+            bindings::sock_create_kern(
+                ns.0.get(),
+                pf as _,
+                bindings::sock_type_SOCK_DGRAM as _,
+                bindings::IPPROTO_UDP as _,
+                &mut socket,
+            )
+        })?;
+
+        // INVARIANT: The socket was just created, so it is valid.
+        let udp = Self { sock: socket };
+
+        // SAFETY: The type invariant guarantees that the socket is valid, and `addr` and `addrlen`
+        // were initialised based on valid values provided in the address enum.
+        to_result(unsafe { bindings::kernel_connect(socket, addr, addrlen as _, 0) })?;
+
+        Ok(udp)
+    }
+
+    /// Sends `buf` as a single datagram to the peer the socket is connected to.
+    ///
+    /// On success, returns the number of bytes sent, which is the length of `buf`. Datagrams that
+    /// are too large are rejected with [`crate::error::code::EMSGSIZE`]. Delivery is not
+    /// guaranteed: errors reported by the peer, such as `ECONNREFUSED`, may be returned by a later
+    /// call instead.
+    ///
+    /// If the send buffer of the socket is full, one of two behaviours will occur:
+    /// - If `block` is `false`, returns [`crate::error::code::EAGAIN`];
+    /// - If `block` is `true`, blocks until an error occurs or the datagram is sent.
+    pub fn send(&self, buf: &[u8], block: bool) -> Result<usize> {
+        let mut msg = bindings::msghdr {
+            msg_flags: if block { 0 } else { bindings::MSG_DONTWAIT },
+            ..bindings::msghdr::default()
+        };
+        let mut vec = bindings::kvec {
+            iov_base: buf.as_ptr() as *mut u8 as _,
+            iov_len: buf.len(),
+        };
+        // SAFETY: The type invariant guarantees that the socket is valid, and `vec` was
+        // initialised with the input buffer.
+        let r = unsafe { bindings::kernel_sendmsg(self.sock, &mut msg, &mut vec, 1, vec.iov_len) };
+        if r < 0 {
+            Err(Error::from_kernel_errno(r))
+        } else {
+            Ok(r as _)
+        }
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        // SAFETY: The type invariant guarantees that the socket is valid.
+        unsafe { bindings::sock_release(self.sock) };
+    }
+}
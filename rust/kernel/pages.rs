@@ -5,8 +5,12 @@
 //! TODO: This module is a work in progress.
 
 use crate::{
-    bindings, error::code::*, io_buffer::IoBufferReader, user_ptr::UserSlicePtrReader, Result,
-    PAGE_SIZE,
+    bindings,
+    error::code::*,
+    io_buffer::IoBufferReader,
+    types::{GfpFlags, NumaNode},
+    user_ptr::UserSlicePtrReader,
+    Result, PAGE_SIZE,
 };
 use core::{marker::PhantomData, ptr};
 
@@ -23,16 +27,33 @@ pub struct Pages<const ORDER: u32> {
 }
 
 impl<const ORDER: u32> Pages<ORDER> {
-    /// Allocates a new set of contiguous pages.
+    /// Allocates a new set of contiguous, zeroed pages.
     pub fn new() -> Result<Self> {
-        // TODO: Consider whether we want to allow callers to specify flags.
+        Self::new_with_flags(GfpFlags::KERNEL | GfpFlags::ZERO | GfpFlags::HIGHMEM)
+    }
+
+    /// Allocates a new set of contiguous pages with the given allocation flags.
+    ///
+    /// The pages are only accessed through temporary mappings, so [`GfpFlags::HIGHMEM`] may be
+    /// included.
+    pub fn new_with_flags(flags: GfpFlags) -> Result<Self> {
+        // SAFETY: This only allocates pages. We check that it succeeds in the next statement.
+        let pages = unsafe { bindings::alloc_pages(flags.as_raw(), ORDER) };
+        if pages.is_null() {
+            return Err(ENOMEM);
+        }
+        // INVARIANTS: We checked that the allocation above succeeded.
+        Ok(Self { pages })
+    }
+
+    /// Allocates a new set of contiguous pages with the given allocation flags, on `node` if
+    /// possible.
+    ///
+    /// The pages come from another node when `node` runs out of memory, unless
+    /// `__GFP_THISNODE` is included in `flags`.
+    pub fn new_on_node(node: NumaNode, flags: GfpFlags) -> Result<Self> {
         // SAFETY: This only allocates pages. We check that it succeeds in the next statement.
-        let pages = unsafe {
-            bindings::alloc_pages(
-                bindings::GFP_KERNEL | bindings::__GFP_ZERO | bindings::__GFP_HIGHMEM,
-                ORDER,
-            )
-        };
+        let pages = unsafe { bindings::alloc_pages_node(node.id(), flags.as_raw(), ORDER) };
         if pages.is_null() {
             return Err(ENOMEM);
         }
@@ -0,0 +1,396 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Performance events.
+//!
+//! Allows Rust modules to register software PMUs that expose their own counters to `perf`.
+//!
+//! C header: [`include/linux/perf_event.h`](../../../../include/linux/perf_event.h)
+//!
+//! Reference: <https://perf.wiki.kernel.org/>
+
+use crate::{
+    bindings,
+    error::{code::*, from_kernel_result},
+    str::CString,
+    to_result,
+    types::ForeignOwnable,
+    Result, ScopeGuard, ThisModule,
+};
+use alloc::boxed::Box;
+use core::{cell::UnsafeCell, fmt, marker::PhantomData, pin::Pin};
+use macros::vtable;
+
+/// Flags passed to the `add`, `start` and `stop` callbacks.
+pub mod flags {
+    use crate::bindings;
+
+    /// The event should be started as soon as it is added.
+    pub const START: i32 = bindings::PERF_EF_START as _;
+
+    /// The event count should be reloaded on start.
+    pub const RELOAD: i32 = bindings::PERF_EF_RELOAD as _;
+
+    /// The event count should be updated on stop.
+    pub const UPDATE: i32 = bindings::PERF_EF_UPDATE as _;
+}
+
+/// The event is stopped, see `PERF_HES_STOPPED`.
+const STATE_STOPPED: i32 = bindings::PERF_HES_STOPPED as _;
+
+/// The count of the event is up to date, see `PERF_HES_UPTODATE`.
+const STATE_UPTODATE: i32 = bindings::PERF_HES_UPTODATE as _;
+
+/// Wraps the kernel's `struct perf_event`.
+///
+/// # Invariants
+///
+/// The pointer `Event::ptr` is non-null and valid.
+pub struct Event {
+    ptr: *mut bindings::perf_event,
+}
+
+impl Event {
+    /// Creates a new event wrapper.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `ptr` is non-null and valid for the lifetime of the object.
+    unsafe fn from_ptr(ptr: *mut bindings::perf_event) -> Self {
+        // INVARIANT: The safety requirements guarantee the invariants.
+        Self { ptr }
+    }
+
+    /// Returns the `config` field of the attributes the event was created with.
+    ///
+    /// Drivers usually use it to select which of their counters the event refers to.
+    pub fn config(&self) -> u64 {
+        // SAFETY: `self.ptr` is valid by the type invariants.
+        unsafe { (*self.ptr).attr.config }
+    }
+
+    /// Returns the CPU the event is bound to, or `None` if it follows a task.
+    pub fn cpu(&self) -> Option<u32> {
+        // SAFETY: `self.ptr` is valid by the type invariants.
+        let cpu = unsafe { (*self.ptr).cpu };
+        cpu.try_into().ok()
+    }
+
+    /// Returns the sampling period, or `None` if the event is only counting.
+    pub fn sample_period(&self) -> Option<u64> {
+        // SAFETY: `self.ptr` is valid by the type invariants.
+        let period = unsafe { (*self.ptr).hw.sample_period };
+        if period == 0 {
+            None
+        } else {
+            Some(period)
+        }
+    }
+
+    /// Returns the current count of the event.
+    pub fn count(&self) -> u64 {
+        // SAFETY: `self.ptr` is valid by the type invariants.
+        unsafe { bindings::local64_read(&mut (*self.ptr).count) as _ }
+    }
+
+    /// Adds `delta` to the count of the event.
+    pub fn add_count(&self, delta: u64) {
+        // SAFETY: `self.ptr` is valid by the type invariants, and `local64_add` is atomic.
+        unsafe { bindings::local64_add(delta as _, &mut (*self.ptr).count) };
+    }
+
+    /// Signals that the sampling period has elapsed, recording a sample for the current context.
+    ///
+    /// Returns `true` if the event should be throttled, in which case the caller should stop
+    /// generating samples until the event is started again.
+    ///
+    /// It must be called from interrupt context (for example, from an `hrtimer` callback), which
+    /// is where software PMUs usually generate their samples.
+    pub fn overflow(&self) -> bool {
+        let mut data = bindings::perf_sample_data::default();
+        // SAFETY: `self.ptr` is valid by the type invariants, and `data` is a valid local.
+        unsafe {
+            bindings::perf_sample_data_init(&mut data, 0, (*self.ptr).hw.last_period);
+            bindings::perf_event_overflow(self.ptr, &mut data, bindings::get_irq_regs()) != 0
+        }
+    }
+
+    fn prev_count(&self) -> u64 {
+        // SAFETY: `self.ptr` is valid by the type invariants.
+        unsafe { bindings::local64_read(&mut (*self.ptr).hw.prev_count) as _ }
+    }
+
+    fn set_prev_count(&self, value: u64) {
+        // SAFETY: `self.ptr` is valid by the type invariants.
+        unsafe { bindings::local64_set(&mut (*self.ptr).hw.prev_count, value as _) };
+    }
+
+    fn state(&self) -> i32 {
+        // SAFETY: `self.ptr` is valid by the type invariants. The state is only accessed by the
+        // callbacks of the PMU, which the perf core serialises for each event.
+        unsafe { (*self.ptr).hw.state }
+    }
+
+    fn set_state(&self, state: i32) {
+        // SAFETY: As in `state`.
+        unsafe { (*self.ptr).hw.state = state };
+    }
+}
+
+/// Corresponds to the callbacks of the kernel's `struct pmu`.
+///
+/// Implementers only need to be able to read the current value of a counter; the bookkeeping
+/// required by the perf core (tracking the previous value and accumulating deltas) is done by
+/// [`Registration`].
+#[vtable]
+pub trait Operations {
+    /// The type of the context data stored in the registration and made available to callbacks.
+    type Data: ForeignOwnable + Send + Sync = ();
+
+    /// Validates a new event.
+    ///
+    /// Called only for events whose type matches the registered PMU, so implementers typically
+    /// only need to check [`Event::config`].
+    fn event_init(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>, _event: &Event) -> Result {
+        Ok(())
+    }
+
+    /// Returns the current value of the counter selected by `event`.
+    ///
+    /// The value is expected to be monotonically increasing.
+    fn read(data: <Self::Data as ForeignOwnable>::Borrowed<'_>, event: &Event) -> u64;
+
+    /// Called when the event starts counting, after the count has been snapshotted.
+    ///
+    /// Sampling PMUs usually start their sampling timer here.
+    fn start(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>, _event: &Event, _flags: i32) {}
+
+    /// Called when the event stops counting, before the final count is accumulated.
+    ///
+    /// Sampling PMUs usually cancel their sampling timer here.
+    fn stop(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>, _event: &Event, _flags: i32) {}
+}
+
+/// A registration of a software PMU.
+///
+/// Events of the PMU hold a reference to the module given when registering, so it cannot be
+/// unloaded while they exist. The registration is meant to be owned by that module, so that it is
+/// only dropped once the events are gone: dropping it earlier frees data that the callbacks of
+/// the remaining events still use.
+///
+/// # Invariants
+///
+/// `data` holds a pointer returned by [`ForeignOwnable::into_foreign`] when `registered` is
+/// `true`.
+pub struct Registration<T: Operations> {
+    pmu: UnsafeCell<bindings::pmu>,
+    data: *const core::ffi::c_void,
+    name: Option<CString>,
+    registered: bool,
+    _p: PhantomData<T>,
+}
+
+impl<T: Operations> Registration<T> {
+    /// Creates a new [`Registration`] but does not register it yet.
+    ///
+    /// It is allowed to move.
+    pub fn new() -> Self {
+        // INVARIANT: `registered` is `false`.
+        Self {
+            pmu: UnsafeCell::new(bindings::pmu::default()),
+            data: core::ptr::null(),
+            name: None,
+            registered: false,
+            _p: PhantomData,
+        }
+    }
+
+    /// Registers a PMU.
+    ///
+    /// Returns a pinned heap-allocated representation of the registration.
+    pub fn new_pinned(
+        name: fmt::Arguments<'_>,
+        module: &'static ThisModule,
+        data: T::Data,
+    ) -> Result<Pin<Box<Self>>> {
+        let mut reg = Pin::from(Box::try_new(Self::new())?);
+        reg.as_mut().register(name, module, data)?;
+        Ok(reg)
+    }
+
+    /// Registers a PMU with the perf core.
+    ///
+    /// The PMU gets a dynamically allocated type, which userspace can find in
+    /// `/sys/bus/event_source/devices/<name>/type`.
+    ///
+    /// Events of the PMU hold a reference to `module`, which must be the module that owns the
+    /// registration.
+    ///
+    /// It must be pinned because the memory block that represents the registration is
+    /// self-referential.
+    pub fn register(
+        self: Pin<&mut Self>,
+        name: fmt::Arguments<'_>,
+        module: &'static ThisModule,
+        data: T::Data,
+    ) -> Result {
+        // SAFETY: We never move out of `this`.
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.registered {
+            return Err(EINVAL);
+        }
+
+        let name = CString::try_from_fmt(name)?;
+        let data_pointer = data.into_foreign();
+
+        // SAFETY: `data_pointer` comes from the call to `into_foreign` above.
+        let guard = ScopeGuard::new(|| unsafe {
+            T::Data::from_foreign(data_pointer);
+        });
+
+        let pmu = this.pmu.get_mut();
+        pmu.module = module.0;
+        pmu.task_ctx_nr = bindings::perf_event_task_context_perf_sw_context as _;
+        pmu.capabilities = bindings::PERF_PMU_CAP_NO_EXCLUDE as _;
+        pmu.event_init = Some(Self::event_init_callback);
+        pmu.add = Some(Self::add_callback);
+        pmu.del = Some(Self::del_callback);
+        pmu.start = Some(Self::start_callback);
+        pmu.stop = Some(Self::stop_callback);
+        pmu.read = Some(Self::read_callback);
+        this.data = data_pointer;
+
+        // SAFETY: `pmu` is fully initialised and, since `self` is pinned, will remain at the same
+        // address until it is unregistered in `drop`.
+        to_result(unsafe { bindings::perf_pmu_register(this.pmu.get(), name.as_char_ptr(), -1) })?;
+
+        // INVARIANT: `data` was set above and registration succeeded.
+        this.registered = true;
+        this.name = Some(name);
+        guard.dismiss();
+        Ok(())
+    }
+
+    /// Returns the context data of the registration that owns `event`.
+    ///
+    /// # Safety
+    ///
+    /// `event` must be a valid event created by the PMU of a registered [`Registration<T>`].
+    unsafe fn data<'a>(
+        event: *mut bindings::perf_event,
+    ) -> <T::Data as ForeignOwnable>::Borrowed<'a> {
+        // SAFETY: The safety requirements guarantee that `pmu` is embedded in a registration.
+        // `UnsafeCell` is transparent, so a pointer to the `pmu` field has the same address.
+        let reg = crate::container_of!(unsafe { (*event).pmu }, Self, pmu);
+        // SAFETY: The registration is registered, so by the type invariants `data` was returned
+        // by `into_foreign`. It is only released in `drop`, after the PMU is unregistered.
+        unsafe { T::Data::borrow((*reg).data) }
+    }
+
+    /// Accumulates the counter delta since the last snapshot into the event count.
+    fn update(data: <T::Data as ForeignOwnable>::Borrowed<'_>, event: &Event) {
+        let now = T::read(data, event);
+        let prev = event.prev_count();
+        event.set_prev_count(now);
+        event.add_count(now.wrapping_sub(prev));
+    }
+
+    unsafe extern "C" fn event_init_callback(event: *mut bindings::perf_event) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The perf core only calls this callback with valid events.
+            let (attr_type, pmu_type) = unsafe { ((*event).attr.type_, (*(*event).pmu).type_) };
+            if attr_type != pmu_type as u32 {
+                return Err(ENOENT);
+            }
+            // SAFETY: The event belongs to this PMU, so it is safe to retrieve the data.
+            let data = unsafe { Self::data(event) };
+            // SAFETY: `event` is valid for the duration of the callback.
+            T::event_init(data, unsafe { &Event::from_ptr(event) })?;
+            Ok(0)
+        }
+    }
+
+    unsafe extern "C" fn add_callback(
+        event: *mut bindings::perf_event,
+        flags: core::ffi::c_int,
+    ) -> core::ffi::c_int {
+        // SAFETY: `event` is valid for the duration of the callback.
+        let ev = unsafe { Event::from_ptr(event) };
+        // The event has no count to update until it is started.
+        ev.set_state(STATE_STOPPED | STATE_UPTODATE);
+        if flags & flags::START != 0 {
+            // SAFETY: The safety requirements are the same as `start_callback`'s.
+            unsafe { Self::start_callback(event, flags::RELOAD) };
+        }
+        0
+    }
+
+    unsafe extern "C" fn del_callback(event: *mut bindings::perf_event, _flags: core::ffi::c_int) {
+        // SAFETY: The safety requirements are the same as `stop_callback`'s.
+        unsafe { Self::stop_callback(event, flags::UPDATE) };
+    }
+
+    unsafe extern "C" fn start_callback(event: *mut bindings::perf_event, flags: core::ffi::c_int) {
+        // SAFETY: `event` is valid for the duration of the callback.
+        let ev = unsafe { Event::from_ptr(event) };
+        // SAFETY: The perf core only calls this callback with valid events of this PMU.
+        ev.set_prev_count(T::read(unsafe { Self::data(event) }, &ev));
+        ev.set_state(0);
+        T::start(unsafe { Self::data(event) }, &ev, flags);
+    }
+
+    unsafe extern "C" fn stop_callback(event: *mut bindings::perf_event, flags: core::ffi::c_int) {
+        // SAFETY: `event` is valid for the duration of the callback.
+        let ev = unsafe { Event::from_ptr(event) };
+        if ev.state() & STATE_STOPPED == 0 {
+            // SAFETY: The perf core only calls this callback with valid events of this PMU.
+            T::stop(unsafe { Self::data(event) }, &ev, flags);
+            ev.set_state(ev.state() | STATE_STOPPED);
+        }
+        // The count is only updated once after the event was started, since `prev_count` is not
+        // meaningful otherwise.
+        if flags & flags::UPDATE != 0 && ev.state() & STATE_UPTODATE == 0 {
+            // SAFETY: As above.
+            Self::update(unsafe { Self::data(event) }, &ev);
+            ev.set_state(ev.state() | STATE_UPTODATE);
+        }
+    }
+
+    unsafe extern "C" fn read_callback(event: *mut bindings::perf_event) {
+        // SAFETY: `event` is valid for the duration of the callback.
+        let ev = unsafe { Event::from_ptr(event) };
+        // The count of a stopped event was updated when it stopped.
+        if ev.state() & STATE_STOPPED == 0 {
+            // SAFETY: The perf core only calls this callback with valid events of this PMU.
+            Self::update(unsafe { Self::data(event) }, &ev);
+        }
+    }
+}
+
+impl<T: Operations> Default for Registration<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// SAFETY: `Registration` does not expose any of its state across threads.
+unsafe impl<T: Operations> Sync for Registration<T> {}
+
+// SAFETY: `Registration` is not restricted to a single thread, and its `T::Data` is also `Send`
+// so it may be moved to different threads.
+#[allow(clippy::non_send_fields_in_send_ty)]
+unsafe impl<T: Operations> Send for Registration<T> {}
+
+impl<T: Operations> Drop for Registration<T> {
+    /// Removes the registration from the kernel if it has completed successfully before.
+    fn drop(&mut self) {
+        if self.registered {
+            // SAFETY: `registered` being `true` indicates that a previous call to
+            // `perf_pmu_register` succeeded.
+            unsafe { bindings::perf_pmu_unregister(self.pmu.get()) };
+
+            // SAFETY: The type invariants guarantee that `data` came from `into_foreign`, and no
+            // callbacks can run anymore now that the PMU is unregistered.
+            unsafe { T::Data::from_foreign(self.data) };
+        }
+    }
+}
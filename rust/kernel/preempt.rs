@@ -0,0 +1,71 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Execution context.
+//!
+//! Code that is shared between callbacks that run in different contexts, for example a statistics
+//! recorder called both from file operations and from timers, can use these functions to find out
+//! where it is running, and [`debug_assert_sleepable!`] to check that it is allowed to sleep.
+//!
+//! C header: [`include/linux/preempt.h`](../../../../include/linux/preempt.h)
+
+use crate::bindings;
+
+/// Returns `true` if running in interrupt context, that is, in a hard or soft interrupt handler,
+/// in a non-maskable interrupt handler, or with bottom halves disabled.
+pub fn in_interrupt() -> bool {
+    // SAFETY: It only reads the preemption count of the current CPU.
+    unsafe { bindings::in_interrupt() }
+}
+
+/// Returns `true` if running in task context, that is, on behalf of a task and not in any kind
+/// of interrupt handler.
+///
+/// Unlike `!in_interrupt()`, this is also `true` with bottom halves disabled. It does not mean
+/// that sleeping is allowed, since preemption or interrupts may be disabled.
+pub fn in_task() -> bool {
+    // SAFETY: It only reads the preemption count of the current CPU.
+    unsafe { bindings::in_task() }
+}
+
+/// Returns `true` if the current task may be preempted, in which case it may also sleep.
+///
+/// Without `CONFIG_PREEMPT_COUNT`, the kernel does not track whether preemption is disabled and
+/// this always returns `false`.
+pub fn preemptible() -> bool {
+    // SAFETY: It only reads the preemption count and the interrupt state of the current CPU.
+    unsafe { bindings::preemptible() }
+}
+
+/// Checks that the caller is allowed to sleep.
+///
+/// With `CONFIG_DEBUG_ATOMIC_SLEEP`, it reports the caller, with a stack trace, if it runs in
+/// atomic context, for example in an interrupt handler or with a spinlock held, like `might_sleep`
+/// does in C. Otherwise, it does nothing.
+///
+/// Functions that may sleep, but only do so in some of their paths, should use it at the start, so
+/// that callers from the wrong context are caught even if the paths that sleep are rare.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::debug_assert_sleepable;
+///
+/// fn flush(buffer: &mut [u8]) {
+///     // The buffer is written to the device with a call that sleeps when the device is busy.
+///     debug_assert_sleepable!();
+///     buffer.fill(0);
+/// }
+/// ```
+#[macro_export]
+macro_rules! debug_assert_sleepable {
+    () => {
+        #[cfg(CONFIG_DEBUG_ATOMIC_SLEEP)]
+        // SAFETY: The file name is a static null-terminated string.
+        unsafe {
+            $crate::bindings::__might_sleep(
+                $crate::c_str!(::core::file!()).as_char_ptr(),
+                ::core::line!() as _,
+            )
+        };
+    };
+}
@@ -40,3 +40,5 @@ pub use super::static_assert;
 pub use super::error::{code::*, Error, Result};
 
 pub use super::{str::CStr, ARef, ThisModule};
+
+pub use super::{box_ext::BoxExt, vec_ext::VecExt};
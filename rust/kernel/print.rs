@@ -13,7 +13,6 @@ use core::{
 
 use crate::str::RawFormatter;
 
-#[cfg(CONFIG_PRINTK)]
 use crate::bindings;
 
 // Called from `vsprintf` with format specifier `%pA`.
@@ -88,6 +87,137 @@ pub mod format_strings {
     pub static CONT: [u8; LENGTH] = generate(true, bindings::KERN_CONT);
 }
 
+/// The level of a log message.
+///
+/// Corresponds to the kernel's `KERN_*` constants, from the most to the least severe. It allows
+/// choosing the level at runtime with [`printk!`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// The system is unusable, see [`pr_emerg!`].
+    Emerg,
+
+    /// Action must be taken immediately, see [`pr_alert!`].
+    Alert,
+
+    /// Critical conditions, see [`pr_crit!`].
+    Crit,
+
+    /// Error conditions, see [`pr_err!`].
+    Err,
+
+    /// Warning conditions, see [`pr_warn!`].
+    Warning,
+
+    /// Normal but significant conditions, see [`pr_notice!`].
+    Notice,
+
+    /// Informational messages, see [`pr_info!`].
+    Info,
+
+    /// Debug-level messages.
+    ///
+    /// Unlike [`pr_debug!`], messages printed at this level with [`printk!`] are never compiled
+    /// out.
+    Debug,
+}
+
+impl Level {
+    /// Returns the fixed format string for the level.
+    ///
+    /// Public but hidden since it should only be used from public macros.
+    #[doc(hidden)]
+    pub fn format_string(self) -> &'static [u8; format_strings::LENGTH] {
+        match self {
+            Level::Emerg => &format_strings::EMERG,
+            Level::Alert => &format_strings::ALERT,
+            Level::Crit => &format_strings::CRIT,
+            Level::Err => &format_strings::ERR,
+            Level::Warning => &format_strings::WARNING,
+            Level::Notice => &format_strings::NOTICE,
+            Level::Info => &format_strings::INFO,
+            Level::Debug => &format_strings::DEBUG,
+        }
+    }
+}
+
+/// A pointer formatted like the kernel's `%p` specifier.
+///
+/// The address is hashed before being printed, so messages do not reveal the layout of the kernel
+/// address space. Prefer it over formatting raw pointers with `{:p}` in log messages and in files
+/// visible to users.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::print::HashedPtr;
+///
+/// let value = 42;
+/// pr_info!("value at {}\n", HashedPtr::new(&value));
+/// ```
+#[derive(Clone, Copy)]
+pub struct HashedPtr(*const c_void);
+
+impl HashedPtr {
+    /// Creates a new adapter for `ptr`.
+    pub fn new<T: ?Sized>(ptr: *const T) -> Self {
+        Self(ptr as *const c_void)
+    }
+}
+
+impl fmt::Display for HashedPtr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format_ptr(f, b"%p\0", self.0)
+    }
+}
+
+/// A pointer formatted like the kernel's `%pK` specifier, for files that expose kernel addresses
+/// to privileged users.
+///
+/// What is printed depends on the `kptr_restrict` sysctl: with the default of `0`, the address is
+/// hashed like with [`HashedPtr`]; with `1`, it is only printed as is if the current task has
+/// `CAP_SYSLOG`, and as zeros otherwise; with `2`, it is always printed as zeros. The check is
+/// made against the current task, so it must be formatted from the task that reads the output,
+/// for example in a `read` or `show` callback, and not from interrupt context, where `pK-error`
+/// is printed instead.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::print::RestrictedPtr;
+///
+/// let value = 42;
+/// pr_info!("value at {}\n", RestrictedPtr::new(&value));
+/// ```
+#[derive(Clone, Copy)]
+pub struct RestrictedPtr(*const c_void);
+
+impl RestrictedPtr {
+    /// Creates a new adapter for `ptr`.
+    pub fn new<T: ?Sized>(ptr: *const T) -> Self {
+        Self(ptr as *const c_void)
+    }
+}
+
+impl fmt::Display for RestrictedPtr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format_ptr(f, b"%pK\0", self.0)
+    }
+}
+
+/// Formats `ptr` with `vsprintf` and the null-terminated format string `format`, which must have a
+/// single pointer specifier.
+fn format_ptr(f: &mut fmt::Formatter<'_>, format: &[u8], ptr: *const c_void) -> fmt::Result {
+    // Large enough for a 64-bit address or the placeholders printed before the hashing key is
+    // available or when a restricted pointer cannot be checked, and a null terminator.
+    let mut buf = [0u8; 24];
+    // SAFETY: `buf` is valid for `buf.len()` bytes and the format string is null-terminated with
+    // a single pointer specifier. `snprintf` always null-terminates its output.
+    let len =
+        unsafe { bindings::snprintf(buf.as_mut_ptr() as _, buf.len(), format.as_ptr() as _, ptr) };
+    let len = (len.max(0) as usize).min(buf.len() - 1);
+    f.write_str(core::str::from_utf8(&buf[..len]).map_err(|_| fmt::Error)?)
+}
+
 /// Prints a message via the kernel's [`_printk`].
 ///
 /// Public but hidden since it should only be used from public macros.
@@ -415,3 +545,54 @@ macro_rules! pr_cont (
         $crate::print_macro!($crate::print::format_strings::CONT, true, $($arg)*)
     )
 );
+
+/// Prints a message at the given [`Level`].
+///
+/// It is like the `pr_*!` macros (e.g. [`pr_info!`]), but the level is an expression evaluated at
+/// runtime, which is convenient when it depends on, for example, the severity of an error. Like
+/// in C, a message can be continued with [`pr_cont!`].
+///
+/// Equivalent to the kernel's [`printk`] function.
+///
+/// Mimics the interface of [`std::print!`]. See [`core::fmt`] and
+/// [`alloc::format!`] for information about the formatting syntax.
+///
+/// [`printk`]: https://www.kernel.org/doc/html/latest/core-api/printk-basics.html#c.printk
+/// [`std::print!`]: https://doc.rust-lang.org/std/macro.print.html
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::{pr_cont, printk};
+/// use kernel::print::Level;
+///
+/// let retries = 3;
+/// let level = if retries > 2 { Level::Warning } else { Level::Info };
+/// printk!(level, "took {} retries:", retries);
+/// pr_cont!(" done\n");
+/// ```
+#[cfg(not(testlib))]
+#[macro_export]
+#[allow(clippy::crate_in_macro_def)]
+macro_rules! printk (
+    ($level:expr, $($arg:tt)+) => (
+        // See `print_macro` for why a `match` is used. The level is also evaluated outside the
+        // `unsafe` block.
+        match ($crate::print::Level::format_string($level), format_args!($($arg)+)) {
+            // SAFETY: The format string comes from `Level`, so it is one of the fixed ones, and
+            // `__LOG_PREFIX` is null-terminated, see `print_macro`.
+            (format_string, args) => unsafe {
+                $crate::print::call_printk(format_string, crate::__LOG_PREFIX, args);
+            }
+        }
+    );
+);
+
+/// Stub for doctests
+#[cfg(testlib)]
+#[macro_export]
+macro_rules! printk (
+    ($level:expr, $($arg:tt)+) => (
+        ()
+    );
+);
@@ -0,0 +1,186 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! The proc filesystem.
+//!
+//! Allows Rust code to create entries in `/proc`.
+//!
+//! C header: [`include/linux/proc_fs.h`](../../../../include/linux/proc_fs.h)
+
+use crate::{
+    bindings,
+    error::{code::*, from_kernel_result},
+    seq_file::{SeqFile, SeqFileAdapter, SeqOperations},
+    str::CStr,
+    Result,
+};
+use alloc::boxed::Box;
+
+/// The data of an entry created by [`ProcDirEntry::new_single`].
+struct Single<T> {
+    data: T,
+    show: fn(&T, &mut SeqFile) -> Result,
+}
+
+/// An entry in `/proc`.
+///
+/// The entry is removed when this object is dropped.
+///
+/// # Invariants
+///
+/// `entry` is a valid entry in `/proc`, and `data` is valid while it exists. `free_data` frees
+/// `data`.
+pub struct ProcDirEntry {
+    entry: *mut bindings::proc_dir_entry,
+    data: *mut core::ffi::c_void,
+    free_data: unsafe fn(*mut core::ffi::c_void),
+}
+
+impl ProcDirEntry {
+    /// Creates a read-only file called `name` at the root of `/proc`, whose contents are generated
+    /// by `show` every time the file is read from the start.
+    ///
+    /// This is useful for simple status files and does not require a full
+    /// [`crate::seq_file::SeqOperations`] implementation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kernel::prelude::*;
+    /// use kernel::{c_str, proc_fs::ProcDirEntry, seq_file::SeqFile};
+    ///
+    /// struct Counters {
+    ///     hits: u64,
+    ///     misses: u64,
+    /// }
+    ///
+    /// fn show(counters: &Counters, m: &mut SeqFile) -> Result {
+    ///     writeln!(m, "hits: {}", counters.hits);
+    ///     writeln!(m, "misses: {}", counters.misses);
+    ///     Ok(())
+    /// }
+    ///
+    /// fn create() -> Result<ProcDirEntry> {
+    ///     let counters = Counters { hits: 0, misses: 0 };
+    ///     ProcDirEntry::new_single(c_str!("rust_counters"), counters, show)
+    /// }
+    /// ```
+    pub fn new_single<T: Send + Sync>(
+        name: &CStr,
+        data: T,
+        show: fn(&T, &mut SeqFile) -> Result,
+    ) -> Result<Self> {
+        let data = Box::into_raw(Box::try_new(Single { data, show })?);
+
+        // SAFETY: `name` is a valid C string, and `data` remains valid until the entry is removed
+        // in `drop`. The default mode of `0` makes the file readable by everyone.
+        let entry = unsafe {
+            bindings::proc_create_single_data(
+                name.as_char_ptr(),
+                0,
+                core::ptr::null_mut(),
+                Some(Self::single_show::<T>),
+                data as _,
+            )
+        };
+        if entry.is_null() {
+            // SAFETY: `data` came from `Box::into_raw` above and the entry was not created.
+            drop(unsafe { Box::from_raw(data) });
+            return Err(ENOMEM);
+        }
+
+        // INVARIANT: `entry` was just created with `data`, which `free_single` frees.
+        Ok(Self {
+            entry,
+            data: data as _,
+            free_data: Self::free_single::<T>,
+        })
+    }
+
+    /// Creates a read-only file called `name` at the root of `/proc`, whose records are produced
+    /// by the sequence operations `T`.
+    ///
+    /// `data` is passed to [`SeqOperations::open`] every time the file is opened, and each open
+    /// file has its own private [`SeqOperations::OpenState`].
+    pub fn new_seq_private<T: SeqOperations>(name: &CStr, data: T::OpenData) -> Result<Self>
+    where
+        T::OpenData: Send,
+    {
+        let data = Box::into_raw(Box::try_new(data)?);
+
+        // SAFETY: `name` is a valid C string, and `data` remains valid until the entry is removed
+        // in `drop`. `proc_create_data` stores `data` in `i_private` of the inode of the entry,
+        // where the open callback of `PROC_OPS` expects it. The default mode of `0` makes the
+        // file readable by everyone.
+        let entry = unsafe {
+            bindings::proc_create_data(
+                name.as_char_ptr(),
+                0,
+                core::ptr::null_mut(),
+                &SeqFileAdapter::<T>::PROC_OPS,
+                data as _,
+            )
+        };
+        if entry.is_null() {
+            // SAFETY: `data` came from `Box::into_raw` above and the entry was not created.
+            drop(unsafe { Box::from_raw(data) });
+            return Err(ENOMEM);
+        }
+
+        // INVARIANT: `entry` was just created with `data`, which `free_boxed` frees.
+        Ok(Self {
+            entry,
+            data: data as _,
+            free_data: Self::free_boxed::<T::OpenData>,
+        })
+    }
+
+    unsafe extern "C" fn single_show<T>(
+        m: *mut bindings::seq_file,
+        _v: *mut core::ffi::c_void,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The kernel passes the `seq_file` being shown.
+            let single = unsafe { (*m).private as *const Single<T> };
+            if single.is_null() {
+                debug_assert!(false, "proc entry shown without data");
+                return Err(EINVAL);
+            }
+            // SAFETY: `single_open` sets `private` to the data passed to
+            // `proc_create_single_data`, which remains valid while the entry exists.
+            let single = unsafe { &*single };
+            // SAFETY: The kernel passes the `seq_file` being shown.
+            let mut file = unsafe { SeqFile::from_ptr(m) };
+            (single.show)(&single.data, &mut file)?;
+            Ok(0)
+        }
+    }
+
+    unsafe fn free_single<T>(data: *mut core::ffi::c_void) {
+        // SAFETY: The caller passes the pointer stored by `new_single`, which came from
+        // `Box::into_raw`.
+        drop(unsafe { Box::from_raw(data as *mut Single<T>) });
+    }
+
+    unsafe fn free_boxed<T>(data: *mut core::ffi::c_void) {
+        // SAFETY: The caller passes the pointer stored by `new_seq_private`, which came from
+        // `Box::into_raw`.
+        drop(unsafe { Box::from_raw(data as *mut T) });
+    }
+}
+
+impl Drop for ProcDirEntry {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, `entry` is valid. `proc_remove` waits for ongoing
+        // accesses to complete, so `data` can be freed afterwards.
+        unsafe {
+            bindings::proc_remove(self.entry);
+            (self.free_data)(self.data);
+        }
+    }
+}
+
+// SAFETY: The data of the entry is `Send`, and it is only freed once the entry is removed.
+unsafe impl Send for ProcDirEntry {}
+
+// SAFETY: `ProcDirEntry` has no methods that take `&self`.
+unsafe impl Sync for ProcDirEntry {}
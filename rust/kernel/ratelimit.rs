@@ -0,0 +1,138 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Rate limiting.
+//!
+//! A [`TokenBucket`] throttles operations that are expensive or that user space should not be
+//! able to trigger at will, such as hardware resets requested through debugfs, while still
+//! allowing short bursts.
+
+use crate::bindings;
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// A token bucket, which allows an operation at a steady rate, with bursts of a given size.
+///
+/// The bucket holds up to `burst` tokens and starts full. Each operation takes a token, and a
+/// token is added back every `period`. [`TokenBucket::try_acquire`] fails when the bucket is
+/// empty, so callers can reject or postpone the operation.
+///
+/// Tokens are added lazily, from the time elapsed since the last acquisition, so no timer runs
+/// while the bucket is idle. The state fits in a single atomic, so acquiring tokens never blocks
+/// and may happen in any context, including interrupt handlers.
+///
+/// # Examples
+///
+/// Limiting resets requested through a debugfs file to one every ten seconds, with up to three at
+/// once:
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use core::time::Duration;
+/// use kernel::ratelimit::TokenBucket;
+///
+/// static RESETS: TokenBucket = TokenBucket::new(Duration::from_secs(10), 3);
+///
+/// fn request_reset() -> Result {
+///     if !RESETS.try_acquire() {
+///         return Err(EBUSY);
+///     }
+///     // Reset the hardware.
+///     Ok(())
+/// }
+/// ```
+pub struct TokenBucket {
+    /// The time it takes to add one token, in nanoseconds.
+    period_ns: u64,
+
+    /// The time it takes to fill the bucket from empty, in nanoseconds.
+    capacity_ns: u64,
+
+    /// The time at which the bucket is full again, in nanoseconds, unless it is in the past.
+    full_at: AtomicU64,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket of `burst` tokens, which gets a token back every `period`.
+    ///
+    /// A `burst` of zero is treated as one.
+    pub const fn new(period: Duration, burst: u32) -> Self {
+        let period_ns = period.as_nanos();
+        let period_ns = if period_ns > u64::MAX as u128 {
+            u64::MAX
+        } else {
+            period_ns as u64
+        };
+        let burst = if burst == 0 { 1 } else { burst };
+        Self {
+            period_ns,
+            capacity_ns: period_ns.saturating_mul(burst as u64),
+            full_at: AtomicU64::new(0),
+        }
+    }
+
+    /// Takes a token from the bucket.
+    ///
+    /// Returns `false`, and leaves the bucket unchanged, if it is empty.
+    pub fn try_acquire(&self) -> bool {
+        self.try_acquire_n(1)
+    }
+
+    /// Takes `count` tokens from the bucket at once.
+    ///
+    /// Returns `false`, and leaves the bucket unchanged, if it holds fewer than `count` tokens.
+    pub fn try_acquire_n(&self, count: u32) -> bool {
+        let cost = self.period_ns.saturating_mul(count as u64);
+        let now = now_ns();
+        let mut full_at = self.full_at.load(Ordering::Relaxed);
+        loop {
+            // The bucket is missing the tokens that are added until it is full.
+            let new = full_at.max(now).saturating_add(cost);
+            if new - now > self.capacity_ns {
+                return false;
+            }
+            match self.full_at.compare_exchange_weak(
+                full_at,
+                new,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(current) => full_at = current,
+            }
+        }
+    }
+
+    /// Returns the number of tokens in the bucket.
+    ///
+    /// Other users may take tokens concurrently, so a subsequent [`TokenBucket::try_acquire`]
+    /// may still fail.
+    pub fn available(&self) -> u32 {
+        let now = now_ns();
+        let missing_ns = self.full_at.load(Ordering::Relaxed).saturating_sub(now);
+        match self.period_ns {
+            0 => u32::MAX,
+            period_ns => (self.capacity_ns.saturating_sub(missing_ns) / period_ns) as u32,
+        }
+    }
+
+    /// Returns how long it takes until a token is added to the bucket, or zero if it holds at
+    /// least one.
+    pub fn time_to_next(&self) -> Duration {
+        let now = now_ns();
+        let missing_ns = self.full_at.load(Ordering::Relaxed).saturating_sub(now);
+        Duration::from_nanos(missing_ns.saturating_sub(self.capacity_ns - self.period_ns))
+    }
+
+    /// Fills the bucket.
+    pub fn reset(&self) {
+        self.full_at.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Returns the current time in nanoseconds.
+fn now_ns() -> u64 {
+    // SAFETY: This function has no safety requirements.
+    unsafe { bindings::ktime_get_ns() }
+}
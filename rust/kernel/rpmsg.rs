@@ -0,0 +1,440 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Remote processor messaging (rpmsg) devices, drivers and endpoints.
+//!
+//! Rpmsg channels connect the kernel to services running on coprocessors. Drivers bind to
+//! channels by name and exchange messages through endpoints; every device comes with a default
+//! endpoint whose messages are delivered to [`Driver::callback`], and further endpoints can be
+//! created with [`Endpoint::new`].
+//!
+//! C header: [`include/linux/rpmsg.h`](../../../../include/linux/rpmsg.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/staging/rpmsg.html>
+
+use crate::{
+    bindings, device, driver,
+    error::{code::*, from_kernel_result},
+    str::CStr,
+    to_result,
+    types::ForeignOwnable,
+    Result, ThisModule,
+};
+use core::marker::PhantomData;
+
+/// The address that lets the rpmsg core pick one, or that designates the default remote one.
+pub const ADDR_ANY: u32 = bindings::RPMSG_ADDR_ANY;
+
+/// A registration of an rpmsg driver.
+pub type Registration<T> = driver::Registration<Adapter<T>>;
+
+/// Id of an rpmsg channel, matched by name.
+#[derive(Clone, Copy)]
+pub struct DeviceId {
+    /// The name of the channel. It is truncated to `RPMSG_NAME_SIZE - 1` bytes.
+    pub name: &'static CStr,
+}
+
+// SAFETY: `ZERO` is all zeroed-out and `to_rawid` stores `offset` in
+// `rpmsg_device_id::driver_data`.
+unsafe impl const driver::RawDeviceId for DeviceId {
+    type RawType = bindings::rpmsg_device_id;
+    const ZERO: Self::RawType = bindings::rpmsg_device_id {
+        name: [0; bindings::RPMSG_NAME_SIZE as usize],
+        driver_data: 0,
+    };
+
+    fn to_rawid(&self, offset: isize) -> Self::RawType {
+        let mut id = Self::ZERO;
+        let bytes = self.name.as_bytes_with_nul();
+        let mut i = 0;
+        while i < bytes.len() && i < id.name.len() - 1 {
+            id.name[i] = bytes[i] as _;
+            i += 1;
+        }
+        id.driver_data = offset as _;
+        id
+    }
+}
+
+/// An rpmsg driver.
+pub trait Driver {
+    /// Data stored on device by driver.
+    type Data: ForeignOwnable + Send + Sync + driver::DeviceRemoval = ();
+
+    /// The type holding information about each device id supported by the driver.
+    type IdInfo: 'static = ();
+
+    /// The table of device ids supported by the driver.
+    const ID_TABLE: Option<driver::IdTable<'static, DeviceId, Self::IdInfo>> = None;
+
+    /// Probes for the device with the given id.
+    fn probe(dev: &mut Device, id_info: Option<&Self::IdInfo>) -> Result<Self::Data>;
+
+    /// Cleans any resources up that are associated with the device.
+    ///
+    /// This is called when the driver is detached from the device.
+    fn remove(_data: &Self::Data) {}
+
+    /// Called when a message is received on the default endpoint of the device.
+    ///
+    /// `src` is the address of the sender. Messages that arrive before [`Driver::probe`] returns
+    /// are dropped.
+    fn callback(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _dev: &Device,
+        _msg: &[u8],
+        _src: u32,
+    ) -> Result {
+        Ok(())
+    }
+}
+
+/// An adapter for the registration of rpmsg drivers.
+pub struct Adapter<T: Driver>(T);
+
+impl<T: Driver> driver::DriverOps for Adapter<T> {
+    type RegType = bindings::rpmsg_driver;
+
+    unsafe fn register(
+        reg: *mut bindings::rpmsg_driver,
+        name: &'static CStr,
+        module: &'static ThisModule,
+    ) -> Result {
+        // SAFETY: By the safety requirements of this function (defined in the trait definition),
+        // `reg` is non-null and valid.
+        let rpdrv = unsafe { &mut *reg };
+        rpdrv.drv.name = name.as_char_ptr();
+        rpdrv.probe = Some(probe_callback::<T>);
+        rpdrv.remove = Some(remove_callback::<T>);
+        rpdrv.callback = Some(rx_callback::<T>);
+        if let Some(t) = T::ID_TABLE {
+            rpdrv.id_table = t.as_ref();
+        }
+        // SAFETY: By the safety requirements of this function, `reg` is valid and fully
+        // initialised, and the id table has a static lifetime.
+        to_result(unsafe { bindings::__register_rpmsg_driver(reg, module.0) })
+    }
+
+    unsafe fn unregister(reg: *mut bindings::rpmsg_driver) {
+        // SAFETY: By the safety requirements of this function (defined in the trait definition),
+        // `reg` was passed (and updated) by a previous successful call to
+        // `__register_rpmsg_driver`.
+        unsafe { bindings::unregister_rpmsg_driver(reg) };
+    }
+}
+
+unsafe extern "C" fn probe_callback<T: Driver>(
+    rpdev: *mut bindings::rpmsg_device,
+) -> core::ffi::c_int {
+    from_kernel_result! {
+        // SAFETY: `rpdev` is valid by the contract with the C code. `dev` is alive only for the
+        // duration of this call, so it is guaranteed to remain alive for the lifetime of `dev`.
+        let mut dev = unsafe { Device::from_ptr(rpdev) };
+        // SAFETY: The rpmsg core sets the name of `id` when it creates the device. It does not
+        // copy the context information of the matching entry, so it is looked up by name.
+        let info = unsafe { find_id_info::<T>(&(*rpdev).id) };
+        let data = T::probe(&mut dev, info)?;
+        let ptr = T::Data::into_foreign(data);
+        // SAFETY: `rpdev` is valid for write by the contract with the C code.
+        unsafe { bindings::dev_set_drvdata(&mut (*rpdev).dev, ptr as _) };
+        Ok(0)
+    }
+}
+
+/// Finds the context information of the entry of `T`'s id table that has the same name as `id`.
+///
+/// # Safety
+///
+/// `id` must be valid.
+unsafe fn find_id_info<T: Driver>(id: &bindings::rpmsg_device_id) -> Option<&'static T::IdInfo> {
+    let table = T::ID_TABLE?;
+    let mut entry: *const bindings::rpmsg_device_id = table.as_ref();
+    // SAFETY: The table is zero-terminated, so the loop stops at the sentinel at the latest.
+    // The offsets were stored by `DeviceId::to_rawid`, so they point to the context information
+    // of each entry.
+    unsafe {
+        while (*entry).name[0] != 0 {
+            if (*entry).name == id.name {
+                let ptr = entry
+                    .cast::<u8>()
+                    .offset((*entry).driver_data as _)
+                    .cast::<Option<T::IdInfo>>();
+                // The id table has a static lifetime, so `ptr` is valid for read.
+                return (*ptr).as_ref();
+            }
+            entry = entry.add(1);
+        }
+    }
+    None
+}
+
+unsafe extern "C" fn remove_callback<T: Driver>(rpdev: *mut bindings::rpmsg_device) {
+    // The rpmsg core only destroys the default endpoint after calling `remove`, so destroy it
+    // here to ensure that `rx_callback` cannot use the data after it is freed.
+    // SAFETY: `rpdev` is valid by the contract with the C code, and the core skips destroying
+    // the endpoint when it is null.
+    unsafe {
+        if !(*rpdev).ept.is_null() {
+            bindings::rpmsg_destroy_ept((*rpdev).ept);
+            (*rpdev).ept = core::ptr::null_mut();
+        }
+    }
+
+    // SAFETY: `rpdev` is valid by the contract with the C code.
+    let ptr = unsafe { bindings::dev_get_drvdata(&(*rpdev).dev) };
+    // SAFETY: The value was stored by a previous call to `dev_set_drvdata` in `probe_callback`
+    // above; the value comes from a call to `T::Data::into_foreign`.
+    let data = unsafe { T::Data::from_foreign(ptr) };
+    T::remove(&data);
+    <T::Data as driver::DeviceRemoval>::device_remove(&data);
+}
+
+unsafe extern "C" fn rx_callback<T: Driver>(
+    rpdev: *mut bindings::rpmsg_device,
+    msg: *mut core::ffi::c_void,
+    len: core::ffi::c_int,
+    _priv: *mut core::ffi::c_void,
+    src: u32,
+) -> core::ffi::c_int {
+    from_kernel_result! {
+        // SAFETY: `rpdev` is valid by the contract with the C code.
+        let ptr = unsafe { bindings::dev_get_drvdata(&(*rpdev).dev) };
+        if ptr.is_null() {
+            // The device has not been probed yet.
+            return Ok(0);
+        }
+        // SAFETY: `ptr` was stored by `probe_callback` and is only freed in `remove_callback`,
+        // after it destroys the default endpoint.
+        let data = unsafe { T::Data::borrow(ptr) };
+        // SAFETY: `rpdev` is valid for the duration of the call, and `msg` for `len` bytes.
+        let (dev, msg) = unsafe {
+            (
+                Device::from_ptr(rpdev),
+                core::slice::from_raw_parts(msg as *const u8, len as _),
+            )
+        };
+        T::callback(data, &dev, msg, src)?;
+        Ok(0)
+    }
+}
+
+/// An rpmsg device.
+///
+/// # Invariants
+///
+/// The field `ptr` is non-null and valid for the lifetime of the object.
+pub struct Device {
+    ptr: *mut bindings::rpmsg_device,
+}
+
+impl Device {
+    /// Creates a new device from the given pointer.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be non-null and valid. It must remain valid for the lifetime of the returned
+    /// instance.
+    unsafe fn from_ptr(ptr: *mut bindings::rpmsg_device) -> Self {
+        // INVARIANT: The safety requirements of the function ensure the lifetime invariant.
+        Self { ptr }
+    }
+
+    /// Returns the local address of the channel.
+    pub fn src(&self) -> u32 {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).src }
+    }
+
+    /// Returns the remote address of the channel.
+    pub fn dst(&self) -> u32 {
+        // SAFETY: By the type invariants, `self.ptr` is valid.
+        unsafe { (*self.ptr).dst }
+    }
+
+    /// Sends `msg` to the remote address of the channel through the default endpoint.
+    ///
+    /// Sleeps until there is room for the message, for up to 15 seconds.
+    pub fn send(&self, msg: &[u8]) -> Result {
+        // SAFETY: By the type invariants, `self.ptr` is valid, and so is its default endpoint
+        // while the device is bound.
+        let ept = unsafe { (*self.ptr).ept };
+        // SAFETY: `ept` is valid and `msg` is valid for `msg.len()` bytes.
+        to_result(unsafe { bindings::rpmsg_send(ept, msg.as_ptr() as _, msg.len().try_into()?) })
+    }
+
+    /// Sends `msg` through the default endpoint without sleeping.
+    ///
+    /// Returns `ENOMEM` if there is no room for the message.
+    pub fn try_send(&self, msg: &[u8]) -> Result {
+        // SAFETY: By the type invariants, `self.ptr` is valid, and so is its default endpoint
+        // while the device is bound.
+        let ept = unsafe { (*self.ptr).ept };
+        // SAFETY: `ept` is valid and `msg` is valid for `msg.len()` bytes.
+        to_result(unsafe { bindings::rpmsg_trysend(ept, msg.as_ptr() as _, msg.len().try_into()?) })
+    }
+}
+
+// SAFETY: The device returned by `raw_device` is the raw rpmsg device.
+unsafe impl device::RawDevice for Device {
+    fn raw_device(&self) -> *mut bindings::device {
+        // SAFETY: By the type invariants, we know that `self.ptr` is non-null and valid.
+        unsafe { &mut (*self.ptr).dev }
+    }
+}
+
+/// Handles the messages received by an [`Endpoint`].
+pub trait EndpointHandler {
+    /// The type of the context data stored in the endpoint and made available to the callback.
+    type Data: ForeignOwnable + Send + Sync = ();
+
+    /// Called when a message is received on the endpoint.
+    ///
+    /// `src` is the address of the sender.
+    fn receive(data: <Self::Data as ForeignOwnable>::Borrowed<'_>, msg: &[u8], src: u32) -> Result;
+}
+
+/// An additional endpoint of an rpmsg device.
+///
+/// # Invariants
+///
+/// `ept` is a valid endpoint whose `priv_` field holds a pointer returned by
+/// [`ForeignOwnable::into_foreign`].
+pub struct Endpoint<T: EndpointHandler> {
+    ept: *mut bindings::rpmsg_endpoint,
+    _p: PhantomData<T>,
+}
+
+impl<T: EndpointHandler> Endpoint<T> {
+    /// Creates a new endpoint on `dev` with the local address `src`, or an address picked by
+    /// the rpmsg core if it is [`ADDR_ANY`].
+    ///
+    /// The endpoint must be dropped before the device is removed.
+    pub fn new(dev: &Device, name: &CStr, src: u32, data: T::Data) -> Result<Self> {
+        let mut chinfo = bindings::rpmsg_channel_info {
+            src,
+            dst: ADDR_ANY,
+            ..Default::default()
+        };
+        if name.len() >= chinfo.name.len() {
+            return Err(ENAMETOOLONG);
+        }
+        for (d, s) in chinfo.name.iter_mut().zip(name.as_bytes()) {
+            *d = *s as _;
+        }
+
+        let ptr = data.into_foreign();
+        // SAFETY: `dev` is valid by its type invariants, and `ptr` came from `into_foreign`.
+        let ept = unsafe {
+            bindings::rpmsg_create_ept(dev.ptr, Some(Self::rx_callback), ptr as _, chinfo)
+        };
+        if ept.is_null() {
+            // SAFETY: The endpoint was not created, so `ptr` is not used anywhere else.
+            unsafe { T::Data::from_foreign(ptr) };
+            return Err(ENOMEM);
+        }
+
+        // INVARIANT: `ept` was just created with `ptr` as its private data.
+        Ok(Self {
+            ept,
+            _p: PhantomData,
+        })
+    }
+
+    /// Returns the local address of the endpoint.
+    pub fn addr(&self) -> u32 {
+        // SAFETY: By the type invariants, `self.ept` is valid.
+        unsafe { (*self.ept).addr }
+    }
+
+    /// Sends `msg` from the endpoint to the remote address `dst`.
+    ///
+    /// Sleeps until there is room for the message, for up to 15 seconds.
+    pub fn send_to(&self, msg: &[u8], dst: u32) -> Result {
+        // SAFETY: By the type invariants, `self.ept` is valid, and `msg` is valid for
+        // `msg.len()` bytes.
+        to_result(unsafe {
+            bindings::rpmsg_sendto(self.ept, msg.as_ptr() as _, msg.len().try_into()?, dst)
+        })
+    }
+
+    unsafe extern "C" fn rx_callback(
+        _rpdev: *mut bindings::rpmsg_device,
+        msg: *mut core::ffi::c_void,
+        len: core::ffi::c_int,
+        priv_: *mut core::ffi::c_void,
+        src: u32,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: `priv_` was passed to `rpmsg_create_ept` in `new`, and is only freed once
+            // the endpoint is destroyed. `msg` is valid for `len` bytes.
+            let (data, msg) = unsafe {
+                (
+                    T::Data::borrow(priv_),
+                    core::slice::from_raw_parts(msg as *const u8, len as _),
+                )
+            };
+            T::receive(data, msg, src)?;
+            Ok(0)
+        }
+    }
+}
+
+// SAFETY: `Endpoint` only exposes methods that may be called from any thread.
+unsafe impl<T: EndpointHandler> Sync for Endpoint<T> {}
+
+// SAFETY: `Endpoint` is not restricted to a single thread, and its `T::Data` is also `Send` so it
+// may be moved to different threads.
+unsafe impl<T: EndpointHandler> Send for Endpoint<T> {}
+
+impl<T: EndpointHandler> Drop for Endpoint<T> {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, `self.ept` is valid and `priv_` came from
+        // `into_foreign`. Once the endpoint is destroyed, the callback cannot run anymore.
+        unsafe {
+            let ptr = (*self.ept).priv_;
+            bindings::rpmsg_destroy_ept(self.ept);
+            T::Data::from_foreign(ptr);
+        }
+    }
+}
+
+/// Declares a kernel module that exposes a single rpmsg driver.
+///
+/// # Examples
+///
+/// ```ignore
+/// # use kernel::{c_str, define_rpmsg_id_table, module_rpmsg_driver, rpmsg};
+/// #
+/// struct MyDriver;
+/// impl rpmsg::Driver for MyDriver {
+///     // [...]
+/// #   fn probe(_dev: &mut rpmsg::Device, _id_info: Option<&Self::IdInfo>) -> Result {
+/// #       Ok(())
+/// #   }
+/// #   define_rpmsg_id_table! {(), [
+/// #       ({ name: c_str!("rpmsg-client-sample") }, None),
+/// #   ]}
+/// }
+///
+/// module_rpmsg_driver! {
+///     type: MyDriver,
+///     name: "module_name",
+///     author: "Author name",
+///     license: "GPL",
+/// }
+/// ```
+#[macro_export]
+macro_rules! module_rpmsg_driver {
+    ($($f:tt)*) => {
+        $crate::module_driver!(<T>, $crate::rpmsg::Adapter<T>, { $($f)* });
+    };
+}
+
+/// Defines the id table for rpmsg devices.
+#[macro_export]
+macro_rules! define_rpmsg_id_table {
+    ($data_type:ty, $($t:tt)*) => {
+        type IdInfo = $data_type;
+        $crate::define_id_table!(ID_TABLE, $crate::rpmsg::DeviceId, $data_type, $($t)*);
+    };
+}
@@ -0,0 +1,700 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Sequence files.
+//!
+//! Allows Rust code to expose a sequence of records as a file, where each record is formatted with
+//! its [`Display`] implementation. The kernel takes care of buffering and of resuming the
+//! iteration at the right place when the file is read in several chunks.
+//!
+//! C header: [`include/linux/seq_file.h`](../../../../include/linux/seq_file.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/filesystems/seq_file.html>
+
+use crate::{
+    bindings,
+    error::{code::*, from_kernel_result},
+    str::CStringBuf,
+    sync::{smutex, Arc, ArcBorrow},
+    to_result,
+    types::{ForeignOwnable, GfpFlags},
+    Result, ThisModule,
+};
+use alloc::boxed::Box;
+use core::{
+    fmt::{self, Display, Write},
+    marker::PhantomData,
+    ptr,
+    time::Duration,
+};
+
+#[cfg(CONFIG_DEBUG_FS)]
+use crate::{
+    debugfs::{self, DebugFsDirectory, DebugFsFile},
+    str::CStr,
+    Mode,
+};
+
+/// Corresponds to the kernel's `struct seq_operations`.
+///
+/// Every time the file is read, [`SeqOperations::start`] is called to get a fresh iterator, which
+/// is advanced to the position the reader is at. Each item it yields is then written to the file.
+///
+/// Work that should only happen once per open file, such as taking a snapshot of a table so that
+/// all reads see consistent records, belongs in [`SeqOperations::open_state`]. The resulting
+/// [`SeqOperations::OpenState`] is kept until the file is released and passed to every call to
+/// [`SeqOperations::start`].
+///
+/// # Examples
+///
+/// Shows a table whose rows are copied once when the file is opened, and then borrowed by every
+/// read without further allocations, with a buffer large enough for all of them:
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use core::fmt;
+/// use kernel::{
+///     seq_file::SeqOperations,
+///     sync::{Arc, ArcBorrow, Mutex},
+/// };
+///
+/// #[derive(Clone)]
+/// struct Row {
+///     id: u32,
+///     value: u64,
+/// }
+///
+/// impl fmt::Display for Row {
+///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         writeln!(f, "{}: {}", self.id, self.value)
+///     }
+/// }
+///
+/// struct Table {
+///     rows: Mutex<Vec<Row>>,
+/// }
+///
+/// struct TableFile;
+///
+/// impl SeqOperations for TableFile {
+///     type OpenData = Arc<Table>;
+///     type DataWrapper = Arc<Table>;
+///     type OpenState = Vec<Row>;
+///     type IteratorWrapper<'a> = core::slice::Iter<'a, Row>;
+///     type Item<'a> = &'a Row;
+///
+///     fn open(table: &Arc<Table>) -> Result<Arc<Table>> {
+///         Ok(table.clone())
+///     }
+///
+///     fn open_state(table: ArcBorrow<'_, Table>) -> Result<Vec<Row>> {
+///         let rows = table.rows.lock();
+///         let mut copy = Vec::try_with_capacity(rows.len())?;
+///         copy.try_extend_from_slice(&rows)?;
+///         Ok(copy)
+///     }
+///
+///     fn buffer_size_hint(_table: ArcBorrow<'_, Table>, rows: &Vec<Row>) -> usize {
+///         // Rows are rarely longer than 32 bytes.
+///         rows.len() * 32
+///     }
+///
+///     fn start<'a>(
+///         _table: ArcBorrow<'a, Table>,
+///         rows: &'a mut Vec<Row>,
+///     ) -> Option<core::slice::Iter<'a, Row>> {
+///         Some(rows.iter())
+///     }
+/// }
+/// ```
+pub trait SeqOperations {
+    /// The type of the data passed to [`SeqOperations::open`] when the file is opened.
+    type OpenData: Sync = ();
+
+    /// The type of the data created when the file is opened and passed to
+    /// [`SeqOperations::start`].
+    type DataWrapper: ForeignOwnable;
+
+    /// The type of the state kept for each open file, stored in the `private` field of its
+    /// `struct seq_file`.
+    type OpenState: Default + Send = ();
+
+    /// The iterator over the records of the file.
+    ///
+    /// It may borrow from the data and the state of the file, which outlive the iteration.
+    type IteratorWrapper<'a>: Iterator<Item = Self::Item<'a>>
+    where
+        Self: 'a;
+
+    /// The type of the records of the file.
+    ///
+    /// Records may borrow from the data and the state of the file as well, so that large tables
+    /// can be shown without copying each row.
+    type Item<'a>: Display
+    where
+        Self: 'a;
+
+    /// Creates the data used by the iterators of a new open file.
+    fn open(open_data: &Self::OpenData) -> Result<Self::DataWrapper>;
+
+    /// Creates the state of a new open file.
+    ///
+    /// It is called once when the file is opened, after [`SeqOperations::open`].
+    fn open_state(
+        _data: <Self::DataWrapper as ForeignOwnable>::Borrowed<'_>,
+    ) -> Result<Self::OpenState> {
+        Ok(Self::OpenState::default())
+    }
+
+    /// Returns the size of the buffer to allocate for the contents of a new open file, or zero to
+    /// let the kernel start with a page.
+    ///
+    /// Whenever the contents of a read do not fit in the buffer, the kernel doubles its size and
+    /// generates them again from the start, so files that are known to be large, such as big
+    /// tables, can save those passes by returning an estimate of their size. It is called once
+    /// when the file is opened, after [`SeqOperations::open_state`]. The hint is ignored if the
+    /// buffer cannot be allocated.
+    fn buffer_size_hint(
+        _data: <Self::DataWrapper as ForeignOwnable>::Borrowed<'_>,
+        _state: &Self::OpenState,
+    ) -> usize {
+        0
+    }
+
+    /// Returns an iterator over the records of the file, or [`None`] if there are none.
+    ///
+    /// Calls for the same open file are serialised by the kernel, so `state` may be updated, for
+    /// example to reuse work done during a previous read. The iterator is dropped before the next
+    /// call, so it may keep borrowing from `data` and `state` until then.
+    fn start<'a>(
+        data: <Self::DataWrapper as ForeignOwnable>::Borrowed<'a>,
+        state: &'a mut Self::OpenState,
+    ) -> Option<Self::IteratorWrapper<'a>>;
+
+    /// Cleans up when the file is released.
+    ///
+    /// It is called exactly once for each successful call to [`SeqOperations::open`], with the
+    /// data it returned and the state of the file. That includes the case where opening the file
+    /// fails afterwards, for example because [`SeqOperations::open_state`] fails, in which case
+    /// `state` is the default value.
+    fn release(_data: Self::DataWrapper, _state: Self::OpenState) {}
+}
+
+/// A sequence file whose contents are being generated.
+///
+/// Output that does not fit in the buffer of the file is not an error that needs handling: the
+/// kernel notices it and generates the contents again with a larger buffer. Because of that,
+/// [`write!`] on a [`SeqFile`] does not return a result.
+///
+/// # Invariants
+///
+/// The pointer is a valid `struct seq_file` that is being shown.
+pub struct SeqFile(*mut bindings::seq_file);
+
+impl SeqFile {
+    /// Creates a new [`SeqFile`] from a raw pointer.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a valid `struct seq_file` that is being shown, and it must remain so for the
+    /// lifetime of the returned object.
+    pub(crate) unsafe fn from_ptr(ptr: *mut bindings::seq_file) -> Self {
+        // INVARIANT: The safety requirements guarantee the invariants.
+        Self(ptr)
+    }
+
+    /// Writes `data` to the file.
+    pub fn write(&mut self, data: &[u8]) {
+        // SAFETY: By the type invariants, the `seq_file` is valid. `data` is valid for
+        // `data.len()` bytes.
+        unsafe { bindings::seq_write(self.0, data.as_ptr() as _, data.len()) };
+    }
+
+    /// Writes formatted output to the file.
+    ///
+    /// This is what [`write!`] and [`writeln!`] call.
+    pub fn write_fmt(&mut self, args: fmt::Arguments<'_>) {
+        let _ = Write::write_fmt(self, args);
+    }
+}
+
+impl Write for SeqFile {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // SAFETY: By the type invariants, the `seq_file` is valid. `s` is valid for `s.len()`
+        // bytes. If the buffer overflows, the kernel retries with a larger one.
+        let ret = unsafe { bindings::seq_write(self.0, s.as_ptr() as _, s.len()) };
+        if ret != 0 {
+            return Err(fmt::Error);
+        }
+        Ok(())
+    }
+}
+
+/// The state of an open file, stored in the `private` field of its `struct seq_file`.
+///
+/// # Invariants
+///
+/// `data` holds a value returned by [`ForeignOwnable::into_foreign`].
+struct OpenFile<T: SeqOperations> {
+    data: *const core::ffi::c_void,
+    state: T::OpenState,
+}
+
+impl<T: SeqOperations> Drop for OpenFile<T> {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, `data` came from `into_foreign`, and it is not used
+        // once the open file is dropped.
+        let data = unsafe { T::DataWrapper::from_foreign(self.data) };
+        T::release(data, core::mem::take(&mut self.state));
+    }
+}
+
+/// The state of an ongoing iteration, stored in the `v` pointer of the sequence operations.
+///
+/// It borrows from the open file for `'a`, which the callbacks choose to be the duration of the
+/// iteration: the open file is only freed when the file is released, after the iteration stops.
+struct IterState<'a, T: SeqOperations + 'a> {
+    iter: T::IteratorWrapper<'a>,
+    current: T::Item<'a>,
+}
+
+/// Implements the file operations of a sequence file whose records are produced by `T`.
+pub struct SeqFileAdapter<T>(PhantomData<T>);
+
+impl<T: SeqOperations> SeqFileAdapter<T> {
+    unsafe extern "C" fn start_callback(
+        m: *mut bindings::seq_file,
+        pos: *mut bindings::loff_t,
+    ) -> *mut core::ffi::c_void {
+        // SAFETY: `private` was set in `open_callback` to an `OpenFile` that is only freed when
+        // the file is released. The kernel holds the lock of the `seq_file` until the iteration
+        // stops, so nothing else accesses it concurrently.
+        let open = unsafe { &mut *((*m).private as *mut OpenFile<T>) };
+        // SAFETY: By the type invariants, `data` came from `into_foreign`, and `from_foreign` is
+        // only called when `open` is dropped.
+        let data = unsafe { T::DataWrapper::borrow(open.data) };
+        let mut iter = match T::start(data, &mut open.state) {
+            Some(iter) => iter,
+            None => return ptr::null_mut(),
+        };
+
+        // SAFETY: The kernel passes a valid position.
+        let pos = unsafe { *pos };
+        let current = match usize::try_from(pos).ok().and_then(|pos| iter.nth(pos)) {
+            Some(item) => item,
+            None => return ptr::null_mut(),
+        };
+
+        match Box::try_new(IterState::<'_, T> { iter, current }) {
+            Ok(state) => Box::into_raw(state) as _,
+            // SAFETY: `ERR_PTR` only encodes the error number in a pointer.
+            Err(_) => unsafe { bindings::ERR_PTR(ENOMEM.to_kernel_errno() as _) },
+        }
+    }
+
+    unsafe extern "C" fn next_callback(
+        _m: *mut bindings::seq_file,
+        v: *mut core::ffi::c_void,
+        pos: *mut bindings::loff_t,
+    ) -> *mut core::ffi::c_void {
+        // SAFETY: The kernel passes a valid position.
+        unsafe { *pos += 1 };
+
+        // SAFETY: `v` was returned by `start_callback` or a previous call to `next_callback`, so
+        // it is a valid, exclusively owned iteration state.
+        let state = unsafe { &mut *(v as *mut IterState<'_, T>) };
+        match state.iter.next() {
+            Some(item) => {
+                state.current = item;
+                v
+            }
+            None => {
+                // SAFETY: `v` came from `Box::into_raw` and the kernel does not use it once this
+                // function returns null.
+                drop(unsafe { Box::from_raw(v as *mut IterState<'_, T>) });
+                ptr::null_mut()
+            }
+        }
+    }
+
+    unsafe extern "C" fn stop_callback(_m: *mut bindings::seq_file, v: *mut core::ffi::c_void) {
+        // SAFETY: `IS_ERR` only inspects the value of the pointer.
+        if !v.is_null() && !unsafe { bindings::IS_ERR(v) } {
+            // SAFETY: `v` is a valid iteration state returned by `start_callback` or
+            // `next_callback`, and it is not used after the iteration stops.
+            drop(unsafe { Box::from_raw(v as *mut IterState<'_, T>) });
+        }
+    }
+
+    unsafe extern "C" fn show_callback(
+        m: *mut bindings::seq_file,
+        v: *mut core::ffi::c_void,
+    ) -> core::ffi::c_int {
+        // SAFETY: `v` is a valid iteration state returned by `start_callback` or `next_callback`.
+        let state = unsafe { &*(v as *const IterState<'_, T>) };
+        // SAFETY: The kernel passes the `seq_file` being shown.
+        let mut m = unsafe { SeqFile::from_ptr(m) };
+        write!(m, "{}", state.current);
+        0
+    }
+
+    const SEQ_OPS: bindings::seq_operations = bindings::seq_operations {
+        start: Some(Self::start_callback),
+        stop: Some(Self::stop_callback),
+        next: Some(Self::next_callback),
+        show: Some(Self::show_callback),
+    };
+
+    unsafe extern "C" fn open_callback(
+        inode: *mut bindings::inode,
+        file: *mut bindings::file,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The kernel passes a valid inode.
+            let open_data = unsafe { (*inode).i_private as *const T::OpenData };
+            if open_data.is_null() {
+                debug_assert!(false, "sequence file opened without open data");
+                return Err(EINVAL);
+            }
+            // SAFETY: By the safety requirements of `debugfs::FileVtable`, and by the invariants of
+            // `ProcDirEntry` for `/proc` entries, `i_private` points to the open data, which is
+            // valid while the file exists.
+            let open_data = unsafe { &*open_data };
+            let data = T::open(open_data)?.into_foreign();
+
+            // INVARIANT: `data` was just returned by `into_foreign`.
+            let mut open = OpenFile::<T> {
+                data,
+                state: T::OpenState::default(),
+            };
+            // SAFETY: `data` remains valid until `open` is dropped.
+            open.state = T::open_state(unsafe { T::DataWrapper::borrow(data) })?;
+            // SAFETY: `data` remains valid until `open` is dropped.
+            let size = T::buffer_size_hint(unsafe { T::DataWrapper::borrow(data) }, &open.state);
+            let open = Box::try_new(open)?;
+
+            // SAFETY: `file` is valid, and `SEQ_OPS` is a static that lives forever.
+            to_result(unsafe { bindings::seq_open(file, &Self::SEQ_OPS) })?;
+
+            // SAFETY: `seq_open` succeeded, so `private_data` points to a new `seq_file`.
+            let m = unsafe { &mut *((*file).private_data as *mut bindings::seq_file) };
+            m.private = Box::into_raw(open) as _;
+            if size > 0 {
+                // Allocated like `single_open_size` does. The buffer is freed with `kvfree` by
+                // `seq_release`, and the kernel allocates one itself on the first read if this
+                // fails.
+                // SAFETY: FFI call with no safety requirements.
+                let buf = unsafe {
+                    bindings::kvmalloc_node(size, GfpFlags::KERNEL.as_raw(), bindings::NUMA_NO_NODE)
+                };
+                if !buf.is_null() {
+                    m.buf = buf as _;
+                    m.size = size;
+                }
+            }
+            Ok(0)
+        }
+    }
+
+    unsafe extern "C" fn release_callback(
+        inode: *mut bindings::inode,
+        file: *mut bindings::file,
+    ) -> core::ffi::c_int {
+        // SAFETY: The file was opened by `open_callback`, so `private_data` points to a
+        // `seq_file` whose `private` field was set to a boxed `OpenFile`. No other callbacks can
+        // run anymore.
+        unsafe {
+            let m = (*file).private_data as *mut bindings::seq_file;
+            drop(Box::from_raw((*m).private as *mut OpenFile<T>));
+            bindings::seq_release(inode, file)
+        }
+    }
+
+    /// The operations of a `/proc` entry whose records are produced by `T`.
+    ///
+    /// Like debugfs, `/proc` stores the data of an entry in `i_private` of its inode, so the same
+    /// callbacks work for both.
+    ///
+    /// Unlike [`Vtable`], they need no module owner: `proc_ops` has none, because removing the
+    /// entry waits for the callbacks that are running and releases the files that are still open.
+    #[cfg(CONFIG_PROC_FS)]
+    pub(crate) const PROC_OPS: bindings::proc_ops = bindings::proc_ops {
+        proc_flags: 0,
+        proc_open: Some(Self::open_callback),
+        proc_read: None,
+        proc_read_iter: Some(bindings::seq_read_iter),
+        proc_write: None,
+        proc_lseek: Some(bindings::seq_lseek),
+        proc_release: Some(Self::release_callback),
+        proc_poll: None,
+        proc_ioctl: None,
+        #[cfg(CONFIG_COMPAT)]
+        proc_compat_ioctl: None,
+        proc_mmap: None,
+        proc_get_unmapped_area: None,
+    };
+
+    const VTABLE: bindings::file_operations = bindings::file_operations {
+        open: Some(Self::open_callback),
+        release: Some(Self::release_callback),
+        read: Some(bindings::seq_read),
+        write: None,
+        llseek: Some(bindings::seq_lseek),
+        check_flags: None,
+        compat_ioctl: None,
+        copy_file_range: None,
+        fallocate: None,
+        fadvise: None,
+        fasync: None,
+        flock: None,
+        flush: None,
+        fsync: None,
+        get_unmapped_area: None,
+        iterate: None,
+        iterate_shared: None,
+        iopoll: None,
+        lock: None,
+        mmap: None,
+        mmap_supported_flags: 0,
+        owner: ptr::null_mut(),
+        poll: None,
+        read_iter: Some(bindings::seq_read_iter),
+        remap_file_range: None,
+        sendpage: None,
+        setlease: None,
+        show_fdinfo: None,
+        splice_read: None,
+        splice_write: None,
+        unlocked_ioctl: None,
+        uring_cmd: None,
+        uring_cmd_iopoll: None,
+        write_iter: None,
+    };
+}
+
+/// The file operations of a sequence file whose records are produced by `T`, with the module that
+/// owns them.
+///
+/// They are defined in the module that implements `T` with [`crate::file_vtable!`], so that open
+/// files keep it loaded. `/proc` entries do not need them: `/proc` has no module owner, and waits
+/// for the files that are open to be released when the entry is removed.
+pub struct Vtable<T> {
+    fops: bindings::file_operations,
+    _p: PhantomData<T>,
+}
+
+// SAFETY: The file operations are never modified after they are created.
+unsafe impl<T> Sync for Vtable<T> {}
+
+impl<T: SeqOperations> Vtable<T> {
+    /// Creates the file operations of the sequence files whose records are produced by `T`, which
+    /// belong to `module`.
+    ///
+    /// `module` must be the module that implements `T`, so that open files keep it loaded.
+    pub const fn new(module: &'static ThisModule) -> Self {
+        let mut fops = SeqFileAdapter::<T>::VTABLE;
+        fops.owner = module.0;
+        Self {
+            fops,
+            _p: PhantomData,
+        }
+    }
+}
+
+/// The source of the contents of a [`CachedContent`].
+pub trait ContentSource: Send + Sync {
+    /// Writes the contents to `out`, which is empty.
+    fn generate(&self, out: &mut CStringBuf) -> Result;
+}
+
+/// The contents generated by a [`CachedContent`], and when.
+struct Cached {
+    generated_at: u64,
+    text: Arc<CStringBuf>,
+}
+
+/// Contents that are expensive to generate, kept for a while so that files that are read often
+/// do not generate them again for every read.
+///
+/// The contents are generated by `T` the first time they are needed, and again when they are
+/// needed after `ttl` has passed or after [`CachedContent::invalidate`] is called. They are
+/// shown in a sequence file by [`CachedContentFile`]. Each open file keeps the contents it got
+/// when it was opened, so reads of a file in several chunks see consistent contents.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use core::time::Duration;
+/// use kernel::{
+///     seq_file::{CachedContent, ContentSource},
+///     str::CStringBuf,
+///     sync::Arc,
+/// };
+///
+/// struct Device {
+///     channels: u32,
+/// }
+///
+/// impl ContentSource for Device {
+///     fn generate(&self, out: &mut CStringBuf) -> Result {
+///         for channel in 0..self.channels {
+///             // Querying the state of a channel is slow.
+///             out.try_write_fmt(fmt!("channel {}: idle\n", channel))?;
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// fn create_report(device: Device) -> Result<Arc<CachedContent<Device>>> {
+///     Ok(Arc::try_new(CachedContent::new(device, Duration::from_secs(1)))?)
+/// }
+///
+/// fn on_reset(report: &CachedContent<Device>) {
+///     // The state of the channels changed, so the next read shows it right away.
+///     report.invalidate();
+/// }
+/// ```
+pub struct CachedContent<T: ContentSource> {
+    source: T,
+    ttl_ns: u64,
+    cache: smutex::Mutex<Option<Cached>>,
+}
+
+impl<T: ContentSource> CachedContent<T> {
+    /// Creates new contents generated by `source`, and kept for `ttl` after they are generated.
+    ///
+    /// A `ttl` of [`Duration::MAX`] keeps them until they are invalidated.
+    pub fn new(source: T, ttl: Duration) -> Self {
+        Self {
+            source,
+            ttl_ns: ttl.as_nanos().try_into().unwrap_or(u64::MAX),
+            cache: smutex::Mutex::new(None),
+        }
+    }
+
+    /// Returns the source of the contents.
+    pub fn source(&self) -> &T {
+        &self.source
+    }
+
+    /// Returns the contents, generating them if they were never generated, have expired or were
+    /// invalidated.
+    ///
+    /// Callers that find the contents expired at the same time wait for one of them to generate
+    /// them. Errors are returned to the caller that generated them, and nothing is kept.
+    pub fn get(&self) -> Result<Arc<CStringBuf>> {
+        let mut cache = self.cache.lock();
+        // SAFETY: This function has no safety requirements.
+        let now = unsafe { bindings::ktime_get_ns() };
+        if let Some(cached) = &*cache {
+            if now.wrapping_sub(cached.generated_at) < self.ttl_ns {
+                return Ok(cached.text.clone());
+            }
+        }
+
+        let mut text = CStringBuf::new();
+        self.source.generate(&mut text)?;
+        let text = Arc::try_new(text)?;
+        *cache = Some(Cached {
+            generated_at: now,
+            text: text.clone(),
+        });
+        Ok(text)
+    }
+
+    /// Discards the contents, so that they are generated again the next time they are needed.
+    ///
+    /// Files that are already open keep showing the contents they got.
+    pub fn invalidate(&self) {
+        // The contents are dropped after the lock is released.
+        let _old = self.cache.lock().take();
+    }
+}
+
+/// The record of a [`CachedContentFile`], which holds all the contents.
+pub struct CachedText<'a>(&'a CStringBuf);
+
+impl Display for CachedText<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The contents were written with `fmt`, so they are valid UTF-8.
+        f.write_str(core::str::from_utf8(self.0.as_bytes()).map_err(|_| fmt::Error)?)
+    }
+}
+
+/// The sequence operations of a file that shows a [`CachedContent`].
+pub struct CachedContentFile<T>(PhantomData<T>);
+
+impl<T: ContentSource + 'static> SeqOperations for CachedContentFile<T> {
+    type OpenData = Arc<CachedContent<T>>;
+    type DataWrapper = Arc<CachedContent<T>>;
+    type OpenState = Option<Arc<CStringBuf>>;
+    type IteratorWrapper<'a> = core::option::IntoIter<CachedText<'a>>;
+    type Item<'a> = CachedText<'a>;
+
+    fn open(content: &Arc<CachedContent<T>>) -> Result<Arc<CachedContent<T>>> {
+        Ok(content.clone())
+    }
+
+    fn open_state(content: ArcBorrow<'_, CachedContent<T>>) -> Result<Option<Arc<CStringBuf>>> {
+        Ok(Some(content.get()?))
+    }
+
+    fn buffer_size_hint(
+        _content: ArcBorrow<'_, CachedContent<T>>,
+        text: &Option<Arc<CStringBuf>>,
+    ) -> usize {
+        text.as_ref().map_or(0, |text| text.len() + 1)
+    }
+
+    fn start<'a>(
+        _content: ArcBorrow<'a, CachedContent<T>>,
+        text: &'a mut Option<Arc<CStringBuf>>,
+    ) -> Option<core::option::IntoIter<CachedText<'a>>> {
+        let text = text.as_ref()?;
+        Some(Some(CachedText(text)).into_iter())
+    }
+}
+
+// SAFETY: `open_callback` only interprets `i_private` as a pointer to `T::OpenData`.
+#[cfg(CONFIG_DEBUG_FS)]
+unsafe impl<T: SeqOperations> debugfs::FileVtable for SeqFileAdapter<T> {
+    type OpenData = T::OpenData;
+    type Vtable = Vtable<T>;
+
+    fn fops(vtable: &'static Vtable<T>) -> &'static bindings::file_operations {
+        &vtable.fops
+    }
+}
+
+/// Creates a new sequence file called `name` with permissions `mode`, in `parent` or at the root
+/// of debugfs.
+///
+/// `vtable` holds the file operations, defined by the module that implements `T`. `data` is
+/// passed to [`SeqOperations::open`] every time the file is opened. The file is removed when the
+/// returned handle is dropped.
+#[cfg(CONFIG_DEBUG_FS)]
+pub fn debugfs_create_file<T: SeqOperations>(
+    name: &CStr,
+    parent: Option<Arc<DebugFsDirectory>>,
+    mode: Mode,
+    vtable: &'static Vtable<T>,
+    data: T::OpenData,
+) -> Result<DebugFsFile<SeqFileAdapter<T>>> {
+    debugfs::debugfs_create(name, parent, mode, vtable, data)
+}
+
+/// Creates a new file called `name` with permissions `mode`, in `parent` or at the root of
+/// debugfs, that shows `content`.
+///
+/// `vtable` holds the file operations, defined by the module that implements `T`. The file is
+/// removed when the returned handle is dropped.
+#[cfg(CONFIG_DEBUG_FS)]
+pub fn debugfs_create_cached_file<T: ContentSource + 'static>(
+    name: &CStr,
+    parent: Option<Arc<DebugFsDirectory>>,
+    mode: Mode,
+    vtable: &'static Vtable<CachedContentFile<T>>,
+    content: Arc<CachedContent<T>>,
+) -> Result<DebugFsFile<SeqFileAdapter<CachedContentFile<T>>>> {
+    debugfs_create_file(name, parent, mode, vtable, content)
+}
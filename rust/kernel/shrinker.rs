@@ -0,0 +1,293 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Memory shrinkers.
+//!
+//! Caches register a shrinker so that the memory management subsystem can ask them to release
+//! memory when it runs low, instead of reclaiming it only from the page cache and other users.
+//! The shrinker reports how many objects could be freed, and frees some of them on request.
+//!
+//! C header: [`include/linux/shrinker.h`](../../../../include/linux/shrinker.h)
+
+use alloc::boxed::Box;
+
+use crate::{
+    bindings, c_str,
+    error::code::*,
+    str::CString,
+    to_result,
+    types::{ForeignOwnable, GfpFlags},
+    Result, ScopeGuard,
+};
+
+use core::{
+    cell::UnsafeCell,
+    ffi::{c_ulong, c_void},
+    fmt,
+    marker::PhantomData,
+    pin::Pin,
+};
+
+/// Returned by `scan_objects` when the shrinker cannot free anything right now.
+const SHRINK_STOP: c_ulong = !0;
+
+/// Returned by `count_objects` when there is nothing to free.
+const SHRINK_EMPTY: c_ulong = !0 - 1;
+
+/// The default cost of recreating an object, see [`Shrinker::SEEKS`].
+pub const DEFAULT_SEEKS: u32 = 2;
+
+/// The parameters of a call to a shrinker.
+#[repr(transparent)]
+pub struct ShrinkControl(bindings::shrink_control);
+
+impl ShrinkControl {
+    /// Creates a reference to a [`ShrinkControl`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `ptr` is valid and remains valid for the lifetime of the
+    /// returned [`ShrinkControl`] instance, and that it is not accessed by anyone else meanwhile.
+    unsafe fn from_raw<'a>(ptr: *mut bindings::shrink_control) -> &'a mut Self {
+        // SAFETY: The safety requirements guarantee the validity of the dereference, while the
+        // `ShrinkControl` type being transparent makes the cast ok.
+        unsafe { &mut *ptr.cast() }
+    }
+
+    /// Returns the number of objects that the shrinker should try to free.
+    pub fn nr_to_scan(&self) -> usize {
+        self.0.nr_to_scan as _
+    }
+
+    /// Records the number of objects that the shrinker looked at, when it differs from
+    /// [`ShrinkControl::nr_to_scan`].
+    pub fn set_nr_scanned(&mut self, count: usize) {
+        self.0.nr_scanned = count as _;
+    }
+
+    /// Returns the NUMA node that memory is reclaimed for.
+    ///
+    /// It is only relevant to shrinkers whose objects belong to a node.
+    pub fn nid(&self) -> i32 {
+        self.0.nid
+    }
+
+    /// Returns the flags of the allocation that triggered the reclaim.
+    ///
+    /// Shrinkers that must sleep or enter filesystems to free their objects check
+    /// [`GfpFlags::DIRECT_RECLAIM`] or [`GfpFlags::FS`], and return [`ScanResult::Stop`] if the
+    /// allocation does not allow it.
+    pub fn gfp_flags(&self) -> GfpFlags {
+        GfpFlags::from_raw(self.0.gfp_mask)
+    }
+}
+
+/// The result of [`Shrinker::scan`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScanResult {
+    /// The given number of objects were freed.
+    Freed(usize),
+
+    /// Nothing can be freed in this context, for example because it would deadlock, and the
+    /// shrinker should not be called again during this reclaim.
+    Stop,
+}
+
+/// Callbacks of a shrinker, called when memory is reclaimed.
+///
+/// The callbacks may run in the context of any task that allocates memory, including one that
+/// is in the middle of using the cache. They must therefore not take locks that are held while
+/// allocating memory, nor allocate memory themselves.
+pub trait Shrinker {
+    /// The data of the shrinker, usually a reference to the cache.
+    type Data: ForeignOwnable + Send + Sync;
+
+    /// The cost of recreating an object, relative to reading a page from disk.
+    ///
+    /// Shrinkers with higher costs are asked to free fewer objects.
+    const SEEKS: u32 = DEFAULT_SEEKS;
+
+    /// The number of objects [`Shrinker::scan`] is asked to free at once, or zero for the default.
+    const BATCH: usize = 0;
+
+    /// Returns the number of objects that could be freed, which may be an estimate.
+    fn count(data: <Self::Data as ForeignOwnable>::Borrowed<'_>, sc: &ShrinkControl) -> usize;
+
+    /// Frees up to [`ShrinkControl::nr_to_scan`] objects.
+    fn scan(
+        data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        sc: &mut ShrinkControl,
+    ) -> ScanResult;
+}
+
+/// A registration of a shrinker.
+///
+/// # Examples
+///
+/// A pool of spare buffers that is emptied under memory pressure:
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::shrinker::{self, ScanResult, ShrinkControl, Shrinker};
+/// use kernel::sync::{smutex::Mutex, Arc, ArcBorrow};
+///
+/// struct Pool {
+///     spare: Mutex<Vec<Box<[u8; 4096]>>>,
+/// }
+///
+/// impl Shrinker for Pool {
+///     type Data = Arc<Pool>;
+///
+///     fn count(pool: ArcBorrow<'_, Pool>, _sc: &ShrinkControl) -> usize {
+///         pool.spare.lock().len()
+///     }
+///
+///     fn scan(pool: ArcBorrow<'_, Pool>, sc: &mut ShrinkControl) -> ScanResult {
+///         // The lock is never held while memory is allocated.
+///         let mut spare = pool.spare.lock();
+///         let count = sc.nr_to_scan().min(spare.len());
+///         let len = spare.len() - count;
+///         spare.truncate(len);
+///         ScanResult::Freed(count)
+///     }
+/// }
+///
+/// fn create() -> Result<(Arc<Pool>, Pin<Box<shrinker::Registration<Pool>>>)> {
+///     let pool = Arc::try_new(Pool {
+///         spare: Mutex::new(Vec::new()),
+///     })?;
+///     let reg = shrinker::Registration::new_pinned(fmt!("example-pool"), pool.clone())?;
+///     Ok((pool, reg))
+/// }
+/// ```
+pub struct Registration<T: Shrinker> {
+    shrinker: UnsafeCell<bindings::shrinker>,
+    data: *const c_void,
+    registered: bool,
+    _p: PhantomData<T>,
+}
+
+impl<T: Shrinker> Registration<T> {
+    /// Creates new instance of registration.
+    ///
+    /// The shrinker must be registered.
+    pub fn new() -> Self {
+        Self {
+            shrinker: UnsafeCell::new(bindings::shrinker::default()),
+            data: core::ptr::null(),
+            registered: false,
+            _p: PhantomData,
+        }
+    }
+
+    /// Returns a registered and pinned, heap-allocated representation of the registration.
+    pub fn new_pinned(name: fmt::Arguments<'_>, data: T::Data) -> Result<Pin<Box<Self>>> {
+        let mut reg = Pin::from(Box::try_new(Self::new())?);
+        reg.as_mut().register(name, data)?;
+        Ok(reg)
+    }
+
+    /// Registers the shrinker with the memory management subsystem.
+    ///
+    /// The name shows up in debugfs when `CONFIG_SHRINKER_DEBUG` is enabled. The callbacks may be
+    /// called as soon as this returns, until the registration is dropped.
+    pub fn register(self: Pin<&mut Self>, name: fmt::Arguments<'_>, data: T::Data) -> Result {
+        // SAFETY: We never move out of `this`.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        if this.registered {
+            return Err(EINVAL);
+        }
+
+        let name = CString::try_from_fmt(name)?;
+        let data_pointer = data.into_foreign();
+
+        // SAFETY: `data_pointer` comes from the call to `data.into_foreign()` above.
+        let guard = ScopeGuard::new(|| unsafe {
+            T::Data::from_foreign(data_pointer);
+        });
+
+        this.data = data_pointer;
+        let shrinker = this.shrinker.get_mut();
+        shrinker.count_objects = Some(Self::count_callback);
+        shrinker.scan_objects = Some(Self::scan_callback);
+        shrinker.seeks = T::SEEKS as _;
+        shrinker.batch = T::BATCH as _;
+
+        // SAFETY: The shrinker is initialised above, and is pinned, so it remains valid until it
+        // is unregistered when the registration is dropped. The name is copied.
+        to_result(unsafe {
+            bindings::register_shrinker(
+                this.shrinker.get(),
+                c_str!("%s").as_char_ptr(),
+                name.as_char_ptr(),
+            )
+        })?;
+
+        this.registered = true;
+        guard.dismiss();
+        Ok(())
+    }
+
+    unsafe extern "C" fn count_callback(
+        shrinker: *mut bindings::shrinker,
+        sc: *mut bindings::shrink_control,
+    ) -> c_ulong {
+        // SAFETY: The shrinker is embedded in a registration, which is pinned and outlives the
+        // callbacks.
+        let reg = unsafe { &*crate::container_of!(shrinker, Self, shrinker) };
+        // SAFETY: `data` was set to the result of `into_foreign` before the shrinker was
+        // registered, and is only dropped after it is unregistered.
+        let data = unsafe { T::Data::borrow(reg.data) };
+        // SAFETY: The shrink control is valid for the duration of the callback.
+        let sc = unsafe { ShrinkControl::from_raw(sc) };
+        match T::count(data, sc) {
+            0 => SHRINK_EMPTY,
+            count => count as _,
+        }
+    }
+
+    unsafe extern "C" fn scan_callback(
+        shrinker: *mut bindings::shrinker,
+        sc: *mut bindings::shrink_control,
+    ) -> c_ulong {
+        // SAFETY: The shrinker is embedded in a registration, which is pinned and outlives the
+        // callbacks.
+        let reg = unsafe { &*crate::container_of!(shrinker, Self, shrinker) };
+        // SAFETY: `data` was set to the result of `into_foreign` before the shrinker was
+        // registered, and is only dropped after it is unregistered.
+        let data = unsafe { T::Data::borrow(reg.data) };
+        // SAFETY: The shrink control is valid for the duration of the callback.
+        let sc = unsafe { ShrinkControl::from_raw(sc) };
+        match T::scan(data, sc) {
+            ScanResult::Freed(count) => count as _,
+            ScanResult::Stop => SHRINK_STOP,
+        }
+    }
+}
+
+impl<T: Shrinker> Default for Registration<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Shrinker> Drop for Registration<T> {
+    fn drop(&mut self) {
+        if self.registered {
+            // SAFETY: The shrinker was registered. Once this returns, no callback is running, and
+            // none will start.
+            unsafe { bindings::unregister_shrinker(self.shrinker.get()) };
+            // SAFETY: `data` came from `into_foreign` when the shrinker was registered, and the
+            // callbacks that borrowed it are done.
+            unsafe { T::Data::from_foreign(self.data) };
+        }
+    }
+}
+
+// SAFETY: `Registration` does not expose any of its state across threads.
+unsafe impl<T: Shrinker> Sync for Registration<T> {}
+
+// SAFETY: `Registration` is not restricted to a single thread,
+// its `T::Data` is also `Send` so it may be moved to different threads.
+#[allow(clippy::non_send_fields_in_send_ty)]
+unsafe impl<T: Shrinker> Send for Registration<T> {}
@@ -0,0 +1,91 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Signals interrupting blocking operations.
+//!
+//! A task that sleeps on behalf of a system call should wake up when a signal is sent to it, or at
+//! least when it is being killed, so that the signal is handled without waiting for the event.
+//! Interruptible and killable waits, such as [`Completion::wait_interruptible`], report that they
+//! were interrupted with [`SignalPending`], and so does [`SignalPending::check`] for code that
+//! checks for signals between steps of a long operation.
+//!
+//! [`SignalPending`] converts to `ERESTARTSYS`, so the system call is restarted transparently
+//! after the signal is handled when its handler allows it, and fails with `EINTR` otherwise.
+//! Operations that cannot be restarted, for example because they already had side effects, or that
+//! do not run on behalf of a system call, use [`SignalPending::no_restart`] instead.
+//!
+//! [`Completion::wait_interruptible`]: crate::sync::Completion::wait_interruptible
+
+use crate::{error::code::*, task::Task, Error};
+
+/// A blocking operation was interrupted because a signal is pending for the current task.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::signal::SignalPending;
+///
+/// fn fill(buf: &mut [u8], chunk: usize) -> Result<usize> {
+///     let mut filled = 0;
+///     for part in buf.chunks_mut(chunk) {
+///         if let Err(e) = SignalPending::check() {
+///             // Report the partial fill rather than restarting from scratch.
+///             return if filled > 0 { Ok(filled) } else { Err(e.into()) };
+///         }
+///         part.fill(0xff);
+///         filled += part.len();
+///     }
+///     Ok(filled)
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SignalPending {
+    fatal: bool,
+}
+
+impl SignalPending {
+    /// Returns an error if a signal is pending for the current task.
+    pub fn check() -> Result<(), Self> {
+        if Task::current().signal_pending() {
+            Err(Self::current())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns an error if a fatal signal is pending for the current task.
+    ///
+    /// This is the check for operations that are killable but not interruptible.
+    pub fn check_fatal() -> Result<(), Self> {
+        if Task::current().fatal_signal_pending() {
+            Err(Self { fatal: true })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the interruption of a wait of the current task, which is known to have a pending
+    /// signal.
+    pub(crate) fn current() -> Self {
+        Self {
+            fatal: Task::current().fatal_signal_pending(),
+        }
+    }
+
+    /// Returns `true` if the pending signal is fatal, that is, the task is being killed and will
+    /// not return to user space.
+    pub fn is_fatal(self) -> bool {
+        self.fatal
+    }
+
+    /// Returns the error for an operation that must not be restarted, `EINTR`.
+    pub fn no_restart(self) -> Error {
+        EINTR
+    }
+}
+
+impl From<SignalPending> for Error {
+    fn from(_: SignalPending) -> Error {
+        ERESTARTSYS
+    }
+}
@@ -0,0 +1,649 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! ALSA sound cards and PCM devices.
+//!
+//! Allows drivers to create a sound card with a single PCM device whose sample data is moved by
+//! Rust callbacks rather than through a memory-mapped DMA buffer.
+//!
+//! C headers: [`include/sound/core.h`](../../../../include/sound/core.h) and
+//! [`include/sound/pcm.h`](../../../../include/sound/pcm.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/sound/kernel-api/writing-an-alsa-driver.html>
+
+use crate::{
+    bindings, device,
+    error::{code::*, from_kernel_result},
+    io_buffer::{IoBufferReader, IoBufferWriter},
+    str::CStr,
+    to_result,
+    types::ForeignOwnable,
+    user_ptr::UserSlicePtr,
+    Result, ThisModule,
+};
+use alloc::boxed::Box;
+use core::marker::PhantomData;
+use macros::vtable;
+
+/// Sample formats, to be combined in [`Hardware::formats`].
+pub mod formats {
+    /// Unsigned 8-bit samples.
+    pub const U8: u64 = 1 << 1;
+
+    /// Signed 16-bit little-endian samples.
+    pub const S16_LE: u64 = 1 << 2;
+
+    /// Signed 24-bit little-endian samples in 32-bit words.
+    pub const S24_LE: u64 = 1 << 6;
+
+    /// Signed 32-bit little-endian samples.
+    pub const S32_LE: u64 = 1 << 10;
+}
+
+/// Sample rates, to be combined in [`Hardware::rates`].
+pub mod rates {
+    use crate::bindings;
+
+    /// 8000 Hz.
+    pub const RATE_8000: u32 = bindings::SNDRV_PCM_RATE_8000;
+
+    /// 16000 Hz.
+    pub const RATE_16000: u32 = bindings::SNDRV_PCM_RATE_16000;
+
+    /// 44100 Hz.
+    pub const RATE_44100: u32 = bindings::SNDRV_PCM_RATE_44100;
+
+    /// 48000 Hz.
+    pub const RATE_48000: u32 = bindings::SNDRV_PCM_RATE_48000;
+
+    /// Any rate between the minimum and the maximum.
+    pub const CONTINUOUS: u32 = bindings::SNDRV_PCM_RATE_CONTINUOUS;
+}
+
+/// The capabilities of a PCM device, reported to userspace when a substream is opened.
+#[derive(Clone, Copy)]
+pub struct Hardware {
+    /// A combination of the [`formats`] constants.
+    pub formats: u64,
+
+    /// A combination of the [`rates`] constants.
+    pub rates: u32,
+
+    /// The minimum sample rate in Hz.
+    pub rate_min: u32,
+
+    /// The maximum sample rate in Hz.
+    pub rate_max: u32,
+
+    /// The minimum number of channels.
+    pub channels_min: u32,
+
+    /// The maximum number of channels.
+    pub channels_max: u32,
+
+    /// The maximum size of the buffer in bytes.
+    pub buffer_bytes_max: usize,
+
+    /// The minimum size of a period in bytes.
+    pub period_bytes_min: usize,
+
+    /// The maximum size of a period in bytes.
+    pub period_bytes_max: usize,
+
+    /// The minimum number of periods in the buffer.
+    pub periods_min: u32,
+
+    /// The maximum number of periods in the buffer.
+    pub periods_max: u32,
+}
+
+impl Hardware {
+    fn to_raw(self) -> bindings::snd_pcm_hardware {
+        bindings::snd_pcm_hardware {
+            info: bindings::SNDRV_PCM_INFO_INTERLEAVED | bindings::SNDRV_PCM_INFO_BLOCK_TRANSFER,
+            formats: self.formats,
+            rates: self.rates,
+            rate_min: self.rate_min,
+            rate_max: self.rate_max,
+            channels_min: self.channels_min,
+            channels_max: self.channels_max,
+            buffer_bytes_max: self.buffer_bytes_max,
+            period_bytes_min: self.period_bytes_min,
+            period_bytes_max: self.period_bytes_max,
+            periods_min: self.periods_min,
+            periods_max: self.periods_max,
+            ..Default::default()
+        }
+    }
+}
+
+/// The direction of a substream.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Samples flow from userspace to the device.
+    Playback,
+
+    /// Samples flow from the device to userspace.
+    Capture,
+}
+
+/// A command passed to [`PcmOperations::trigger`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TriggerCommand {
+    /// Start the transfer.
+    Start,
+
+    /// Stop the transfer.
+    Stop,
+
+    /// Pause the transfer.
+    PausePush,
+
+    /// Resume a paused transfer.
+    PauseRelease,
+
+    /// Stop the transfer because the system is suspending.
+    Suspend,
+
+    /// Restart the transfer after the system resumed.
+    Resume,
+}
+
+impl TriggerCommand {
+    fn from_raw(cmd: core::ffi::c_int) -> Result<Self> {
+        Ok(match cmd as u32 {
+            bindings::SNDRV_PCM_TRIGGER_START => Self::Start,
+            bindings::SNDRV_PCM_TRIGGER_STOP => Self::Stop,
+            bindings::SNDRV_PCM_TRIGGER_PAUSE_PUSH => Self::PausePush,
+            bindings::SNDRV_PCM_TRIGGER_PAUSE_RELEASE => Self::PauseRelease,
+            bindings::SNDRV_PCM_TRIGGER_SUSPEND => Self::Suspend,
+            bindings::SNDRV_PCM_TRIGGER_RESUME => Self::Resume,
+            _ => return Err(EINVAL),
+        })
+    }
+}
+
+/// Wraps the kernel's `struct snd_pcm_substream`.
+///
+/// # Invariants
+///
+/// The pointer `Substream::ptr` is non-null and valid, and so is its runtime.
+pub struct Substream {
+    ptr: *mut bindings::snd_pcm_substream,
+}
+
+impl Substream {
+    /// Creates a new substream wrapper.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `ptr` is non-null and valid for the lifetime of the object,
+    /// and that the substream is open.
+    unsafe fn from_ptr(ptr: *mut bindings::snd_pcm_substream) -> Self {
+        // INVARIANT: The safety requirements guarantee the invariants.
+        Self { ptr }
+    }
+
+    fn runtime(&self) -> &bindings::snd_pcm_runtime {
+        // SAFETY: `self.ptr` and its runtime are valid by the type invariants.
+        unsafe { &*(*self.ptr).runtime }
+    }
+
+    /// Returns the direction of the substream.
+    pub fn direction(&self) -> Direction {
+        // SAFETY: `self.ptr` is valid by the type invariants.
+        if unsafe { (*self.ptr).stream } as u32 == bindings::SNDRV_PCM_STREAM_PLAYBACK {
+            Direction::Playback
+        } else {
+            Direction::Capture
+        }
+    }
+
+    /// Returns the sample rate in Hz.
+    pub fn rate(&self) -> u32 {
+        self.runtime().rate
+    }
+
+    /// Returns the number of channels.
+    pub fn channels(&self) -> u32 {
+        self.runtime().channels
+    }
+
+    /// Returns the size of the buffer in frames.
+    pub fn buffer_size(&self) -> usize {
+        self.runtime().buffer_size as _
+    }
+
+    /// Returns the size of a period in frames.
+    pub fn period_size(&self) -> usize {
+        self.runtime().period_size as _
+    }
+
+    /// Converts a number of frames to a number of bytes.
+    pub fn frames_to_bytes(&self, frames: usize) -> usize {
+        frames * self.runtime().frame_bits as usize / 8
+    }
+
+    /// Notifies the PCM core that a period has been transferred.
+    ///
+    /// This may be called from any context, including interrupt handlers and timers.
+    pub fn period_elapsed(&self) {
+        // SAFETY: `self.ptr` is valid by the type invariants.
+        unsafe { bindings::snd_pcm_period_elapsed(self.ptr) };
+    }
+}
+
+/// Corresponds to the kernel's `struct snd_pcm_ops`.
+///
+/// Positions and lengths passed to the copy callbacks are in bytes, and `channel` is `-1` since
+/// all substreams are interleaved.
+#[vtable]
+pub trait PcmOperations {
+    /// The type of the context data stored in the card and made available to callbacks.
+    type Data: ForeignOwnable + Send + Sync = ();
+
+    /// The capabilities of the device.
+    const HARDWARE: Hardware;
+
+    /// Called when a substream is opened.
+    fn open(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>, _sub: &Substream) -> Result {
+        Ok(())
+    }
+
+    /// Called when a substream is closed.
+    fn close(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>, _sub: &Substream) {}
+
+    /// Called before a transfer starts, once the parameters are set.
+    fn prepare(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>, _sub: &Substream) -> Result {
+        Ok(())
+    }
+
+    /// Starts, stops, pauses or resumes a transfer.
+    ///
+    /// Called in atomic context, so it must not sleep.
+    fn trigger(
+        data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        sub: &Substream,
+        cmd: TriggerCommand,
+    ) -> Result;
+
+    /// Returns the current hardware position in the buffer, in frames.
+    ///
+    /// Called in atomic context, so it must not sleep.
+    fn pointer(data: <Self::Data as ForeignOwnable>::Borrowed<'_>, sub: &Substream) -> usize;
+
+    /// Copies samples written by userspace into the device buffer at byte offset `pos`.
+    fn playback_copy(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _sub: &Substream,
+        _channel: i32,
+        _pos: usize,
+        _src: &mut impl IoBufferReader,
+    ) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Copies samples captured by the device at byte offset `pos` to userspace.
+    fn capture_copy(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _sub: &Substream,
+        _channel: i32,
+        _pos: usize,
+        _dst: &mut impl IoBufferWriter,
+    ) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Fills `len` bytes of the device buffer at byte offset `pos` with silence.
+    fn fill_silence(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _sub: &Substream,
+        _channel: i32,
+        _pos: usize,
+        _len: usize,
+    ) -> Result {
+        Err(EINVAL)
+    }
+}
+
+/// A kernel buffer passed to the copy callbacks when the PCM core transfers samples internally.
+struct KernelBuffer {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl IoBufferReader for KernelBuffer {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    unsafe fn read_raw(&mut self, out: *mut u8, len: usize) -> Result {
+        if len > self.len {
+            return Err(EFAULT);
+        }
+        // SAFETY: `self.ptr` is valid for `self.len` bytes, and the caller guarantees that `out`
+        // is valid for `len` bytes.
+        unsafe { core::ptr::copy(self.ptr, out, len) };
+        // SAFETY: `len` is at most `self.len`, so the result is within the buffer.
+        self.ptr = unsafe { self.ptr.add(len) };
+        self.len -= len;
+        Ok(())
+    }
+}
+
+impl IoBufferWriter for KernelBuffer {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn clear(&mut self, len: usize) -> Result {
+        if len > self.len {
+            return Err(EFAULT);
+        }
+        // SAFETY: `self.ptr` is valid for `self.len` bytes, and `len` is at most that.
+        unsafe {
+            core::ptr::write_bytes(self.ptr, 0, len);
+            self.ptr = self.ptr.add(len);
+        }
+        self.len -= len;
+        Ok(())
+    }
+
+    unsafe fn write_raw(&mut self, data: *const u8, len: usize) -> Result {
+        if len > self.len {
+            return Err(EFAULT);
+        }
+        // SAFETY: `self.ptr` is valid for `self.len` bytes, and the caller guarantees that `data`
+        // is valid for `len` bytes.
+        unsafe {
+            core::ptr::copy(data, self.ptr, len);
+            self.ptr = self.ptr.add(len);
+        }
+        self.len -= len;
+        Ok(())
+    }
+}
+
+struct PcmVtable<T>(PhantomData<T>);
+
+impl<T: PcmOperations> PcmVtable<T> {
+    fn build() -> bindings::snd_pcm_ops {
+        bindings::snd_pcm_ops {
+            open: Some(Self::open_callback),
+            close: Some(Self::close_callback),
+            prepare: Some(Self::prepare_callback),
+            trigger: Some(Self::trigger_callback),
+            pointer: Some(Self::pointer_callback),
+            copy_user: Some(Self::copy_user_callback),
+            copy_kernel: Some(Self::copy_kernel_callback),
+            fill_silence: if T::HAS_FILL_SILENCE {
+                Some(Self::fill_silence_callback)
+            } else {
+                None
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Returns the card data and a wrapper for `sub`.
+    ///
+    /// # Safety
+    ///
+    /// `sub` must be an open substream of a PCM device created by [`Registration`].
+    unsafe fn get<'a>(
+        sub: *mut bindings::snd_pcm_substream,
+    ) -> (<T::Data as ForeignOwnable>::Borrowed<'a>, Substream) {
+        // SAFETY: The PCM core copies the `private_data` of the PCM device, which `Registration`
+        // initialised with a value returned by `into_foreign`, to its substreams; `from_foreign`
+        // is only called once the device is freed.
+        unsafe {
+            (
+                T::Data::borrow((*sub).private_data),
+                Substream::from_ptr(sub),
+            )
+        }
+    }
+
+    unsafe extern "C" fn open_callback(sub: *mut bindings::snd_pcm_substream) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The PCM core only calls this with valid substreams of our device, after
+            // setting up the runtime.
+            let (data, s) = unsafe { Self::get(sub) };
+            // SAFETY: The runtime is valid and owned by the substream being opened.
+            unsafe { (*(*sub).runtime).hw = T::HARDWARE.to_raw() };
+            T::open(data, &s)?;
+            Ok(0)
+        }
+    }
+
+    unsafe extern "C" fn close_callback(sub: *mut bindings::snd_pcm_substream) -> core::ffi::c_int {
+        // SAFETY: The PCM core only calls this with open substreams of our device.
+        let (data, s) = unsafe { Self::get(sub) };
+        T::close(data, &s);
+        0
+    }
+
+    unsafe extern "C" fn prepare_callback(
+        sub: *mut bindings::snd_pcm_substream,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The PCM core only calls this with open substreams of our device.
+            let (data, s) = unsafe { Self::get(sub) };
+            T::prepare(data, &s)?;
+            Ok(0)
+        }
+    }
+
+    unsafe extern "C" fn trigger_callback(
+        sub: *mut bindings::snd_pcm_substream,
+        cmd: core::ffi::c_int,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The PCM core only calls this with open substreams of our device.
+            let (data, s) = unsafe { Self::get(sub) };
+            T::trigger(data, &s, TriggerCommand::from_raw(cmd)?)?;
+            Ok(0)
+        }
+    }
+
+    unsafe extern "C" fn pointer_callback(
+        sub: *mut bindings::snd_pcm_substream,
+    ) -> bindings::snd_pcm_uframes_t {
+        // SAFETY: The PCM core only calls this with open substreams of our device.
+        let (data, s) = unsafe { Self::get(sub) };
+        T::pointer(data, &s) as _
+    }
+
+    fn copy(
+        data: <T::Data as ForeignOwnable>::Borrowed<'_>,
+        s: &Substream,
+        channel: i32,
+        pos: usize,
+        buf: &mut (impl IoBufferReader + IoBufferWriter),
+    ) -> Result {
+        match s.direction() {
+            Direction::Playback => T::playback_copy(data, s, channel, pos, buf),
+            Direction::Capture => T::capture_copy(data, s, channel, pos, buf),
+        }
+    }
+
+    unsafe extern "C" fn copy_user_callback(
+        sub: *mut bindings::snd_pcm_substream,
+        channel: core::ffi::c_int,
+        pos: core::ffi::c_ulong,
+        buf: *mut core::ffi::c_void,
+        bytes: core::ffi::c_ulong,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The PCM core only calls this with open substreams of our device.
+            let (data, s) = unsafe { Self::get(sub) };
+            // SAFETY: `buf` is a user buffer of `bytes` bytes, and a single slice is created for
+            // it, so there are no TOCTOU issues.
+            let (mut reader, mut writer) =
+                unsafe { UserSlicePtr::new(buf, bytes as _) }.reader_writer();
+            match s.direction() {
+                Direction::Playback => T::playback_copy(data, &s, channel, pos as _, &mut reader)?,
+                Direction::Capture => T::capture_copy(data, &s, channel, pos as _, &mut writer)?,
+            }
+            Ok(0)
+        }
+    }
+
+    unsafe extern "C" fn copy_kernel_callback(
+        sub: *mut bindings::snd_pcm_substream,
+        channel: core::ffi::c_int,
+        pos: core::ffi::c_ulong,
+        buf: *mut core::ffi::c_void,
+        bytes: core::ffi::c_ulong,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The PCM core only calls this with open substreams of our device.
+            let (data, s) = unsafe { Self::get(sub) };
+            let mut buf = KernelBuffer {
+                ptr: buf as _,
+                len: bytes as _,
+            };
+            Self::copy(data, &s, channel, pos as _, &mut buf)?;
+            Ok(0)
+        }
+    }
+
+    unsafe extern "C" fn fill_silence_callback(
+        sub: *mut bindings::snd_pcm_substream,
+        channel: core::ffi::c_int,
+        pos: core::ffi::c_ulong,
+        bytes: core::ffi::c_ulong,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The PCM core only calls this with open substreams of our device.
+            let (data, s) = unsafe { Self::get(sub) };
+            T::fill_silence(data, &s, channel, pos as _, bytes as _)?;
+            Ok(0)
+        }
+    }
+
+    unsafe extern "C" fn private_free_callback(pcm: *mut bindings::snd_pcm) {
+        // SAFETY: `private_data` was initialised by `Registration::new` with a value returned by
+        // `into_foreign`, and the device is being freed so no callbacks can run anymore.
+        unsafe { T::Data::from_foreign((*pcm).private_data) };
+    }
+}
+
+/// Copies `src` into the fixed-size C string `dst`, truncating it if needed.
+fn copy_name(dst: &mut [core::ffi::c_char], src: &CStr) {
+    let len = core::cmp::min(src.len(), dst.len() - 1);
+    for (d, s) in dst.iter_mut().zip(&src.as_bytes()[..len]) {
+        *d = *s as _;
+    }
+    dst[len] = 0;
+}
+
+/// A registered sound card with a single PCM device.
+///
+/// # Invariants
+///
+/// `card` is a valid and registered sound card.
+pub struct Registration<T: PcmOperations> {
+    card: *mut bindings::snd_card,
+    _ops: Box<bindings::snd_pcm_ops>,
+    _p: PhantomData<T>,
+}
+
+impl<T: PcmOperations> Registration<T> {
+    /// Creates and registers a sound card with a PCM device that has `playback` playback and
+    /// `capture` capture substreams.
+    ///
+    /// `id` is the identifier of the card, and `name` the name shown to users.
+    pub fn new(
+        parent: &dyn device::RawDevice,
+        id: &'static CStr,
+        name: &CStr,
+        playback: u32,
+        capture: u32,
+        data: T::Data,
+        module: &'static ThisModule,
+    ) -> Result<Self> {
+        let ops = Box::try_new(PcmVtable::<T>::build())?;
+
+        let mut card = core::ptr::null_mut();
+        // SAFETY: `parent` is valid and `card` is a valid destination. `-1` lets the core pick
+        // the card index.
+        to_result(unsafe {
+            bindings::snd_card_new(
+                parent.raw_device(),
+                -1,
+                id.as_char_ptr(),
+                module.0,
+                0,
+                &mut card,
+            )
+        })?;
+
+        // On error, freeing the card also frees the PCM device and its data.
+        let guard = crate::ScopeGuard::new(|| {
+            // SAFETY: `card` was successfully created above and has not been registered.
+            unsafe { bindings::snd_card_free(card) };
+        });
+
+        // SAFETY: `card` was just created, so it is valid and nothing else references it.
+        unsafe {
+            copy_name(&mut (*card).driver, id);
+            copy_name(&mut (*card).shortname, name);
+            copy_name(&mut (*card).longname, name);
+        }
+
+        let mut pcm = core::ptr::null_mut();
+        // SAFETY: `card` is valid and `pcm` is a valid destination.
+        to_result(unsafe {
+            bindings::snd_pcm_new(
+                card,
+                name.as_char_ptr(),
+                0,
+                playback as _,
+                capture as _,
+                &mut pcm,
+            )
+        })?;
+
+        // SAFETY: `pcm` was just created. `ops` is heap-allocated and outlives the card, which
+        // is freed in `drop` before `ops` is.
+        unsafe {
+            copy_name(&mut (*pcm).name, name);
+            (*pcm).private_data = data.into_foreign() as _;
+            (*pcm).private_free = Some(PcmVtable::<T>::private_free_callback);
+            if playback > 0 {
+                bindings::snd_pcm_set_ops(pcm, bindings::SNDRV_PCM_STREAM_PLAYBACK as _, &*ops);
+            }
+            if capture > 0 {
+                bindings::snd_pcm_set_ops(pcm, bindings::SNDRV_PCM_STREAM_CAPTURE as _, &*ops);
+            }
+        }
+
+        // SAFETY: `card` is fully initialised.
+        to_result(unsafe { bindings::snd_card_register(card) })?;
+        guard.dismiss();
+
+        // INVARIANT: `card` was registered above.
+        Ok(Self {
+            card,
+            _ops: ops,
+            _p: PhantomData,
+        })
+    }
+}
+
+// SAFETY: `Registration` does not expose any of its state across threads.
+unsafe impl<T: PcmOperations> Sync for Registration<T> {}
+
+// SAFETY: `Registration` is not restricted to a single thread, and its `T::Data` is also `Send`
+// so it may be moved to different threads.
+#[allow(clippy::non_send_fields_in_send_ty)]
+unsafe impl<T: PcmOperations> Send for Registration<T> {}
+
+impl<T: PcmOperations> Drop for Registration<T> {
+    /// Frees the card, waiting for all its files to be closed.
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, `card` is valid. `snd_card_free` only returns once no
+        // callbacks can run anymore, so `_ops` may be freed afterwards.
+        unsafe { bindings::snd_card_free(self.card) };
+    }
+}
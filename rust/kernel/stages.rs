@@ -0,0 +1,106 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Resources acquired in stages.
+//!
+//! Module initialisation usually acquires several resources in turn, each of which may depend on
+//! the previous ones: a device is allocated, then registered, then exposed in debugfs, and so on.
+//! They must be released in the reverse order, so that nothing is torn down while a later stage
+//! still uses it.
+//!
+//! While [`crate::Module::init`] runs, each stage is a local variable, so if a stage fails, the
+//! ones that were already acquired are dropped in reverse order when the error is returned. Once
+//! they are moved into the module, however, they are dropped in the order of the fields of its
+//! struct, which is easy to get wrong. The [`init_stages`] macro declares a struct whose fields
+//! are dropped in the reverse of their declaration order, which makes the contract explicit: the
+//! fields are declared in the order in which the stages are initialised.
+
+/// Declares a struct whose fields are dropped in the reverse of their declaration order.
+///
+/// The fields are meant to be declared in the order in which they are initialised, so that each
+/// one is dropped before the ones it may depend on. The struct is created with the generated
+/// `new` function, which takes the fields in the same order, and its fields are accessed like
+/// those of any other struct, through [`core::mem::ManuallyDrop`].
+///
+/// Initialise the stages in local variables and only then pass them to `new`: if a stage fails,
+/// the ones before it are dropped in reverse order as well.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::{
+///     c_str,
+///     debugfs::{self, DebugFsDirectory, DebugFsFile, MODE_444},
+///     file::{self, File},
+///     init_stages, miscdev,
+///     sync::Arc,
+/// };
+///
+/// struct Device;
+///
+/// #[vtable]
+/// impl file::Operations for Device {
+///     type OpenData = Arc<Device>;
+///
+///     fn open(_device: &Arc<Device>, _file: &File) -> Result {
+///         Ok(())
+///     }
+/// }
+///
+/// init_stages! {
+///     struct Stages {
+///         device: Arc<Device>,
+///         registration: Pin<Box<miscdev::Registration<Device>>>,
+///         debugfs: DebugFsFile<Device>,
+///     }
+/// }
+///
+/// fn init_device(dir: Arc<DebugFsDirectory>) -> Result<Stages> {
+///     let device = Arc::try_new(Device)?;
+///     let vtable = kernel::file_vtable!(miscdev::Vtable<Device>);
+///     let registration =
+///         miscdev::Registration::new_pinned(vtable, fmt!("example"), device.clone())?;
+///     // If this fails, `registration` is dropped before `device`.
+///     let vtable = kernel::file_vtable!(debugfs::Vtable<Device>);
+///     let debugfs =
+///         DebugFsFile::create(c_str!("state"), Some(dir), MODE_444, vtable, device.clone())?;
+///     // Once created, `debugfs` is dropped first, then `registration`, then `device`.
+///     Ok(Stages::new(device, registration, debugfs))
+/// }
+/// ```
+#[macro_export]
+macro_rules! init_stages {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$fmeta:meta])* $fvis:vis $field:ident: $ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $($(#[$fmeta])* $fvis $field: core::mem::ManuallyDrop<$ty>,)*
+        }
+
+        impl $name {
+            /// Creates the struct out of its stages, in the order in which they were initialised.
+            #[allow(clippy::too_many_arguments)]
+            $vis fn new($($field: $ty),*) -> Self {
+                Self {
+                    $($field: core::mem::ManuallyDrop::new($field),)*
+                }
+            }
+        }
+
+        impl Drop for $name {
+            fn drop(&mut self) {
+                $crate::init_stages!(@drop_reverse self; $($field)*);
+            }
+        }
+    };
+    (@drop_reverse $self:ident;) => {};
+    (@drop_reverse $self:ident; $first:ident $($rest:ident)*) => {
+        $crate::init_stages!(@drop_reverse $self; $($rest)*);
+        // SAFETY: Each field is dropped exactly once, here, and the struct is not used afterwards.
+        unsafe { core::mem::ManuallyDrop::drop(&mut $self.$first) };
+    };
+}
@@ -1,6 +1,34 @@
 // SPDX-License-Identifier: GPL-2.0
 
 //! Static assert.
+//!
+//! Besides [`static_assert!`], this provides shorthands for the checks that are most common on
+//! types shared with C, such as [`static_assert_size!`] and [`static_assert_layout!`], and the
+//! `const` functions they are built on, so that mismatches are caught when the kernel is built.
+//!
+//! [`static_assert!`]: crate::static_assert!
+//! [`static_assert_size!`]: crate::static_assert_size!
+//! [`static_assert_layout!`]: crate::static_assert_layout!
+
+use core::mem::{align_of, size_of};
+
+/// Returns whether `value` is a multiple of `align`, which must be a power of two.
+///
+/// Returns `false` if `align` is not a power of two.
+pub const fn is_aligned(value: usize, align: usize) -> bool {
+    align.is_power_of_two() && value & (align - 1) == 0
+}
+
+/// Returns whether `A` and `B` have the same size and alignment, so that a pointer to one can be
+/// cast to a pointer to the other.
+pub const fn same_layout<A, B>() -> bool {
+    size_of::<A>() == size_of::<B>() && align_of::<A>() == align_of::<B>()
+}
+
+/// Returns whether a value of type `T` fits in a buffer of `len` bytes.
+pub const fn fits_in<T>(len: usize) -> bool {
+    size_of::<T>() <= len
+}
 
 /// Static assert (i.e. compile-time assert).
 ///
@@ -8,6 +36,8 @@
 ///
 /// The feature may be added to Rust in the future: see [RFC 2790].
 ///
+/// An optional message is shown when the assertion fails.
+///
 /// [`_Static_assert`]: https://en.cppreference.com/w/c/language/_Static_assert
 /// [`static_assert`]: https://en.cppreference.com/w/cpp/language/static_assert
 /// [RFC 2790]: https://github.com/rust-lang/rfcs/issues/2790
@@ -25,10 +55,107 @@
 ///     x + 2
 /// }
 /// static_assert!(f(40) == 42);
+///
+/// const BUF_SIZE: usize = 64;
+/// static_assert!(BUF_SIZE.is_power_of_two(), "BUF_SIZE must be a power of two");
 /// ```
 #[macro_export]
 macro_rules! static_assert {
-    ($condition:expr) => {
+    ($condition:expr $(,)?) => {
         const _: () = core::assert!($condition);
     };
+    ($condition:expr, $msg:expr $(,)?) => {
+        const _: () = core::assert!($condition, $msg);
+    };
+}
+
+/// Asserts at compile time that the size of a type is `size` bytes.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::static_assert_size;
+/// #[repr(C)]
+/// struct Header {
+///     magic: u32,
+///     len: u32,
+/// }
+///
+/// static_assert_size!(Header, 8);
+/// static_assert_size!(u64, 8);
+/// ```
+#[macro_export]
+macro_rules! static_assert_size {
+    ($type:ty, $size:expr $(,)?) => {
+        $crate::static_assert!(
+            core::mem::size_of::<$type>() == $size,
+            concat!(
+                "size of `",
+                stringify!($type),
+                "` is not ",
+                stringify!($size)
+            )
+        );
+    };
+}
+
+/// Asserts at compile time that the alignment of a type is `align` bytes.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::static_assert_align;
+/// #[repr(C, align(64))]
+/// struct PerCpu {
+///     count: u64,
+/// }
+///
+/// static_assert_align!(PerCpu, 64);
+/// ```
+#[macro_export]
+macro_rules! static_assert_align {
+    ($type:ty, $align:expr $(,)?) => {
+        $crate::static_assert!(
+            core::mem::align_of::<$type>() == $align,
+            concat!(
+                "alignment of `",
+                stringify!($type),
+                "` is not ",
+                stringify!($align)
+            )
+        );
+    };
+}
+
+/// Asserts at compile time that two types have the same size and alignment.
+///
+/// It is meant for Rust types that stand for a C type, such as wrappers of bindings that are not
+/// `#[repr(transparent)]`, whose pointers are cast to pointers to the C type.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::static_assert_layout;
+/// #[repr(C)]
+/// struct Pair {
+///     first: u32,
+///     second: u32,
+/// }
+///
+/// static_assert_layout!(Pair, [u32; 2]);
+/// ```
+#[macro_export]
+macro_rules! static_assert_layout {
+    ($a:ty, $b:ty $(,)?) => {
+        $crate::static_assert!(
+            $crate::static_assert::same_layout::<$a, $b>(),
+            concat!(
+                "`",
+                stringify!($a),
+                "` and `",
+                stringify!($b),
+                "` have different layouts"
+            )
+        );
+    };
 }
@@ -0,0 +1,514 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Statistics counters.
+//!
+//! A [`StatSet`] is a fixed set of named counters that can be updated cheaply from any context,
+//! including interrupt handlers. `StatSetDir` exports them to debugfs, with one file per counter
+//! and a summary file. A [`Histogram`] tracks the distribution of values such as latencies.
+
+use crate::{bindings, prelude::*};
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+#[cfg(CONFIG_DEBUG_FS)]
+use crate::{
+    c_str,
+    debugfs::{DebugFsDirectory, DebugFsFile, MODE_444},
+    seq_file::{self, SeqFileAdapter, SeqOperations},
+    str::CString,
+    sync::{Arc, ArcBorrow},
+    ThisModule,
+};
+
+/// The number of counters that fit in a cache line.
+///
+/// The counters of each CPU are padded to a multiple of this so that CPUs updating their own
+/// counters do not contend for the same cache lines.
+const COUNTERS_PER_LINE: usize = 64 / core::mem::size_of::<u64>();
+
+/// Returns the number of possible CPU ids.
+fn nr_cpu_ids() -> usize {
+    crate::cpu::nr_cpu_ids() as usize
+}
+
+/// Returns the id of the CPU the caller is running on.
+///
+/// The caller may be migrated to another CPU right after this returns, so the result is only a
+/// hint.
+fn this_cpu_id() -> usize {
+    // SAFETY: This function has no safety requirements.
+    unsafe { bindings::raw_smp_processor_id() as usize }
+}
+
+/// A named set of `N` counters.
+///
+/// Each CPU has its own copy of the counters, so updating them is cheap even when many CPUs do so
+/// concurrently, and reading a counter adds up the copies. The counters are identified by their
+/// index in the array of names given to [`StatSet::new`].
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::stats::StatSet;
+///
+/// const READS: usize = 0;
+/// const WRITES: usize = 1;
+/// const ERRORS: usize = 2;
+///
+/// fn account(stats: &StatSet<3>, write: bool, result: Result) {
+///     stats.inc(if write { WRITES } else { READS });
+///     if result.is_err() {
+///         stats.inc(ERRORS);
+///     }
+/// }
+///
+/// let stats = StatSet::new(["reads", "writes", "errors"])?;
+/// account(&stats, false, Ok(()));
+/// account(&stats, true, Err(EIO));
+/// assert_eq!(stats.get(READS), 1);
+/// assert_eq!(stats.get(ERRORS), 1);
+/// # Ok::<(), Error>(())
+/// ```
+///
+/// # Invariants
+///
+/// `counters` holds `stride` counters for each possible CPU, and `stride` is at least `N`.
+pub struct StatSet<const N: usize> {
+    names: [&'static str; N],
+    counters: Vec<AtomicU64>,
+    stride: usize,
+}
+
+impl<const N: usize> StatSet<N> {
+    /// Creates a new set of counters called `names`, all starting at zero.
+    pub fn new(names: [&'static str; N]) -> Result<Self> {
+        let stride = (N + COUNTERS_PER_LINE - 1) / COUNTERS_PER_LINE * COUNTERS_PER_LINE;
+        let len = stride.checked_mul(nr_cpu_ids()).ok_or(ENOMEM)?;
+        let mut counters = Vec::try_with_capacity(len)?;
+        for _ in 0..len {
+            counters.try_push(AtomicU64::new(0))?;
+        }
+
+        // INVARIANT: There are `stride` counters for each possible CPU.
+        Ok(Self {
+            names,
+            counters,
+            stride,
+        })
+    }
+
+    /// Returns the name of the counter at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not less than `N`.
+    pub fn name(&self, index: usize) -> &'static str {
+        self.names[index]
+    }
+
+    /// Adds `value` to the counter at `index`.
+    ///
+    /// It may be called from any context.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not less than `N`.
+    pub fn add(&self, index: usize, value: u64) {
+        assert!(index < N);
+        // If the caller migrates in the meantime, it updates the copy of another CPU, which is
+        // still correct because the update is atomic.
+        let cpu = this_cpu_id();
+        self.counters[cpu * self.stride + index].fetch_add(value, Ordering::Relaxed);
+    }
+
+    /// Increments the counter at `index`.
+    ///
+    /// It may be called from any context.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not less than `N`.
+    pub fn inc(&self, index: usize) {
+        self.add(index, 1);
+    }
+
+    /// Returns the current value of the counter at `index`.
+    ///
+    /// Updates that happen concurrently may or may not be included.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not less than `N`.
+    pub fn get(&self, index: usize) -> u64 {
+        assert!(index < N);
+        self.counters
+            .iter()
+            .skip(index)
+            .step_by(self.stride)
+            .fold(0u64, |sum, c| sum.wrapping_add(c.load(Ordering::Relaxed)))
+    }
+
+    /// Returns an iterator over the names and current values of all the counters.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, u64)> + '_ {
+        (0..N).map(move |i| (self.names[i], self.get(i)))
+    }
+
+    /// Sets all the counters back to zero.
+    ///
+    /// Updates that happen concurrently may or may not be lost.
+    pub fn reset(&self) {
+        for c in self.counters.iter() {
+            c.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A line of the debugfs files of a [`StatSet`].
+pub struct StatLine {
+    name: Option<&'static str>,
+    value: u64,
+}
+
+impl fmt::Display for StatLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            Some(name) => writeln!(f, "{}: {}", name, self.value),
+            None => writeln!(f, "{}", self.value),
+        }
+    }
+}
+
+/// The sequence operations of the summary file of a [`StatSet`], with one line per counter.
+#[cfg(CONFIG_DEBUG_FS)]
+pub struct SummaryFile<const N: usize>;
+
+#[cfg(CONFIG_DEBUG_FS)]
+impl<const N: usize> SeqOperations for SummaryFile<N> {
+    type OpenData = Arc<StatSet<N>>;
+    type DataWrapper = Arc<StatSet<N>>;
+    type IteratorWrapper<'a> = core::array::IntoIter<StatLine, N>;
+    type Item<'a> = StatLine;
+
+    fn open(stats: &Arc<StatSet<N>>) -> Result<Arc<StatSet<N>>> {
+        Ok(stats.clone())
+    }
+
+    fn start<'a>(
+        stats: ArcBorrow<'a, StatSet<N>>,
+        _state: &'a mut (),
+    ) -> Option<Self::IteratorWrapper<'a>> {
+        // The values are all read before the first line is shown, so they are close in time.
+        let mut index = 0;
+        let lines = stats.names.map(|name| {
+            let line = StatLine {
+                name: Some(name),
+                value: stats.get(index),
+            };
+            index += 1;
+            line
+        });
+        Some(lines.into_iter())
+    }
+}
+
+/// The sequence operations of the file of a single counter of a [`StatSet`].
+#[cfg(CONFIG_DEBUG_FS)]
+pub struct CounterFile<const N: usize>;
+
+#[cfg(CONFIG_DEBUG_FS)]
+impl<const N: usize> SeqOperations for CounterFile<N> {
+    type OpenData = (Arc<StatSet<N>>, usize);
+    type DataWrapper = Box<(Arc<StatSet<N>>, usize)>;
+    type IteratorWrapper<'a> = core::iter::Once<StatLine>;
+    type Item<'a> = StatLine;
+
+    fn open(data: &(Arc<StatSet<N>>, usize)) -> Result<Self::DataWrapper> {
+        Ok(Box::try_new(data.clone())?)
+    }
+
+    fn start<'a>(
+        data: &'a (Arc<StatSet<N>>, usize),
+        _state: &'a mut (),
+    ) -> Option<Self::IteratorWrapper<'a>> {
+        let (stats, index) = data;
+        Some(core::iter::once(StatLine {
+            name: None,
+            value: stats.get(*index),
+        }))
+    }
+}
+
+/// The file operations of the files of a [`StatSetDir`], with the module that owns them.
+///
+/// They are defined in the module that creates the directory with [`crate::file_vtable!`].
+#[cfg(CONFIG_DEBUG_FS)]
+pub struct StatSetVtables<const N: usize> {
+    counter: seq_file::Vtable<CounterFile<N>>,
+    summary: seq_file::Vtable<SummaryFile<N>>,
+}
+
+#[cfg(CONFIG_DEBUG_FS)]
+impl<const N: usize> StatSetVtables<N> {
+    /// Creates the file operations of the files of a [`StatSetDir`], which belong to `module`.
+    pub const fn new(module: &'static ThisModule) -> Self {
+        Self {
+            counter: seq_file::Vtable::new(module),
+            summary: seq_file::Vtable::new(module),
+        }
+    }
+}
+
+/// The debugfs directory of a [`StatSet`].
+///
+/// It contains a read-only file per counter, named after it, and a `summary` file with the names
+/// and values of all the counters. The directory is removed when this object is dropped.
+#[cfg(CONFIG_DEBUG_FS)]
+pub struct StatSetDir<const N: usize> {
+    dir: Arc<DebugFsDirectory>,
+    summary: DebugFsFile<SeqFileAdapter<SummaryFile<N>>>,
+    _counters: Vec<DebugFsFile<SeqFileAdapter<CounterFile<N>>>>,
+}
+
+#[cfg(CONFIG_DEBUG_FS)]
+impl<const N: usize> StatSetDir<N> {
+    /// Creates a directory called `name`, in `parent` or at the root of debugfs, that exports
+    /// the counters of `stats`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kernel::prelude::*;
+    /// use kernel::{
+    ///     c_str,
+    ///     stats::{StatSet, StatSetDir, StatSetVtables},
+    ///     sync::Arc,
+    /// };
+    ///
+    /// fn export(stats: Arc<StatSet<2>>) -> Result<StatSetDir<2>> {
+    ///     let vtables = kernel::file_vtable!(StatSetVtables<2>);
+    ///     StatSetDir::create(c_str!("rust_stats"), None, vtables, stats)
+    /// }
+    /// ```
+    pub fn create(
+        name: &CStr,
+        parent: Option<Arc<DebugFsDirectory>>,
+        vtables: &'static StatSetVtables<N>,
+        stats: Arc<StatSet<N>>,
+    ) -> Result<Self> {
+        let dir = DebugFsDirectory::create(name, parent)?;
+
+        let mut counters = Vec::try_with_capacity(N)?;
+        for (index, counter) in stats.names.iter().enumerate() {
+            let name = CString::try_from_fmt(fmt!("{counter}"))?;
+            counters.try_push(seq_file::debugfs_create_file::<CounterFile<N>>(
+                &name,
+                Some(dir.clone()),
+                MODE_444,
+                &vtables.counter,
+                (stats.clone(), index),
+            )?)?;
+        }
+
+        let summary = seq_file::debugfs_create_file::<SummaryFile<N>>(
+            c_str!("summary"),
+            Some(dir.clone()),
+            MODE_444,
+            &vtables.summary,
+            stats,
+        )?;
+
+        Ok(Self {
+            dir,
+            summary,
+            _counters: counters,
+        })
+    }
+
+    /// Returns the counters exported by the directory.
+    pub fn stats(&self) -> &Arc<StatSet<N>> {
+        self.summary.open_data()
+    }
+
+    /// Returns the directory, for example to create more entries in it.
+    pub fn dir(&self) -> &Arc<DebugFsDirectory> {
+        &self.dir
+    }
+}
+
+/// The number of buckets of a [`Histogram`].
+///
+/// Bucket 0 counts the value 0, and bucket `i > 0` counts the values in `2^(i-1)..2^i`.
+pub const HISTOGRAM_BUCKETS: usize = u64::BITS as usize + 1;
+
+/// A distribution of values in buckets of exponentially increasing size.
+///
+/// It is meant for values that vary over several orders of magnitude, such as the latency of an
+/// operation. [`Histogram::record`] may be called from any context, including interrupt handlers,
+/// and the distribution can be shown in debugfs with [`debugfs_create_histogram`].
+///
+/// # Examples
+///
+/// ```
+/// use kernel::stats::Histogram;
+///
+/// static LATENCY: Histogram = Histogram::new();
+///
+/// for ns in [0, 1, 100, 120, 5000] {
+///     LATENCY.record(ns);
+/// }
+/// assert_eq!(LATENCY.count(), 5);
+/// assert_eq!(LATENCY.bucket(Histogram::bucket_of(100)), 2);
+/// ```
+pub struct Histogram {
+    buckets: [AtomicU64; HISTOGRAM_BUCKETS],
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    /// Creates a new empty histogram.
+    pub const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicU64 = AtomicU64::new(0);
+        Self {
+            buckets: [ZERO; HISTOGRAM_BUCKETS],
+            sum: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the index of the bucket that counts `value`.
+    pub const fn bucket_of(value: u64) -> usize {
+        (u64::BITS - value.leading_zeros()) as usize
+    }
+
+    /// Returns the range of values counted by the bucket at `index`, inclusive.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not less than [`HISTOGRAM_BUCKETS`].
+    pub fn bucket_range(index: usize) -> (u64, u64) {
+        assert!(index < HISTOGRAM_BUCKETS);
+        match index {
+            0 => (0, 0),
+            _ => (1 << (index - 1), u64::MAX >> (u64::BITS as usize - index)),
+        }
+    }
+
+    /// Records an occurrence of `value`.
+    ///
+    /// It may be called from any context.
+    pub fn record(&self, value: u64) {
+        self.buckets[Self::bucket_of(value)].fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+    }
+
+    /// Returns the number of values counted by the bucket at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not less than [`HISTOGRAM_BUCKETS`].
+    pub fn bucket(&self, index: usize) -> u64 {
+        self.buckets[index].load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of values recorded.
+    pub fn count(&self) -> u64 {
+        self.buckets
+            .iter()
+            .fold(0u64, |sum, b| sum.wrapping_add(b.load(Ordering::Relaxed)))
+    }
+
+    /// Returns the sum of the values recorded, wrapping around on overflow.
+    pub fn sum(&self) -> u64 {
+        self.sum.load(Ordering::Relaxed)
+    }
+
+    /// Forgets all the values recorded so far.
+    ///
+    /// Values recorded concurrently may or may not be forgotten.
+    pub fn reset(&self) {
+        for b in self.buckets.iter() {
+            b.store(0, Ordering::Relaxed);
+        }
+        self.sum.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A line of the debugfs file of a [`Histogram`], with the range and count of a bucket.
+pub struct HistogramLine {
+    index: usize,
+    count: u64,
+}
+
+impl fmt::Display for HistogramLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (low, high) = Histogram::bucket_range(self.index);
+        writeln!(f, "{:>20} - {:<20} {}", low, high, self.count)
+    }
+}
+
+/// The sequence operations of the debugfs file of a [`Histogram`].
+///
+/// The file shows a line per bucket with the range of the bucket and its count, from the lowest to
+/// the highest bucket that is not empty.
+#[cfg(CONFIG_DEBUG_FS)]
+pub struct HistogramFile;
+
+#[cfg(CONFIG_DEBUG_FS)]
+type HistogramLines =
+    core::iter::Skip<core::iter::Take<core::array::IntoIter<HistogramLine, HISTOGRAM_BUCKETS>>>;
+
+#[cfg(CONFIG_DEBUG_FS)]
+impl SeqOperations for HistogramFile {
+    type OpenData = Arc<Histogram>;
+    type DataWrapper = Arc<Histogram>;
+    type IteratorWrapper<'a> = HistogramLines;
+    type Item<'a> = HistogramLine;
+
+    fn open(histogram: &Arc<Histogram>) -> Result<Arc<Histogram>> {
+        Ok(histogram.clone())
+    }
+
+    fn start<'a>(
+        histogram: ArcBorrow<'a, Histogram>,
+        _state: &'a mut (),
+    ) -> Option<HistogramLines> {
+        // The buckets are all read before the first line is shown, so they are close in time.
+        let mut index = 0;
+        let lines = [(); HISTOGRAM_BUCKETS].map(|_| {
+            let line = HistogramLine {
+                index,
+                count: histogram.bucket(index),
+            };
+            index += 1;
+            line
+        });
+        let first = lines.iter().position(|l| l.count != 0)?;
+        let last = lines.iter().rposition(|l| l.count != 0)?;
+        Some(lines.into_iter().take(last + 1).skip(first))
+    }
+}
+
+/// Creates a read-only file called `name`, in `parent` or at the root of debugfs, that shows the
+/// distribution of `histogram`.
+///
+/// The file is removed when the returned handle is dropped.
+#[cfg(CONFIG_DEBUG_FS)]
+pub fn debugfs_create_histogram(
+    name: &CStr,
+    parent: Option<Arc<DebugFsDirectory>>,
+    histogram: Arc<Histogram>,
+) -> Result<DebugFsFile<SeqFileAdapter<HistogramFile>>> {
+    // `HistogramFile` is implemented by this crate alone, so its files do not keep any module
+    // loaded.
+    static VTABLE: seq_file::Vtable<HistogramFile> = seq_file::Vtable::new(&crate::BUILTIN_MODULE);
+    seq_file::debugfs_create_file(name, parent, MODE_444, &VTABLE, histogram)
+}
@@ -129,6 +129,29 @@ impl CStr {
         Ok(unsafe { Self::from_bytes_with_nul_unchecked(bytes) })
     }
 
+    /// Creates a [`CStr`] from the bytes of `bytes` up to and including the first `NUL`.
+    ///
+    /// Unlike [`CStr::from_bytes_with_nul`], the slice may continue after the `NUL`, as with
+    /// fixed-size buffers filled by C code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kernel::str::CStr;
+    /// let buf = *b"eth0\0\0\0\0";
+    /// let name = CStr::from_bytes_until_nul(&buf).unwrap();
+    /// assert_eq!(name.as_bytes(), b"eth0");
+    /// assert!(CStr::from_bytes_until_nul(b"eth0").is_err());
+    /// ```
+    pub fn from_bytes_until_nul(bytes: &[u8]) -> Result<&Self, CStrConvertError> {
+        let nul = bytes
+            .iter()
+            .position(|&c| c == 0)
+            .ok_or(CStrConvertError::NotNulTerminated)?;
+        // SAFETY: The last byte is the first `NUL`, so there is no other.
+        Ok(unsafe { Self::from_bytes_with_nul_unchecked(&bytes[..=nul]) })
+    }
+
     /// Creates a [`CStr`] from a `[u8]` without performing any additional
     /// checks.
     ///
@@ -322,6 +345,76 @@ where
     }
 }
 
+/// Removes the leading and trailing ASCII whitespace of `data`.
+pub fn trim_whitespace(data: &BStr) -> &BStr {
+    let start = data
+        .iter()
+        .position(|c| !c.is_ascii_whitespace())
+        .unwrap_or(data.len());
+    let end = data
+        .iter()
+        .rposition(|c| !c.is_ascii_whitespace())
+        .map_or(start, |i| i + 1);
+    &data[start..end]
+}
+
+/// Returns the value that user space means to write in `data`.
+///
+/// This is how writes to sysctl and debugfs files are interpreted, so that they all accept the
+/// same forms: the data stops at the first `NUL`, if any, surrounding whitespace such as the
+/// newline added by `echo` is removed, and so are matching single or double quotes around the
+/// rest.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::str::canonicalize_input;
+/// assert_eq!(canonicalize_input(b"1\n"), b"1");
+/// assert_eq!(canonicalize_input(b"  \"low power\"\n"), b"low power");
+/// assert_eq!(canonicalize_input(b"'it\"s'\0garbage"), b"it\"s");
+/// assert_eq!(canonicalize_input(b"\"unbalanced\n"), b"\"unbalanced");
+/// ```
+pub fn canonicalize_input(data: &BStr) -> &BStr {
+    let data = match data.iter().position(|&c| c == 0) {
+        Some(nul) => &data[..nul],
+        None => data,
+    };
+    let data = trim_whitespace(data);
+    match data {
+        [b'"', inner @ .., b'"'] | [b'\'', inner @ .., b'\''] => inner,
+        _ => data,
+    }
+}
+
+/// Parses a boolean written by user space.
+///
+/// After [`canonicalize_input`], `1`, `y`, `yes`, `on`, `true` and `enable` are `true`, and `0`,
+/// `n`, `no`, `off`, `false` and `disable` are `false`, regardless of case. Anything else is
+/// rejected with `EINVAL`.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::str::parse_bool;
+/// assert_eq!(parse_bool(b"1\n"), Ok(true));
+/// assert_eq!(parse_bool(b"Enable"), Ok(true));
+/// assert_eq!(parse_bool(b"\"off\""), Ok(false));
+/// assert!(parse_bool(b"2").is_err());
+/// ```
+pub fn parse_bool(data: &BStr) -> Result<bool, Error> {
+    const TRUE: [&[u8]; 6] = [b"1", b"y", b"yes", b"on", b"true", b"enable"];
+    const FALSE: [&[u8]; 6] = [b"0", b"n", b"no", b"off", b"false", b"disable"];
+
+    let data = canonicalize_input(data);
+    if TRUE.iter().any(|s| s.eq_ignore_ascii_case(data)) {
+        Ok(true)
+    } else if FALSE.iter().any(|s| s.eq_ignore_ascii_case(data)) {
+        Ok(false)
+    } else {
+        Err(EINVAL)
+    }
+}
+
 /// Creates a new [`CStr`] from a string literal.
 ///
 /// The string literal should not contain any `NUL` bytes.
@@ -511,6 +604,51 @@ impl fmt::Write for Formatter {
     }
 }
 
+/// Formats `args` into the buffer of `len` bytes at `buf`, followed by a `NUL` byte.
+///
+/// If the output does not fit, it is cut short so that it ends with `marker` just before the
+/// `NUL` byte. Returns the number of bytes written, not counting the `NUL` byte, and whether the
+/// output was truncated.
+///
+/// # Safety
+///
+/// The memory region starting at `buf` and extending for `len` bytes must be valid for writes, and
+/// `len` must be greater than the length of `marker`.
+pub(crate) unsafe fn format_truncated(
+    buf: *mut u8,
+    len: usize,
+    marker: &str,
+    args: fmt::Arguments<'_>,
+) -> Result<(usize, bool), Error> {
+    // Leave room for the `NUL` byte.
+    let limit = len - 1;
+    // SAFETY: The first `limit` bytes of the buffer are valid for writes.
+    let mut f = unsafe { RawFormatter::from_buffer(buf, limit) };
+    // `RawFormatter` keeps counting past the end of the buffer, so this only fails if one of the
+    // `Display` implementations fails.
+    f.write_fmt(args).map_err(|_| EINVAL)?;
+
+    let truncated = f.bytes_written() > limit;
+    let written = if truncated {
+        // SAFETY: `marker` fits in the first `limit` bytes of the buffer by the safety
+        // requirements, and it cannot overlap with the buffer since it is a shared reference.
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                marker.as_ptr(),
+                buf.add(limit - marker.len()),
+                marker.len(),
+            )
+        };
+        limit
+    } else {
+        f.bytes_written()
+    };
+
+    // SAFETY: `written` is at most `limit`, which is less than `len`.
+    unsafe { *buf.add(written) = 0 };
+    Ok((written, truncated))
+}
+
 /// An owned string that is guaranteed to have exactly one `NUL` byte, which is at the end.
 ///
 /// Used for interoperability with kernel APIs that take C strings.
@@ -584,6 +722,274 @@ impl Deref for CString {
     }
 }
 
+/// A buffer for building C strings, whose memory is reused when it is cleared.
+///
+/// Unlike [`CString::try_from_fmt`], which allocates a new string every time, it only allocates
+/// when the text appended to it does not fit in the memory it already has. It suits read handlers
+/// and loops that format a string over and over.
+///
+/// # Invariants
+///
+/// `buf` is either empty, for the empty string, or `NUL`-terminated with no other `NUL` bytes.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::str::CStringBuf;
+///
+/// let mut line = CStringBuf::new();
+/// for i in 0..3 {
+///     line.clear();
+///     line.try_write_fmt(fmt!("item {}", i))?;
+///     line.try_write_fmt(fmt!(": {}", i * 10))?;
+///     assert!(line.as_bytes().starts_with(b"item "));
+/// }
+/// assert_eq!(line.as_bytes_with_nul(), b"item 2: 20\0");
+///
+/// // Text with a `NUL` byte is rejected, and the buffer is left unchanged.
+/// assert!(line.try_write_fmt(fmt!("a\0b")).is_err());
+/// assert_eq!(line.as_bytes(), b"item 2: 20");
+///
+/// let s = line.try_into_c_string()?;
+/// assert_eq!(s.as_bytes(), b"item 2: 20");
+/// # Ok::<(), kernel::error::Error>(())
+/// ```
+#[derive(Default)]
+pub struct CStringBuf {
+    buf: Vec<u8>,
+}
+
+impl CStringBuf {
+    /// Creates a new empty buffer, without allocating.
+    pub const fn new() -> Self {
+        // INVARIANT: The buffer is empty.
+        Self { buf: Vec::new() }
+    }
+
+    /// Creates a new empty buffer with room for a string of `capacity` bytes.
+    pub fn try_with_capacity(capacity: usize) -> Result<Self, Error> {
+        // INVARIANT: The buffer is empty.
+        Ok(Self {
+            buf: Vec::try_with_capacity(capacity.checked_add(1).ok_or(ENOMEM)?)?,
+        })
+    }
+
+    /// Empties the buffer, keeping its memory for the next strings.
+    pub fn clear(&mut self) {
+        // INVARIANT: The buffer is empty.
+        self.buf.clear();
+    }
+
+    /// Appends formatted text to the string.
+    ///
+    /// If the text does not fit in the buffer, the buffer grows to the exact size that is needed.
+    /// Fails with `EINVAL` if the text contains a `NUL` byte, or with `ENOMEM`, in which cases the
+    /// string is left unchanged.
+    pub fn try_write_fmt(&mut self, args: fmt::Arguments<'_>) -> Result<(), Error> {
+        // Calculate the size of the text.
+        let mut f = RawFormatter::new();
+        f.write_fmt(args)?;
+        let added = f.bytes_written();
+        if added == 0 {
+            return Ok(());
+        }
+
+        // The text overwrites the current `NUL` terminator, if any, and is followed by a new one.
+        let start = self.len();
+        let len = start.checked_add(added + 1).ok_or(ENOMEM)?;
+        self.buf.try_reserve(len - self.buf.len())?;
+
+        // SAFETY: The buffer has a capacity of at least `len` bytes, so the `added + 1` bytes
+        // from `start` are valid for writes.
+        let text = unsafe { self.buf.as_mut_ptr().add(start) };
+        // SAFETY: `text` is valid for writes of `added + 1` bytes, as explained above.
+        let mut f = unsafe { Formatter::from_buffer(text, added + 1) };
+        if let Ok(()) = f.write_fmt(args).and_then(|_| f.write_str("\0")) {
+            let written = f.bytes_written();
+            // SAFETY: The `written` bytes at `text` were just initialised by `f`.
+            let new = unsafe { core::slice::from_raw_parts(text, written) };
+            if !new[..written - 1].contains(&0) {
+                // SAFETY: The bytes up to `start + written` are initialised: the first `start`
+                // ones were already, and the others were just written.
+                // INVARIANT: The text has no `NUL` bytes and is followed by a `NUL` terminator.
+                unsafe { self.buf.set_len(start + written) };
+                return Ok(());
+            }
+        }
+
+        // Restore the `NUL` terminator that the text overwrote, if there was one.
+        // SAFETY: `text` is valid for writes, as explained above.
+        unsafe { text.write(0) };
+        Err(EINVAL)
+    }
+
+    /// Returns the string built so far.
+    pub fn as_c_str(&self) -> &CStr {
+        if self.buf.is_empty() {
+            // SAFETY: The slice is a single `NUL` byte.
+            return unsafe { CStr::from_bytes_with_nul_unchecked(b"\0") };
+        }
+        // SAFETY: By the type invariants, a non-empty buffer is `NUL`-terminated and has no other
+        // `NUL` bytes.
+        unsafe { CStr::from_bytes_with_nul_unchecked(&self.buf) }
+    }
+
+    /// Converts the buffer into a [`CString`], without copying the string.
+    pub fn try_into_c_string(mut self) -> Result<CString, Error> {
+        if self.buf.is_empty() {
+            self.buf.try_push(0)?;
+        }
+        // INVARIANT: The buffer is not empty, so by the type invariants it is `NUL`-terminated
+        // and has no other `NUL` bytes.
+        Ok(CString { buf: self.buf })
+    }
+}
+
+impl Deref for CStringBuf {
+    type Target = CStr;
+
+    fn deref(&self) -> &CStr {
+        self.as_c_str()
+    }
+}
+
+/// Displays a byte slice as a hex dump.
+///
+/// The format is that of `print_hex_dump` with `DUMP_PREFIX_OFFSET` and 16 bytes per line.
+///
+/// Each line shows the offset of its first byte, its bytes in hex, and the same bytes as
+/// ASCII, with `.` for characters that are not printable. Lines are separated by newlines, and
+/// the last one is not followed by one.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::str::{hex_dump, CString};
+/// let s = CString::try_from_fmt(fmt!("{}", hex_dump(b"Rust\x00\x01"))).unwrap();
+/// assert_eq!(
+///     s.as_bytes_with_nul(),
+///     "00000000: 52 75 73 74 00 01                                Rust..\0".as_bytes()
+/// );
+/// ```
+pub fn hex_dump(data: &[u8]) -> HexDump<'_> {
+    HexDump(data)
+}
+
+/// A byte slice that is displayed as a hex dump; see [`hex_dump`].
+pub struct HexDump<'a>(&'a [u8]);
+
+impl fmt::Display for HexDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const BYTES_PER_LINE: usize = 16;
+
+        for (i, line) in self.0.chunks(BYTES_PER_LINE).enumerate() {
+            if i > 0 {
+                f.write_char('\n')?;
+            }
+            write!(f, "{:08x}:", i * BYTES_PER_LINE)?;
+            for b in line {
+                write!(f, " {:02x}", b)?;
+            }
+            // Align the ASCII column of a short last line with the lines above.
+            for _ in line.len()..BYTES_PER_LINE {
+                f.write_str("   ")?;
+            }
+            f.write_str("  ")?;
+            for &b in line {
+                let c = if (0x20..0x7f).contains(&b) { b } else { b'.' };
+                f.write_char(c as char)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A number of bytes that is displayed with a binary unit prefix, such as `1.5 KiB`.
+///
+/// Sizes from 1 KiB are shown with one decimal, rounded down.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::str::{ByteSize, CString};
+/// let (a, b, c) = (ByteSize(512), ByteSize(1536), ByteSize(1 << 30));
+/// let s = CString::try_from_fmt(fmt!("{} {} {}", a, b, c)).unwrap();
+/// assert_eq!(s.as_bytes_with_nul(), "512 B 1.5 KiB 1.0 GiB\0".as_bytes());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(pub u64);
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+        if self.0 < 1024 {
+            return write!(f, "{} B", self.0);
+        }
+
+        // The largest unit that is at most the size. `u64::MAX` is less than 1024 EiB.
+        let exp = ((u64::BITS - 1 - self.0.leading_zeros()) / 10) as usize;
+        let unit = 1u64 << (exp * 10);
+        let whole = self.0 / unit;
+        // Computed in 128 bits, since the remainder times ten may not fit in 64.
+        let tenths = (u128::from(self.0 % unit) * 10 / u128::from(unit)) as u64;
+        write!(f, "{}.{} {}", whole, tenths, UNITS[exp - 1])
+    }
+}
+
+/// Displays the set bits of `value` with the names in `names`; see [`BitFlags`].
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::str::{bit_flags, CString};
+/// const NAMES: &[(u64, &str)] = &[(0x1, "READ"), (0x2, "WRITE"), (0x4, "EXEC")];
+///
+/// let s = CString::try_from_fmt(fmt!("{}", bit_flags(0x3, NAMES))).unwrap();
+/// assert_eq!(s.as_bytes_with_nul(), "READ|WRITE\0".as_bytes());
+///
+/// let s = CString::try_from_fmt(fmt!("{}", bit_flags(0x14, NAMES))).unwrap();
+/// assert_eq!(s.as_bytes_with_nul(), "EXEC|0x10\0".as_bytes());
+///
+/// let s = CString::try_from_fmt(fmt!("{}", bit_flags(0, NAMES))).unwrap();
+/// assert_eq!(s.as_bytes_with_nul(), "0\0".as_bytes());
+/// ```
+pub fn bit_flags<'a>(value: u64, names: &'a [(u64, &'a str)]) -> BitFlags<'a> {
+    BitFlags { value, names }
+}
+
+/// A set of flags that is displayed by name.
+///
+/// The names of the flags that are set are shown in the order of `names`, separated by `|`. Bits
+/// without a name are shown together in hex at the end, and a value with no bits set is shown as
+/// `0`.
+pub struct BitFlags<'a> {
+    value: u64,
+    names: &'a [(u64, &'a str)],
+}
+
+impl fmt::Display for BitFlags<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.value == 0 {
+            return f.write_str("0");
+        }
+
+        let mut rest = self.value;
+        let mut sep = "";
+        for &(flag, name) in self.names {
+            if flag != 0 && self.value & flag == flag {
+                write!(f, "{}{}", sep, name)?;
+                rest &= !flag;
+                sep = "|";
+            }
+        }
+        if rest != 0 {
+            write!(f, "{}{:#x}", sep, rest)?;
+        }
+        Ok(())
+    }
+}
+
 /// A convenience alias for [`core::format_args`].
 #[macro_export]
 macro_rules! fmt {
@@ -25,28 +25,44 @@ use crate::{bindings, str::CStr};
 use core::{cell::UnsafeCell, mem::MaybeUninit, pin::Pin};
 
 mod arc;
+mod barrier;
+mod c_ref;
+mod completion;
 mod condvar;
 mod guard;
 mod locked_by;
+mod mapped_guard;
 mod mutex;
 mod nowait;
+mod once;
+mod ordered;
 pub mod rcu;
 mod revocable;
 mod rwsem;
 mod seqlock;
 pub mod smutex;
+mod snapshot;
 mod spinlock;
+mod srcu;
 
 pub use arc::{new_refcount, Arc, ArcBorrow, StaticArc, UniqueArc};
+pub use barrier::{barrier, read_once, smp_mb, smp_rmb, smp_wmb, write_once};
+pub use c_ref::CRef;
+pub use completion::Completion;
 pub use condvar::CondVar;
 pub use guard::{Guard, Lock, LockFactory, LockInfo, LockIniter, ReadLock, WriteLock};
 pub use locked_by::LockedBy;
+pub use mapped_guard::MappedGuard;
 pub use mutex::{Mutex, RevocableMutex, RevocableMutexGuard};
 pub use nowait::{NoWaitLock, NoWaitLockGuard};
+pub use once::{Lazy, OnceCell};
+pub use ordered::lock_both;
 pub use revocable::{Revocable, RevocableGuard};
 pub use rwsem::{RevocableRwSemaphore, RevocableRwSemaphoreGuard, RwSemaphore};
 pub use seqlock::{SeqLock, SeqLockReadGuard};
-pub use spinlock::{RawSpinLock, SpinLock};
+pub use snapshot::Snapshot;
+pub use spinlock::{DisabledInterrupts, RawSpinLock, SleepSafe, SpinLock};
+pub use srcu::{Srcu, SrcuReadGuard};
 
 /// Represents a lockdep class. It's a wrapper around C's `lock_class_key`.
 #[repr(transparent)]
@@ -0,0 +1,121 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Memory barriers and single-copy accesses.
+//!
+//! These are the Rust counterparts of `READ_ONCE`, `WRITE_ONCE` and the `smp_*` barriers. Most code
+//! should use locks or atomics instead. These are meant for data that is shared with C code that
+//! follows the kernel memory model, or with hardware.
+//!
+//! C header: [`include/asm-generic/barrier.h`](../../../../include/asm-generic/barrier.h)
+//!
+//! Reference: <https://www.kernel.org/doc/Documentation/memory-barriers.txt>
+
+use crate::{bindings, build_assert};
+use core::sync::atomic::{compiler_fence, Ordering};
+
+/// Prevents the compiler from reordering memory accesses across this point.
+///
+/// This is the equivalent of C's `barrier()`. It has no effect on the order in which other CPUs
+/// observe the accesses.
+#[inline(always)]
+pub fn barrier() {
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Orders all memory accesses before the barrier against all memory accesses after it, as
+/// observed by other CPUs.
+///
+/// This is the equivalent of C's `smp_mb()`.
+#[inline(always)]
+pub fn smp_mb() {
+    // SAFETY: A memory barrier has no safety requirements. On uniprocessor kernels, it is only a
+    // compiler barrier.
+    unsafe { bindings::smp_mb() };
+}
+
+/// Orders all loads before the barrier against all loads after it, as observed by other CPUs.
+///
+/// This is the equivalent of C's `smp_rmb()`.
+#[inline(always)]
+pub fn smp_rmb() {
+    // SAFETY: A memory barrier has no safety requirements. On uniprocessor kernels, it is only a
+    // compiler barrier.
+    unsafe { bindings::smp_rmb() };
+}
+
+/// Orders all stores before the barrier against all stores after it, as observed by other CPUs.
+///
+/// This is the equivalent of C's `smp_wmb()`.
+#[inline(always)]
+pub fn smp_wmb() {
+    // SAFETY: A memory barrier has no safety requirements. On uniprocessor kernels, it is only a
+    // compiler barrier.
+    unsafe { bindings::smp_wmb() };
+}
+
+/// Reads the value at `ptr` with a single access that the compiler cannot tear, merge, repeat or
+/// omit.
+///
+/// This is the equivalent of C's `READ_ONCE()`. It does not order the access against other
+/// memory accesses; use the barriers in this module for that.
+///
+/// `T` must have a size of 1, 2, 4 or 8 bytes, otherwise the build fails.
+///
+/// # Safety
+///
+/// `ptr` must be valid for reads and properly aligned. Concurrent writes to the same location must
+/// also be single-copy accesses, for example from [`write_once`] or C's `WRITE_ONCE()`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::sync::{read_once, smp_rmb};
+///
+/// struct Shared {
+///     ready: bool,
+///     value: u32,
+/// }
+///
+/// /// # Safety
+/// ///
+/// /// `shared` must be valid and only written to with `write_once`.
+/// unsafe fn consume(shared: *const Shared) -> Option<u32> {
+///     // SAFETY: The caller guarantees that `shared` is valid.
+///     if !unsafe { read_once(core::ptr::addr_of!((*shared).ready)) } {
+///         return None;
+///     }
+///
+///     // Pairs with the `smp_wmb` of the producer, between writing `value` and `ready`.
+///     smp_rmb();
+///
+///     // SAFETY: The caller guarantees that `shared` is valid.
+///     Some(unsafe { read_once(core::ptr::addr_of!((*shared).value)) })
+/// }
+/// ```
+#[inline(always)]
+pub unsafe fn read_once<T: Copy>(ptr: *const T) -> T {
+    build_assert!(matches!(core::mem::size_of::<T>(), 1 | 2 | 4 | 8));
+    // SAFETY: The caller guarantees that `ptr` is valid for reads and aligned, and values of the
+    // supported sizes are read with a single instruction.
+    unsafe { ptr.read_volatile() }
+}
+
+/// Writes `value` to `ptr` with a single access that the compiler cannot tear, merge, repeat or
+/// omit.
+///
+/// This is the equivalent of C's `WRITE_ONCE()`. It does not order the access against other
+/// memory accesses; use the barriers in this module for that.
+///
+/// `T` must have a size of 1, 2, 4 or 8 bytes, otherwise the build fails.
+///
+/// # Safety
+///
+/// `ptr` must be valid for writes and properly aligned. Concurrent accesses to the same location
+/// must also be single-copy accesses, for example from [`read_once`] or C's `READ_ONCE()`.
+#[inline(always)]
+pub unsafe fn write_once<T: Copy>(ptr: *mut T, value: T) {
+    build_assert!(matches!(core::mem::size_of::<T>(), 1 | 2 | 4 | 8));
+    // SAFETY: The caller guarantees that `ptr` is valid for writes and aligned, and values of the
+    // supported sizes are written with a single instruction.
+    unsafe { ptr.write_volatile(value) }
+}
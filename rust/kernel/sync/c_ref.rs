@@ -0,0 +1,224 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! References to Rust objects held by C code.
+//!
+//! A [`CRef`] is an [`Arc`] whose type has been erased, so that it can be handed to C code, which
+//! keeps it in a `struct rust_ref` and manages its reference count with `rust_ref_get()` and
+//! `rust_ref_put()`. The object stays alive for as long as C or Rust code holds a reference to it,
+//! and it is freed by the Rust side when the last one goes away.
+//!
+//! C header: [`include/linux/rust_ref.h`](../../../../include/linux/rust_ref.h)
+
+use super::Arc;
+use core::{ffi::c_void, mem::ManuallyDrop};
+
+/// The functions that manage the reference count of the object of a [`CRef`].
+///
+/// They are specific to the type of the object, which C code does not know about.
+struct CRefOps {
+    get: unsafe fn(*const c_void),
+    put: unsafe fn(*const c_void),
+}
+
+/// Provides the [`CRefOps`] of `Arc<T>`.
+struct Ops<T>(core::marker::PhantomData<T>);
+
+impl<T: Send + Sync + 'static> Ops<T> {
+    /// Increments the reference count of `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `Arc::<T>::into_raw`, and the reference it owns must not
+    /// have been released yet.
+    unsafe fn get(ptr: *const c_void) {
+        // SAFETY: The caller guarantees that `ptr` came from `Arc::<T>::into_raw` and that the
+        // reference it owns is still held. `ManuallyDrop` keeps that reference.
+        let arc = ManuallyDrop::new(unsafe { Arc::from_raw(ptr as *const T) });
+        // The new reference is owned by the caller from now on.
+        let _ = ManuallyDrop::new(Arc::clone(&arc));
+    }
+
+    /// Releases the reference owned by `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `Arc::<T>::into_raw`, and the reference it owns is released
+    /// by this call, so it must not be used anymore.
+    unsafe fn put(ptr: *const c_void) {
+        // SAFETY: The caller guarantees that `ptr` came from `Arc::<T>::into_raw` and gives up the
+        // reference it owns.
+        drop(unsafe { Arc::from_raw(ptr as *const T) });
+    }
+
+    const OPS: CRefOps = CRefOps {
+        get: Self::get,
+        put: Self::put,
+    };
+}
+
+/// A counted reference to a Rust object, with the layout of C's `struct rust_ref`.
+///
+/// It owns a reference to an `Arc<T>` whose type is only known by the code that created it. C
+/// code receives it by value, for example as the argument of a function or as a field of a struct
+/// passed to a C subsystem, and passes it back the same way. It may copy it with `rust_ref_get()`,
+/// which takes a new reference, and must release each copy with `rust_ref_put()`.
+///
+/// The layout is guaranteed to be that of the C struct: a pointer to the object, which is the
+/// pointer returned by [`Arc::into_raw`], followed by a pointer that is private to Rust code.
+///
+/// # Examples
+///
+/// ```ignore
+/// use kernel::sync::{Arc, CRef};
+///
+/// struct Device {
+///     id: u32,
+/// }
+///
+/// extern "C" {
+///     // Stores `dev` until `c_subsys_unregister` is called, and releases it then.
+///     fn c_subsys_register(dev: CRef);
+///
+///     // Returns a new reference to the device with the given id, or a null one.
+///     fn c_subsys_find(id: u32) -> CRef;
+/// }
+///
+/// fn register(dev: &Arc<Device>) {
+///     // SAFETY: The subsystem takes over the reference.
+///     unsafe { c_subsys_register(CRef::new(dev.clone())) };
+/// }
+///
+/// fn find(id: u32) -> Option<Arc<Device>> {
+///     // SAFETY: The subsystem only stores the references passed to `c_subsys_register`, which
+///     // are all to a `Device`.
+///     unsafe { c_subsys_find(id).into_arc::<Device>() }
+/// }
+/// ```
+///
+/// # Invariants
+///
+/// If `ptr` is not null, it was returned by `Arc::<T>::into_raw` and owns one of the references
+/// to the object, and `ops` is `Ops::<T>::OPS` for the same `T`. Otherwise, `ops` is null.
+#[repr(C)]
+pub struct CRef {
+    ptr: *const c_void,
+    ops: *const CRefOps,
+}
+
+// SAFETY: The object is `Send` and `Sync`, as required by `CRef::new`, so both the reference
+// and the object may be used and released from any thread.
+unsafe impl Send for CRef {}
+
+// SAFETY: Shared references to a `CRef` only give access to its object, which is `Sync`.
+unsafe impl Sync for CRef {}
+
+impl CRef {
+    /// Creates a reference that owns `arc`, to be handed to C code.
+    pub fn new<T: Send + Sync + 'static>(arc: Arc<T>) -> Self {
+        // INVARIANT: `ptr` comes from `Arc::<T>::into_raw` and `ops` matches `T`.
+        Self {
+            ptr: Arc::into_raw(arc) as _,
+            ops: &Ops::<T>::OPS,
+        }
+    }
+
+    /// Creates a reference to no object, like a null `struct rust_ref` in C.
+    pub const fn null() -> Self {
+        // INVARIANT: `ptr` and `ops` are both null.
+        Self {
+            ptr: core::ptr::null(),
+            ops: core::ptr::null(),
+        }
+    }
+
+    /// Returns `true` if the reference is null.
+    pub fn is_null(&self) -> bool {
+        self.ptr.is_null()
+    }
+
+    /// Returns a pointer to the object, or null if the reference is null.
+    ///
+    /// It is the same pointer that C code sees in the `ptr` field, and is only valid while the
+    /// reference is held.
+    pub fn as_ptr(&self) -> *const c_void {
+        self.ptr
+    }
+
+    /// Borrows the object, or returns [`None`] if the reference is null.
+    ///
+    /// # Safety
+    ///
+    /// The reference must have been created by [`CRef::new`] with an `Arc<T>`.
+    pub unsafe fn as_ref<T>(&self) -> Option<&T> {
+        // SAFETY: By the type invariants, a non-null `ptr` points to an object kept alive by the
+        // reference, which is a `T` by the safety requirements.
+        unsafe { (self.ptr as *const T).as_ref() }
+    }
+
+    /// Converts the reference back to an [`Arc`], or returns [`None`] if the reference is null.
+    ///
+    /// # Safety
+    ///
+    /// The reference must have been created by [`CRef::new`] with an `Arc<T>`.
+    pub unsafe fn into_arc<T>(self) -> Option<Arc<T>> {
+        let this = ManuallyDrop::new(self);
+        if this.ptr.is_null() {
+            return None;
+        }
+        // SAFETY: By the type invariants, `ptr` came from `Arc::<T>::into_raw`, for the `T`
+        // guaranteed by the safety requirements, and owns a reference, which is transferred to
+        // the returned `Arc` since `self` is not dropped.
+        Some(unsafe { Arc::from_raw(this.ptr as *const T) })
+    }
+}
+
+impl Clone for CRef {
+    fn clone(&self) -> Self {
+        if !self.ptr.is_null() {
+            // SAFETY: By the type invariants, `ops` matches the type of the object that `ptr`
+            // owns a reference to.
+            unsafe { ((*self.ops).get)(self.ptr) };
+        }
+        // INVARIANT: A new reference was taken above, and is owned by the copy.
+        Self {
+            ptr: self.ptr,
+            ops: self.ops,
+        }
+    }
+}
+
+impl Drop for CRef {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            // SAFETY: By the type invariants, `ops` matches the type of the object that `ptr`
+            // owns a reference to, and the reference is not used after this.
+            unsafe { ((*self.ops).put)(self.ptr) };
+        }
+    }
+}
+
+/// Takes a new reference to the object of `r`, and returns it.
+///
+/// Returns a null reference if `r` is null.
+///
+/// # Safety
+///
+/// `r` must point to a valid `struct rust_ref`, which holds the reference it owns.
+#[no_mangle]
+pub unsafe extern "C" fn rust_ref_get(r: *const CRef) -> CRef {
+    // SAFETY: The caller guarantees that `r` is valid.
+    unsafe { (*r).clone() }
+}
+
+/// Releases the reference `r`, freeing its object if it was the last one.
+///
+/// Does nothing if `r` is null.
+///
+/// # Safety
+///
+/// `r` must be a reference created by Rust code or by [`rust_ref_get`], and it must not be used
+/// after this call.
+#[no_mangle]
+pub unsafe extern "C" fn rust_ref_put(r: CRef) {
+    drop(r);
+}
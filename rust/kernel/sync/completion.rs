@@ -0,0 +1,157 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Completions.
+//!
+//! C header: [`include/linux/completion.h`](../../../../include/linux/completion.h)
+
+use super::LockClassKey;
+use crate::{bindings, init::PinInit, signal::SignalPending, str::CStr, types::Opaque};
+use core::marker::PhantomPinned;
+
+/// An event that tasks wait for, which is signalled once.
+///
+/// Waiters block until [`Completion::complete`] is called, which lets one of them through, or
+/// [`Completion::complete_all`], which lets all the current and future waiters through.
+///
+/// Waits on behalf of user space should be interruptible or at least killable, so that the task can
+/// handle signals while the event is late: [`Completion::wait_interruptible`] and
+/// [`Completion::wait_killable`] return [`SignalPending`] when they are interrupted.
+///
+/// Instances must be initialised in place, for example with the [`new_completion`] macro.
+///
+/// # Examples
+///
+/// The following example waits for a kernel thread to be done with its setup.
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::{init::InPlaceInit, new_completion, task::Task};
+/// use kernel::sync::{Arc, Completion, UniqueArc};
+///
+/// fn start() -> Result {
+///     let ready = UniqueArc::try_pin_init(new_completion!("start::ready"))?;
+///     let ready: Arc<Completion> = ready.into();
+///     let signal = ready.clone();
+///     Task::spawn(fmt!("worker"), move || {
+///         // Set up, then let the caller go on.
+///         signal.complete_all();
+///     })?;
+///     ready.wait_killable()?;
+///     Ok(())
+/// }
+/// ```
+///
+/// # Invariants
+///
+/// `completion` has been initialised with `init_completion`.
+///
+/// [`new_completion`]: crate::new_completion
+pub struct Completion {
+    completion: Opaque<bindings::completion>,
+    _pin: PhantomPinned,
+}
+
+// SAFETY: A completion can be signalled, waited for and dropped from any thread.
+unsafe impl Send for Completion {}
+
+// SAFETY: All the C functions that `Completion` calls on a shared reference are safe to call
+// concurrently.
+unsafe impl Sync for Completion {}
+
+impl Completion {
+    /// Creates an initialiser of a new completion, which is not completed.
+    ///
+    /// Users are encouraged to use the [`new_completion`] macro instead, which creates a lock
+    /// class for each call site.
+    ///
+    /// [`new_completion`]: crate::new_completion
+    pub fn new(name: &'static CStr, key: &'static LockClassKey) -> impl PinInit<Self> {
+        let init = move |slot: *mut Self| {
+            // SAFETY: `slot` is valid for writes by the safety requirements of `PinInit`. `Opaque`
+            // is transparent, so the field can be cast to its contents.
+            let completion = unsafe { core::ptr::addr_of_mut!((*slot).completion) }
+                .cast::<bindings::completion>();
+
+            // SAFETY: `completion` points to memory that is not going to move anymore. The name
+            // and the key are static, so they outlive the completion. This is what
+            // `init_completion` does, with a lock class of the caller.
+            unsafe {
+                core::ptr::addr_of_mut!((*completion).done).write(0);
+                bindings::__init_swait_queue_head(
+                    core::ptr::addr_of_mut!((*completion).wait),
+                    name.as_char_ptr(),
+                    key.get(),
+                );
+            }
+
+            // INVARIANT: The completion was just initialised.
+            Ok(())
+        };
+        // SAFETY: The closure always initialises the completion.
+        unsafe { crate::init::pin_init_from_closure(init) }
+    }
+
+    /// Signals the event to one waiter, or to the next one if there are none.
+    ///
+    /// It may be called from any context.
+    pub fn complete(&self) {
+        // SAFETY: By the type invariants, `completion` is initialised.
+        unsafe { bindings::complete(self.completion.get()) };
+    }
+
+    /// Signals the event to all the waiters, current and future.
+    ///
+    /// It may be called from any context.
+    pub fn complete_all(&self) {
+        // SAFETY: By the type invariants, `completion` is initialised.
+        unsafe { bindings::complete_all(self.completion.get()) };
+    }
+
+    /// Returns `true` if the event was signalled and a wait would not block.
+    pub fn is_done(&self) -> bool {
+        // SAFETY: By the type invariants, `completion` is initialised.
+        unsafe { bindings::completion_done(self.completion.get()) }
+    }
+
+    /// Consumes the event if it was signalled, without blocking.
+    ///
+    /// Returns `false` if a wait would have blocked.
+    pub fn try_wait(&self) -> bool {
+        // SAFETY: By the type invariants, `completion` is initialised.
+        unsafe { bindings::try_wait_for_completion(self.completion.get()) }
+    }
+
+    /// Waits for the event, ignoring signals.
+    ///
+    /// This must only be used when the event is known to come soon, since the task cannot even
+    /// be killed in the meantime.
+    pub fn wait(&self) {
+        crate::debug_assert_sleepable!();
+        // SAFETY: By the type invariants, `completion` is initialised.
+        unsafe { bindings::wait_for_completion(self.completion.get()) };
+    }
+
+    /// Waits for the event, or until a signal is sent to the task.
+    pub fn wait_interruptible(&self) -> Result<(), SignalPending> {
+        crate::debug_assert_sleepable!();
+        // SAFETY: By the type invariants, `completion` is initialised.
+        let ret = unsafe { bindings::wait_for_completion_interruptible(self.completion.get()) };
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(SignalPending::current())
+        }
+    }
+
+    /// Waits for the event, or until the task is killed.
+    pub fn wait_killable(&self) -> Result<(), SignalPending> {
+        crate::debug_assert_sleepable!();
+        // SAFETY: By the type invariants, `completion` is initialised.
+        let ret = unsafe { bindings::wait_for_completion_killable(self.completion.get()) };
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(SignalPending::current())
+        }
+    }
+}
@@ -0,0 +1,96 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Lock guards projected to a part of the protected data.
+
+use super::{Guard, Lock, LockInfo, WriteLock};
+use crate::types::True;
+use core::{
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+};
+
+/// A guard that keeps a lock held and gives access to a part of the data it protects.
+///
+/// It is created by [`Guard::map`], so that a function can hand out access to a single field of
+/// the protected data without exposing the rest of it. The lock is released when the guard is
+/// dropped.
+///
+/// # Invariants
+///
+/// `value` points into the data protected by the lock that `guard` holds, and is valid for writes
+/// while the lock is held.
+pub struct MappedGuard<'a, L: Lock<I> + ?Sized, U: ?Sized, I: LockInfo = WriteLock> {
+    guard: Guard<'a, L, I>,
+    value: NonNull<U>,
+}
+
+impl<'a, L: Lock<I> + ?Sized, I: LockInfo<Writable = True>> Guard<'a, L, I> {
+    /// Consumes the guard and returns one that only gives access to the part of the protected data
+    /// selected by `f`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kernel::prelude::*;
+    /// use kernel::sync::{MappedGuard, Mutex};
+    ///
+    /// struct SharedStateInner {
+    ///     token_count: usize,
+    ///     read_count: usize,
+    /// }
+    ///
+    /// struct SharedState {
+    ///     inner: Mutex<SharedStateInner>,
+    /// }
+    ///
+    /// impl SharedState {
+    ///     /// Gives access to the number of reads, but not to the tokens.
+    ///     fn read_count(&self) -> MappedGuard<'_, Mutex<SharedStateInner>, usize> {
+    ///         self.inner.lock().map(|inner| &mut inner.read_count)
+    ///     }
+    /// }
+    ///
+    /// fn record_read(state: &SharedState) {
+    ///     *state.read_count() += 1;
+    /// }
+    /// ```
+    pub fn map<U: ?Sized>(
+        mut self,
+        f: impl FnOnce(&mut L::Inner) -> &mut U,
+    ) -> MappedGuard<'a, L, U, I> {
+        let value = NonNull::from(f(&mut *self));
+        // INVARIANT: `f` returned a part of the protected data, which lives in the lock rather
+        // than in the guard, so it remains valid when the guard is moved.
+        MappedGuard { guard: self, value }
+    }
+}
+
+impl<'a, L: Lock<I> + ?Sized, U: ?Sized, I: LockInfo> MappedGuard<'a, L, U, I> {
+    /// Consumes the guard and returns one that only gives access to the part of the data
+    /// selected by `f`, within the part that this guard gives access to.
+    pub fn map<V: ?Sized>(mut self, f: impl FnOnce(&mut U) -> &mut V) -> MappedGuard<'a, L, V, I> {
+        let value = NonNull::from(f(&mut *self));
+        // INVARIANT: `value` points into the data that `self.value` points into.
+        MappedGuard {
+            guard: self.guard,
+            value,
+        }
+    }
+}
+
+impl<L: Lock<I> + ?Sized, U: ?Sized, I: LockInfo> Deref for MappedGuard<'_, L, U, I> {
+    type Target = U;
+
+    fn deref(&self) -> &U {
+        // SAFETY: By the type invariants, `value` is valid while the lock is held.
+        unsafe { self.value.as_ref() }
+    }
+}
+
+impl<L: Lock<I> + ?Sized, U: ?Sized, I: LockInfo> DerefMut for MappedGuard<'_, L, U, I> {
+    fn deref_mut(&mut self) -> &mut U {
+        // SAFETY: By the type invariants, `value` is valid for writes while the lock is held, and
+        // the guard is borrowed mutably, so there are no other references to it.
+        unsafe { self.value.as_mut() }
+    }
+}
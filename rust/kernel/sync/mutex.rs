@@ -0,0 +1,228 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! A kernel mutex.
+//!
+//! This module allows Rust code to use the kernel's [`struct mutex`].
+//!
+//! [`struct mutex`]: ../../../../include/linux/mutex.h
+
+use super::{Guard, Lock, LockClassKey, LockFactory, LockIniter, WriteLock};
+use crate::{bindings, error::code::*, signal::SignalPending, str::CStr, types::Opaque, Result};
+use core::{cell::UnsafeCell, marker::PhantomPinned, pin::Pin, time::Duration};
+
+/// Safely initialises a [`Mutex`] with the given name, generating a new lock class.
+#[macro_export]
+macro_rules! mutex_init {
+    ($mutex:expr, $name:literal) => {
+        $crate::init_with_lockdep!($mutex, $name)
+    };
+}
+
+/// Exposes the kernel's [`struct mutex`]. When multiple threads attempt to lock the same mutex,
+/// only one at a time is allowed to progress, the others will block (sleep) until the mutex is
+/// unlocked, at which point another thread will be allowed to wake up and make progress.
+///
+/// A [`Mutex`] must first be initialised with a call to [`Mutex::init_lock`] before it can be
+/// used. The [`mutex_init`] macro is provided to automatically assign a new lock class to a mutex
+/// instance.
+///
+/// Since it may block, [`Mutex`] needs to be used with care in atomic contexts.
+///
+/// [`Mutex::lock`] sleeps uninterruptibly. Code that runs on behalf of user space, such as file
+/// operations, should use [`Mutex::lock_interruptible`] instead, or [`Mutex::lock_timeout`] when
+/// the mutex may be held for a long time, so that the task does not hang on it.
+///
+/// [`struct mutex`]: ../../../include/linux/mutex.h
+pub struct Mutex<T: ?Sized> {
+    /// The kernel `struct mutex` object.
+    mutex: Opaque<bindings::mutex>,
+
+    /// A mutex needs to be pinned because it contains a [`struct list_head`] that is
+    /// self-referential, so it cannot be safely moved once it is initialised.
+    ///
+    /// [`struct list_head`]: ../../../include/linux/types.h
+    _pin: PhantomPinned,
+
+    /// The data protected by the mutex.
+    data: UnsafeCell<T>,
+}
+
+// SAFETY: `Mutex` can be transferred across thread boundaries iff the data it protects can.
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+
+// SAFETY: `Mutex` serialises the interior mutability it provides, so it is `Sync` as long as the
+// data it protects is `Send`.
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    /// Constructs a new mutex.
+    ///
+    /// # Safety
+    ///
+    /// The caller must call [`Mutex::init_lock`] before using the mutex.
+    pub const unsafe fn new(t: T) -> Self {
+        Self {
+            mutex: Opaque::uninit(),
+            data: UnsafeCell::new(t),
+            _pin: PhantomPinned,
+        }
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Locks the mutex and gives the caller access to the data protected by it. Only one thread at
+    /// a time is allowed to access the protected data.
+    pub fn lock(&self) -> Guard<'_, Self> {
+        let ctx = self.lock_noguard();
+        // SAFETY: The mutex was just acquired.
+        unsafe { Guard::new(self, ctx) }
+    }
+
+    /// Locks the mutex like [`Mutex::lock`], as the `subclass` nesting level of its lock class.
+    ///
+    /// This tells lockdep that the caller holds another mutex of the same class, which it
+    /// acquired first, in an order that rules out deadlocks. Without `CONFIG_DEBUG_LOCK_ALLOC`,
+    /// there is no lockdep to tell, and the C function does not exist, so this is [`Mutex::lock`].
+    pub(crate) fn lock_nested(&self, subclass: u32) -> Guard<'_, Self> {
+        #[cfg(CONFIG_DEBUG_LOCK_ALLOC)]
+        let ctx = {
+            // SAFETY: `mutex` points to valid memory.
+            unsafe { bindings::mutex_lock_nested(self.mutex.get(), subclass) };
+            EmptyGuardContext
+        };
+        #[cfg(not(CONFIG_DEBUG_LOCK_ALLOC))]
+        let ctx = {
+            let _ = subclass;
+            self.lock_noguard()
+        };
+        // SAFETY: The mutex was just acquired.
+        unsafe { Guard::new(self, ctx) }
+    }
+
+    /// Locks the mutex if it is not held, without sleeping.
+    ///
+    /// Returns [`None`] if the mutex is held by another thread, or by the caller.
+    pub fn try_lock(&self) -> Option<Guard<'_, Self>> {
+        // SAFETY: `mutex` points to valid memory.
+        if unsafe { bindings::mutex_trylock(self.mutex.get()) } == 0 {
+            return None;
+        }
+        // SAFETY: The mutex was just acquired.
+        Some(unsafe { Guard::new(self, EmptyGuardContext) })
+    }
+
+    /// Locks the mutex like [`Mutex::lock`], but stops waiting if a signal is sent to the task.
+    ///
+    /// Returns [`SignalPending`] when interrupted, which converts to `ERESTARTSYS`. Callers that
+    /// must not restart the system call report `EINTR` with [`SignalPending::no_restart`].
+    pub fn lock_interruptible(&self) -> core::result::Result<Guard<'_, Self>, SignalPending> {
+        // SAFETY: `mutex` points to valid memory.
+        if unsafe { bindings::mutex_lock_interruptible(self.mutex.get()) } != 0 {
+            return Err(SignalPending::current());
+        }
+        // SAFETY: The mutex was just acquired.
+        Ok(unsafe { Guard::new(self, EmptyGuardContext) })
+    }
+
+    /// Locks the mutex like [`Mutex::lock`], but only waits while a fatal signal is not pending.
+    pub fn lock_killable(&self) -> core::result::Result<Guard<'_, Self>, SignalPending> {
+        // SAFETY: `mutex` points to valid memory.
+        if unsafe { bindings::mutex_lock_killable(self.mutex.get()) } != 0 {
+            return Err(SignalPending::current());
+        }
+        // SAFETY: The mutex was just acquired.
+        Ok(unsafe { Guard::new(self, EmptyGuardContext) })
+    }
+
+    /// Locks the mutex, waiting at most `timeout` for it to be released.
+    ///
+    /// The C mutex has no timed variant, so this polls [`Mutex::try_lock`] once per jiffy, which
+    /// makes it unfair to waiters. It is meant for paths that must give up on a stuck mutex, not
+    /// for contended ones. The wait is interruptible.
+    ///
+    /// Returns `ETIMEDOUT` if the mutex is still held after `timeout`, and `ERESTARTSYS` if a
+    /// signal is sent to the task.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kernel::prelude::*;
+    /// use core::time::Duration;
+    /// use kernel::sync::Mutex;
+    ///
+    /// fn read_status(regs: &Mutex<u32>) -> Result<u32> {
+    ///     let regs = regs.lock_timeout(Duration::from_millis(100))?;
+    ///     Ok(*regs)
+    /// }
+    /// ```
+    pub fn lock_timeout(&self, timeout: Duration) -> Result<Guard<'_, Self>> {
+        let ms = timeout.as_millis().try_into().unwrap_or(u32::MAX);
+        // `msecs_to_jiffies` is inline, so this calls the out-of-line conversion it falls back to
+        // for values that are not constant.
+        // SAFETY: This function has no safety requirements.
+        let mut remaining = unsafe { bindings::__msecs_to_jiffies(ms) };
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return Ok(guard);
+            }
+            if remaining == 0 {
+                return Err(ETIMEDOUT);
+            }
+            SignalPending::check()?;
+            // SAFETY: This function has no safety requirements.
+            unsafe { bindings::schedule_timeout_interruptible(1) };
+            remaining -= 1;
+        }
+    }
+}
+
+impl<T> LockFactory for Mutex<T> {
+    type LockedType<U> = Mutex<U>;
+
+    unsafe fn new_lock<U>(data: U) -> Mutex<U> {
+        // SAFETY: The safety requirements of `new_lock` also require that `init_lock` be called.
+        unsafe { Mutex::new(data) }
+    }
+}
+
+impl<T> LockIniter for Mutex<T> {
+    fn init_lock(self: Pin<&mut Self>, name: &'static CStr, key: &'static LockClassKey) {
+        // SAFETY: `mutex` is pinned, and the name and the key are static, so they outlive it.
+        unsafe { bindings::__mutex_init(self.mutex.get(), name.as_char_ptr(), key.get()) };
+    }
+}
+
+/// The context of a guard of a [`Mutex`], which needs no state.
+pub struct EmptyGuardContext;
+
+// SAFETY: The underlying kernel `struct mutex` object ensures mutual exclusion.
+unsafe impl<T: ?Sized> Lock for Mutex<T> {
+    type Inner = T;
+    type GuardContext = EmptyGuardContext;
+
+    fn lock_noguard(&self) -> EmptyGuardContext {
+        // SAFETY: `mutex` points to valid memory.
+        unsafe { bindings::mutex_lock(self.mutex.get()) };
+        EmptyGuardContext
+    }
+
+    unsafe fn unlock(&self, _: &mut EmptyGuardContext) {
+        // SAFETY: The safety requirements of the function ensure that the mutex is owned by the
+        // caller.
+        unsafe { bindings::mutex_unlock(self.mutex.get()) };
+    }
+
+    fn locked_data(&self) -> &UnsafeCell<T> {
+        &self.data
+    }
+}
+
+/// A revocable mutex.
+///
+/// That is, a mutex to which access can be revoked at runtime. It is a specialisation of the more
+/// generic [`super::revocable::Revocable`].
+pub type RevocableMutex<T> = super::revocable::Revocable<Mutex<()>, T>;
+
+/// A guard for a revocable mutex.
+pub type RevocableMutexGuard<'a, T, I = WriteLock> =
+    super::revocable::RevocableGuard<'a, Mutex<()>, T, I>;
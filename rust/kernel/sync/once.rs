@@ -0,0 +1,188 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Cells that are initialised once, on first use.
+//!
+//! These allow module-global state to live in statics, without `static mut`: the state is created
+//! the first time it is needed, for example by a callback from C that does not receive any
+//! context, and is shared by all the users that come afterwards.
+
+use super::smutex::Mutex;
+use crate::{error::code::EINVAL, Result};
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// A cell that is written to at most once.
+///
+/// Initialisation may sleep: concurrent callers of [`OnceCell::get_or_try_init`] wait for the
+/// first one to finish, and only run their own initialiser if it failed. Once the cell is
+/// initialised, [`OnceCell::get`] is lock-free and may be called from any context.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::sync::OnceCell;
+///
+/// struct Tables {
+///     crc: Vec<u32>,
+/// }
+///
+/// static TABLES: OnceCell<Tables> = OnceCell::new();
+///
+/// fn tables() -> Result<&'static Tables> {
+///     TABLES.get_or_try_init(|| {
+///         let mut crc = Vec::try_with_capacity(256)?;
+///         for i in 0..256u32 {
+///             crc.try_push(i.wrapping_mul(0x04c11db7))?;
+///         }
+///         Ok(Tables { crc })
+///     })
+/// }
+///
+/// assert_eq!(tables()?.crc[1], 0x04c11db7);
+/// # Ok::<(), Error>(())
+/// ```
+///
+/// # Invariants
+///
+/// `value` is initialised once `initialised` is `true`, and it is never modified afterwards.
+pub struct OnceCell<T> {
+    initialised: AtomicBool,
+    init_lock: Mutex<()>,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// SAFETY: The value is only shared once initialised, and may be initialised on one thread and
+// used on another.
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> {}
+
+// SAFETY: The cell owns its value, which is `Send`.
+unsafe impl<T: Send> Send for OnceCell<T> {}
+
+impl<T> OnceCell<T> {
+    /// Creates a new empty cell.
+    pub const fn new() -> Self {
+        // INVARIANT: The cell is not initialised.
+        Self {
+            initialised: AtomicBool::new(false),
+            init_lock: Mutex::new(()),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Returns the value of the cell, or `None` if it has not been initialised yet.
+    pub fn get(&self) -> Option<&T> {
+        if self.initialised.load(Ordering::Acquire) {
+            // SAFETY: The value is initialised and never modified again, by the type invariants.
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value of the cell, initialising it with `f` if it is empty.
+    ///
+    /// If `f` fails, the cell is left empty and the error is returned, so a later call may try
+    /// again. This may sleep, so it must be called from process context. `f` must not use the
+    /// cell itself, otherwise it deadlocks.
+    pub fn get_or_try_init(&self, f: impl FnOnce() -> Result<T>) -> Result<&T> {
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+
+        let _guard = self.init_lock.lock();
+        // Another thread may have initialised the cell while we waited for the lock.
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+
+        let value = f()?;
+        // SAFETY: The cell is not initialised, so no references to the value exist, and other
+        // writers are excluded by `init_lock`.
+        unsafe { (*self.value.get()).write(value) };
+        // INVARIANT: The value was just initialised.
+        self.initialised.store(true, Ordering::Release);
+
+        // SAFETY: The value was just initialised.
+        Ok(unsafe { (*self.value.get()).assume_init_ref() })
+    }
+
+    /// Initialises the cell with `value`.
+    ///
+    /// Returns `value` back if the cell was already initialised.
+    pub fn set(&self, value: T) -> core::result::Result<(), T> {
+        let mut value = Some(value);
+        // The initialiser only runs, and takes the value, if the cell is still empty.
+        let _ = self.get_or_try_init(|| value.take().ok_or(EINVAL));
+        match value {
+            None => Ok(()),
+            Some(value) => Err(value),
+        }
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for OnceCell<T> {
+    fn drop(&mut self) {
+        if *self.initialised.get_mut() {
+            // SAFETY: The value is initialised, by the type invariants, and is not used again.
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+/// A value that is created by a function the first time it is accessed.
+///
+/// This is a [`OnceCell`] that knows how to initialise itself. Since creating kernel objects may
+/// fail, the value is only accessible through [`Lazy::get`], which returns the error of the
+/// initialiser if it fails; the next access then tries again.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::sync::Lazy;
+///
+/// static GREETING: Lazy<Vec<u8>> = Lazy::new(|| {
+///     let mut v = Vec::new();
+///     v.try_extend_from_slice(b"hello")?;
+///     Ok(v)
+/// });
+///
+/// assert_eq!(&GREETING.get()?[..], b"hello");
+/// # Ok::<(), Error>(())
+/// ```
+pub struct Lazy<T, F = fn() -> Result<T>> {
+    cell: OnceCell<T>,
+    init: F,
+}
+
+impl<T, F: Fn() -> Result<T>> Lazy<T, F> {
+    /// Creates a new value that is initialised by `init` when it is first accessed.
+    pub const fn new(init: F) -> Self {
+        Self {
+            cell: OnceCell::new(),
+            init,
+        }
+    }
+
+    /// Returns the value, initialising it first if needed.
+    ///
+    /// This may sleep the first time the value is accessed, like [`OnceCell::get_or_try_init`].
+    pub fn get(&self) -> Result<&T> {
+        self.cell.get_or_try_init(&self.init)
+    }
+
+    /// Returns the value if it has already been initialised.
+    pub fn get_if_initialised(&self) -> Option<&T> {
+        self.cell.get()
+    }
+}
@@ -0,0 +1,57 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Acquisition of several locks in a consistent order.
+
+use super::{Guard, Mutex};
+use crate::{bindings, error::code::EDEADLK, Result};
+
+/// Locks two mutexes and returns their guards, in the order of the arguments.
+///
+/// The mutexes are always acquired in increasing order of their addresses, regardless of the
+/// order of the arguments. So two threads that call `lock_both(a, b)` and `lock_both(b, a)`
+/// concurrently cannot deadlock, as they could if each of them locked its first argument first.
+///
+/// The second mutex is locked as a nested lock, so that lockdep accepts the pair even when both
+/// mutexes belong to the same lock class, as fields of two instances of a struct do.
+///
+/// The guards can be dropped independently, in any order.
+///
+/// Returns [`EDEADLK`] if `a` and `b` are the same mutex.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::sync::{lock_both, Mutex};
+///
+/// struct Account {
+///     balance: Mutex<u64>,
+/// }
+///
+/// fn transfer(from: &Account, to: &Account, amount: u64) -> Result {
+///     let (mut from_balance, mut to_balance) = lock_both(&from.balance, &to.balance)?;
+///     *from_balance = from_balance.checked_sub(amount).ok_or(EINVAL)?;
+///     *to_balance += amount;
+///     Ok(())
+/// }
+/// ```
+pub fn lock_both<'a, A: ?Sized, B: ?Sized>(
+    a: &'a Mutex<A>,
+    b: &'a Mutex<B>,
+) -> Result<(Guard<'a, Mutex<A>>, Guard<'a, Mutex<B>>)> {
+    let a_addr = a as *const Mutex<A> as *const u8;
+    let b_addr = b as *const Mutex<B> as *const u8;
+
+    if a_addr == b_addr {
+        return Err(EDEADLK);
+    }
+
+    let nesting = bindings::SINGLE_DEPTH_NESTING;
+    if a_addr < b_addr {
+        let a_guard = a.lock();
+        Ok((a_guard, b.lock_nested(nesting)))
+    } else {
+        let b_guard = b.lock();
+        Ok((a.lock_nested(nesting), b_guard))
+    }
+}
@@ -0,0 +1,107 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Consistent snapshots of small structures.
+
+use super::{LockClassKey, NeedsLockClass, SeqLock, SpinLock};
+use crate::str::CStr;
+use core::pin::Pin;
+
+/// A value that can be copied out consistently without blocking its writers.
+///
+/// This is meant for structures made of several related fields, such as statistics, that are
+/// updated often and read occasionally. Readers get a copy of the whole structure with
+/// [`Snapshot::get`] and can then format it at their leisure, for example from a `seq_file`, while
+/// writers keep updating the shared value.
+///
+/// Writers are serialised by a spinlock and never wait for readers. Readers retry if a writer
+/// modified the value while it was being copied, so `T` should be small and writers should not
+/// hold the value for long.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::{init::InPlaceInit, new_snapshot, seq_file::SeqFile, sync::Snapshot};
+///
+/// #[derive(Clone, Copy, Default)]
+/// struct Stats {
+///     reads: u64,
+///     bytes_read: u64,
+///     errors: u64,
+/// }
+///
+/// fn account_read(stats: &Snapshot<Stats>, result: Result<usize>) {
+///     stats.update(|s| match result {
+///         Ok(len) => {
+///             s.reads += 1;
+///             s.bytes_read += len as u64;
+///         }
+///         Err(_) => s.errors += 1,
+///     });
+/// }
+///
+/// fn show(stats: &Snapshot<Stats>, m: &mut SeqFile) -> Result {
+///     // The fields are consistent with each other, and no lock is held while formatting.
+///     let s = stats.get();
+///     writeln!(m, "reads: {} ({} bytes)", s.reads, s.bytes_read);
+///     writeln!(m, "errors: {}", s.errors);
+///     Ok(())
+/// }
+///
+/// fn create() -> Result<Pin<Box<Snapshot<Stats>>>> {
+///     Box::try_pin_init(new_snapshot!(Stats::default(), "Device::stats"))
+/// }
+/// ```
+pub struct Snapshot<T: Copy> {
+    inner: SeqLock<SpinLock<T>>,
+}
+
+impl<T: Copy> Snapshot<T> {
+    /// Constructs a new snapshot holder.
+    ///
+    /// # Safety
+    ///
+    /// The caller must call [`NeedsLockClass::init`] before using it. Users are encouraged to use
+    /// the [`new_snapshot`] macro instead, which takes care of that.
+    ///
+    /// [`new_snapshot`]: crate::new_snapshot
+    pub unsafe fn new(value: T) -> Self {
+        Self {
+            // SAFETY: The caller guarantees that `init` is called before the seqlock is used.
+            inner: unsafe { SeqLock::new(value) },
+        }
+    }
+
+    /// Returns a copy of the current value.
+    ///
+    /// The copy reflects the value between two updates, never a partially updated one.
+    pub fn get(&self) -> T {
+        self.inner.read(|value| *value)
+    }
+
+    /// Modifies the value in place.
+    ///
+    /// Concurrent calls to [`Snapshot::get`] do not observe the value until `f` returns.
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let mut guard = self.inner.write();
+        f(&mut guard)
+    }
+
+    /// Replaces the value with `value`.
+    pub fn set(&self, value: T) {
+        self.update(|v| *v = value);
+    }
+}
+
+impl<T: Copy> NeedsLockClass for Snapshot<T> {
+    fn init(
+        self: Pin<&mut Self>,
+        name: &'static CStr,
+        key1: &'static LockClassKey,
+        key2: &'static LockClassKey,
+    ) {
+        // SAFETY: `inner` is pinned when `self` is.
+        let inner = unsafe { self.map_unchecked_mut(|s| &mut s.inner) };
+        inner.init(name, key1, key2);
+    }
+}
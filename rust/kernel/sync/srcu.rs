@@ -0,0 +1,160 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Sleepable read-copy update.
+//!
+//! C header: [`include/linux/srcu.h`](../../../../include/linux/srcu.h)
+
+use super::LockClassKey;
+use crate::{bindings, error::to_result, init::PinInit, str::CStr, types::Opaque};
+use core::marker::{PhantomData, PhantomPinned};
+
+/// A sleepable read-copy update (SRCU) domain.
+///
+/// Unlike regular RCU, readers of an SRCU domain are allowed to sleep while in their read-side
+/// critical section. Writers wait for readers with [`Srcu::synchronize`], which only waits for
+/// readers of the same domain.
+///
+/// Instances must be initialised in place, for example with the [`new_srcu`] macro.
+///
+/// # Examples
+///
+/// The following example replaces a value that readers may access while sleeping, and frees the
+/// old one once no reader can be using it anymore.
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use core::sync::atomic::{AtomicPtr, Ordering};
+/// use kernel::{init::InPlaceInit, new_srcu, sync::Srcu};
+///
+/// struct Config {
+///     srcu: Pin<Box<Srcu>>,
+///     current: AtomicPtr<u32>,
+/// }
+///
+/// impl Config {
+///     fn value(&self) -> u32 {
+///         let _guard = self.srcu.read_lock();
+///         // SAFETY: `current` is always valid, and it is only freed once `synchronize` returns,
+///         // which cannot happen while the guard is held.
+///         unsafe { *self.current.load(Ordering::Acquire) }
+///     }
+///
+///     fn update(&self, value: u32) -> Result {
+///         let new = Box::into_raw(Box::try_new(value)?);
+///         let old = self.current.swap(new, Ordering::AcqRel);
+///         self.srcu.synchronize();
+///         // SAFETY: `old` came from `Box::into_raw`, and no reader can be using it anymore.
+///         drop(unsafe { Box::from_raw(old) });
+///         Ok(())
+///     }
+/// }
+///
+/// fn create() -> Result<Config> {
+///     Ok(Config {
+///         srcu: Box::try_pin_init(new_srcu!("Config::srcu"))?,
+///         current: AtomicPtr::new(Box::into_raw(Box::try_new(0)?)),
+///     })
+/// }
+/// ```
+///
+/// # Invariants
+///
+/// `srcu` has been initialised with `init_srcu_struct`.
+///
+/// [`new_srcu`]: crate::new_srcu
+pub struct Srcu {
+    srcu: Opaque<bindings::srcu_struct>,
+    _pin: PhantomPinned,
+}
+
+// SAFETY: An SRCU domain can be used and cleaned up from any thread.
+unsafe impl Send for Srcu {}
+
+// SAFETY: All the C functions that `Srcu` calls on a shared reference are safe to call
+// concurrently.
+unsafe impl Sync for Srcu {}
+
+impl Srcu {
+    /// Creates an initialiser of a new SRCU domain.
+    ///
+    /// Users are encouraged to use the [`new_srcu`] macro instead, which creates a lock class for
+    /// each call site.
+    ///
+    /// [`new_srcu`]: crate::new_srcu
+    pub fn new(name: &'static CStr, key: &'static LockClassKey) -> impl PinInit<Self> {
+        let init = move |slot: *mut Self| {
+            // SAFETY: `slot` is valid for writes by the safety requirements of `PinInit`. `Opaque`
+            // is transparent, so the field can be cast to its contents.
+            let srcu =
+                unsafe { core::ptr::addr_of_mut!((*slot).srcu) }.cast::<bindings::srcu_struct>();
+
+            // SAFETY: `srcu` points to memory that is not going to move anymore. The name and the
+            // key are static, so they outlive the domain.
+            #[cfg(CONFIG_DEBUG_LOCK_ALLOC)]
+            let ret = unsafe { bindings::__init_srcu_struct(srcu, name.as_char_ptr(), key.get()) };
+
+            #[cfg(not(CONFIG_DEBUG_LOCK_ALLOC))]
+            let ret = {
+                let _ = (name, key);
+                // SAFETY: `srcu` points to memory that is not going to move anymore.
+                unsafe { bindings::init_srcu_struct(srcu) }
+            };
+
+            // INVARIANT: The domain is initialised when this succeeds.
+            to_result(ret)
+        };
+        // SAFETY: `init_srcu_struct` either initialises the domain or fails without allocating
+        // anything, in which case `slot` is left uninitialised.
+        unsafe { crate::init::pin_init_from_closure(init) }
+    }
+
+    /// Enters a read-side critical section.
+    ///
+    /// The section ends when the returned guard is dropped. The caller may sleep while holding the
+    /// guard, but it delays writers calling [`Srcu::synchronize`] until then.
+    pub fn read_lock(&self) -> SrcuReadGuard<'_> {
+        // SAFETY: By the type invariants, `srcu` is initialised.
+        let idx = unsafe { bindings::srcu_read_lock(self.srcu.get()) };
+        SrcuReadGuard {
+            srcu: self,
+            idx,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Waits until all the read-side critical sections that started before the call have ended.
+    ///
+    /// This function may sleep, so it must not be called from atomic context or from within a
+    /// read-side critical section of the same domain.
+    pub fn synchronize(&self) {
+        // SAFETY: By the type invariants, `srcu` is initialised.
+        unsafe { bindings::synchronize_srcu(self.srcu.get()) };
+    }
+}
+
+impl Drop for Srcu {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, `srcu` is initialised. No read-side critical section can
+        // be active because guards borrow the domain.
+        unsafe { bindings::cleanup_srcu_struct(self.srcu.get()) };
+    }
+}
+
+/// A read-side critical section of an [`Srcu`] domain.
+///
+/// The section ends when the guard is dropped.
+#[must_use = "the read-side critical section ends when the guard is dropped"]
+pub struct SrcuReadGuard<'a> {
+    srcu: &'a Srcu,
+    idx: core::ffi::c_int,
+    // The section must end in the task that started it.
+    _not_send: PhantomData<*mut ()>,
+}
+
+impl Drop for SrcuReadGuard<'_> {
+    fn drop(&mut self) {
+        // SAFETY: `idx` was returned by `srcu_read_lock` on the same domain, and the section has
+        // not been ended yet.
+        unsafe { bindings::srcu_read_unlock(self.srcu.srcu.get(), self.idx) };
+    }
+}
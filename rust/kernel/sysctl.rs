@@ -16,7 +16,7 @@ use crate::{
     bindings,
     error::code::*,
     io_buffer::IoBufferWriter,
-    str::CStr,
+    str::{parse_bool, CStr},
     types,
     user_ptr::{UserSlicePtr, UserSlicePtrWriter},
     Result,
@@ -31,20 +31,6 @@ pub trait SysctlStorage: Sync {
     fn read_value(&self, data: &mut UserSlicePtrWriter) -> (usize, Result);
 }
 
-fn trim_whitespace(mut data: &[u8]) -> &[u8] {
-    while !data.is_empty() && (data[0] == b' ' || data[0] == b'\t' || data[0] == b'\n') {
-        data = &data[1..];
-    }
-    while !data.is_empty()
-        && (data[data.len() - 1] == b' '
-            || data[data.len() - 1] == b'\t'
-            || data[data.len() - 1] == b'\n')
-    {
-        data = &data[..data.len() - 1];
-    }
-    data
-}
-
 impl<T> SysctlStorage for &T
 where
     T: SysctlStorage,
@@ -60,17 +46,7 @@ where
 
 impl SysctlStorage for atomic::AtomicBool {
     fn store_value(&self, data: &[u8]) -> (usize, Result) {
-        let result = match trim_whitespace(data) {
-            b"0" => {
-                self.store(false, atomic::Ordering::Relaxed);
-                Ok(())
-            }
-            b"1" => {
-                self.store(true, atomic::Ordering::Relaxed);
-                Ok(())
-            }
-            _ => Err(EINVAL),
-        };
+        let result = parse_bool(data).map(|value| self.store(value, atomic::Ordering::Relaxed));
         (data.len(), result)
     }
 
@@ -188,7 +164,7 @@ impl<T: SysctlStorage> Drop for Sysctl<T> {
 
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use crate::str::trim_whitespace;
 
     #[test]
     fn test_trim_whitespace() {
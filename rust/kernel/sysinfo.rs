@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! System information.
+//!
+//! The figures that `/proc/meminfo`, `/proc/uptime` and `/proc/loadavg` show, for modules that
+//! report them along with their own state, for example in debugfs.
+//!
+//! C headers: [`include/linux/mm.h`](../../../../include/linux/mm.h),
+//! [`include/linux/timekeeping.h`](../../../../include/linux/timekeeping.h) and
+//! [`include/linux/sched/loadavg.h`](../../../../include/linux/sched/loadavg.h)
+
+use crate::bindings;
+use core::{fmt, time::Duration};
+
+/// A snapshot of the usage of memory, as returned by [`meminfo`].
+///
+/// All sizes are in bytes.
+#[derive(Clone, Copy, Debug)]
+pub struct MemInfo {
+    /// The usable memory, that is, the physical memory minus the memory reserved by the kernel.
+    pub total: u64,
+
+    /// The memory that is not used at all.
+    pub free: u64,
+
+    /// The memory used by `tmpfs` and shared memory.
+    pub shared: u64,
+
+    /// The memory used by block device buffers.
+    pub buffers: u64,
+
+    /// The high memory, which is not permanently mapped by the kernel.
+    pub total_high: u64,
+
+    /// The high memory that is not used at all.
+    pub free_high: u64,
+}
+
+/// Returns a snapshot of the usage of memory.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::sysinfo;
+///
+/// let mem = sysinfo::meminfo();
+/// pr_info!("{} of {} KiB free\n", mem.free >> 10, mem.total >> 10);
+/// assert!(mem.free <= mem.total);
+/// ```
+pub fn meminfo() -> MemInfo {
+    let mut info = bindings::sysinfo::default();
+    // SAFETY: `info` is valid for writes for the duration of the call.
+    unsafe { bindings::si_meminfo(&mut info) };
+
+    let unit = u64::from(info.mem_unit);
+    let bytes = |pages: core::ffi::c_ulong| (pages as u64).saturating_mul(unit);
+    MemInfo {
+        total: bytes(info.totalram),
+        free: bytes(info.freeram),
+        shared: bytes(info.sharedram),
+        buffers: bytes(info.bufferram),
+        total_high: bytes(info.totalhigh),
+        free_high: bytes(info.freehigh),
+    }
+}
+
+/// Returns the time elapsed since boot, including the time spent in suspend.
+///
+/// This is the first figure of `/proc/uptime`.
+pub fn uptime() -> Duration {
+    // SAFETY: FFI call with no safety requirements.
+    let ns = unsafe { bindings::ktime_get_with_offset(bindings::tk_offsets_TK_OFFS_BOOT) };
+    Duration::from_nanos(ns as u64)
+}
+
+/// The number of bits of the fractional part of a [`Load`].
+const FSHIFT: u32 = 11;
+
+/// A load average, as a fixed-point number with [`FSHIFT`] bits of fractional part.
+///
+/// It is formatted with two decimals, like in `/proc/loadavg`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Load(core::ffi::c_ulong);
+
+impl Load {
+    /// Returns the integer part of the load.
+    pub fn integer(self) -> u64 {
+        (self.0 >> FSHIFT) as u64
+    }
+
+    /// Returns the first two decimals of the load, between 0 and 99.
+    pub fn hundredths(self) -> u64 {
+        (((self.0 & ((1 << FSHIFT) - 1)) * 100) >> FSHIFT) as u64
+    }
+}
+
+impl fmt::Display for Load {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:02}", self.integer(), self.hundredths())
+    }
+}
+
+/// The load averages over the last 1, 5 and 15 minutes, as returned by [`loadavg`].
+#[derive(Clone, Copy, Debug)]
+pub struct LoadAvg {
+    /// The load average over the last minute.
+    pub one: Load,
+
+    /// The load average over the last 5 minutes.
+    pub five: Load,
+
+    /// The load average over the last 15 minutes.
+    pub fifteen: Load,
+}
+
+impl fmt::Display for LoadAvg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.one, self.five, self.fifteen)
+    }
+}
+
+/// Returns the load averages, that is, the average number of runnable and uninterruptible tasks.
+///
+/// They are rounded to two decimals like in `/proc/loadavg`.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::sysinfo;
+///
+/// pr_info!("load average: {}\n", sysinfo::loadavg());
+/// ```
+pub fn loadavg() -> LoadAvg {
+    let mut loads: [core::ffi::c_ulong; 3] = [0; 3];
+    // Half of the last decimal shown, so that loads are rounded rather than truncated.
+    let offset = (1 << FSHIFT) / 200;
+    // SAFETY: `loads` is valid for writes of the three averages.
+    unsafe { bindings::get_avenrun(loads.as_mut_ptr(), offset, 0) };
+    LoadAvg {
+        one: Load(loads[0]),
+        five: Load(loads[1]),
+        fifteen: Load(loads[2]),
+    }
+}
@@ -5,8 +5,11 @@
 //! C header: [`include/linux/sched.h`](../../../../include/linux/sched.h).
 
 use crate::{
-    bindings, c_str, error::from_kernel_err_ptr, types::ForeignOwnable, ARef, AlwaysRefCounted,
-    Result, ScopeGuard,
+    bindings, c_str,
+    error::from_kernel_err_ptr,
+    sync::{rcu, read_once},
+    types::ForeignOwnable,
+    ARef, AlwaysRefCounted, Result, ScopeGuard,
 };
 use alloc::boxed::Box;
 use core::{cell::UnsafeCell, fmt, marker::PhantomData, ops::Deref, ptr};
@@ -68,6 +71,9 @@ unsafe impl Sync for Task {}
 /// The type of process identifiers (PIDs).
 type Pid = bindings::pid_t;
 
+/// The size of the buffer returned by [`Task::comm`].
+pub const TASK_COMM_LEN: usize = bindings::TASK_COMM_LEN as usize;
+
 impl Task {
     /// Returns a task reference for the currently executing task/thread.
     pub fn current<'a>() -> TaskRef<'a> {
@@ -100,12 +106,75 @@ impl Task {
         unsafe { core::ptr::addr_of!((*self.0.get()).pid).read() }
     }
 
+    /// Returns the name of the executable of the task, padded with `NUL` bytes.
+    ///
+    /// The name is copied while the task is locked, so it is consistent even if it is being
+    /// changed concurrently.
+    pub fn comm(&self) -> [u8; TASK_COMM_LEN] {
+        let mut comm = [0u8; TASK_COMM_LEN];
+        // SAFETY: By the type invariant, we know that `self.0` is valid. `comm` is valid for
+        // writes of its length.
+        unsafe { bindings::__get_task_comm(comm.as_mut_ptr().cast(), comm.len(), self.0.get()) };
+        comm
+    }
+
+    /// Returns the scheduling state of the task, made of the `TASK_*` state bits.
+    ///
+    /// The state of a task other than the current one may change as soon as it is read.
+    pub fn state(&self) -> u32 {
+        // SAFETY: By the type invariant, we know that `self.0` is valid. The scheduler updates the
+        // state with single-copy accesses.
+        unsafe { read_once(core::ptr::addr_of!((*self.0.get()).__state)) }
+    }
+
+    /// Returns an iterator over all the processes in the system, that is, over the group leaders
+    /// of all the thread groups, except for the idle task.
+    ///
+    /// This is the equivalent of C's `for_each_process()`. The tasks remain valid while the RCU
+    /// read-side lock is held, but processes that start or exit during the iteration may or may
+    /// not be visited.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kernel::{sync::rcu, task::Task};
+    ///
+    /// fn count_kernel_threads() -> usize {
+    ///     let guard = rcu::read_lock();
+    ///     Task::processes(&guard).filter(|t| t.is_kthread()).count()
+    /// }
+    /// ```
+    pub fn processes(_guard: &rcu::Guard) -> Processes<'_> {
+        // SAFETY: `init_task` is a static that lives forever.
+        let init = unsafe { core::ptr::addr_of_mut!(bindings::init_task) };
+        Processes {
+            // SAFETY: `init_task` is always valid, and the RCU read-side lock is held.
+            next: unsafe { next_task(init) },
+            init,
+            _guard: PhantomData,
+        }
+    }
+
+    /// Returns `true` if the task is a kernel thread.
+    pub fn is_kthread(&self) -> bool {
+        // SAFETY: By the type invariant, we know that `self.0` is valid. `PF_KTHREAD` never
+        // changes once the task is created.
+        let flags = unsafe { core::ptr::addr_of!((*self.0.get()).flags).read() };
+        flags & bindings::PF_KTHREAD != 0
+    }
+
     /// Determines whether the given task has pending signals.
     pub fn signal_pending(&self) -> bool {
         // SAFETY: By the type invariant, we know that `self.0` is valid.
         unsafe { bindings::signal_pending(self.0.get()) != 0 }
     }
 
+    /// Determines whether the given task has a pending fatal signal, that is, is being killed.
+    pub fn fatal_signal_pending(&self) -> bool {
+        // SAFETY: By the type invariant, we know that `self.0` is valid.
+        unsafe { bindings::fatal_signal_pending(self.0.get()) != 0 }
+    }
+
     /// Starts a new kernel thread and runs it.
     ///
     /// # Examples
@@ -211,6 +280,48 @@ unsafe impl AlwaysRefCounted for Task {
     }
 }
 
+/// Returns the process that follows `task` in the list of all processes.
+///
+/// # Safety
+///
+/// `task` must be a valid process, and the RCU read-side lock must be held.
+unsafe fn next_task(task: *mut bindings::task_struct) -> *mut bindings::task_struct {
+    // SAFETY: The caller guarantees that `task` is valid. The list is modified under RCU, which
+    // publishes the pointer with a release store.
+    let next = unsafe { read_once(core::ptr::addr_of!((*task).tasks.next)) };
+    crate::container_of!(next, bindings::task_struct, tasks) as *mut _
+}
+
+/// An iterator over the processes in the system, created by [`Task::processes`].
+///
+/// # Invariants
+///
+/// The RCU read-side lock is held for `'a`, and `next` is a process in the list that starts at
+/// `init`.
+pub struct Processes<'a> {
+    next: *mut bindings::task_struct,
+    init: *mut bindings::task_struct,
+    _guard: PhantomData<&'a rcu::Guard>,
+}
+
+impl<'a> Iterator for Processes<'a> {
+    type Item = &'a Task;
+
+    fn next(&mut self) -> Option<&'a Task> {
+        if self.next == self.init {
+            return None;
+        }
+
+        let task = self.next;
+        // SAFETY: By the type invariants, `task` is a valid process and the RCU read-side lock is
+        // held.
+        self.next = unsafe { next_task(task) };
+        // SAFETY: Tasks are freed after an RCU grace period once they leave the list, so `task`
+        // remains valid while the RCU read-side lock is held, that is, for `'a`.
+        Some(unsafe { &*task.cast() })
+    }
+}
+
 /// A wrapper for a shared reference to [`Task`] that isn't [`Send`].
 ///
 /// We make this explicitly not [`Send`] so that we can use it to represent the current thread
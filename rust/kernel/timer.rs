@@ -0,0 +1,188 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Kernel timers.
+//!
+//! A [`Timer`] calls [`TimerCallback::run`] once a delay has elapsed. The callback runs in softirq
+//! context, so it must not sleep; work that needs to sleep is queued on a work queue from there.
+//!
+//! Timers that poll or do housekeeping should be created with [`TimerFlags::DEFERRABLE`]: an idle
+//! CPU does not wake up to run them, but runs them the next time it wakes up for another reason.
+//! This keeps them from waking up a system that is idle or about to suspend.
+//!
+//! C header: [`include/linux/timer.h`](../../../../include/linux/timer.h)
+
+use crate::{bindings, init::PinInit, str::CStr, sync::LockClassKey, types::Opaque};
+use core::{marker::PhantomPinned, ops::Deref, time::Duration};
+
+crate::bitflags! {
+    /// Flags of a timer, see [`Timer::new`].
+    pub struct TimerFlags: u32 {
+        /// The timer does not wake up an idle CPU; it runs when the CPU wakes up for another
+        /// reason instead, so it may run late.
+        const DEFERRABLE = bindings::TIMER_DEFERRABLE as u32;
+
+        /// The timer runs on the CPU it was scheduled on, instead of one picked to save power.
+        const PINNED = bindings::TIMER_PINNED as u32;
+    }
+}
+
+/// The callback of a [`Timer`].
+pub trait TimerCallback: Send + Sync + Sized {
+    /// Called in softirq context when the timer expires.
+    ///
+    /// It must not sleep. It may schedule the timer again, for example to run periodically.
+    fn run(timer: &Timer<Self>);
+}
+
+/// A timer that calls [`TimerCallback::run`] on its data when it expires.
+///
+/// Dropping the timer cancels it and waits for its callback to return, even if the callback keeps
+/// scheduling it again.
+///
+/// Instances must be initialised in place, for example with the [`new_timer`] macro.
+///
+/// # Examples
+///
+/// Polling the counters of a device every second, without waking up the system to do so:
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use core::sync::atomic::{AtomicU64, Ordering};
+/// use core::time::Duration;
+/// use kernel::timer::{Timer, TimerCallback, TimerFlags};
+/// use kernel::{init::InPlaceInit, new_timer};
+///
+/// struct Poller {
+///     polls: AtomicU64,
+/// }
+///
+/// impl TimerCallback for Poller {
+///     fn run(timer: &Timer<Self>) {
+///         timer.polls.fetch_add(1, Ordering::Relaxed);
+///         timer.schedule(Duration::from_secs(1));
+///     }
+/// }
+///
+/// fn start() -> Result<Pin<Box<Timer<Poller>>>> {
+///     let poller = Poller {
+///         polls: AtomicU64::new(0),
+///     };
+///     let timer = Box::try_pin_init(new_timer!(poller, TimerFlags::DEFERRABLE))?;
+///     timer.schedule(Duration::from_secs(1));
+///     Ok(timer)
+/// }
+/// ```
+///
+/// # Invariants
+///
+/// `timer` has been initialised with `init_timer_key`, with `run_callback` as its function.
+///
+/// [`new_timer`]: crate::new_timer
+pub struct Timer<T: TimerCallback> {
+    timer: Opaque<bindings::timer_list>,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+// SAFETY: The timer can be scheduled, cancelled and dropped from any thread, and `T` is `Send`.
+unsafe impl<T: TimerCallback> Send for Timer<T> {}
+
+// SAFETY: All the C functions that `Timer` calls on a shared reference are safe to call
+// concurrently, and `T` is `Sync`.
+unsafe impl<T: TimerCallback> Sync for Timer<T> {}
+
+impl<T: TimerCallback> Timer<T> {
+    /// Creates an initialiser of a new timer with the given data, which is not scheduled.
+    ///
+    /// Users are encouraged to use the [`new_timer`] macro instead, which creates a lock class for
+    /// each call site.
+    ///
+    /// [`new_timer`]: crate::new_timer
+    pub fn new(
+        data: T,
+        flags: TimerFlags,
+        name: &'static CStr,
+        key: &'static LockClassKey,
+    ) -> impl PinInit<Self> {
+        let init = move |slot: *mut Self| {
+            // SAFETY: `slot` is valid for writes by the safety requirements of `PinInit`.
+            unsafe { core::ptr::addr_of_mut!((*slot).data).write(data) };
+
+            // SAFETY: `slot` is valid for writes by the safety requirements of `PinInit`. `Opaque`
+            // is transparent, so the field can be cast to its contents.
+            let timer =
+                unsafe { core::ptr::addr_of_mut!((*slot).timer) }.cast::<bindings::timer_list>();
+
+            // SAFETY: `timer` points to memory that is not going to move anymore. The name and the
+            // key are static, so they outlive the timer. This is what `timer_setup` does, with a
+            // lock class of the caller.
+            unsafe {
+                bindings::init_timer_key(
+                    timer,
+                    Some(Self::run_callback),
+                    flags.bits(),
+                    name.as_char_ptr(),
+                    key.get(),
+                )
+            };
+
+            // INVARIANT: The timer was just initialised, with `run_callback`.
+            Ok(())
+        };
+        // SAFETY: The closure always initialises both the data and the timer.
+        unsafe { crate::init::pin_init_from_closure(init) }
+    }
+
+    /// Schedules the timer to expire once `delay` has elapsed.
+    ///
+    /// The delay is rounded up to a whole number of jiffies. If the timer is already scheduled,
+    /// its expiry time is changed, and `true` is returned.
+    ///
+    /// It may be called from any context, including the callback of the timer.
+    pub fn schedule(&self, delay: Duration) -> bool {
+        let ms = delay.as_millis().try_into().unwrap_or(u32::MAX);
+        // `msecs_to_jiffies` is inline, so this calls the out-of-line conversion it falls back to
+        // for values that are not constant.
+        // SAFETY: This function has no safety requirements.
+        let delay = unsafe { bindings::__msecs_to_jiffies(ms) };
+        // SAFETY: `jiffies` is always valid, and is read atomically by the volatile access.
+        let now = unsafe { core::ptr::read_volatile(core::ptr::addr_of!(bindings::jiffies)) };
+        // SAFETY: By the type invariants, `timer` is initialised.
+        unsafe { bindings::mod_timer(self.timer.get(), now.wrapping_add(delay) as _) != 0 }
+    }
+
+    /// Cancels the timer, and waits for its callback to return if it is running.
+    ///
+    /// Returns `true` if the timer was scheduled. The callback may schedule the timer again
+    /// while this waits for it, so timers that are scheduled from their own callback must be
+    /// stopped in another way, or dropped.
+    ///
+    /// It must not be called from interrupt context, nor from the callback of the timer.
+    pub fn cancel(&self) -> bool {
+        // SAFETY: By the type invariants, `timer` is initialised.
+        unsafe { bindings::del_timer_sync(self.timer.get()) != 0 }
+    }
+
+    unsafe extern "C" fn run_callback(timer: *mut bindings::timer_list) {
+        // SAFETY: The timer only calls `run_callback` on timers that are embedded in a `Timer`,
+        // which cancels it before it is dropped.
+        let this = unsafe { &*crate::container_of!(timer, Self, timer) };
+        T::run(this);
+    }
+}
+
+impl<T: TimerCallback> Deref for Timer<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.data
+    }
+}
+
+impl<T: TimerCallback> Drop for Timer<T> {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, `timer` is initialised. Once this returns, the callback
+        // is not running, and the timer cannot be scheduled again.
+        unsafe { bindings::timer_shutdown_sync(self.timer.get()) };
+    }
+}
@@ -0,0 +1,401 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! TTY drivers.
+//!
+//! Allows Rust code to register a TTY driver with a fixed number of lines, for example to
+//! implement a virtual serial port.
+//!
+//! C headers: [`include/linux/tty_driver.h`](../../../../include/linux/tty_driver.h) and
+//! [`include/linux/tty_flip.h`](../../../../include/linux/tty_flip.h)
+
+use crate::{
+    bindings,
+    error::{code::*, from_kernel_err_ptr, from_kernel_result},
+    str::CString,
+    to_result,
+    types::ForeignOwnable,
+    Result, ThisModule,
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{cell::UnsafeCell, fmt, marker::PhantomData, pin::Pin};
+use macros::vtable;
+
+/// Wraps the kernel's `struct tty_struct`.
+///
+/// # Invariants
+///
+/// The pointer `Tty::ptr` is non-null and valid, and so is its `port` field.
+pub struct Tty {
+    ptr: *mut bindings::tty_struct,
+}
+
+impl Tty {
+    /// Creates a new TTY wrapper.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `ptr` is non-null and valid for the lifetime of the object,
+    /// and that its port is set.
+    unsafe fn from_ptr(ptr: *mut bindings::tty_struct) -> Self {
+        // INVARIANT: The safety requirements guarantee the invariants.
+        Self { ptr }
+    }
+
+    /// Returns the index of the line this TTY corresponds to.
+    pub fn index(&self) -> u32 {
+        // SAFETY: `self.ptr` is valid by the type invariants.
+        unsafe { (*self.ptr).index as _ }
+    }
+
+    /// Passes `data` to the line discipline as if it had been received by the device.
+    ///
+    /// Returns the number of bytes that were queued, which may be less than the length of `data`
+    /// if the flip buffer is full.
+    pub fn push_input(&self, data: &[u8]) -> usize {
+        // SAFETY: `self.ptr` and its port are valid by the type invariants.
+        let port = unsafe { (*self.ptr).port };
+
+        // SAFETY: `port` is valid and `data` is valid for `data.len()` bytes.
+        let queued = unsafe {
+            bindings::tty_insert_flip_string_fixed_flag(
+                port,
+                data.as_ptr(),
+                bindings::TTY_NORMAL as _,
+                data.len(),
+            )
+        };
+
+        // SAFETY: `port` is valid.
+        unsafe { bindings::tty_flip_buffer_push(port) };
+        queued as _
+    }
+
+    /// Wakes up writers waiting for room in the device.
+    ///
+    /// Drivers call this after their output buffer drains, when [`Operations::write_room`] may
+    /// report more room than before.
+    pub fn wakeup(&self) {
+        // SAFETY: `self.ptr` is valid by the type invariants.
+        unsafe { bindings::tty_wakeup(self.ptr) };
+    }
+}
+
+/// Corresponds to the kernel's `struct tty_operations`.
+#[vtable]
+pub trait Operations {
+    /// The type of the context data stored in the registration and made available to callbacks.
+    type Data: ForeignOwnable + Send + Sync = ();
+
+    /// Called when a line is opened.
+    fn open(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>, _tty: &Tty) -> Result {
+        Ok(())
+    }
+
+    /// Called when a file descriptor of a line is closed.
+    ///
+    /// It is called once for each successful call to [`Operations::open`] on the line, but not
+    /// after the opens that failed.
+    fn close(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>, _tty: &Tty) {}
+
+    /// Called to transmit `buf` through the device.
+    ///
+    /// Returns the number of bytes accepted, which must not exceed what was last reported by
+    /// [`Operations::write_room`].
+    fn write(
+        data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        tty: &Tty,
+        buf: &[u8],
+    ) -> Result<usize>;
+
+    /// Returns the number of bytes the device can currently accept.
+    fn write_room(data: <Self::Data as ForeignOwnable>::Borrowed<'_>, tty: &Tty) -> u32;
+}
+
+struct OperationsVtable<T>(PhantomData<T>);
+
+impl<T: Operations> OperationsVtable<T> {
+    fn build() -> bindings::tty_operations {
+        bindings::tty_operations {
+            open: Some(Self::open_callback),
+            close: Some(Self::close_callback),
+            write: Some(Self::write_callback),
+            write_room: Some(Self::write_room_callback),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the data registered with the driver of `tty`.
+    ///
+    /// # Safety
+    ///
+    /// `tty` must be valid and its driver must have been registered by [`Registration`].
+    unsafe fn data<'a>(
+        tty: *mut bindings::tty_struct,
+    ) -> <T::Data as ForeignOwnable>::Borrowed<'a> {
+        // SAFETY: By the safety requirements, `driver_state` was initialised by `register` with
+        // a value returned by `into_foreign`, and `from_foreign` is only called once the driver is
+        // unregistered and no line uses it anymore.
+        unsafe { T::Data::borrow((*(*tty).driver).driver_state) }
+    }
+
+    // The TTY core calls `close` after every `open`, including those that failed. The port is
+    // opened first, since `tty_port_open` counts the open even when it fails, which balances the
+    // `tty_port_close` in `close_callback`. The number of failed opens that are not closed yet is
+    // kept in `driver_data`, so that `T::close` is only called after the opens that succeeded.
+    // Opens and closes of a TTY are serialised by the TTY lock.
+
+    unsafe extern "C" fn open_callback(
+        tty: *mut bindings::tty_struct,
+        file: *mut bindings::file,
+    ) -> core::ffi::c_int {
+        // SAFETY: The TTY core only calls this with valid TTYs of our driver, whose port was
+        // linked by `register`, and a valid `file`.
+        let result = to_result(unsafe { bindings::tty_port_open((*tty).port, tty, file) })
+            .and_then(|_| {
+                // SAFETY: As above.
+                let (data, t) = unsafe { (Self::data(tty), Tty::from_ptr(tty)) };
+                T::open(data, &t)
+            });
+        match result {
+            Ok(()) => 0,
+            Err(e) => {
+                // SAFETY: `tty` is valid, and `driver_data` is only used by these callbacks.
+                unsafe { (*tty).driver_data = ((*tty).driver_data as usize + 1) as _ };
+                e.to_kernel_errno()
+            }
+        }
+    }
+
+    unsafe extern "C" fn close_callback(tty: *mut bindings::tty_struct, file: *mut bindings::file) {
+        // SAFETY: The TTY core only calls this with valid TTYs of our driver, and `driver_data`
+        // is only used by these callbacks.
+        let failed = unsafe { (*tty).driver_data as usize };
+        if failed > 0 {
+            // SAFETY: As above.
+            unsafe { (*tty).driver_data = (failed - 1) as _ };
+        } else {
+            // SAFETY: As above.
+            let (data, t) = unsafe { (Self::data(tty), Tty::from_ptr(tty)) };
+            T::close(data, &t);
+        }
+        // SAFETY: `tty` and `file` are valid, and so is the port.
+        unsafe { bindings::tty_port_close((*tty).port, tty, file) };
+    }
+
+    unsafe extern "C" fn write_callback(
+        tty: *mut bindings::tty_struct,
+        buf: *const u8,
+        count: core::ffi::c_int,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: The TTY core only calls this with valid TTYs of our driver, and `buf` is
+            // valid for `count` bytes.
+            let (data, t, buf) = unsafe {
+                (
+                    Self::data(tty),
+                    Tty::from_ptr(tty),
+                    core::slice::from_raw_parts(buf, count as _),
+                )
+            };
+            let written = T::write(data, &t, buf)?;
+            Ok(written.try_into()?)
+        }
+    }
+
+    unsafe extern "C" fn write_room_callback(tty: *mut bindings::tty_struct) -> core::ffi::c_uint {
+        // SAFETY: The TTY core only calls this with valid TTYs of our driver.
+        let (data, t) = unsafe { (Self::data(tty), Tty::from_ptr(tty)) };
+        T::write_room(data, &t)
+    }
+}
+
+/// The state of a registered TTY driver that the TTY core references.
+struct Driver {
+    ports: Vec<bindings::tty_port>,
+    ops: UnsafeCell<bindings::tty_operations>,
+    port_ops: UnsafeCell<bindings::tty_port_operations>,
+    name: CString,
+}
+
+/// A registration of a TTY driver.
+///
+/// Unregistering the driver does not close the lines that are open, which keep using it. If the
+/// registration is dropped while lines are still open, the driver and its data are leaked
+/// instead, so that the lines keep working until they are closed; they hold a reference to the
+/// module given when registering, so it cannot be unloaded meanwhile.
+///
+/// # Invariants
+///
+/// When `driver` is not null, it is a valid registered TTY driver whose `driver_state` holds a
+/// pointer returned by [`ForeignOwnable::into_foreign`], and which references the operations,
+/// ports and name of `state`, which is then `Some`.
+pub struct Registration<T: Operations> {
+    driver: *mut bindings::tty_driver,
+    state: Option<Box<Driver>>,
+    _p: PhantomData<T>,
+}
+
+impl<T: Operations> Registration<T> {
+    /// Creates a new [`Registration`] but does not register it yet.
+    ///
+    /// It is allowed to move.
+    pub fn new() -> Self {
+        // INVARIANT: `driver` is null.
+        Self {
+            driver: core::ptr::null_mut(),
+            state: None,
+            _p: PhantomData,
+        }
+    }
+
+    /// Registers a TTY driver.
+    ///
+    /// Returns a pinned heap-allocated representation of the registration.
+    pub fn new_pinned(
+        name: fmt::Arguments<'_>,
+        lines: u32,
+        data: T::Data,
+        module: &'static ThisModule,
+    ) -> Result<Pin<Box<Self>>> {
+        let mut reg = Pin::from(Box::try_new(Self::new())?);
+        reg.as_mut().register(name, lines, data, module)?;
+        Ok(reg)
+    }
+
+    /// Registers a TTY driver with `lines` lines with the rest of the kernel.
+    ///
+    /// The device nodes are named after `name` followed by the line index, for example
+    /// `/dev/ttyRUST0`.
+    pub fn register(
+        self: Pin<&mut Self>,
+        name: fmt::Arguments<'_>,
+        lines: u32,
+        data: T::Data,
+        module: &'static ThisModule,
+    ) -> Result {
+        // SAFETY: We never move out of `this`.
+        let this = unsafe { self.get_unchecked_mut() };
+        if !this.driver.is_null() || lines == 0 {
+            return Err(EINVAL);
+        }
+
+        let mut ports = Vec::new();
+        ports.try_reserve_exact(lines as _)?;
+        for _ in 0..lines {
+            ports.try_push(bindings::tty_port::default())?;
+        }
+        let mut state = Box::try_new(Driver {
+            ports,
+            ops: UnsafeCell::new(OperationsVtable::<T>::build()),
+            port_ops: UnsafeCell::new(bindings::tty_port_operations::default()),
+            name: CString::try_from_fmt(name)?,
+        })?;
+
+        // SAFETY: FFI call with valid arguments.
+        let driver = from_kernel_err_ptr(unsafe {
+            bindings::__tty_alloc_driver(lines, module.0, bindings::TTY_DRIVER_REAL_RAW as _)
+        })?;
+
+        // SAFETY: `driver` was just allocated, so it is valid and nothing else references it.
+        unsafe {
+            (*driver).driver_name = state.name.as_char_ptr();
+            (*driver).name = state.name.as_char_ptr();
+            (*driver).type_ = bindings::TTY_DRIVER_TYPE_SERIAL as _;
+            (*driver).subtype = bindings::SERIAL_TYPE_NORMAL as _;
+            (*driver).init_termios = bindings::tty_std_termios;
+            bindings::tty_set_operations(driver, state.ops.get());
+        }
+
+        // The ports do not move anymore: `ports` is fully populated and `state` is boxed.
+        let port_ops = state.port_ops.get();
+        for (i, port) in state.ports.iter_mut().enumerate() {
+            // SAFETY: `port` and `driver` are valid, and `i` is less than `lines`.
+            unsafe {
+                bindings::tty_port_init(port);
+                port.ops = port_ops;
+                bindings::tty_port_link_device(port, driver, i as _);
+            }
+        }
+
+        let data_pointer = data.into_foreign();
+        // SAFETY: `driver` is valid.
+        unsafe { (*driver).driver_state = data_pointer as _ };
+
+        // SAFETY: `driver` is fully initialised, and the name, operations and ports it references
+        // remain valid until they are released or leaked in `drop`.
+        if let Err(e) = to_result(unsafe { bindings::tty_register_driver(driver) }) {
+            // SAFETY: `driver` was not registered, so we own the only reference to it, and
+            // `data_pointer` comes from the call to `into_foreign` above.
+            unsafe {
+                bindings::tty_driver_kref_put(driver);
+                Self::destroy_ports(&mut state.ports);
+                T::Data::from_foreign(data_pointer);
+            }
+            return Err(e);
+        }
+
+        // INVARIANT: The driver was registered with the state set up above.
+        this.driver = driver;
+        this.state = Some(state);
+        Ok(())
+    }
+
+    /// Destroys and removes all `ports`.
+    ///
+    /// # Safety
+    ///
+    /// The ports must have been initialised and must not be in use anymore.
+    unsafe fn destroy_ports(ports: &mut Vec<bindings::tty_port>) {
+        for port in ports.iter_mut() {
+            // SAFETY: By the safety requirements, `port` is initialised and no longer used.
+            unsafe { bindings::tty_port_destroy(port) };
+        }
+        ports.clear();
+    }
+}
+
+impl<T: Operations> Default for Registration<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// SAFETY: `Registration` does not expose any of its state across threads.
+unsafe impl<T: Operations> Sync for Registration<T> {}
+
+// SAFETY: `Registration` is not restricted to a single thread, and its `T::Data` is also `Send`
+// so it may be moved to different threads.
+#[allow(clippy::non_send_fields_in_send_ty)]
+unsafe impl<T: Operations> Send for Registration<T> {}
+
+impl<T: Operations> Drop for Registration<T> {
+    /// Removes the registration from the kernel if it has completed successfully before.
+    fn drop(&mut self) {
+        let mut state = match self.state.take() {
+            Some(state) => state,
+            None => return,
+        };
+
+        // SAFETY: By the type invariants, `driver` is valid and registered.
+        unsafe { bindings::tty_unregister_driver(self.driver) };
+
+        // Open lines hold a reference to the driver, which they drop once they no longer use it.
+        // No line can be opened anymore, so if there are none, nothing else uses the driver.
+        // SAFETY: `driver` is valid, since we still hold our reference to it.
+        if unsafe { bindings::kref_read(&(*self.driver).kref) } > 1 {
+            crate::pr_warn!("TTY driver still has open lines, leaking it\n");
+            Box::leak(state);
+            return;
+        }
+
+        // SAFETY: By the type invariants, `driver_state` came from `into_foreign`. The driver is
+        // unregistered and no line uses it anymore, so no callbacks can run, and the ports and
+        // the state can be released.
+        unsafe {
+            let data_pointer = (*self.driver).driver_state;
+            bindings::tty_driver_kref_put(self.driver);
+            Self::destroy_ports(&mut state.ports);
+            T::Data::from_foreign(data_pointer);
+        }
+    }
+}
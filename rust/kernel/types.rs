@@ -4,8 +4,8 @@
 //!
 //! C header: [`include/linux/types.h`](../../../../include/linux/types.h)
 
-use crate::bindings;
-use alloc::boxed::Box;
+use crate::{bindings, error::code::EINVAL, io_buffer::ReadableFromBytes, Result};
+use alloc::{boxed::Box, vec::Vec};
 use core::{
     cell::UnsafeCell,
     marker::PhantomData,
@@ -17,19 +17,134 @@ use core::{
 
 /// Permissions.
 ///
+/// Used instead of raw integers by the APIs that create files, such as those of debugfs and
+/// sysctl, so that the argument cannot be confused with other integers.
+///
 /// C header: [`include/uapi/linux/stat.h`](../../../../include/uapi/linux/stat.h)
 ///
 /// C header: [`include/linux/stat.h`](../../../../include/linux/stat.h)
+///
+/// # Examples
+///
+/// ```
+/// use kernel::Mode;
+///
+/// const MODE: Mode = Mode::perm(0o640);
+/// assert_eq!(MODE.as_int(), 0o640);
+/// assert!(!MODE.is_world_writable());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Mode(bindings::umode_t);
 
 impl Mode {
     /// Creates a [`Mode`] from an integer.
-    pub fn from_int(m: u16) -> Mode {
+    pub const fn from_int(m: u16) -> Mode {
         Mode(m)
     }
 
+    /// Creates a [`Mode`] with the permission bits `perm`, such as `0o644`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `perm` has bits set other than the permission ones, which is a compile-time
+    /// error when the mode is a constant.
+    pub const fn perm(perm: u16) -> Mode {
+        assert!(
+            perm & !0o7777 == 0,
+            "`perm` must only contain permission bits"
+        );
+        Mode(perm)
+    }
+
     /// Returns the mode as an integer.
-    pub fn as_int(&self) -> u16 {
+    pub const fn as_int(&self) -> u16 {
+        self.0
+    }
+
+    /// Returns `true` if everyone may write to the file.
+    pub const fn is_world_writable(&self) -> bool {
+        self.0 & 0o002 != 0
+    }
+}
+
+/// Flags that control how memory is allocated.
+///
+/// The flags are combined with `|`, for example `GfpFlags::KERNEL | GfpFlags::ZERO`.
+///
+/// C header: [`include/linux/gfp_types.h`](../../../../include/linux/gfp_types.h)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GfpFlags(bindings::gfp_t);
+
+impl GfpFlags {
+    /// Allocations that may sleep and reclaim memory, for use in process context.
+    pub const KERNEL: Self = Self(bindings::GFP_KERNEL);
+
+    /// Allocations that must not sleep, for example in interrupt handlers or while holding a
+    /// spinlock. They may use the emergency reserves.
+    pub const ATOMIC: Self = Self(bindings::GFP_ATOMIC);
+
+    /// Allocations that must not sleep, and that fail rather than use the emergency reserves.
+    pub const NOWAIT: Self = Self(bindings::GFP_NOWAIT);
+
+    /// Zeroes the allocated memory.
+    pub const ZERO: Self = Self(bindings::__GFP_ZERO);
+
+    /// Allows pages to be allocated from high memory.
+    pub const HIGHMEM: Self = Self(bindings::__GFP_HIGHMEM);
+
+    /// Allows the allocation to sleep while it reclaims memory itself.
+    pub const DIRECT_RECLAIM: Self = Self(bindings::__GFP_DIRECT_RECLAIM);
+
+    /// Allows the allocation to call into filesystems to reclaim memory.
+    pub const FS: Self = Self(bindings::__GFP_FS);
+
+    /// Creates flags from the raw value used by C functions.
+    pub const fn from_raw(flags: bindings::gfp_t) -> Self {
+        Self(flags)
+    }
+
+    /// Returns the flags as the raw value expected by C functions.
+    pub const fn as_raw(self) -> bindings::gfp_t {
+        self.0
+    }
+
+    /// Returns `true` if all the flags in `other` are set.
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl ops::BitOr for GfpFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// A NUMA node, which memory is allocated on.
+///
+/// Drivers allocate the buffers that a device accesses often on the node of the device, returned
+/// by [`crate::device::RawDevice::numa_node`], so that the accesses stay local.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NumaNode(i32);
+
+impl NumaNode {
+    /// No node in particular, which lets the allocator use the node of the current CPU.
+    pub const ANY: Self = Self(bindings::NUMA_NO_NODE);
+
+    /// Returns the node with the given id.
+    ///
+    /// Returns `EINVAL` if `id` is not a valid node id.
+    pub fn new(id: i32) -> Result<Self> {
+        if id < 0 || id as u32 >= bindings::MAX_NUMNODES {
+            return Err(EINVAL);
+        }
+        Ok(Self(id))
+    }
+
+    /// Returns the id of the node, or `NUMA_NO_NODE` for [`NumaNode::ANY`].
+    pub const fn id(self) -> i32 {
         self.0
     }
 }
@@ -91,11 +206,18 @@ pub trait ForeignOwnable: Sized {
 impl<T: 'static> ForeignOwnable for Box<T> {
     type Borrowed<'a> = &'a T;
 
+    #[cfg_attr(CONFIG_RUST_DEBUG_ASSERTIONS, track_caller)]
     fn into_foreign(self) -> *const core::ffi::c_void {
-        Box::into_raw(self) as _
+        let ptr = Box::into_raw(self) as _;
+        #[cfg(CONFIG_RUST_DEBUG_ASSERTIONS)]
+        crate::foreign_tracking::track_into::<T>(ptr, core::panic::Location::caller());
+        ptr
     }
 
+    #[cfg_attr(CONFIG_RUST_DEBUG_ASSERTIONS, track_caller)]
     unsafe fn borrow<'a>(ptr: *const core::ffi::c_void) -> &'a T {
+        #[cfg(CONFIG_RUST_DEBUG_ASSERTIONS)]
+        crate::foreign_tracking::check_live::<T>(ptr, core::panic::Location::caller());
         // SAFETY: The safety requirements for this function ensure that the object is still alive,
         // so it is safe to dereference the raw pointer.
         // The safety requirements of `from_foreign` also ensure that the object remains alive for
@@ -103,7 +225,10 @@ impl<T: 'static> ForeignOwnable for Box<T> {
         unsafe { &*ptr.cast() }
     }
 
+    #[cfg_attr(CONFIG_RUST_DEBUG_ASSERTIONS, track_caller)]
     unsafe fn from_foreign(ptr: *const core::ffi::c_void) -> Self {
+        #[cfg(CONFIG_RUST_DEBUG_ASSERTIONS)]
+        crate::foreign_tracking::track_from::<T>(ptr, core::panic::Location::caller());
         // SAFETY: The safety requirements of this function ensure that `ptr` comes from a previous
         // call to `Self::into_foreign`.
         unsafe { Box::from_raw(ptr as _) }
@@ -125,6 +250,7 @@ impl ForeignOwnable for () {
 impl<T: ForeignOwnable + Deref> ForeignOwnable for Pin<T> {
     type Borrowed<'a> = T::Borrowed<'a>;
 
+    #[cfg_attr(CONFIG_RUST_DEBUG_ASSERTIONS, track_caller)]
     fn into_foreign(self) -> *const core::ffi::c_void {
         // SAFETY: We continue to treat the pointer as pinned by returning just a pointer to it to
         // the caller.
@@ -132,12 +258,14 @@ impl<T: ForeignOwnable + Deref> ForeignOwnable for Pin<T> {
         inner.into_foreign()
     }
 
+    #[cfg_attr(CONFIG_RUST_DEBUG_ASSERTIONS, track_caller)]
     unsafe fn borrow<'a>(ptr: *const core::ffi::c_void) -> Self::Borrowed<'a> {
         // SAFETY: The safety requirements for this function are the same as the ones for
         // `T::borrow`.
         unsafe { T::borrow(ptr) }
     }
 
+    #[cfg_attr(CONFIG_RUST_DEBUG_ASSERTIONS, track_caller)]
     unsafe fn from_foreign(p: *const core::ffi::c_void) -> Self {
         // SAFETY: The object was originally pinned.
         // The passed pointer comes from a previous call to `T::into_foreign`.
@@ -281,6 +409,117 @@ impl<T, F: FnOnce(T)> Drop for ScopeGuard<T, F> {
     }
 }
 
+/// Types whose memory can be cleared, so that secrets they hold do not linger after they are
+/// dropped.
+///
+/// Clearing a value leaves it valid, with all its bytes set to zero, so it is implemented for
+/// types that can hold any bit pattern.
+pub trait Zeroize {
+    /// Sets all the bytes of the value to zero, in a way that the compiler does not optimise
+    /// away even if the value is never read again.
+    fn zeroize(&mut self);
+}
+
+impl<T: ReadableFromBytes> Zeroize for [T] {
+    fn zeroize(&mut self) {
+        // SAFETY: The slice is valid for writes of its size, and zero is a valid bit pattern of
+        // `T` because it implements `ReadableFromBytes`.
+        unsafe {
+            bindings::memzero_explicit(self.as_mut_ptr().cast(), core::mem::size_of_val(self))
+        };
+    }
+}
+
+impl<T: ReadableFromBytes, const N: usize> Zeroize for [T; N] {
+    fn zeroize(&mut self) {
+        self[..].zeroize();
+    }
+}
+
+/// Clears the whole capacity of the vector, including the spare capacity left by elements that
+/// were removed. Copies left behind when the vector was reallocated are not cleared, so the
+/// capacity should be reserved up front.
+impl<T: ReadableFromBytes> Zeroize for Vec<T> {
+    fn zeroize(&mut self) {
+        // SAFETY: The buffer of the vector is valid for writes of its capacity, and zero is a valid
+        // bit pattern of `T` because it implements `ReadableFromBytes`.
+        unsafe {
+            bindings::memzero_explicit(
+                self.as_mut_ptr().cast(),
+                self.capacity() * core::mem::size_of::<T>(),
+            )
+        };
+    }
+}
+
+impl<T: Zeroize + ?Sized> Zeroize for Box<T> {
+    fn zeroize(&mut self) {
+        (**self).zeroize();
+    }
+}
+
+macro_rules! impl_zeroize_int {
+    ($($t:ty),*) => {
+        $(impl Zeroize for $t {
+            fn zeroize(&mut self) {
+                core::slice::from_mut(self).zeroize();
+            }
+        })*
+    };
+}
+
+impl_zeroize_int!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+/// Clears the value it wraps when dropped.
+///
+/// It is meant for buffers that hold keys, passwords or other secrets, so that they do not remain
+/// in freed memory. The wrapped value is accessed through [`Deref`] and [`DerefMut`].
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::{io_buffer::IoBufferReader, types::Zeroizing};
+///
+/// fn set_key(data: &mut impl IoBufferReader) -> Result {
+///     let mut key = Zeroizing::new([0u8; 32]);
+///     if data.len() != key.len() {
+///         return Err(EINVAL);
+///     }
+///     data.read_slice(&mut key[..])?;
+///     // Use the key. It is cleared when `key` goes out of scope, including on errors.
+///     Ok(())
+/// }
+/// ```
+pub struct Zeroizing<T: Zeroize>(T);
+
+impl<T: Zeroize> Zeroizing<T> {
+    /// Wraps `value`, which is cleared when the wrapper is dropped.
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T: Zeroize> Deref for Zeroizing<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Zeroize> DerefMut for Zeroizing<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Zeroize> Drop for Zeroizing<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
 /// Stores an opaque value.
 ///
 /// This is meant to be used with FFI objects that are never interpreted by Rust code.
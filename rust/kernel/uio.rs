@@ -0,0 +1,278 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Userspace I/O (UIO) devices.
+//!
+//! Allows drivers to export interrupts and memory regions of a device to userspace, which then
+//! implements the rest of the driver.
+//!
+//! C header: [`include/linux/uio_driver.h`](../../../../include/linux/uio_driver.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/driver-api/uio-howto.html>
+
+use crate::{
+    bindings, device,
+    error::{code::*, from_kernel_result},
+    irq,
+    str::CString,
+    to_result,
+    types::ForeignOwnable,
+    Result, ScopeGuard, ThisModule,
+};
+use alloc::boxed::Box;
+use core::{cell::UnsafeCell, fmt, marker::PhantomData, pin::Pin};
+use macros::vtable;
+
+/// The maximum number of memory regions a UIO device can export.
+pub const MAX_MAPS: usize = bindings::MAX_UIO_MAPS as _;
+
+/// The kind of memory backing a [`MemRegion`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MemType {
+    /// Physical memory, for example device registers.
+    Phys,
+
+    /// Logical memory, for example allocated with `kmalloc`.
+    Logical,
+
+    /// Virtual memory, for example allocated with `vmalloc`.
+    Virtual,
+}
+
+impl MemType {
+    fn as_raw(self) -> core::ffi::c_int {
+        (match self {
+            MemType::Phys => bindings::UIO_MEM_PHYS,
+            MemType::Logical => bindings::UIO_MEM_LOGICAL,
+            MemType::Virtual => bindings::UIO_MEM_VIRTUAL,
+        }) as _
+    }
+}
+
+/// A memory region exported to userspace.
+///
+/// Userspace maps region `N` by calling `mmap(2)` on the UIO device with an offset of `N` pages.
+pub struct MemRegion {
+    addr: u64,
+    size: u64,
+    kind: MemType,
+}
+
+impl MemRegion {
+    /// Creates a new memory region description.
+    ///
+    /// For [`MemType::Phys`] regions `addr` is a physical address; for the other kinds it is a
+    /// kernel virtual address.
+    ///
+    /// # Safety
+    ///
+    /// Userspace can map the whole region once the device is registered, so the caller must
+    /// ensure that it may access the `size` bytes at `addr`. For [`MemType::Phys`] regions, they
+    /// must belong to the device, for example its registers. For the other kinds, they must be
+    /// allocated as described by `kind`, and remain allocated until the registration is dropped
+    /// and the mappings created by userspace are gone.
+    pub const unsafe fn new(addr: u64, size: u64, kind: MemType) -> Self {
+        Self { addr, size, kind }
+    }
+}
+
+/// Corresponds to the callbacks of the kernel's `struct uio_info`.
+#[vtable]
+pub trait Operations {
+    /// The type of the context data stored in the registration and made available to callbacks.
+    type Data: ForeignOwnable + Send + Sync = ();
+
+    /// Called from interrupt context when the device interrupt fires.
+    ///
+    /// Implementations usually just acknowledge or mask the interrupt in hardware and return
+    /// [`irq::Return::Handled`], after which userspace is notified through `read(2)`.
+    fn handle_irq(data: <Self::Data as ForeignOwnable>::Borrowed<'_>) -> irq::Return;
+
+    /// Called when userspace writes to the device to enable (`on` is `true`) or disable the
+    /// interrupt.
+    fn irq_control(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>, _on: bool) -> Result {
+        Err(ENOSYS)
+    }
+}
+
+/// A registration of a UIO device.
+///
+/// # Invariants
+///
+/// `info.priv_` holds a pointer returned by [`ForeignOwnable::into_foreign`] when `registered`
+/// is `true`.
+pub struct Registration<T: Operations> {
+    info: UnsafeCell<bindings::uio_info>,
+    name: Option<CString>,
+    version: Option<CString>,
+    registered: bool,
+    _p: PhantomData<T>,
+}
+
+impl<T: Operations> Registration<T> {
+    /// Creates a new [`Registration`] but does not register it yet.
+    ///
+    /// It is allowed to move.
+    pub fn new() -> Self {
+        // INVARIANT: `registered` is `false`.
+        Self {
+            info: UnsafeCell::new(bindings::uio_info::default()),
+            name: None,
+            version: None,
+            registered: false,
+            _p: PhantomData,
+        }
+    }
+
+    /// Registers a UIO device.
+    ///
+    /// Returns a pinned heap-allocated representation of the registration.
+    pub fn new_pinned(
+        parent: &dyn device::RawDevice,
+        name: fmt::Arguments<'_>,
+        version: fmt::Arguments<'_>,
+        irq: Option<u32>,
+        regions: &[MemRegion],
+        data: T::Data,
+        module: &'static ThisModule,
+    ) -> Result<Pin<Box<Self>>> {
+        let mut reg = Pin::from(Box::try_new(Self::new())?);
+        reg.as_mut()
+            .register(parent, name, version, irq, regions, data, module)?;
+        Ok(reg)
+    }
+
+    /// Registers a UIO device with the rest of the kernel.
+    ///
+    /// `irq` is the interrupt line of the device, if any; it is requested by the UIO core on
+    /// behalf of the driver. At most [`MAX_MAPS`] memory regions can be exported.
+    ///
+    /// It must be pinned because the memory block that represents the registration is
+    /// self-referential.
+    #[allow(clippy::too_many_arguments)]
+    pub fn register(
+        self: Pin<&mut Self>,
+        parent: &dyn device::RawDevice,
+        name: fmt::Arguments<'_>,
+        version: fmt::Arguments<'_>,
+        irq: Option<u32>,
+        regions: &[MemRegion],
+        data: T::Data,
+        module: &'static ThisModule,
+    ) -> Result {
+        // SAFETY: We never move out of `this`.
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.registered {
+            return Err(EINVAL);
+        }
+
+        if regions.len() > MAX_MAPS {
+            return Err(E2BIG);
+        }
+
+        let name = CString::try_from_fmt(name)?;
+        let version = CString::try_from_fmt(version)?;
+        let data_pointer = data.into_foreign();
+
+        // SAFETY: `data_pointer` comes from the call to `into_foreign` above.
+        let guard = ScopeGuard::new(|| unsafe {
+            T::Data::from_foreign(data_pointer);
+        });
+
+        let info = this.info.get_mut();
+        info.name = name.as_char_ptr();
+        info.version = version.as_char_ptr();
+        for (mem, region) in info.mem.iter_mut().zip(regions) {
+            mem.addr = region.addr as _;
+            mem.size = region.size as _;
+            mem.memtype = region.kind.as_raw();
+        }
+        info.irq = match irq {
+            Some(irq) => irq as _,
+            None => bindings::UIO_IRQ_NONE as _,
+        };
+        info.handler = Some(Self::handler_callback);
+        info.irqcontrol = if T::HAS_IRQ_CONTROL {
+            Some(Self::irqcontrol_callback)
+        } else {
+            None
+        };
+        info.priv_ = data_pointer as _;
+
+        // SAFETY: `info` is fully initialised and, since `self` is pinned, will remain at the
+        // same address until it is unregistered in `drop`. `parent` is a valid device.
+        to_result(unsafe {
+            bindings::__uio_register_device(module.0, parent.raw_device(), this.info.get())
+        })?;
+
+        // INVARIANT: `priv_` was set above and registration succeeded.
+        this.registered = true;
+        this.name = Some(name);
+        this.version = Some(version);
+        guard.dismiss();
+        Ok(())
+    }
+
+    /// Notifies userspace that an event occurred.
+    ///
+    /// This is only needed for events that don't come from the device interrupt, as the UIO
+    /// core already notifies userspace when [`Operations::handle_irq`] reports the interrupt as
+    /// handled.
+    pub fn notify(&self) {
+        if self.registered {
+            // SAFETY: The device is registered, so `info` is valid.
+            unsafe { bindings::uio_event_notify(self.info.get()) };
+        }
+    }
+
+    unsafe extern "C" fn handler_callback(
+        _irq: core::ffi::c_int,
+        info: *mut bindings::uio_info,
+    ) -> bindings::irqreturn_t {
+        // SAFETY: `priv_` was initialised with a value returned by `into_foreign` before the
+        // device was registered, and `from_foreign` is only called after it is unregistered.
+        let data = unsafe { T::Data::borrow((*info).priv_) };
+        T::handle_irq(data) as _
+    }
+
+    unsafe extern "C" fn irqcontrol_callback(
+        info: *mut bindings::uio_info,
+        irq_on: i32,
+    ) -> core::ffi::c_int {
+        from_kernel_result! {
+            // SAFETY: `priv_` was initialised with a value returned by `into_foreign` before the
+            // device was registered, and `from_foreign` is only called after it is unregistered.
+            let data = unsafe { T::Data::borrow((*info).priv_) };
+            T::irq_control(data, irq_on != 0)?;
+            Ok(0)
+        }
+    }
+}
+
+impl<T: Operations> Default for Registration<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// SAFETY: `Registration` only exposes `notify`, which may be called from any thread.
+unsafe impl<T: Operations> Sync for Registration<T> {}
+
+// SAFETY: `Registration` is not restricted to a single thread, and its `T::Data` is also `Send`
+// so it may be moved to different threads.
+#[allow(clippy::non_send_fields_in_send_ty)]
+unsafe impl<T: Operations> Send for Registration<T> {}
+
+impl<T: Operations> Drop for Registration<T> {
+    /// Removes the registration from the kernel if it has completed successfully before.
+    fn drop(&mut self) {
+        if self.registered {
+            // SAFETY: `registered` being `true` indicates that a previous call to
+            // `__uio_register_device` succeeded.
+            unsafe { bindings::uio_unregister_device(self.info.get()) };
+
+            // SAFETY: By the type invariants, `priv_` came from `into_foreign`, and no callbacks
+            // can run anymore now that the device is unregistered.
+            unsafe { T::Data::from_foreign(self.info.get_mut().priv_) };
+        }
+    }
+}
@@ -173,3 +173,110 @@ impl IoBufferWriter for UserSlicePtrWriter {
         Ok(())
     }
 }
+
+/// A short string copied from userspace, such as a command written to a control file.
+///
+/// The string is stored inline, in a buffer of `N` bytes, and a single trailing newline is
+/// removed, since most strings written by shell commands end with one. Strings that do not fit are
+/// rejected with `EINVAL` rather than truncated.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::{io_buffer::IoBufferReader, user_ptr::UserString};
+///
+/// fn write_mode(data: &mut impl IoBufferReader) -> Result<usize> {
+///     let len = data.len();
+///     let mode = UserString::<16>::read_from(data)?;
+///     match mode.to_str()? {
+///         "on" => pr_info!("Enabled\n"),
+///         "off" => pr_info!("Disabled\n"),
+///         _ => return Err(EINVAL),
+///     }
+///     Ok(len)
+/// }
+/// ```
+///
+/// # Invariants
+///
+/// `len <= N`.
+pub struct UserString<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> UserString<N> {
+    /// Reads all the data remaining in `reader` as a string.
+    ///
+    /// Returns `EINVAL` if there are more than `N` bytes to read, not counting a trailing newline.
+    pub fn read_from(reader: &mut impl IoBufferReader) -> Result<Self> {
+        let len = reader.len();
+        // The trailing newline is not stored, so there may be one more byte than fits.
+        if len > N + 1 {
+            return Err(EINVAL);
+        }
+
+        let mut buf = [0u8; N];
+        let stored = core::cmp::min(len, N);
+        reader.read_slice(&mut buf[..stored])?;
+        if len > N {
+            let newline: u8 = reader.read()?;
+            if newline != b'\n' {
+                return Err(EINVAL);
+            }
+            // INVARIANT: `stored` is `N`.
+            return Ok(Self { buf, len: stored });
+        }
+
+        // INVARIANT: `len` is at most `N`.
+        Ok(Self::trimmed(buf, len))
+    }
+
+    /// Copies the `NUL`-terminated string at `ptr` from userspace.
+    ///
+    /// The buffer also holds the terminating `NUL` while the string is copied, so strings of `N`
+    /// bytes or more are rejected with `EINVAL`. Returns `EFAULT` if `ptr` does not point to
+    /// mapped, readable memory.
+    ///
+    /// # Safety
+    ///
+    /// Callers must be careful to avoid time-of-check-time-of-use (TOCTOU) issues, as with
+    /// [`UserSlicePtr::new`]: the string should only be copied once.
+    pub unsafe fn from_user_ptr(ptr: *const core::ffi::c_char) -> Result<Self> {
+        let mut buf = [0u8; N];
+        // SAFETY: `buf` is valid for writes of `N` bytes, and `strncpy_from_user` checks that the
+        // source is within the user range.
+        let ret = unsafe { bindings::strncpy_from_user(buf.as_mut_ptr().cast(), ptr, N as _) };
+        if ret < 0 {
+            return Err(EFAULT);
+        }
+
+        // A return value of `N` means that no `NUL` was found in the first `N` bytes.
+        let len = ret as usize;
+        if len >= N {
+            return Err(EINVAL);
+        }
+
+        // INVARIANT: `len` is less than `N`.
+        Ok(Self::trimmed(buf, len))
+    }
+
+    /// Creates a string out of the first `len` bytes of `buf`, without their trailing newline.
+    fn trimmed(buf: [u8; N], mut len: usize) -> Self {
+        if len > 0 && buf[len - 1] == b'\n' {
+            len -= 1;
+        }
+        Self { buf, len }
+    }
+
+    /// Returns the raw bytes of the string.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Returns the string if it is valid UTF-8, `EINVAL` otherwise.
+    pub fn to_str(&self) -> Result<&str> {
+        core::str::from_utf8(self.as_bytes()).map_err(|_| EINVAL)
+    }
+}
@@ -0,0 +1,143 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Growing vectors with explicit allocation flags.
+//!
+//! The methods of [`Vec`] allocate with [`GfpFlags::KERNEL`], so they may sleep. Vectors that are
+//! filled in interrupt handlers or while holding a spinlock use the methods of [`VecExt`] instead,
+//! which take the flags of each allocation, usually [`GfpFlags::ATOMIC`] or [`GfpFlags::NOWAIT`].
+//!
+//! Memory allocated with any flags is freed the same way, so a vector grown with [`VecExt`] is an
+//! ordinary [`Vec`] that the other methods keep using.
+
+use crate::{bindings, error::code::*, types::GfpFlags, NumaNode, Result};
+use alloc::vec::Vec;
+use core::{alloc::Layout, ffi::c_void, mem};
+
+/// Methods of [`Vec`] that take the flags of the allocations they make.
+///
+/// They fail with `ENOMEM` instead of aborting when the allocation fails, and leave the vector
+/// untouched in that case.
+///
+/// # Examples
+///
+/// Recording events into a vector protected by a spinlock, whose capacity is reserved beforehand
+/// when possible:
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::{sync::SpinLock, GfpFlags};
+///
+/// struct Events {
+///     pending: SpinLock<Vec<u32>>,
+/// }
+///
+/// impl Events {
+///     /// Called from process context, when the number of events is known.
+///     fn prepare(&self, count: usize) -> Result {
+///         let mut pending = self.pending.lock();
+///         // The allocation must not sleep while the spinlock is held.
+///         pending.try_reserve_in(count, GfpFlags::NOWAIT)
+///     }
+///
+///     fn record(&self, status: u32) -> Result {
+///         self.pending.lock().try_push_in(status, GfpFlags::ATOMIC)
+///     }
+/// }
+/// ```
+pub trait VecExt<T>: Sized {
+    /// Creates an empty vector with room for at least `capacity` elements.
+    fn try_with_capacity_in(capacity: usize, flags: GfpFlags) -> Result<Self>;
+
+    /// Creates an empty vector with room for at least `capacity` elements, allocated on `node` if
+    /// possible.
+    ///
+    /// Growing the vector beyond `capacity` later may move it to another node.
+    fn try_with_capacity_on_node(capacity: usize, node: NumaNode) -> Result<Self>;
+
+    /// Makes room for at least `additional` more elements.
+    ///
+    /// Like [`Vec::try_reserve`], the capacity may grow by more than requested, so that pushing
+    /// elements one at a time does not allocate every time.
+    fn try_reserve_in(&mut self, additional: usize, flags: GfpFlags) -> Result;
+
+    /// Appends `value`, growing the vector with `flags` if it is full.
+    fn try_push_in(&mut self, value: T, flags: GfpFlags) -> Result;
+
+    /// Appends clones of the elements of `other`, growing the vector with `flags` if needed.
+    fn try_extend_from_slice_in(&mut self, other: &[T], flags: GfpFlags) -> Result
+    where
+        T: Clone;
+}
+
+impl<T> VecExt<T> for Vec<T> {
+    fn try_with_capacity_in(capacity: usize, flags: GfpFlags) -> Result<Self> {
+        let mut v = Vec::new();
+        v.try_reserve_in(capacity, flags)?;
+        Ok(v)
+    }
+
+    fn try_with_capacity_on_node(capacity: usize, node: NumaNode) -> Result<Self> {
+        let size = Layout::array::<T>(capacity).map_err(|_| ENOMEM)?.size();
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+
+        // SAFETY: This only allocates memory. We check that it succeeds in the next statement.
+        let ptr = unsafe { bindings::__kmalloc_node(size, GfpFlags::KERNEL.as_raw(), node.id()) };
+        if ptr.is_null() {
+            return Err(ENOMEM);
+        }
+
+        // SAFETY: `ptr` was allocated by `kmalloc` for `capacity` elements, and the kernel
+        // allocator frees it with `kfree` like its own allocations.
+        Ok(unsafe { Vec::from_raw_parts(ptr.cast(), 0, capacity) })
+    }
+
+    fn try_reserve_in(&mut self, additional: usize, flags: GfpFlags) -> Result {
+        let len = self.len();
+        let needed = len.checked_add(additional).ok_or(ENOMEM)?;
+        // The capacity of vectors of zero-sized types is never exceeded.
+        if needed <= self.capacity() {
+            return Ok(());
+        }
+
+        let capacity = needed.max(self.capacity().saturating_mul(2));
+        let size = Layout::array::<T>(capacity).map_err(|_| ENOMEM)?.size();
+        let mut old = mem::ManuallyDrop::new(mem::take(self));
+        let old_ptr = if old.capacity() == 0 {
+            core::ptr::null()
+        } else {
+            old.as_mut_ptr() as *const c_void
+        };
+
+        // SAFETY: `old_ptr` is either null or was allocated by the kernel allocator, which uses
+        // `krealloc` as well. It is not used again if the reallocation succeeds.
+        let new = unsafe { bindings::krealloc(old_ptr, size, flags.as_raw()) }.cast::<T>();
+        if new.is_null() {
+            // SAFETY: The vector was taken apart above and `krealloc` leaves it untouched when it
+            // fails.
+            *self = unsafe { Vec::from_raw_parts(old.as_mut_ptr(), len, old.capacity()) };
+            return Err(ENOMEM);
+        }
+
+        // SAFETY: `new` is an allocation of the kernel allocator for `capacity` elements, of which
+        // the first `len` were moved there from the old allocation by `krealloc`.
+        *self = unsafe { Vec::from_raw_parts(new, len, capacity) };
+        Ok(())
+    }
+
+    fn try_push_in(&mut self, value: T, flags: GfpFlags) -> Result {
+        self.try_reserve_in(1, flags)?;
+        // The capacity was just reserved, so this does not allocate.
+        Ok(self.try_push(value)?)
+    }
+
+    fn try_extend_from_slice_in(&mut self, other: &[T], flags: GfpFlags) -> Result
+    where
+        T: Clone,
+    {
+        self.try_reserve_in(other.len(), flags)?;
+        // The capacity was just reserved, so this does not allocate.
+        Ok(self.try_extend_from_slice(other)?)
+    }
+}
@@ -210,13 +210,47 @@ impl Queue {
     /// Callers should first consider using one of the existing ones (e.g. [`system`]) before
     /// deciding to create a new one.
     pub fn try_new(name: fmt::Arguments<'_>) -> Result<BoxedQueue> {
+        Self::try_new_with_flags(name, QueueFlags::empty(), 0)
+    }
+
+    /// Tries to allocate a new work queue with the given flags.
+    ///
+    /// `max_active` is the number of work items of the queue that may run at the same time on
+    /// each CPU, or in total for [`QueueFlags::UNBOUND`] queues; zero selects the default.
+    ///
+    /// # Examples
+    ///
+    /// A queue for housekeeping work that must neither run while the system is suspended, nor keep
+    /// CPUs busy just to run it:
+    ///
+    /// ```
+    /// # use kernel::prelude::*;
+    /// use kernel::workqueue::{BoxedQueue, Queue, QueueFlags};
+    ///
+    /// fn housekeeping_queue() -> Result<BoxedQueue> {
+    ///     Queue::try_new_with_flags(
+    ///         fmt!("example-housekeeping"),
+    ///         QueueFlags::FREEZABLE | QueueFlags::POWER_EFFICIENT,
+    ///         1,
+    ///     )
+    /// }
+    /// ```
+    pub fn try_new_with_flags(
+        name: fmt::Arguments<'_>,
+        flags: QueueFlags,
+        max_active: u32,
+    ) -> Result<BoxedQueue> {
+        if max_active > bindings::WQ_MAX_ACTIVE as u32 {
+            return Err(EINVAL);
+        }
+
         // SAFETY: We use a format string that requires an `fmt::Arguments` pointer as the first
         // and only argument.
         let ptr = unsafe {
             bindings::alloc_workqueue(
                 c_str!("%pA").as_char_ptr(),
-                0,
-                0,
+                flags.bits(),
+                max_active as _,
                 &name as *const _ as *const core::ffi::c_void,
             )
         };
@@ -399,6 +433,36 @@ impl Work {
     }
 }
 
+crate::bitflags! {
+    /// Flags of a work queue, see [`Queue::try_new_with_flags`].
+    pub struct QueueFlags: u32 {
+        /// Work items are not bound to the CPU they are queued on, and are not concurrency
+        /// managed.
+        const UNBOUND = bindings::WQ_UNBOUND as u32;
+
+        /// The queue is frozen while the system suspends: work items queued meanwhile only run
+        /// once it has resumed.
+        ///
+        /// Work that touches hardware which is powered down during suspend, or that would keep
+        /// the system from suspending, should go to a freezable queue.
+        const FREEZABLE = bindings::WQ_FREEZABLE as u32;
+
+        /// The queue has a rescuer thread, so that its work items make progress when memory is
+        /// short. Queues used while reclaiming memory must set it.
+        const MEM_RECLAIM = bindings::WQ_MEM_RECLAIM as u32;
+
+        /// Work items run in high-priority worker threads.
+        const HIGHPRI = bindings::WQ_HIGHPRI as u32;
+
+        /// Work items may use the CPU for long, and are not counted by concurrency management.
+        const CPU_INTENSIVE = bindings::WQ_CPU_INTENSIVE as u32;
+
+        /// The queue becomes unbound when the `workqueue.power_efficient` kernel parameter is
+        /// set, so that its work items do not wake up idle CPUs.
+        const POWER_EFFICIENT = bindings::WQ_POWER_EFFICIENT as u32;
+    }
+}
+
 /// A boxed owned workqueue.
 ///
 /// # Invariants
@@ -0,0 +1,197 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Rust memory-mapped ring buffer sample.
+//!
+//! Exposes a single-producer, single-consumer byte ring through a misc device. Writes to the
+//! device append to the ring; userspace consumes the data by mapping the device and advancing the
+//! tail index stored in the first (header) page. `poll(2)` reports the device as readable while
+//! the ring is not empty.
+//!
+//! The layout of the mapping is:
+//!
+//! - page 0: header, with the `head` index at offset 0 and the `tail` index at offset 4 (both
+//!   native-endian `u32`s counting bytes modulo the data size);
+//! - pages 1..=`DATA_PAGES`: ring data.
+
+use kernel::prelude::*;
+use kernel::{
+    bindings,
+    file::{self, File, PollTable},
+    init::InPlaceInit,
+    io_buffer::IoBufferReader,
+    miscdev, mm, new_condvar, new_mutex,
+    pages::Pages,
+    pin_init,
+    sync::{Arc, ArcBorrow, CondVar, Mutex, UniqueArc},
+    PAGE_SIZE,
+};
+
+module! {
+    type: RustMmapRing,
+    name: "rust_mmap_ring",
+    author: "Rust for Linux Contributors",
+    description: "Rust memory-mapped ring buffer sample",
+    license: "GPL",
+}
+
+const DATA_PAGES: usize = 4;
+const DATA_SIZE: usize = DATA_PAGES * PAGE_SIZE;
+const HEAD_OFFSET: usize = 0;
+const TAIL_OFFSET: usize = 4;
+
+struct RingInner {
+    head: u32,
+}
+
+struct Ring {
+    header: Pages<0>,
+    data: Vec<Pages<0>>,
+    readable: CondVar,
+    inner: Mutex<RingInner>,
+}
+
+impl Ring {
+    fn try_new() -> Result<Arc<Self>> {
+        let mut data = Vec::try_with_capacity(DATA_PAGES)?;
+        for _ in 0..DATA_PAGES {
+            data.try_push(Pages::new()?)?;
+        }
+
+        let ring = UniqueArc::try_pin_init(pin_init!(Self {
+            header: Pages::new()?,
+            data,
+            readable <- new_condvar!("Ring::readable"),
+            inner <- new_mutex!(RingInner { head: 0 }, "Ring::inner"),
+        }))?;
+        Ok(ring.into())
+    }
+
+    fn read_index(&self, offset: usize) -> Result<u32> {
+        let mut value = 0u32;
+        let dest = &mut value as *mut u32 as *mut u8;
+        // SAFETY: `dest` points to a `u32` local and any bit pattern is a valid `u32`.
+        unsafe { self.header.read(dest, offset, 4)? };
+        Ok(value % DATA_SIZE as u32)
+    }
+
+    fn write_index(&self, offset: usize, value: u32) -> Result {
+        let src = &value as *const u32 as *const u8;
+        // SAFETY: `src` points to a `u32` local, which has no padding.
+        unsafe { self.header.write(src, offset, 4) }
+    }
+
+    /// Returns the number of bytes that the consumer has not read yet.
+    fn used(&self, head: u32) -> Result<usize> {
+        let tail = self.read_index(TAIL_OFFSET)?;
+        Ok((head as usize + DATA_SIZE - tail as usize) % DATA_SIZE)
+    }
+
+    /// Copies `buf` into the data pages starting at byte `pos` of the ring.
+    fn copy_in(&self, mut pos: usize, mut buf: &[u8]) -> Result {
+        while !buf.is_empty() {
+            let page = &self.data[pos / PAGE_SIZE];
+            let offset = pos % PAGE_SIZE;
+            let len = core::cmp::min(buf.len(), PAGE_SIZE - offset);
+            // SAFETY: `buf` is valid for `len` bytes and contains plain bytes.
+            unsafe { page.write(buf.as_ptr(), offset, len)? };
+            buf = &buf[len..];
+            pos = (pos + len) % DATA_SIZE;
+        }
+        Ok(())
+    }
+}
+
+struct RingFile;
+
+#[vtable]
+impl file::Operations for RingFile {
+    type Data = Arc<Ring>;
+    type OpenData = Arc<Ring>;
+
+    fn open(ring: &Arc<Ring>, _file: &File) -> Result<Self::Data> {
+        Ok(ring.clone())
+    }
+
+    fn write(
+        ring: ArcBorrow<'_, Ring>,
+        _file: &File,
+        data: &mut impl IoBufferReader,
+        _offset: &mut u64,
+    ) -> Result<usize> {
+        let mut inner = ring.inner.lock();
+
+        // One byte is always kept free to tell a full ring from an empty one.
+        let free = DATA_SIZE - 1 - ring.used(inner.head)?;
+        let len = core::cmp::min(free, data.len());
+        if len == 0 {
+            return Err(EAGAIN);
+        }
+
+        let mut buf = Vec::new();
+        buf.try_resize(len, 0u8)?;
+        data.read_slice(&mut buf)?;
+        ring.copy_in(inner.head as usize, &buf)?;
+
+        // Publish the data only after it has been copied in.
+        inner.head = ((inner.head as usize + len) % DATA_SIZE) as u32;
+        ring.write_index(HEAD_OFFSET, inner.head)?;
+        drop(inner);
+
+        ring.readable.notify_all();
+        Ok(len)
+    }
+
+    fn mmap(ring: ArcBorrow<'_, Ring>, _file: &File, vma: &mut mm::virt::Area) -> Result {
+        if vma.pgoff() != 0 || vma.len() != PAGE_SIZE + DATA_SIZE {
+            return Err(EINVAL);
+        }
+
+        if vma.flags() & mm::virt::flags::EXEC != 0 {
+            return Err(EPERM);
+        }
+
+        vma.set_flags((vma.flags() & !mm::virt::flags::MAYEXEC) | mm::virt::flags::DONTEXPAND);
+
+        let start = vma.start();
+        vma.insert_page(start, &ring.header)?;
+        for (i, page) in ring.data.iter().enumerate() {
+            vma.insert_page(start + (i + 1) * PAGE_SIZE, page)?;
+        }
+        Ok(())
+    }
+
+    fn poll(ring: ArcBorrow<'_, Ring>, file: &File, table: &PollTable) -> Result<u32> {
+        // SAFETY: `ring.readable` is only dropped together with `ring`, which outlives `file`
+        // because the file holds a reference to it.
+        unsafe { table.register_wait(file, &ring.readable) };
+
+        let mut mask = bindings::POLLOUT | bindings::POLLWRNORM;
+        if ring.used(ring.inner.lock().head)? != 0 {
+            mask |= bindings::POLLIN | bindings::POLLRDNORM;
+        }
+        Ok(mask)
+    }
+}
+
+struct RustMmapRing {
+    _dev: Pin<Box<miscdev::Registration<RingFile>>>,
+}
+
+impl kernel::Module for RustMmapRing {
+    fn init(name: &'static CStr, _module: &'static ThisModule) -> Result<Self> {
+        pr_info!("Rust memory-mapped ring buffer sample (init)\n");
+
+        let ring = Ring::try_new()?;
+        let vtable = kernel::file_vtable!(miscdev::Vtable<RingFile>);
+
+        Ok(RustMmapRing {
+            _dev: miscdev::Registration::new_pinned(vtable, fmt!("{name}"), ring)?,
+        })
+    }
+}
+
+impl Drop for RustMmapRing {
+    fn drop(&mut self) {
+        pr_info!("Rust memory-mapped ring buffer sample (exit)\n");
+    }
+}
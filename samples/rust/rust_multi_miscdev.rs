@@ -0,0 +1,156 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Rust sample with several misc devices.
+//!
+//! Registers as many misc devices as requested by the `devices` module parameter. Each device has
+//! its own contents: writing to a device replaces them, and reading from it returns them. The
+//! devices are kept in a registry keyed by their dynamically allocated minor numbers, and are all
+//! removed when the module is unloaded.
+
+use kernel::prelude::*;
+use kernel::{
+    file::{self, File},
+    init::InPlaceInit,
+    io_buffer::{IoBufferReader, IoBufferWriter},
+    miscdev, new_mutex, pin_init,
+    rbtree::RBTree,
+    sync::{Arc, ArcBorrow, Mutex, UniqueArc},
+};
+
+module! {
+    type: RustMultiMiscdev,
+    name: "rust_multi_miscdev",
+    author: "Rust for Linux Contributors",
+    description: "Rust sample with several misc devices",
+    license: "GPL",
+    params: {
+        devices: u32 {
+            default: 2,
+            permissions: 0o444,
+            description: "Number of devices to create",
+        },
+    },
+}
+
+/// The maximum number of devices the module creates.
+const MAX_DEVICES: u32 = 16;
+
+/// The maximum number of bytes a device holds.
+const MAX_CONTENTS: usize = 4096;
+
+struct Device {
+    index: u32,
+    contents: Mutex<Vec<u8>>,
+}
+
+impl Device {
+    fn try_new(index: u32) -> Result<Arc<Self>> {
+        let device = UniqueArc::try_pin_init(pin_init!(Self {
+            index,
+            contents <- new_mutex!(Vec::new(), "Device::contents"),
+        }))?;
+        Ok(device.into())
+    }
+}
+
+struct DeviceFile;
+
+#[vtable]
+impl file::Operations for DeviceFile {
+    type Data = Arc<Device>;
+    type OpenData = Arc<Device>;
+
+    fn open(device: &Arc<Device>, _file: &File) -> Result<Self::Data> {
+        Ok(device.clone())
+    }
+
+    fn read(
+        device: ArcBorrow<'_, Device>,
+        _file: &File,
+        data: &mut impl IoBufferWriter,
+        offset: &mut u64,
+    ) -> Result<usize> {
+        let contents = device.contents.lock();
+        let start = usize::try_from(*offset)?;
+        if start >= contents.len() {
+            return Ok(0);
+        }
+
+        let len = core::cmp::min(data.len(), contents.len() - start);
+        data.write_slice(&contents[start..][..len])?;
+        *offset += len as u64;
+        Ok(len)
+    }
+
+    fn write(
+        device: ArcBorrow<'_, Device>,
+        _file: &File,
+        data: &mut impl IoBufferReader,
+        offset: &mut u64,
+    ) -> Result<usize> {
+        let len = data.len();
+        if len > MAX_CONTENTS {
+            return Err(EFBIG);
+        }
+
+        let contents = data.read_all()?;
+        *device.contents.lock() = contents;
+        // The contents are replaced, so the file position is at their end.
+        *offset = len as u64;
+        Ok(len)
+    }
+}
+
+/// A device in the registry.
+struct Entry {
+    // Deregistered before the state is released, so no new files can be opened afterwards.
+    _registration: Pin<Box<miscdev::Registration<DeviceFile>>>,
+    device: Arc<Device>,
+}
+
+struct RustMultiMiscdev {
+    registry: RBTree<i32, Entry>,
+}
+
+impl kernel::Module for RustMultiMiscdev {
+    fn init(name: &'static CStr, _module: &'static ThisModule) -> Result<Self> {
+        let count = *devices.read();
+        pr_info!(
+            "Rust sample with several misc devices (init, {} devices)\n",
+            count
+        );
+
+        if count == 0 || count > MAX_DEVICES {
+            return Err(EINVAL);
+        }
+
+        // If a device fails to register, the ones registered so far are removed when the registry
+        // is dropped.
+        let mut registry = RBTree::new();
+        for index in 0..count {
+            let device = Device::try_new(index)?;
+            let vtable = kernel::file_vtable!(miscdev::Vtable<DeviceFile>);
+            let registration =
+                miscdev::Registration::new_pinned(vtable, fmt!("{name}{index}"), device.clone())?;
+            let minor = registration.minor().ok_or(EINVAL)?;
+            registry.try_insert(
+                minor,
+                Entry {
+                    _registration: registration,
+                    device,
+                },
+            )?;
+        }
+
+        Ok(RustMultiMiscdev { registry })
+    }
+}
+
+impl Drop for RustMultiMiscdev {
+    fn drop(&mut self) {
+        for (minor, entry) in self.registry.iter() {
+            pr_info!("Removing device {} (minor {})\n", entry.device.index, minor);
+        }
+        pr_info!("Rust sample with several misc devices (exit)\n");
+    }
+}
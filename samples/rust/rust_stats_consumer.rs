@@ -0,0 +1,128 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Rust sample using a subsystem exported by another module.
+//!
+//! Uses the counters of `rust_stats_provider` through the functions it exports in the
+//! `RUST_STATS` namespace: it records `requests` requests when it is loaded, checks that a second
+//! reference sees them, and keeps a reference until it is unloaded. The symbols make this module
+//! depend on the provider, so `modprobe` loads the provider first, and the provider cannot be
+//! unloaded while this module is loaded.
+
+use core::ffi::{c_int, c_void};
+use kernel::error::to_result;
+use kernel::prelude::*;
+
+module! {
+    type: RustStatsConsumer,
+    name: "rust_stats_consumer",
+    author: "Rust for Linux Contributors",
+    description: "Rust sample using a subsystem exported by another module",
+    license: "GPL",
+    params: {
+        requests: u64 {
+            default: 16,
+            permissions: 0,
+            description: "Number of requests to record",
+        },
+    },
+}
+
+kernel::module_import_ns!("RUST_STATS");
+
+extern "C" {
+    fn rust_stats_get() -> *const c_void;
+    fn rust_stats_put(stats: *const c_void);
+    fn rust_stats_add(stats: *const c_void, index: usize, value: u64) -> c_int;
+    fn rust_stats_read(stats: *const c_void, index: usize) -> u64;
+}
+
+/// The indices of the counters of the provider.
+const REQUESTS: usize = 0;
+const BYTES: usize = 1;
+
+/// The size of the requests that are recorded.
+const REQUEST_SIZE: u64 = 512;
+
+/// A reference to the counters of the provider.
+///
+/// # Invariants
+///
+/// `ptr` was returned by `rust_stats_get`, and is dropped with `rust_stats_put` when the
+/// reference is dropped.
+struct Stats {
+    ptr: *const c_void,
+}
+
+// SAFETY: The counters may be used and dropped from any thread.
+unsafe impl Send for Stats {}
+
+// SAFETY: The counters may be used from any thread concurrently.
+unsafe impl Sync for Stats {}
+
+impl Stats {
+    fn get() -> Result<Self> {
+        // SAFETY: This function has no safety requirements.
+        let ptr = unsafe { rust_stats_get() };
+        if ptr.is_null() {
+            return Err(ENODEV);
+        }
+        // INVARIANT: `ptr` was just returned by `rust_stats_get`.
+        Ok(Self { ptr })
+    }
+
+    fn add(&self, index: usize, value: u64) -> Result {
+        // SAFETY: By the type invariants, `self.ptr` is a reference that was not dropped yet.
+        to_result(unsafe { rust_stats_add(self.ptr, index, value) })
+    }
+
+    fn read(&self, index: usize) -> u64 {
+        // SAFETY: By the type invariants, `self.ptr` is a reference that was not dropped yet.
+        unsafe { rust_stats_read(self.ptr, index) }
+    }
+}
+
+impl Drop for Stats {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, `self.ptr` was returned by `rust_stats_get`, and it is
+        // not used after this.
+        unsafe { rust_stats_put(self.ptr) };
+    }
+}
+
+struct RustStatsConsumer {
+    _stats: Stats,
+}
+
+impl kernel::Module for RustStatsConsumer {
+    fn init(_name: &'static CStr, _module: &'static ThisModule) -> Result<Self> {
+        pr_info!("Rust stats consumer sample (init)\n");
+
+        let stats = Stats::get()?;
+        let before = stats.read(REQUESTS);
+        let requests = *requests.read();
+        for _ in 0..requests {
+            stats.add(REQUESTS, 1)?;
+            stats.add(BYTES, REQUEST_SIZE)?;
+        }
+
+        // Another reference shares the same counters.
+        let other = Stats::get()?;
+        if other.read(REQUESTS) < before + requests {
+            pr_err!("The counters are not shared between references\n");
+            return Err(EINVAL);
+        }
+        drop(other);
+
+        pr_info!(
+            "Recorded {requests} requests, {} bytes in total\n",
+            stats.read(BYTES)
+        );
+        Ok(RustStatsConsumer { _stats: stats })
+    }
+}
+
+impl Drop for RustStatsConsumer {
+    fn drop(&mut self) {
+        pr_info!("Rust stats consumer sample (exit)\n");
+    }
+}
@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Rust sample exporting a subsystem to other modules.
+//!
+//! Keeps a set of counters, exported in the `rust_stats_provider` directory of debugfs, and
+//! exports functions in the `RUST_STATS` namespace that let other modules update them. Other
+//! modules cannot name the Rust types of this one, so the counters are handed out as references
+//! to an [`Arc`] converted with [`ForeignOwnable`], which C code could use just as well:
+//!
+//! - `rust_stats_get` returns a new reference to the counters.
+//! - `rust_stats_add` adds to the counter at the given index, [`REQUESTS`] or [`BYTES`].
+//! - `rust_stats_read` returns the value of the counter at the given index.
+//! - `rust_stats_put` drops a reference.
+//!
+//! A module that uses these symbols depends on this one, so it is loaded after it and this one
+//! cannot be unloaded before it. `rust_stats_consumer` is such a module.
+
+use core::{
+    ffi::{c_int, c_void},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use kernel::prelude::*;
+use kernel::{
+    export_symbol,
+    module_global::{ModuleGlobal, ModuleGlobalRegistration},
+    stats::{StatSet, StatSetDir, StatSetVtables},
+    sync::Arc,
+    types::ForeignOwnable,
+};
+
+module! {
+    type: RustStatsProvider,
+    name: "rust_stats_provider",
+    author: "Rust for Linux Contributors",
+    description: "Rust sample exporting a subsystem to other modules",
+    license: "GPL",
+}
+
+/// The index of the counter of the requests.
+pub const REQUESTS: usize = 0;
+
+/// The index of the counter of the bytes transferred by the requests.
+pub const BYTES: usize = 1;
+
+const COUNTERS: usize = 2;
+
+type Stats = Arc<StatSet<COUNTERS>>;
+
+static STATS: ModuleGlobal<Stats> = ModuleGlobal::new();
+
+/// The number of references handed out to other modules and not dropped yet.
+static HANDLES: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns a new reference to the counters, or null if the module is being unloaded.
+///
+/// The reference must be dropped with `rust_stats_put`.
+#[no_mangle]
+pub extern "C" fn rust_stats_get() -> *const c_void {
+    match STATS.with(|stats| stats.clone().into_foreign()) {
+        Some(ptr) => {
+            HANDLES.fetch_add(1, Ordering::Relaxed);
+            ptr
+        }
+        None => core::ptr::null(),
+    }
+}
+export_symbol!(rust_stats_get, "RUST_STATS");
+
+/// Drops a reference returned by `rust_stats_get`.
+///
+/// # Safety
+///
+/// `stats` must have been returned by `rust_stats_get`, and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rust_stats_put(stats: *const c_void) {
+    // SAFETY: By the safety requirements, `stats` came from `into_foreign` and is not used again.
+    drop(unsafe { Stats::from_foreign(stats) });
+    HANDLES.fetch_sub(1, Ordering::Relaxed);
+}
+export_symbol!(rust_stats_put, "RUST_STATS");
+
+/// Adds `value` to the counter at `index`.
+///
+/// Returns `-EINVAL` if there is no such counter. It may be called from any context.
+///
+/// # Safety
+///
+/// `stats` must have been returned by `rust_stats_get`, and must not have been dropped yet.
+#[no_mangle]
+pub unsafe extern "C" fn rust_stats_add(stats: *const c_void, index: usize, value: u64) -> c_int {
+    if index >= COUNTERS {
+        return EINVAL.to_kernel_errno();
+    }
+    // SAFETY: By the safety requirements, `stats` came from `into_foreign` and was not dropped.
+    let stats = unsafe { Stats::borrow(stats) };
+    stats.add(index, value);
+    0
+}
+export_symbol!(rust_stats_add, "RUST_STATS");
+
+/// Returns the value of the counter at `index`, or zero if there is no such counter.
+///
+/// # Safety
+///
+/// `stats` must have been returned by `rust_stats_get`, and must not have been dropped yet.
+#[no_mangle]
+pub unsafe extern "C" fn rust_stats_read(stats: *const c_void, index: usize) -> u64 {
+    if index >= COUNTERS {
+        return 0;
+    }
+    // SAFETY: By the safety requirements, `stats` came from `into_foreign` and was not dropped.
+    let stats = unsafe { Stats::borrow(stats) };
+    stats.get(index)
+}
+export_symbol!(rust_stats_read, "RUST_STATS");
+
+struct RustStatsProvider {
+    _global: ModuleGlobalRegistration<Stats>,
+    _dir: StatSetDir<COUNTERS>,
+}
+
+impl kernel::Module for RustStatsProvider {
+    fn init(name: &'static CStr, _module: &'static ThisModule) -> Result<Self> {
+        pr_info!("Rust stats provider sample (init)\n");
+
+        let stats = Arc::try_new(StatSet::new(["requests", "bytes"])?)?;
+        let vtables = kernel::file_vtable!(StatSetVtables<COUNTERS>);
+        let dir = StatSetDir::create(name, None, vtables, stats.clone())?;
+        Ok(RustStatsProvider {
+            _global: STATS.install(stats)?,
+            _dir: dir,
+        })
+    }
+}
+
+impl Drop for RustStatsProvider {
+    fn drop(&mut self) {
+        // The modules that use the counters depend on this one, so they have all been unloaded,
+        // and should have dropped their references.
+        let handles = HANDLES.load(Ordering::Relaxed);
+        if handles != 0 {
+            pr_warn!("{handles} references to the counters were not dropped\n");
+        }
+        pr_info!("Rust stats provider sample (exit)\n");
+    }
+}
@@ -0,0 +1,132 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Rust sample listing the processes in the system.
+//!
+//! Creates `/proc/rust_tasks`, which shows a line per process with its PID, state and name, like
+//! a minimal `ps`. The list is copied when the file is opened, under the RCU read-side lock, and
+//! then shown from the copy, so the reads of an open file are consistent with each other.
+
+use core::fmt;
+use kernel::prelude::*;
+use kernel::{
+    bindings, c_str,
+    proc_fs::ProcDirEntry,
+    seq_file::SeqOperations,
+    sync::rcu,
+    task::{Task, TASK_COMM_LEN},
+};
+
+module! {
+    type: RustTasks,
+    name: "rust_tasks",
+    author: "Rust for Linux Contributors",
+    description: "Rust sample listing the processes in the system",
+    license: "GPL",
+}
+
+/// Room for processes started between counting them and copying them.
+const EXTRA_ROWS: usize = 16;
+
+struct Row {
+    pid: i32,
+    state: char,
+    comm: [u8; TASK_COMM_LEN],
+}
+
+impl Row {
+    fn new(task: &Task) -> Self {
+        Self {
+            pid: task.pid(),
+            state: state_char(task.state()),
+            comm: task.comm(),
+        }
+    }
+}
+
+impl fmt::Display for Row {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let len = self
+            .comm
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(TASK_COMM_LEN);
+        let comm = core::str::from_utf8(&self.comm[..len]).unwrap_or("?");
+        writeln!(f, "{:>7} {} {}", self.pid, self.state, comm)
+    }
+}
+
+/// Returns the letter that `ps` uses for a task state.
+fn state_char(state: u32) -> char {
+    if state == bindings::TASK_RUNNING {
+        'R'
+    } else if state & bindings::TASK_INTERRUPTIBLE != 0 {
+        'S'
+    } else if state & bindings::TASK_UNINTERRUPTIBLE != 0 {
+        'D'
+    } else if state & bindings::__TASK_STOPPED != 0 {
+        'T'
+    } else if state & bindings::__TASK_TRACED != 0 {
+        't'
+    } else {
+        '?'
+    }
+}
+
+/// Copies the processes in the system.
+fn snapshot() -> Result<Vec<Row>> {
+    // Memory cannot be allocated while the RCU read-side lock is held, so the rows are allocated
+    // beforehand. Processes started in the meantime beyond the extra room are left out.
+    let count = Task::processes(&rcu::read_lock()).count();
+    let mut rows = Vec::try_with_capacity(count + EXTRA_ROWS)?;
+
+    let guard = rcu::read_lock();
+    for task in Task::processes(&guard) {
+        if rows.len() == rows.capacity() {
+            break;
+        }
+        // This does not allocate, since there is spare capacity.
+        rows.try_push(Row::new(task))?;
+    }
+    Ok(rows)
+}
+
+struct TasksFile;
+
+impl SeqOperations for TasksFile {
+    type DataWrapper = ();
+    type OpenState = Vec<Row>;
+    type IteratorWrapper<'a> = core::slice::Iter<'a, Row>;
+    type Item<'a> = &'a Row;
+
+    fn open(_open_data: &()) -> Result {
+        Ok(())
+    }
+
+    fn open_state(_data: ()) -> Result<Vec<Row>> {
+        snapshot()
+    }
+
+    fn start<'a>(_data: (), rows: &'a mut Vec<Row>) -> Option<core::slice::Iter<'a, Row>> {
+        Some(rows.iter())
+    }
+}
+
+struct RustTasks {
+    _entry: ProcDirEntry,
+}
+
+impl kernel::Module for RustTasks {
+    fn init(_name: &'static CStr, _module: &'static ThisModule) -> Result<Self> {
+        pr_info!("Rust sample listing the processes in the system (init)\n");
+
+        Ok(RustTasks {
+            _entry: ProcDirEntry::new_seq_private::<TasksFile>(c_str!("rust_tasks"), ())?,
+        })
+    }
+}
+
+impl Drop for RustTasks {
+    fn drop(&mut self) {
+        pr_info!("Rust sample listing the processes in the system (exit)\n");
+    }
+}
@@ -0,0 +1,302 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Rust test device sample.
+//!
+//! Creates a `rust_testdev` directory in debugfs with the following files, meant to be driven by
+//! scripts that exercise the error and teardown paths of the file abstractions:
+//!
+//! - `control`, write-only, accepts one command per write:
+//!   - `alloc <bytes>` allocates and keeps a zeroed buffer of the given size, so that allocation
+//!     failures can be provoked, for example with the `failslab` fault injection capability;
+//!   - `free` releases all the buffers allocated so far;
+//!   - `fail-next-open` makes the next open of `wait` fail with `ENOMEM`;
+//!   - `sleep <ms>` sleeps in the write handler, to race other operations against it;
+//!   - `wake` wakes up the readers blocked on `wait`.
+//! - `wait`, read-only, blocks on the first read until the next `wake` command, then returns a
+//!   line with the state of the device. Opening it for writing fails with `EACCES`.
+//! - `opens` shows how many times `wait` was opened successfully. Writing a number to it sets
+//!   the count, for example to reset it.
+//! - `foreign_objects`, only with `CONFIG_RUST_DEBUG_ASSERTIONS`, lists the Rust objects
+//!   currently owned by C code, such as the data of the open files, so that leaks can be spotted
+//!   after a test.
+//!
+//! Blocked readers are woken up when the module is unloaded, so that removing the files does not
+//! wait for them forever.
+
+use core::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+use kernel::prelude::*;
+use kernel::{
+    c_str,
+    debugfs::{self, AtomicU64File, DebugFsDirectory, DebugFsFile, MODE_200, MODE_444, MODE_644},
+    delay::coarse_sleep,
+    file::{self, File},
+    init::InPlaceInit,
+    io_buffer::{IoBufferReader, IoBufferWriter},
+    new_condvar, new_mutex, pin_init,
+    signal::SignalPending,
+    str::CString,
+    sync::{Arc, ArcBorrow, CondVar, Mutex, UniqueArc},
+    user_ptr::UserString,
+};
+
+module! {
+    type: RustTestdev,
+    name: "rust_testdev",
+    author: "Rust for Linux Contributors",
+    description: "Rust test device sample",
+    license: "GPL",
+}
+
+/// The maximum length of a command.
+const MAX_COMMAND: usize = 64;
+
+/// The longest a single `sleep` command may sleep for, in milliseconds.
+const MAX_SLEEP_MS: u64 = 10_000;
+
+struct State {
+    buffers: Vec<Vec<u8>>,
+    allocated: usize,
+    wakeups: u64,
+    closing: bool,
+}
+
+struct TestDev {
+    fail_next_open: AtomicBool,
+    opens: AtomicU64,
+    woken: CondVar,
+    state: Mutex<State>,
+}
+
+impl TestDev {
+    fn try_new() -> Result<Arc<Self>> {
+        let dev = UniqueArc::try_pin_init(pin_init!(Self {
+            fail_next_open: AtomicBool::new(false),
+            opens: AtomicU64::new(0),
+            woken <- new_condvar!("TestDev::woken"),
+            state <- new_mutex!(
+                State {
+                    buffers: Vec::new(),
+                    allocated: 0,
+                    wakeups: 0,
+                    closing: false,
+                },
+                "TestDev::state"
+            ),
+        }))?;
+        Ok(dev.into())
+    }
+
+    fn alloc(&self, size: usize) -> Result {
+        let mut buffer = Vec::try_with_capacity(size)?;
+        buffer.try_resize(size, 0u8)?;
+
+        let mut state = self.state.lock();
+        state.buffers.try_push(buffer)?;
+        state.allocated += size;
+        Ok(())
+    }
+
+    fn free(&self) {
+        let buffers = {
+            let mut state = self.state.lock();
+            state.allocated = 0;
+            core::mem::take(&mut state.buffers)
+        };
+        // The buffers are freed without holding the lock.
+        drop(buffers);
+    }
+
+    fn wake(&self) {
+        self.state.lock().wakeups += 1;
+        self.woken.notify_all();
+    }
+
+    /// Wakes up all the readers and makes further reads return immediately.
+    fn close(&self) {
+        self.state.lock().closing = true;
+        self.woken.notify_all();
+    }
+
+    fn execute(&self, command: &str) -> Result {
+        let (name, arg) = match command.split_once(' ') {
+            Some((name, arg)) => (name, Some(arg.trim())),
+            None => (command, None),
+        };
+
+        match (name, arg) {
+            ("alloc", Some(size)) => {
+                let size = size.parse::<usize>().map_err(|_| EINVAL)?;
+                self.alloc(size).map_err(|e| {
+                    pr_info!("Allocation of {} bytes failed\n", size);
+                    e
+                })
+            }
+            ("free", None) => {
+                self.free();
+                Ok(())
+            }
+            ("fail-next-open", None) => {
+                self.fail_next_open.store(true, Ordering::Relaxed);
+                Ok(())
+            }
+            ("sleep", Some(ms)) => {
+                let ms = ms.parse::<u64>().map_err(|_| EINVAL)?;
+                if ms > MAX_SLEEP_MS {
+                    return Err(EINVAL);
+                }
+                coarse_sleep(Duration::from_millis(ms));
+                Ok(())
+            }
+            ("wake", None) => {
+                self.wake();
+                Ok(())
+            }
+            _ => Err(EINVAL),
+        }
+    }
+}
+
+struct ControlFile;
+
+#[vtable]
+impl file::Operations for ControlFile {
+    type Data = Arc<TestDev>;
+    type OpenData = Arc<TestDev>;
+
+    fn open(dev: &Arc<TestDev>, _file: &File) -> Result<Self::Data> {
+        Ok(dev.clone())
+    }
+
+    fn write(
+        dev: ArcBorrow<'_, TestDev>,
+        _file: &File,
+        data: &mut impl IoBufferReader,
+        _offset: &mut u64,
+    ) -> Result<usize> {
+        let len = data.len();
+        let command = UserString::<MAX_COMMAND>::read_from(data)?;
+        dev.execute(command.to_str()?.trim())?;
+        Ok(len)
+    }
+}
+
+struct WaitFile;
+
+#[vtable]
+impl file::Operations for WaitFile {
+    type Data = Arc<TestDev>;
+    type OpenData = Arc<TestDev>;
+
+    fn open(dev: &Arc<TestDev>, file: &File) -> Result<Self::Data> {
+        if file.is_writable() {
+            return Err(EACCES);
+        }
+        if dev.fail_next_open.swap(false, Ordering::Relaxed) {
+            return Err(ENOMEM);
+        }
+        dev.opens.fetch_add(1, Ordering::Relaxed);
+        Ok(dev.clone())
+    }
+
+    fn read(
+        dev: ArcBorrow<'_, TestDev>,
+        _file: &File,
+        data: &mut impl IoBufferWriter,
+        offset: &mut u64,
+    ) -> Result<usize> {
+        if *offset != 0 {
+            return Ok(0);
+        }
+
+        let mut state = dev.state.lock();
+        let wakeups = state.wakeups;
+        while state.wakeups == wakeups && !state.closing {
+            if dev.woken.wait(&mut state) {
+                // The wait was interrupted by a signal, which restarts the read once handled.
+                SignalPending::check()?;
+            }
+        }
+
+        let line = CString::try_from_fmt(fmt!(
+            "buffers: {}, allocated: {}, wakeups: {}\n",
+            state.buffers.len(),
+            state.allocated,
+            state.wakeups
+        ))?;
+        drop(state);
+
+        let line = line.as_bytes();
+        let len = core::cmp::min(data.len(), line.len());
+        data.write_slice(&line[..len])?;
+        *offset = len as u64;
+        Ok(len)
+    }
+}
+
+struct RustTestdev {
+    dev: Arc<TestDev>,
+    _control: DebugFsFile<ControlFile>,
+    _wait: DebugFsFile<WaitFile>,
+    _opens: DebugFsFile<AtomicU64File<TestDev>>,
+    #[cfg(CONFIG_RUST_DEBUG_ASSERTIONS)]
+    _foreign:
+        DebugFsFile<kernel::seq_file::SeqFileAdapter<kernel::foreign_tracking::LiveObjectsFile>>,
+}
+
+impl kernel::Module for RustTestdev {
+    fn init(name: &'static CStr, _module: &'static ThisModule) -> Result<Self> {
+        pr_info!("Rust test device sample (init)\n");
+
+        let dev = TestDev::try_new()?;
+        let dir = DebugFsDirectory::create(name, None)?;
+        let control = DebugFsFile::create(
+            c_str!("control"),
+            Some(dir.clone()),
+            MODE_200,
+            kernel::file_vtable!(debugfs::Vtable<ControlFile>),
+            dev.clone(),
+        )?;
+        let wait = DebugFsFile::create(
+            c_str!("wait"),
+            Some(dir.clone()),
+            MODE_444,
+            kernel::file_vtable!(debugfs::Vtable<WaitFile>),
+            dev.clone(),
+        )?;
+        let opens = debugfs::debugfs_create_atomic_u64(
+            c_str!("opens"),
+            Some(dir.clone()),
+            MODE_644,
+            kernel::file_vtable!(debugfs::Vtable<AtomicU64File<TestDev>>),
+            dev.clone(),
+            |dev| &dev.opens,
+        )?;
+        #[cfg(CONFIG_RUST_DEBUG_ASSERTIONS)]
+        let foreign = kernel::foreign_tracking::debugfs_create(
+            c_str!("foreign_objects"),
+            Some(dir),
+            MODE_444,
+        )?;
+
+        Ok(RustTestdev {
+            dev,
+            _control: control,
+            _wait: wait,
+            _opens: opens,
+            #[cfg(CONFIG_RUST_DEBUG_ASSERTIONS)]
+            _foreign: foreign,
+        })
+    }
+}
+
+impl Drop for RustTestdev {
+    fn drop(&mut self) {
+        // Removing the files waits for the operations in progress, so blocked readers must be
+        // woken up first.
+        self.dev.close();
+        pr_info!("Rust test device sample (exit)\n");
+    }
+}
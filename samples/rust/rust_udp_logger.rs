@@ -0,0 +1,232 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Rust UDP logger sample.
+//!
+//! Records events in an [`EventLog`] and streams them to a collector over UDP, so that the history
+//! of a device can be followed from another machine, for example one that is not affected when
+//! the device under test crashes. The collector is set with the `collector_addr` and
+//! `collector_port` parameters, and can be as simple as `nc -klu 5140`.
+//!
+//! Events are recorded without blocking and sent from a work item, in datagrams of up to
+//! [`MAX_DATAGRAM`] bytes with one event per line. Events recorded while the work item is pending
+//! are sent together. For the sake of the example, every write to the `rust_udp_logger/event`
+//! file in debugfs records an event with the number of bytes written.
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use kernel::prelude::*;
+use kernel::{
+    c_str,
+    debugfs::{self, DebugFsDirectory, DebugFsFile, MODE_200},
+    event_log::EventLog,
+    file::{self, File},
+    io_buffer::IoBufferReader,
+    net::{self, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    str::CStringBuf,
+    sync::{smutex, Arc, ArcBorrow, UniqueArc},
+    workqueue::{self, Work},
+};
+
+module! {
+    type: RustUdpLogger,
+    name: "rust_udp_logger",
+    author: "Rust for Linux Contributors",
+    description: "Rust UDP event logger sample",
+    license: "GPL",
+    params: {
+        collector_addr: str {
+            default: b"127.0.0.1",
+            permissions: 0,
+            description: "IPv4 address of the collector",
+        },
+        collector_port: u16 {
+            default: 5140,
+            permissions: 0,
+            description: "UDP port of the collector",
+        },
+    },
+}
+
+/// The number of events kept until they are sent.
+const LOG_SIZE: usize = 256;
+
+/// The maximum size of a datagram, small enough to avoid fragmentation on most links.
+const MAX_DATAGRAM: usize = 1024;
+
+/// The maximum length of the line of an event.
+const MAX_LINE: usize = 64;
+
+#[derive(Clone, Copy)]
+enum Event {
+    Loaded,
+    Write { bytes: usize },
+    Unloading,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Loaded => write!(f, "loaded"),
+            Event::Write { bytes } => write!(f, "write of {bytes} bytes"),
+            Event::Unloading => write!(f, "unloading"),
+        }
+    }
+}
+
+struct Logger {
+    work: Work,
+    log: EventLog<Event, LOG_SIZE>,
+    socket: UdpSocket,
+    /// The position of the first event that was not sent yet.
+    next: AtomicU64,
+    /// The datagram being filled, kept to reuse its allocation.
+    datagram: smutex::Mutex<CStringBuf>,
+    failed: AtomicU64,
+}
+
+impl Logger {
+    fn try_new(collector: &SocketAddr) -> Result<Arc<Self>> {
+        let socket = UdpSocket::try_connect(net::init_ns(), collector)?;
+        let logger = UniqueArc::try_new(Self {
+            // SAFETY: `work` is initialised below.
+            work: unsafe { Work::new() },
+            log: EventLog::new(),
+            socket,
+            next: AtomicU64::new(0),
+            datagram: smutex::Mutex::new(CStringBuf::try_with_capacity(MAX_DATAGRAM)?),
+            failed: AtomicU64::new(0),
+        })?;
+        kernel::init_work_item!(&logger);
+        Ok(logger.into())
+    }
+
+    /// Records `event` and schedules the work item that sends it.
+    fn record(this: &Arc<Self>, event: Event) {
+        this.log.record(event);
+        // If the work item is already pending, it sends this event together with the others.
+        workqueue::system().enqueue(this.clone());
+    }
+
+    /// Sends the events recorded since the last call.
+    ///
+    /// It is only called by the work item, and once it is cancelled, so calls do not overlap.
+    fn flush(&self) {
+        let entries = match self.log.snapshot() {
+            Ok(entries) => entries,
+            // The events are sent with the next ones.
+            Err(_) => return,
+        };
+
+        let mut datagram = self.datagram.lock();
+        let mut next = self.next.load(Ordering::Relaxed);
+        for entry in entries.iter().filter(|entry| entry.pos >= next) {
+            if datagram.len() + MAX_LINE > MAX_DATAGRAM {
+                self.send(&datagram);
+                datagram.clear();
+            }
+            // The capacity was reserved when the logger was created, so this only fails for lines
+            // that are longer than expected, which are skipped.
+            let _ = datagram.try_write_fmt(fmt!("{}", entry));
+            next = entry.pos + 1;
+        }
+        if !datagram.is_empty() {
+            self.send(&datagram);
+            datagram.clear();
+        }
+        self.next.store(next, Ordering::Relaxed);
+    }
+
+    fn send(&self, datagram: &CStr) {
+        // The work item runs on a shared queue, so it does not wait for room in the socket.
+        if let Err(e) = self.socket.send(datagram.as_bytes(), false) {
+            if self.failed.fetch_add(1, Ordering::Relaxed) == 0 {
+                pr_warn!("Sending events failed: {:?}\n", e);
+            }
+        }
+    }
+}
+
+kernel::impl_self_work_adapter!(Logger, work, |logger| logger.flush());
+
+/// Parses an IPv4 address in dotted-decimal notation.
+fn parse_ipv4(text: &[u8]) -> Result<Ipv4Addr> {
+    let mut octets = [0u8; 4];
+    let mut parts = text.split(|&c| c == b'.');
+    for octet in &mut octets {
+        let part = parts.next().ok_or(EINVAL)?;
+        *octet = core::str::from_utf8(part)
+            .ok()
+            .and_then(|part| part.parse().ok())
+            .ok_or(EINVAL)?;
+    }
+    if parts.next().is_some() {
+        return Err(EINVAL);
+    }
+    Ok(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]))
+}
+
+struct EventFile;
+
+#[vtable]
+impl file::Operations for EventFile {
+    type Data = Arc<Logger>;
+    type OpenData = Arc<Logger>;
+
+    fn open(logger: &Arc<Logger>, _file: &File) -> Result<Self::Data> {
+        Ok(logger.clone())
+    }
+
+    fn write(
+        logger: ArcBorrow<'_, Logger>,
+        _file: &File,
+        data: &mut impl IoBufferReader,
+        _offset: &mut u64,
+    ) -> Result<usize> {
+        let bytes = data.len();
+        Logger::record(&logger.into(), Event::Write { bytes });
+        Ok(bytes)
+    }
+}
+
+struct RustUdpLogger {
+    logger: Arc<Logger>,
+    event: Option<DebugFsFile<EventFile>>,
+}
+
+impl kernel::Module for RustUdpLogger {
+    fn init(name: &'static CStr, _module: &'static ThisModule) -> Result<Self> {
+        pr_info!("Rust UDP logger sample (init)\n");
+
+        let addr = parse_ipv4(collector_addr.read()).map_err(|e| {
+            pr_err!("Invalid collector address\n");
+            e
+        })?;
+        let collector = SocketAddr::V4(SocketAddrV4::new(addr, *collector_port.read()));
+        let logger = Logger::try_new(&collector)?;
+
+        let dir = DebugFsDirectory::create(name, None)?;
+        let vtable = kernel::file_vtable!(debugfs::Vtable<EventFile>);
+        let event =
+            DebugFsFile::create(c_str!("event"), Some(dir), MODE_200, vtable, logger.clone())?;
+
+        Logger::record(&logger, Event::Loaded);
+        Ok(RustUdpLogger {
+            logger,
+            event: Some(event),
+        })
+    }
+}
+
+impl Drop for RustUdpLogger {
+    fn drop(&mut self) {
+        // Removing the file waits for the writes in progress, so that no event is recorded, and
+        // the work item is not scheduled again, once it is cancelled.
+        self.event.take();
+        self.logger.log.record(Event::Unloading);
+        self.logger.work.cancel();
+        self.logger.flush();
+        pr_info!("Rust UDP logger sample (exit)\n");
+    }
+}
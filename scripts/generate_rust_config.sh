@@ -0,0 +1,44 @@
+#!/bin/sh
+# SPDX-License-Identifier: GPL-2.0
+#
+# Generates Rust constants for the Kconfig options of a configuration file.
+#
+# Boolean and tristate options become `bool` constants that are `true` only when
+# the option is built in. Modular options get an additional `<name>_MODULE`
+# constant, like in `include/generated/autoconf.h`. Integer, hexadecimal and
+# string options become `i64`, `u64` and `&str` constants respectively.
+
+if [ $# -ne 1 ]; then
+	echo "usage: $0 <config>" >&2
+	exit 1
+fi
+
+awk '
+function emit(name, type, value) {
+	printf "/// The value of `%s`.\npub const %s: %s = %s;\n", name, name, type, value
+}
+
+/^# CONFIG_[A-Za-z0-9_]+ is not set$/ {
+	emit($2, "bool", "false")
+	next
+}
+
+/^CONFIG_[A-Za-z0-9_]+=/ {
+	i = index($0, "=")
+	name = substr($0, 1, i - 1)
+	value = substr($0, i + 1)
+
+	if (value == "y") {
+		emit(name, "bool", "true")
+	} else if (value == "m") {
+		emit(name, "bool", "false")
+		emit(name "_MODULE", "bool", "true")
+	} else if (value ~ /^"/) {
+		emit(name, "&str", value)
+	} else if (value ~ /^0[xX][0-9a-fA-F]+$/) {
+		emit(name, "u64", value)
+	} else if (value ~ /^-?[0-9]+$/) {
+		emit(name, "i64", value)
+	}
+}
+' "$1"